@@ -0,0 +1,49 @@
+// 启用 `grpc` 特性时按 proto/lokipool.proto 中的服务生成tonic服务端与客户端，
+// 消息类型在 src/grpc.rs 中手写，不需要protoc
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc::generate();
+}
+
+#[cfg(feature = "grpc")]
+mod grpc {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    /// 方法名、请求类型、应答类型，以及是否以流返回
+    const METHODS: &[(&str, &str, &str, bool)] = &[
+        ("ListProxies", "ListProxiesRequest", "ListProxiesResponse", false),
+        ("AddProxy", "AddProxyRequest", "Proxy", false),
+        ("RemoveProxy", "RemoveProxyRequest", "RemoveProxyResponse", false),
+        ("TestProxy", "TestProxyRequest", "Job", false),
+        ("TestAll", "TestAllRequest", "Job", false),
+        ("GetJob", "GetJobRequest", "Job", false),
+        ("GetStats", "GetStatsRequest", "Stats", false),
+        ("StreamEvents", "StreamEventsRequest", "Event", true),
+    ];
+
+    pub fn generate() {
+        println!("cargo:rerun-if-changed=build.rs");
+        let service = METHODS.iter().fold(Service::builder().name("LokiPool").package("lokipool.v1"), |service, &(name, input, output, streaming)| {
+            let method = Method::builder()
+                .name(snake_case(name))
+                .route_name(name)
+                .input_type(format!("crate::grpc::proto::{}", input))
+                .output_type(format!("crate::grpc::proto::{}", output))
+                .codec_path("tonic::codec::ProstCodec");
+            let method = if streaming { method.server_streaming() } else { method };
+            service.method(method.build())
+        });
+        Builder::new().compile(&[service.build()]);
+    }
+
+    fn snake_case(name: &str) -> String {
+        let mut snake = String::new();
+        for (i, c) in name.chars().enumerate() {
+            if c.is_ascii_uppercase() && i > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    }
+}
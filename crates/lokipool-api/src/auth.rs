@@ -0,0 +1,171 @@
+//! API认证
+//!
+//! 配置了API密钥或JWT密钥后，修改状态的请求（GET/HEAD/OPTIONS以外的方法）需要 `admin` 权限，
+//! `protect_reads` 时读取请求也需要认证（`read` 或 `admin` 权限）。凭据放在 `Authorization: Bearer <凭据>`
+//! 或 `X-API-Key` 请求头中，可以是静态API密钥，也可以是用HS256签名、带 `scope` 声明的JWT。
+//! 出口节点接口使用各自的令牌认证，PAC文件供无法携带请求头的客户端读取，两者不经过这里。
+
+use std::fmt;
+use std::str::FromStr;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use lokipool_core::constant_time_eq;
+use lokipool_core::time::wall_now;
+use ring::hmac;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::ApiState;
+
+/// 凭据的权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// 只能读取
+    Read,
+    /// 可以读取与修改
+    Admin,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Read => write!(f, "read"),
+            Scope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "admin" => Ok(Scope::Admin),
+            other => Err(format!("未知的API权限: {}", other)),
+        }
+    }
+}
+
+/// 静态API密钥
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub key: String,
+    pub scope: Scope,
+}
+
+impl ApiKey {
+    /// 解析 `权限:密钥` 格式，例如 `admin:s3cret`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (scope, key) = spec.split_once(':').ok_or_else(|| format!("API密钥应为 权限:密钥 格式: {}", spec))?;
+        if key.is_empty() {
+            return Err("API密钥不能为空".to_string());
+        }
+        Ok(Self { key: key.to_string(), scope: scope.parse()? })
+    }
+}
+
+/// 认证设置，没有密钥也没有JWT密钥时不要求认证
+#[derive(Debug, Clone, Default)]
+pub struct ApiAuth {
+    /// 静态API密钥
+    pub keys: Vec<ApiKey>,
+    /// HS256签名JWT的共享密钥
+    pub jwt_secret: Option<String>,
+    /// 读取请求也要求认证
+    pub protect_reads: bool,
+}
+
+impl ApiAuth {
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty() || self.jwt_secret.is_some()
+    }
+
+    /// 凭据对应的权限，无效的凭据返回None
+    pub fn scope_of(&self, credential: &str) -> Option<Scope> {
+        // 逐个比较全部密钥，耗时不随匹配位置变化
+        let matched = self.keys.iter().fold(None, |matched, key| {
+            if constant_time_eq(key.key.as_bytes(), credential.as_bytes()) { Some(key.scope) } else { matched }
+        });
+        matched.or_else(|| self.jwt_secret.as_deref().and_then(|secret| verify_jwt(secret, credential)))
+    }
+}
+
+/// JWT中用到的声明
+#[derive(Debug, Deserialize)]
+struct Claims {
+    #[serde(default)]
+    scope: Option<Scope>,
+    exp: Option<i64>,
+    nbf: Option<i64>,
+}
+
+/// 校验HS256签名与有效期，返回 `scope` 声明，缺少该声明时视为只读
+fn verify_jwt(secret: &str, token: &str) -> Option<Scope> {
+    let mut parts = token.split('.');
+    let (header_part, claims_part, signature_part) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    #[derive(Deserialize)]
+    struct Header {
+        alg: String,
+    }
+    let header: Header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header_part).ok()?).ok()?;
+    if header.alg != "HS256" {
+        return None;
+    }
+    let signature = URL_SAFE_NO_PAD.decode(signature_part).ok()?;
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let signed = &token[..header_part.len() + 1 + claims_part.len()];
+    hmac::verify(&key, signed.as_bytes(), &signature).ok()?;
+
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims_part).ok()?).ok()?;
+    let now = wall_now().timestamp();
+    if claims.exp.is_some_and(|exp| now >= exp) || claims.nbf.is_some_and(|nbf| now < nbf) {
+        return None;
+    }
+    Some(claims.scope.unwrap_or(Scope::Read))
+}
+
+/// 请求头中的凭据
+fn credential(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|value| value.to_str().ok()))
+        .map(str::trim)
+}
+
+/// 不经过认证层的路径，各自处理认证或本就公开
+fn is_exempt(path: &str) -> bool {
+    path == "/proxy.pac" || path == "/api/v1/exits" || path.starts_with("/api/v1/exits/")
+}
+
+/// 按请求方法要求相应的权限，认证失败时应答401，权限不足时应答403
+pub async fn require<B>(State(state): State<ApiState>, request: Request<B>, next: Next<B>) -> Response {
+    let auth = &state.api_config.auth;
+    if !auth.is_enabled() || is_exempt(request.uri().path()) {
+        return next.run(request).await;
+    }
+    let required = match *request.method() {
+        Method::GET | Method::HEAD | Method::OPTIONS if !auth.protect_reads => return next.run(request).await,
+        Method::GET | Method::HEAD | Method::OPTIONS => Scope::Read,
+        _ => Scope::Admin,
+    };
+    match credential(request.headers()).and_then(|credential| auth.scope_of(credential)) {
+        Some(scope) if scope >= required => next.run(request).await,
+        Some(scope) => (StatusCode::FORBIDDEN, format!("需要 {} 权限，当前凭据只有 {} 权限", required, scope)).into_response(),
+        None => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "缺少或无效的API凭据".to_string(),
+        ).into_response(),
+    }
+}
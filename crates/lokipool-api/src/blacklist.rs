@@ -0,0 +1,120 @@
+//! 临时黑名单与隔离观察
+//!
+//! 与 `/api/v1/blocklist` 的永久黑名单不同，这里的代理在冷却期或观察期结束后自动恢复：
+//! 连续失败达到 `blacklist_after_failures` 次的代理被临时拉黑，恢复后的代理先进入隔离观察。
+//! 可以列出这些代理及其到期时间，手动拉黑某个代理，或提前恢复；冷却时长通过 `PATCH /api/v1/config` 修改。
+
+use std::time::{Duration, Instant};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+use chrono::{DateTime, Utc};
+use lokipool_core::time::wall_now;
+use lokipool_core::{Proxy, ProxyStatus};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::ApiState;
+
+/// 暂不参与正常选择的原因
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Sidelined {
+    /// 临时黑名单，冷却期内不会被选中
+    Blacklisted,
+    /// 隔离观察，只接收少量流量，观察期结束后按错误率决定是否恢复
+    Quarantined,
+}
+
+/// 临时拉黑或隔离中的代理
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SidelinedProxy {
+    pub id: String,
+    /// `host:port`
+    pub address: String,
+    pub reason: Sidelined,
+    /// 冷却期或观察期的结束时间；观察期结束后在下一次测试或连接时结算
+    pub until: DateTime<Utc>,
+    /// 距结束的秒数
+    pub remaining_secs: u64,
+    /// 实际流量中连续失败的次数
+    pub consecutive_failures: u32,
+}
+
+impl SidelinedProxy {
+    fn of(proxy: &Proxy, quarantine_period: Duration) -> Option<Self> {
+        let (reason, until) = match (proxy.blacklisted_until, &proxy.quarantine) {
+            (Some(until), _) if proxy.is_blacklisted() => (Sidelined::Blacklisted, until),
+            (_, Some(quarantine)) if proxy.status == ProxyStatus::Quarantined => (Sidelined::Quarantined, quarantine.since + quarantine_period),
+            _ => return None,
+        };
+        let remaining = until.saturating_duration_since(Instant::now());
+        Some(Self {
+            id: proxy.id.clone(),
+            address: format!("{}:{}", proxy.info.host, proxy.info.port),
+            reason,
+            until: wall_now() + chrono::Duration::from_std(remaining).unwrap_or_default(),
+            remaining_secs: remaining.as_secs(),
+            consecutive_failures: proxy.consecutive_failures,
+        })
+    }
+}
+
+/// 手动拉黑的参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BlacklistRequest {
+    /// 冷却时间（秒），缺省为 `blacklist_duration`
+    duration: Option<u64>,
+}
+
+/// 列出临时拉黑与隔离中的代理，按剩余时间排列
+pub async fn list_blacklist(State(state): State<ApiState>) -> Json<Vec<SidelinedProxy>> {
+    let quarantine_period = Duration::from_secs(state.pool.options().quarantine_period);
+    let mut proxies: Vec<SidelinedProxy> = state.pool.get_all_proxies().await
+        .iter()
+        .filter_map(|proxy| SidelinedProxy::of(proxy, quarantine_period))
+        .collect();
+    proxies.sort_by_key(|proxy| proxy.remaining_secs);
+    Json(proxies)
+}
+
+/// 手动把代理加入临时黑名单
+pub async fn blacklist_proxy(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    request: Result<Json<BlacklistRequest>, JsonRejection>,
+) -> Result<Json<SidelinedProxy>, (StatusCode, String)> {
+    let request = match request {
+        Ok(Json(request)) => request,
+        Err(JsonRejection::MissingJsonContentType(_)) => BlacklistRequest::default(),
+        Err(e) => return Err((e.status(), e.body_text())),
+    };
+    let options = state.pool.options();
+    let duration = request.duration.unwrap_or(options.blacklist_duration);
+    if duration == 0 {
+        return Err((StatusCode::BAD_REQUEST, "duration 必须大于0".to_string()));
+    }
+    if !state.pool.blacklist(&id, Duration::from_secs(duration)).await {
+        return Err((StatusCode::NOT_FOUND, format!("没有代理 {}", id)));
+    }
+    let proxy = state.pool.get_all_proxies().await
+        .into_iter()
+        .find(|proxy| proxy.id == id)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("没有代理 {}", id)))?;
+    info!("通过API拉黑代理 {}:{} {} 秒", proxy.info.host, proxy.info.port, duration);
+    SidelinedProxy::of(&proxy, Duration::from_secs(options.quarantine_period))
+        .map(Json)
+        .ok_or((StatusCode::CONFLICT, format!("代理 {} 未能加入黑名单", id)))
+}
+
+/// 提前恢复代理：移出临时黑名单，结束隔离观察
+pub async fn reinstate_proxy(State(state): State<ApiState>, Path(id): Path<String>) -> StatusCode {
+    if !state.pool.reinstate(&id).await {
+        return StatusCode::NOT_FOUND;
+    }
+    info!("通过API恢复代理 {}", id);
+    StatusCode::NO_CONTENT
+}
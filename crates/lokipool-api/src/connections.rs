@@ -0,0 +1,34 @@
+//! 进行中的连接
+//!
+//! API与SOCKS5服务运行在同一进程时，可以列出各监听端口正在转发的连接（客户端、目标、代理、存在时间与流量），
+//! 并强制关闭单个连接。单独运行的 `lokipool-api` 没有连接可查看，这些接口应答503。
+
+use std::sync::Arc;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+use lokipool_core::{ConnectionControl, ConnectionEntry};
+use tracing::info;
+
+use crate::ApiState;
+
+type ApiError = (StatusCode, String);
+
+fn control(state: &ApiState) -> Result<&Arc<dyn ConnectionControl>, ApiError> {
+    state.connections.as_ref()
+        .ok_or((StatusCode::SERVICE_UNAVAILABLE, "API未与SOCKS5服务运行在同一进程，无法查看连接".to_string()))
+}
+
+/// 全部进行中的连接，按接受的先后排列
+pub async fn list_connections(State(state): State<ApiState>) -> Result<Json<Vec<ConnectionEntry>>, ApiError> {
+    Ok(Json(control(&state)?.connections()))
+}
+
+/// 强制关闭连接，成功时应答204
+pub async fn close_connection(State(state): State<ApiState>, Path(id): Path<u64>) -> Result<StatusCode, ApiError> {
+    if !control(&state)?.close(id) {
+        return Err((StatusCode::NOT_FOUND, format!("没有进行中的连接 {}", id)));
+    }
+    info!("通过API关闭连接 {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,137 @@
+//! 代理池事件流
+//!
+//! `GET /api/v1/events/sse` 以Server-Sent Events推送代理池事件（测试完成、状态变化、代理增删等），
+//! 每个事件带递增的ID。客户端断线重连时带上 `Last-Event-ID`，从最近保留的 `REPLAY_CAPACITY` 个事件中补发之后的部分；
+//! 服务重启后ID从头计数，带着更大的ID重连的客户端会收到保留的全部事件。
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::{Stream, StreamExt};
+use lokipool_core::{spawn_logged, PoolEvent, PoolHandle};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::debug;
+
+use crate::ApiState;
+
+/// 保留用于补发的事件数
+const REPLAY_CAPACITY: usize = 1024;
+
+/// 转发通道容量，订阅者落后更多时从保留的事件中补齐
+const LIVE_CAPACITY: usize = 256;
+
+/// 带ID的事件
+pub(crate) type Numbered = (u64, PoolEvent);
+
+#[derive(Debug)]
+struct History {
+    /// 下一个事件的ID，从1开始
+    next_id: u64,
+    events: VecDeque<Numbered>,
+}
+
+/// 最近的事件与实时事件的转发通道，克隆后共享同一份数据
+#[derive(Debug, Clone)]
+pub struct EventHistory {
+    history: Arc<Mutex<History>>,
+    live: broadcast::Sender<Numbered>,
+}
+
+impl Default for EventHistory {
+    fn default() -> Self {
+        Self {
+            history: Arc::new(Mutex::new(History { next_id: 1, events: VecDeque::new() })),
+            live: broadcast::channel(LIVE_CAPACITY).0,
+        }
+    }
+}
+
+impl EventHistory {
+    /// 在后台记录代理池的事件
+    pub fn record_from(&self, pool: &PoolHandle) {
+        let mut events = pool.subscribe();
+        let history = self.clone();
+        spawn_logged("事件流记录", async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => history.push(event),
+                    Err(RecvError::Lagged(skipped)) => debug!("事件流记录落后，跳过 {} 个事件", skipped),
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    fn push(&self, event: PoolEvent) {
+        let mut history = self.history.lock().unwrap();
+        let id = history.next_id;
+        history.next_id += 1;
+        if history.events.len() >= REPLAY_CAPACITY {
+            history.events.pop_front();
+        }
+        history.events.push_back((id, event.clone()));
+        // 在锁内发送，与 `subscribe` 取得的补发部分之间不会漏掉或重复事件
+        let _ = self.live.send((id, event));
+    }
+
+    /// ID大于 `last` 的保留事件
+    fn after(history: &History, last: u64) -> VecDeque<Numbered> {
+        history.events.iter().filter(|(id, _)| *id > last).cloned().collect()
+    }
+
+    /// 从客户端已收到的事件之后开始的推送进度；未带 `Last-Event-ID` 时只推送之后的新事件，
+    /// 带的不是本次运行发出的ID时补发全部保留事件
+    fn subscribe(&self, last: Option<u64>) -> Cursor {
+        let history = self.history.lock().unwrap();
+        let sent = match last {
+            Some(last) if last < history.next_id => last,
+            Some(_) => 0,
+            None => history.next_id - 1,
+        };
+        Cursor { history: self.clone(), backlog: Self::after(&history, sent), live: self.live.subscribe(), sent }
+    }
+
+    /// 先补发再推送实时事件；订阅者落后时从保留的事件中补齐
+    pub(crate) fn numbered(&self, last: Option<u64>) -> impl Stream<Item = Numbered> {
+        let cursor = self.subscribe(last);
+        futures::stream::unfold(cursor, |mut cursor| async move {
+            loop {
+                if let Some((id, event)) = cursor.backlog.pop_front() {
+                    if id <= cursor.sent {
+                        continue;
+                    }
+                    cursor.sent = id;
+                    return Some(((id, event), cursor));
+                }
+                match cursor.live.recv().await {
+                    Ok(numbered) => cursor.backlog.push_back(numbered),
+                    Err(RecvError::Lagged(_)) => {
+                        let history = cursor.history.history.lock().unwrap();
+                        cursor.backlog = Self::after(&history, cursor.sent);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
+
+/// 单个订阅者的推送进度
+struct Cursor {
+    history: EventHistory,
+    backlog: VecDeque<Numbered>,
+    live: broadcast::Receiver<Numbered>,
+    /// 已推送的最大事件ID
+    sent: u64,
+}
+
+/// 以SSE推送代理池事件
+pub async fn sse(State(state): State<ApiState>, headers: HeaderMap) -> Sse<impl Stream<Item = Result<Event, serde_json::Error>>> {
+    let last = headers.get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    let events = state.events.numbered(last).map(|(id, event)| Event::default().id(id.to_string()).json_data(&event));
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,384 @@
+//! 出口节点（Bridge模式）管理
+//!
+//! 远程主机上以 `lokipool exit` 运行的出口代理通过此模块注册到中心实例，
+//! 注册后作为普通上游代理加入代理池，并由后台任务定期进行健康检查。
+//!
+//! 配对流程：管理员通过API签发加入令牌，出口节点使用加入令牌注册并获得
+//! 节点专属密钥，之后的心跳与注销均使用该密钥认证。吊销加入令牌会同时移除
+//! 所有通过该令牌注册的节点。
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use lokipool_core::{constant_time_eq, supervise, Backoff, Pool, PoolHandle, Proxy, ProxyStatus, Stamp};
+use lokipool_core::time::wall_now;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+use tokio::time::timeout;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::ApiState;
+
+/// 出口节点注册请求
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RegisterRequest {
+    /// 加入令牌
+    pub token: String,
+    /// 对外可访问的地址，缺省时使用请求来源地址
+    pub host: Option<String>,
+    /// 出口节点SOCKS5端口
+    pub port: u16,
+    /// 节点名称（可选）
+    pub name: Option<String>,
+    /// 节点所在区域（可选）
+    pub region: Option<String>,
+    /// 节点带宽，单位Mbps（可选）
+    pub bandwidth_mbps: Option<u32>,
+}
+
+/// 出口节点注册响应
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RegisterResponse {
+    /// 分配的代理ID
+    pub id: String,
+    /// 节点专属密钥，用于心跳与注销
+    pub secret: String,
+    /// 心跳间隔（秒）
+    pub heartbeat_interval: u64,
+    /// 中心实例连接节点SOCKS5端口时使用的用户名，节点必须要求该账户
+    pub socks_username: String,
+    /// 中心实例连接节点SOCKS5端口时使用的密码
+    pub socks_password: String,
+}
+
+/// 心跳与注销请求
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AgentAuth {
+    /// 注册时获得的节点密钥
+    pub secret: String,
+}
+
+/// 签发加入令牌请求
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct IssueTokenRequest {
+    /// 令牌备注
+    pub label: Option<String>,
+    /// 有效期（秒），缺省为永久有效
+    pub ttl_secs: Option<u64>,
+    /// 最大使用次数，缺省为不限次数
+    pub max_uses: Option<u32>,
+}
+
+/// 加入令牌
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct JoinToken {
+    /// 令牌值
+    pub token: String,
+    /// 令牌备注
+    pub label: Option<String>,
+    /// 签发时间
+    pub created_at: DateTime<Utc>,
+    /// 过期时间，按单调时钟判断，系统时间跳变不影响有效期
+    pub expires_at: Option<Stamp>,
+    /// 最大使用次数
+    pub max_uses: Option<u32>,
+    /// 已使用次数
+    pub uses: u32,
+}
+
+impl JoinToken {
+    fn is_usable(&self) -> bool {
+        let not_expired = self.expires_at.is_none_or(|at| !at.has_passed());
+        let has_uses = self.max_uses.is_none_or(|max| self.uses < max);
+        not_expired && has_uses
+    }
+}
+
+/// 已注册的出口节点
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ExitAgent {
+    /// 代理池中的代理ID
+    pub id: String,
+    /// 节点名称
+    pub name: Option<String>,
+    /// 节点所在区域
+    pub region: Option<String>,
+    /// 节点带宽（Mbps）
+    pub bandwidth_mbps: Option<u32>,
+    /// 节点地址
+    pub host: String,
+    /// 节点端口
+    pub port: u16,
+    /// 注册时间
+    pub registered_at: DateTime<Utc>,
+    /// 注册所用的加入令牌
+    #[serde(skip)]
+    pub join_token: String,
+    /// 节点专属密钥
+    #[serde(skip)]
+    pub secret: String,
+    /// 最后一次心跳时间
+    #[serde(skip)]
+    pub last_heartbeat: Instant,
+}
+
+/// 出口节点注册表
+#[derive(Debug, Clone, Default)]
+pub struct ExitRegistry {
+    agents: Arc<RwLock<HashMap<String, ExitAgent>>>,
+    tokens: Arc<RwLock<HashMap<String, JoinToken>>>,
+}
+
+impl ExitRegistry {
+    /// 消耗一次加入令牌，令牌无效时返回false
+    async fn consume_token(&self, token: &str, master: Option<&str>) -> bool {
+        if master.is_some_and(|master| constant_time_eq(master.as_bytes(), token.as_bytes())) {
+            return true;
+        }
+        let mut tokens = self.tokens.write().await;
+        match tokens.get_mut(token) {
+            Some(join) if join.is_usable() => {
+                join.uses += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 移除节点并同步从代理池中删除
+    async fn remove_agent(&self, pool: &Pool, id: &str) -> Option<ExitAgent> {
+        let agent = self.agents.write().await.remove(id)?;
+        pool.remove(id).await;
+        Some(agent)
+    }
+}
+
+/// 校验管理员令牌（Authorization: Bearer <exit_token>）
+fn check_admin(state: &ApiState, headers: &HeaderMap) -> Result<(), StatusCode> {
+    let Some(master) = &state.api_config.exit_token else {
+        // 未配置主令牌时禁用Bridge模式
+        return Err(StatusCode::FORBIDDEN);
+    };
+    let provided = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if provided.is_some_and(|provided| constant_time_eq(provided.as_bytes(), master.as_bytes())) {
+        Ok(())
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// 签发加入令牌
+pub async fn issue_token(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    body: Option<Json<IssueTokenRequest>>,
+) -> Result<Json<JoinToken>, StatusCode> {
+    check_admin(&state, &headers)?;
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+
+    let created = Stamp::now();
+    let join = JoinToken {
+        token: Uuid::new_v4().simple().to_string(),
+        label: req.label,
+        created_at: created.wall(),
+        expires_at: req.ttl_secs.map(|ttl| created.after(Duration::from_secs(ttl))),
+        max_uses: req.max_uses,
+        uses: 0,
+    };
+    state.exits.tokens.write().await.insert(join.token.clone(), join.clone());
+    info!("已签发出口节点加入令牌 (备注: {})", join.label.as_deref().unwrap_or("-"));
+
+    Ok(Json(join))
+}
+
+/// 列出加入令牌
+pub async fn list_tokens(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<JoinToken>>, StatusCode> {
+    check_admin(&state, &headers)?;
+    let tokens = state.exits.tokens.read().await;
+    Ok(Json(tokens.values().cloned().collect()))
+}
+
+/// 吊销加入令牌，并移除通过该令牌注册的所有节点
+pub async fn revoke_token(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> StatusCode {
+    if let Err(code) = check_admin(&state, &headers) {
+        return code;
+    }
+    if state.exits.tokens.write().await.remove(&token).is_none() {
+        return StatusCode::NOT_FOUND;
+    }
+
+    let revoked: Vec<String> = state.exits.agents.read().await.values()
+        .filter(|agent| agent.join_token == token)
+        .map(|agent| agent.id.clone())
+        .collect();
+    for id in &revoked {
+        state.exits.remove_agent(&state.pool, id).await;
+    }
+    info!("加入令牌已吊销，移除了 {} 个出口节点", revoked.len());
+
+    StatusCode::NO_CONTENT
+}
+
+/// 列出已注册的出口节点
+pub async fn list_exits(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ExitAgent>>, StatusCode> {
+    check_admin(&state, &headers)?;
+    let agents = state.exits.agents.read().await;
+    Ok(Json(agents.values().cloned().collect()))
+}
+
+/// 注册出口节点
+pub async fn register_exit(
+    State(state): State<ApiState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    Json(req): Json<RegisterRequest>,
+) -> Result<Json<RegisterResponse>, StatusCode> {
+    if state.api_config.exit_token.is_none() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if !state.exits.consume_token(&req.token, state.api_config.exit_token.as_deref()).await {
+        warn!("来自 {} 的出口节点使用了无效的加入令牌", remote);
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let host = req.host.unwrap_or_else(|| remote.ip().to_string());
+    // 每次注册签发新的SOCKS5账户，出口节点据此拒绝中心实例以外的连接
+    let socks_username = format!("exit-{}", Uuid::new_v4().simple());
+    let socks_password = Uuid::new_v4().simple().to_string();
+    let mut proxy = Proxy::new(host.clone(), req.port, Some(socks_username.clone()), Some(socks_password.clone()));
+    proxy.info.location = req.region.clone().or_else(|| req.name.clone());
+
+    // 注册前先确认出口节点可达
+    let reachable = probe_agent(&host, req.port).await;
+    proxy.update_status(if reachable { ProxyStatus::Available } else { ProxyStatus::Failed });
+
+    let id = state.pool.add(proxy).await.map_err(|e| {
+        warn!("出口节点 {}:{} 注册失败: {}", host, req.port, e);
+        StatusCode::INSUFFICIENT_STORAGE
+    })?;
+
+    let secret = Uuid::new_v4().simple().to_string();
+    state.exits.agents.write().await.insert(id.clone(), ExitAgent {
+        id: id.clone(),
+        name: req.name,
+        region: req.region,
+        bandwidth_mbps: req.bandwidth_mbps,
+        host: host.clone(),
+        port: req.port,
+        registered_at: wall_now(),
+        join_token: req.token,
+        secret: secret.clone(),
+        last_heartbeat: Instant::now(),
+    });
+
+    info!("出口节点已注册: {}:{} (ID: {}, 可达: {})", host, req.port, id, reachable);
+
+    Ok(Json(RegisterResponse {
+        id,
+        secret,
+        heartbeat_interval: state.api_config.exit_heartbeat_interval,
+        socks_username,
+        socks_password,
+    }))
+}
+
+/// 出口节点心跳
+pub async fn heartbeat_exit(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(req): Json<AgentAuth>,
+) -> StatusCode {
+    match state.exits.agents.write().await.get_mut(&id) {
+        Some(agent) if constant_time_eq(agent.secret.as_bytes(), req.secret.as_bytes()) => {
+            agent.last_heartbeat = Instant::now();
+            StatusCode::NO_CONTENT
+        }
+        Some(_) => StatusCode::UNAUTHORIZED,
+        // 中心实例重启或节点被吊销后旧ID失效，出口节点需要重新注册
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+/// 注销出口节点
+///
+/// 节点使用自身密钥注销；管理员可通过 `Authorization` 头强制移除任意节点。
+pub async fn deregister_exit(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    body: Option<Json<AgentAuth>>,
+) -> StatusCode {
+    let is_admin = check_admin(&state, &headers).is_ok();
+    let authorized = match state.exits.agents.read().await.get(&id) {
+        Some(agent) => is_admin || body.as_ref().is_some_and(|Json(auth)| constant_time_eq(auth.secret.as_bytes(), agent.secret.as_bytes())),
+        None => return StatusCode::NOT_FOUND,
+    };
+    if !authorized {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    if let Some(agent) = state.exits.remove_agent(&state.pool, &id).await {
+        info!("出口节点已注销: {}:{} (ID: {})", agent.host, agent.port, id);
+    }
+    StatusCode::NO_CONTENT
+}
+
+/// 探测出口节点端口是否可达
+async fn probe_agent(host: &str, port: u16) -> bool {
+    matches!(
+        timeout(Duration::from_secs(5), TcpStream::connect((host, port))).await,
+        Ok(Ok(_))
+    )
+}
+
+/// 启动出口节点健康检查任务
+///
+/// 心跳超时的节点被标记为失败，超过三个心跳周期未响应的节点从池中移除。
+pub fn start_exit_health_check(pool: PoolHandle, exits: ExitRegistry, interval: u64) {
+    supervise("出口节点健康检查", Backoff::default(), move || {
+        let pool = pool.clone();
+        let exits = exits.clone();
+        async move {
+            let interval = Duration::from_secs(interval.max(1));
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let agents: Vec<ExitAgent> = exits.agents.read().await.values().cloned().collect();
+                for agent in agents {
+                    let silent_for = agent.last_heartbeat.elapsed();
+                    if silent_for > interval * 3 {
+                        exits.remove_agent(&pool, &agent.id).await;
+                        warn!("出口节点 {}:{} 长时间无心跳，已移除", agent.host, agent.port);
+                        continue;
+                    }
+
+                    let healthy = silent_for <= interval * 2 && probe_agent(&agent.host, agent.port).await;
+                    let status = if healthy { ProxyStatus::Available } else { ProxyStatus::Failed };
+                    pool.update_status(&agent.id, status).await;
+                }
+            }
+        }
+    });
+}
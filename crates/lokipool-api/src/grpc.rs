@@ -0,0 +1,527 @@
+//! gRPC控制接口
+//!
+//! 启用 `grpc` 特性并设置 `grpc_port` 后，在单独的端口上以gRPC（HTTP/2明文）提供与REST API对应的操作：
+//! 列出、添加、移除与测试代理，查看统计，订阅代理池事件。契约见 `proto/lokipool.proto`，
+//! 认证与REST API相同，凭据放在 `authorization: Bearer <凭据>` 或 `x-api-key` 元数据中。
+
+// tonic的接口以 `Result<_, Status>` 返回错误
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use futures::{Stream, StreamExt};
+use lokipool_core::{CircuitState, PoolEvent, Proxy, ProxyConfig, ProxyStatus};
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+use crate::auth::Scope;
+use crate::jobs::{Job, JobKind, JobState};
+use crate::ApiState;
+
+/// 与 `proto/lokipool.proto` 对应的消息类型，以及生成的服务端与客户端
+pub mod proto {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum ProxyStatus {
+        Unspecified = 0,
+        Available = 1,
+        InUse = 2,
+        Failed = 3,
+        Untested = 4,
+        Unknown = 5,
+        Quarantined = 6,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum CircuitState {
+        Unspecified = 0,
+        Closed = 1,
+        Open = 2,
+        HalfOpen = 3,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Proxy {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub address: String,
+        #[prost(string, tag = "3")]
+        pub proxy_type: String,
+        #[prost(string, optional, tag = "4")]
+        pub location: Option<String>,
+        #[prost(uint32, tag = "5")]
+        pub weight: u32,
+        #[prost(enumeration = "ProxyStatus", tag = "6")]
+        pub status: i32,
+        #[prost(uint64, optional, tag = "7")]
+        pub latency_ms: Option<u64>,
+        #[prost(double, tag = "8")]
+        pub success_rate: f64,
+        #[prost(uint64, tag = "9")]
+        pub active_connections: u64,
+        #[prost(uint64, tag = "10")]
+        pub total_connections: u64,
+        #[prost(uint64, tag = "11")]
+        pub bytes_up: u64,
+        #[prost(uint64, tag = "12")]
+        pub bytes_down: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ListProxiesRequest {
+        #[prost(enumeration = "ProxyStatus", tag = "1")]
+        pub status: i32,
+        #[prost(string, optional, tag = "2")]
+        pub location: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ListProxiesResponse {
+        #[prost(message, repeated, tag = "1")]
+        pub proxies: Vec<Proxy>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AddProxyRequest {
+        #[prost(string, tag = "1")]
+        pub url: String,
+        #[prost(string, optional, tag = "2")]
+        pub location: Option<String>,
+        #[prost(uint32, optional, tag = "3")]
+        pub weight: Option<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RemoveProxyRequest {
+        #[prost(string, tag = "1")]
+        pub id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RemoveProxyResponse {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TestProxyRequest {
+        #[prost(string, tag = "1")]
+        pub id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TestAllRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetJobRequest {
+        #[prost(string, tag = "1")]
+        pub id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct JobResult {
+        #[prost(string, tag = "1")]
+        pub proxy_id: String,
+        #[prost(string, tag = "2")]
+        pub address: String,
+        #[prost(bool, tag = "3")]
+        pub success: bool,
+        #[prost(uint64, optional, tag = "4")]
+        pub latency_ms: Option<u64>,
+        #[prost(string, optional, tag = "5")]
+        pub error: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Job {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub kind: String,
+        #[prost(bool, tag = "3")]
+        pub finished: bool,
+        #[prost(uint64, tag = "4")]
+        pub total: u64,
+        #[prost(uint64, tag = "5")]
+        pub completed: u64,
+        #[prost(uint64, tag = "6")]
+        pub succeeded: u64,
+        #[prost(uint64, tag = "7")]
+        pub failed: u64,
+        #[prost(message, repeated, tag = "8")]
+        pub results: Vec<JobResult>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetStatsRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Stats {
+        #[prost(uint64, tag = "1")]
+        pub total_proxies: u64,
+        #[prost(uint64, tag = "2")]
+        pub available_proxies: u64,
+        #[prost(uint64, tag = "3")]
+        pub total_requests: u64,
+        #[prost(uint64, tag = "4")]
+        pub active_connections: u64,
+        #[prost(uint64, tag = "5")]
+        pub bytes_up: u64,
+        #[prost(uint64, tag = "6")]
+        pub bytes_down: u64,
+        #[prost(double, tag = "7")]
+        pub average_latency_ms: f64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamEventsRequest {
+        #[prost(uint64, optional, tag = "1")]
+        pub last_event_id: Option<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Event {
+        #[prost(uint64, tag = "1")]
+        pub id: u64,
+        #[prost(oneof = "event::Kind", tags = "2, 3, 4, 5, 6, 7, 8")]
+        pub kind: Option<event::Kind>,
+    }
+
+    pub mod event {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Kind {
+            #[prost(message, tag = "2")]
+            ProxyAdded(super::ProxyAdded),
+            #[prost(message, tag = "3")]
+            ProxyRemoved(super::ProxyRemoved),
+            #[prost(message, tag = "4")]
+            StatusChanged(super::StatusChanged),
+            #[prost(message, tag = "5")]
+            CircuitChanged(super::CircuitChanged),
+            #[prost(message, tag = "6")]
+            ThreatDetected(super::ThreatDetected),
+            #[prost(message, tag = "7")]
+            LowCapacity(super::LowCapacity),
+            #[prost(message, tag = "8")]
+            TestCompleted(super::TestCompleted),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ProxyAdded {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub host: String,
+        #[prost(uint32, tag = "3")]
+        pub port: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ProxyRemoved {
+        #[prost(string, tag = "1")]
+        pub id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StatusChanged {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(enumeration = "ProxyStatus", tag = "2")]
+        pub old: i32,
+        #[prost(enumeration = "ProxyStatus", tag = "3")]
+        pub new: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CircuitChanged {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(enumeration = "CircuitState", tag = "2")]
+        pub state: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ThreatDetected {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub host: String,
+        #[prost(uint32, tag = "3")]
+        pub port: u32,
+        #[prost(string, tag = "4")]
+        pub threat: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct LowCapacity {
+        #[prost(uint64, tag = "1")]
+        pub available: u64,
+        #[prost(uint64, tag = "2")]
+        pub min_available: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TestCompleted {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(bool, tag = "2")]
+        pub success: bool,
+        #[prost(uint64, optional, tag = "3")]
+        pub latency_ms: Option<u64>,
+    }
+
+    include!(concat!(env!("OUT_DIR"), "/lokipool.v1.LokiPool.rs"));
+}
+
+use proto::loki_pool_server::{LokiPool, LokiPoolServer};
+
+impl From<ProxyStatus> for proto::ProxyStatus {
+    fn from(status: ProxyStatus) -> Self {
+        match status {
+            ProxyStatus::Available => Self::Available,
+            ProxyStatus::InUse => Self::InUse,
+            ProxyStatus::Failed => Self::Failed,
+            ProxyStatus::Untested => Self::Untested,
+            ProxyStatus::Unknown => Self::Unknown,
+            ProxyStatus::Quarantined => Self::Quarantined,
+        }
+    }
+}
+
+impl proto::ProxyStatus {
+    fn to_core(self) -> Option<ProxyStatus> {
+        match self {
+            Self::Unspecified => None,
+            Self::Available => Some(ProxyStatus::Available),
+            Self::InUse => Some(ProxyStatus::InUse),
+            Self::Failed => Some(ProxyStatus::Failed),
+            Self::Untested => Some(ProxyStatus::Untested),
+            Self::Unknown => Some(ProxyStatus::Unknown),
+            Self::Quarantined => Some(ProxyStatus::Quarantined),
+        }
+    }
+}
+
+impl From<CircuitState> for proto::CircuitState {
+    fn from(state: CircuitState) -> Self {
+        match state {
+            CircuitState::Closed => Self::Closed,
+            CircuitState::Open => Self::Open,
+            CircuitState::HalfOpen => Self::HalfOpen,
+        }
+    }
+}
+
+impl From<&Proxy> for proto::Proxy {
+    fn from(proxy: &Proxy) -> Self {
+        let usage = proxy.usage_stats();
+        Self {
+            id: proxy.id.clone(),
+            address: format!("{}:{}", proxy.info.host, proxy.info.port),
+            proxy_type: proxy.info.proxy_type.clone(),
+            location: proxy.info.location.clone(),
+            weight: proxy.info.weight,
+            status: proto::ProxyStatus::from(proxy.status).into(),
+            latency_ms: proxy.info.last_latency,
+            success_rate: proxy.info.success_rate,
+            active_connections: usage.active_connections as u64,
+            total_connections: usage.total_connections,
+            bytes_up: usage.bytes_up,
+            bytes_down: usage.bytes_down,
+        }
+    }
+}
+
+impl From<Job> for proto::Job {
+    fn from(job: Job) -> Self {
+        let kind = match job.kind {
+            JobKind::TestProxy => "test_proxy",
+            JobKind::TestAll => "test_all",
+            JobKind::TestImported => "test_imported",
+        };
+        Self {
+            id: job.id,
+            kind: kind.to_string(),
+            finished: job.state == JobState::Finished,
+            total: job.total as u64,
+            completed: job.completed as u64,
+            succeeded: job.succeeded as u64,
+            failed: job.failed as u64,
+            results: job.results.into_iter()
+                .map(|result| proto::JobResult {
+                    proxy_id: result.proxy_id,
+                    address: result.address,
+                    success: result.success,
+                    latency_ms: result.latency,
+                    error: result.error,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn event(id: u64, event: PoolEvent) -> proto::Event {
+    use proto::event::Kind;
+    let kind = match event {
+        PoolEvent::ProxyAdded { id, host, port } => Kind::ProxyAdded(proto::ProxyAdded { id, host, port: port.into() }),
+        PoolEvent::ProxyRemoved { id } => Kind::ProxyRemoved(proto::ProxyRemoved { id }),
+        PoolEvent::StatusChanged { id, old, new } => Kind::StatusChanged(proto::StatusChanged {
+            id,
+            old: proto::ProxyStatus::from(old).into(),
+            new: proto::ProxyStatus::from(new).into(),
+        }),
+        PoolEvent::CircuitChanged { id, state } => Kind::CircuitChanged(proto::CircuitChanged {
+            id,
+            state: proto::CircuitState::from(state).into(),
+        }),
+        PoolEvent::ThreatDetected { id, host, port, threat } => Kind::ThreatDetected(proto::ThreatDetected {
+            id,
+            host,
+            port: port.into(),
+            threat: threat.to_string(),
+        }),
+        PoolEvent::LowCapacity { available, min_available } => Kind::LowCapacity(proto::LowCapacity {
+            available: available as u64,
+            min_available: min_available as u64,
+        }),
+        PoolEvent::TestCompleted { id, success, latency } => Kind::TestCompleted(proto::TestCompleted { id, success, latency_ms: latency }),
+    };
+    proto::Event { id, kind: Some(kind) }
+}
+
+/// 元数据中的凭据
+fn credential(metadata: &MetadataMap) -> Option<&str> {
+    metadata.get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| metadata.get("x-api-key").and_then(|value| value.to_str().ok()))
+        .map(str::trim)
+}
+
+/// 与REST API相同的gRPC服务
+pub struct GrpcService {
+    state: ApiState,
+}
+
+impl GrpcService {
+    /// 与REST API一样，修改状态的操作需要 `admin` 权限，`protect_reads` 时读取也需要认证
+    fn authorize<T>(&self, request: &Request<T>, required: Scope) -> Result<(), Status> {
+        let auth = &self.state.api_config.auth;
+        if !auth.is_enabled() || (required == Scope::Read && !auth.protect_reads) {
+            return Ok(());
+        }
+        match credential(request.metadata()).and_then(|credential| auth.scope_of(credential)) {
+            Some(scope) if scope >= required => Ok(()),
+            Some(scope) => Err(Status::permission_denied(format!("需要 {} 权限，当前凭据只有 {} 权限", required, scope))),
+            None => Err(Status::unauthenticated("缺少或无效的API凭据")),
+        }
+    }
+
+    async fn find(&self, id: &str) -> Result<Proxy, Status> {
+        self.state.pool.get_all_proxies().await
+            .into_iter()
+            .find(|proxy| proxy.id == id)
+            .ok_or_else(|| Status::not_found(format!("没有代理 {}", id)))
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl LokiPool for GrpcService {
+    async fn list_proxies(&self, request: Request<proto::ListProxiesRequest>) -> Result<Response<proto::ListProxiesResponse>, Status> {
+        self.authorize(&request, Scope::Read)?;
+        let query = request.into_inner();
+        let status = query.status().to_core();
+        let proxies = self.state.pool.get_all_proxies().await
+            .iter()
+            .filter(|proxy| status.is_none_or(|status| proxy.status == status))
+            .filter(|proxy| query.location.as_ref().is_none_or(|location| {
+                proxy.info.location.as_ref().is_some_and(|tag| tag.eq_ignore_ascii_case(location))
+            }))
+            .map(proto::Proxy::from)
+            .collect();
+        Ok(Response::new(proto::ListProxiesResponse { proxies }))
+    }
+
+    async fn add_proxy(&self, request: Request<proto::AddProxyRequest>) -> Result<Response<proto::Proxy>, Status> {
+        self.authorize(&request, Scope::Admin)?;
+        let request = request.into_inner();
+        let mut config = ProxyConfig::parse(&request.url).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        if let Some(location) = request.location.filter(|location| !location.is_empty()) {
+            config.location = Some(location);
+        }
+        if let Some(weight) = request.weight {
+            config.weight = weight;
+        }
+        crate::proxies::validate(&config).map_err(Status::invalid_argument)?;
+        let address = format!("{}:{}", config.host, config.port);
+        let id = self.state.pool.add_config(config).await.map_err(|e| Status::already_exists(e.to_string()))?;
+        let proxy = self.find(&id).await?;
+        info!("通过gRPC添加代理 {}", address);
+        Ok(Response::new(proto::Proxy::from(&proxy)))
+    }
+
+    async fn remove_proxy(&self, request: Request<proto::RemoveProxyRequest>) -> Result<Response<proto::RemoveProxyResponse>, Status> {
+        self.authorize(&request, Scope::Admin)?;
+        let id = request.into_inner().id;
+        let proxy = self.state.pool.remove(&id).await.ok_or_else(|| Status::not_found(format!("没有代理 {}", id)))?;
+        info!("通过gRPC移除代理 {}:{}", proxy.info.host, proxy.info.port);
+        Ok(Response::new(proto::RemoveProxyResponse {}))
+    }
+
+    async fn test_proxy(&self, request: Request<proto::TestProxyRequest>) -> Result<Response<proto::Job>, Status> {
+        self.authorize(&request, Scope::Admin)?;
+        let proxy = self.find(&request.into_inner().id).await?;
+        let job = self.state.jobs.start(self.state.pool.clone(), JobKind::TestProxy, vec![proxy]);
+        Ok(Response::new(job.into()))
+    }
+
+    async fn test_all(&self, request: Request<proto::TestAllRequest>) -> Result<Response<proto::Job>, Status> {
+        self.authorize(&request, Scope::Admin)?;
+        let proxies = self.state.pool.get_all_proxies().await;
+        let job = self.state.jobs.start(self.state.pool.clone(), JobKind::TestAll, proxies);
+        Ok(Response::new(job.into()))
+    }
+
+    async fn get_job(&self, request: Request<proto::GetJobRequest>) -> Result<Response<proto::Job>, Status> {
+        self.authorize(&request, Scope::Read)?;
+        let id = request.into_inner().id;
+        let job = self.state.jobs.get(&id).ok_or_else(|| Status::not_found(format!("没有任务 {}", id)))?;
+        Ok(Response::new(job.into()))
+    }
+
+    async fn get_stats(&self, request: Request<proto::GetStatsRequest>) -> Result<Response<proto::Stats>, Status> {
+        self.authorize(&request, Scope::Read)?;
+        let metrics = self.state.pool.metrics().await;
+        Ok(Response::new(proto::Stats {
+            total_proxies: metrics.total as u64,
+            available_proxies: metrics.status.available as u64,
+            total_requests: metrics.connections.total_connections,
+            active_connections: metrics.connections.active_connections as u64,
+            bytes_up: metrics.connections.bytes_up,
+            bytes_down: metrics.connections.bytes_down,
+            average_latency_ms: metrics.latency.mean.unwrap_or(0.0),
+        }))
+    }
+
+    type StreamEventsStream = EventStream;
+
+    async fn stream_events(&self, request: Request<proto::StreamEventsRequest>) -> Result<Response<Self::StreamEventsStream>, Status> {
+        self.authorize(&request, Scope::Read)?;
+        let last = request.into_inner().last_event_id;
+        let events = self.state.events.numbered(last).map(|(id, pool_event)| Ok(event(id, pool_event)));
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// 在 `addr` 上提供gRPC服务
+pub(crate) async fn serve(addr: SocketAddr, state: ApiState) -> anyhow::Result<()> {
+    info!("gRPC服务启动在: {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(LokiPoolServer::new(GrpcService { state }))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
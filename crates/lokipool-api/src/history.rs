@@ -0,0 +1,103 @@
+//! 指标历史
+//!
+//! 每隔 `stats_interval` 秒记录一次代理池指标，`GET /api/v1/stats/history?window=24h&step=5m`
+//! 把最近一段时间的样本按步长汇总成可用代理数、平均延迟与请求量的序列。
+//! 设置了 `stats_history_file` 时样本保存到文件，重启后历史不会中断。
+
+use std::time::Duration;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+use chrono::{DateTime, Utc};
+use lokipool_core::stats_history::RETENTION;
+use lokipool_core::time::wall_now;
+use lokipool_core::{spawn_logged, PoolHandle, StatsBucket, StatsHistory, StatsSample};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::ApiState;
+
+/// 一次请求最多返回的时间段数
+const MAX_BUCKETS: u64 = 2000;
+
+/// 每隔 `interval` 记录一次代理池指标
+pub fn start_sampler(pool: PoolHandle, history: StatsHistory, interval: Duration) {
+    spawn_logged("指标历史记录", async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let sample = StatsSample::from_metrics(wall_now(), &pool.metrics().await);
+            if let Err(e) = history.record(sample) {
+                warn!("保存指标历史失败: {}", e);
+            }
+        }
+    });
+}
+
+/// 历史查询参数
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HistoryQuery {
+    /// 查询最近多长时间，如 `30m`、`24h`、`7d`，缺省为 `24h`
+    window: Option<String>,
+    /// 每个时间段的长度，缺省为 `5m`
+    step: Option<String>,
+}
+
+/// 按时间段汇总的指标序列
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct StatsHistoryReport {
+    /// 查询的时长（秒）
+    pub window_secs: u64,
+    /// 每个时间段的长度（秒）
+    pub step_secs: u64,
+    /// 记录样本的间隔（秒）
+    pub interval_secs: u64,
+    /// 序列的结束时间
+    pub end: DateTime<Utc>,
+    /// 按时间先后排列的时间段
+    pub buckets: Vec<StatsBucket>,
+}
+
+/// 最近一段时间的可用代理数、平均延迟与请求量
+pub async fn get_history(
+    State(state): State<ApiState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<StatsHistoryReport>, (StatusCode, String)> {
+    let window = parse_span("window", query.window.as_deref().unwrap_or("24h"))?;
+    let step = parse_span("step", query.step.as_deref().unwrap_or("5m"))?;
+    if window > RETENTION.as_secs() {
+        return Err((StatusCode::BAD_REQUEST, format!("window 不能超过保留时长 {} 秒", RETENTION.as_secs())));
+    }
+    if window / step > MAX_BUCKETS {
+        return Err((StatusCode::BAD_REQUEST, format!("时间段过多，window/step 不能超过 {}", MAX_BUCKETS)));
+    }
+    let end = wall_now();
+    Ok(Json(StatsHistoryReport {
+        window_secs: window,
+        step_secs: step,
+        interval_secs: state.api_config.stats_interval,
+        end,
+        buckets: state.history.series(end, Duration::from_secs(window), Duration::from_secs(step)),
+    }))
+}
+
+/// 解析 `90`、`30s`、`5m`、`24h`、`7d` 形式的时长，返回秒数
+fn parse_span(name: &str, value: &str) -> Result<u64, (StatusCode, String)> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((index, _)) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => 0,
+    };
+    match number.parse::<u64>().ok().and_then(|number| number.checked_mul(scale)) {
+        Some(secs) if secs > 0 => Ok(secs),
+        _ => Err((StatusCode::BAD_REQUEST, format!("无效的{}: {}，应为大于0的时长，如 30m、24h", name, value))),
+    }
+}
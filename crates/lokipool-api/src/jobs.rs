@@ -0,0 +1,210 @@
+//! 后台测试任务
+//!
+//! `POST /api/v1/proxies/:id/test` 与 `POST /api/v1/proxies/test-all` 在后台开始测试并立即返回任务，
+//! 之后通过 `GET /api/v1/jobs/:id` 查看进度与每个代理的测试结果；批量导入时也可以要求测试新加入的代理。
+//! 只保留最近的 `MAX_JOBS` 个任务，更早的已完成任务被丢弃。
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use lokipool_core::time::wall_now;
+use lokipool_core::{spawn_logged, PoolHandle, Proxy};
+use schemars::JsonSchema;
+use serde::Serialize;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::ApiState;
+
+/// 保留的任务数
+const MAX_JOBS: usize = 100;
+
+/// 测试全部代理时同时进行的测试数
+const TEST_CONCURRENCY: usize = 16;
+
+/// 任务类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// 测试单个代理
+    TestProxy,
+    /// 测试全部代理
+    TestAll,
+    /// 测试批量导入的代理
+    TestImported,
+}
+
+/// 任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Finished,
+}
+
+/// 一个代理的测试结果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct JobResult {
+    pub proxy_id: String,
+    /// `host:port`
+    pub address: String,
+    pub success: bool,
+    /// 延迟（毫秒）
+    pub latency: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// 测试任务
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Job {
+    pub id: String,
+    pub kind: JobKind,
+    pub state: JobState,
+    /// 要测试的代理数
+    pub total: usize,
+    /// 已完成测试的代理数
+    pub completed: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// 按完成顺序排列的测试结果
+    pub results: Vec<JobResult>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// 最近的任务，克隆后共享同一份数据
+#[derive(Debug, Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<VecDeque<Job>>>,
+}
+
+impl JobRegistry {
+    /// 创建任务，超出保留数量时丢弃最早的已完成任务
+    fn create(&self, kind: JobKind, total: usize) -> Job {
+        let job = Job {
+            id: Uuid::new_v4().to_string(),
+            kind,
+            state: JobState::Running,
+            total,
+            completed: 0,
+            succeeded: 0,
+            failed: 0,
+            results: Vec::new(),
+            created_at: wall_now(),
+            finished_at: None,
+        };
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.len() >= MAX_JOBS {
+            if let Some(at) = jobs.iter().position(|job| job.state == JobState::Finished) {
+                jobs.remove(at);
+            }
+        }
+        jobs.push_back(job.clone());
+        job
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.lock().unwrap().iter().find(|job| job.id == id).cloned()
+    }
+
+    /// 所有任务，不含逐个代理的结果
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs.lock().unwrap().iter().map(|job| Job { results: Vec::new(), ..job.clone() }).collect()
+    }
+
+    fn record(&self, id: &str, result: JobResult) {
+        if let Some(job) = self.jobs.lock().unwrap().iter_mut().find(|job| job.id == id) {
+            job.completed += 1;
+            if result.success {
+                job.succeeded += 1;
+            } else {
+                job.failed += 1;
+            }
+            job.results.push(result);
+        }
+    }
+
+    fn finish(&self, id: &str) {
+        if let Some(job) = self.jobs.lock().unwrap().iter_mut().find(|job| job.id == id) {
+            job.state = JobState::Finished;
+            job.finished_at = Some(wall_now());
+        }
+    }
+
+    /// 在后台依次测试代理，返回刚创建的任务
+    pub(crate) fn start(&self, pool: PoolHandle, kind: JobKind, proxies: Vec<Proxy>) -> Job {
+        let job = self.create(kind, proxies.len());
+        let jobs = self.clone();
+        let id = job.id.clone();
+        spawn_logged(format!("测试任务 {}", id), async move {
+            futures::stream::iter(proxies)
+                .map(|proxy| {
+                    let pool = pool.clone();
+                    async move { test_one(&pool, &proxy).await }
+                })
+                .buffer_unordered(TEST_CONCURRENCY)
+                .for_each(|result| {
+                    jobs.record(&id, result);
+                    async {}
+                })
+                .await;
+            jobs.finish(&id);
+            if let Some(job) = jobs.get(&id) {
+                info!("测试任务 {} 完成: {} 个成功, {} 个失败", id, job.succeeded, job.failed);
+            }
+        });
+        job
+    }
+}
+
+/// 测试一个代理并把结果应用到代理池
+async fn test_one(pool: &PoolHandle, proxy: &Proxy) -> JobResult {
+    let address = format!("{}:{}", proxy.info.host, proxy.info.port);
+    match pool.test_proxy(&proxy.id).await {
+        Some(result) => JobResult {
+            proxy_id: proxy.id.clone(),
+            address,
+            success: result.success,
+            latency: result.latency,
+            error: result.error,
+        },
+        None => JobResult {
+            proxy_id: proxy.id.clone(),
+            address,
+            success: false,
+            latency: None,
+            error: Some("代理已被移除或代理池正在关闭".to_string()),
+        },
+    }
+}
+
+/// 在后台测试单个代理
+pub async fn test_proxy(State(state): State<ApiState>, Path(id): Path<String>) -> Result<(StatusCode, Json<Job>), StatusCode> {
+    let proxy = state.pool.get_all_proxies().await
+        .into_iter()
+        .find(|proxy| proxy.id == id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let job = state.jobs.start(state.pool.clone(), JobKind::TestProxy, vec![proxy]);
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// 在后台测试全部代理
+pub async fn test_all(State(state): State<ApiState>) -> (StatusCode, Json<Job>) {
+    let proxies = state.pool.get_all_proxies().await;
+    let job = state.jobs.start(state.pool.clone(), JobKind::TestAll, proxies);
+    (StatusCode::ACCEPTED, Json(job))
+}
+
+/// 查看任务的进度与结果
+pub async fn get_job(State(state): State<ApiState>, Path(id): Path<String>) -> Result<Json<Job>, StatusCode> {
+    state.jobs.get(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// 列出最近的任务
+pub async fn list_jobs(State(state): State<ApiState>) -> Json<Vec<Job>> {
+    Json(state.jobs.list())
+}
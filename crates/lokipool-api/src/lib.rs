@@ -2,17 +2,46 @@
 //! 
 //! This library provides HTTP API functionality for managing and monitoring LokiPool.
 
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 use std::net::SocketAddr;
 use axum::{
-    routing::{get},
+    extract::DefaultBodyLimit,
+    routing::{get, post},
     Router, 
-    http::StatusCode,
+    http::{HeaderValue, StatusCode},
     response::Json,
 };
-use lokipool_core::{Pool, Config, ProxyInfo};
-use serde::{Serialize};
-use tracing::{info};
+use lokipool_core::{BlockEntry, ConnectionControl, ListenerControl, PoolHandle, PoolMetrics, Config, ProxyStatus, SourceStatus, StatsHistory, TrafficReport, UsageStats};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::{error, info, warn};
+
+pub mod auth;
+pub mod blacklist;
+pub mod connections;
+pub mod events;
+pub mod exits;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+pub mod jobs;
+pub mod leases;
+pub mod limits;
+pub mod listeners;
+pub mod openapi;
+pub mod proxies;
+pub mod settings;
+pub mod tls;
+
+use auth::{ApiAuth, ApiKey};
+use events::EventHistory;
+use exits::ExitRegistry;
+use jobs::JobRegistry;
+use leases::LeaseRegistry;
+use limits::RateLimiter;
+use tls::ApiTls;
 
 /// API Server配置
 #[derive(Debug, Clone)]
@@ -23,6 +52,34 @@ pub struct ApiConfig {
     pub bind_port: u16,
     /// 是否启用CORS
     pub enable_cors: bool,
+    /// 允许跨域访问的来源，如 `https://dashboard.example.com`；为空时允许任意来源
+    pub cors_origins: Vec<String>,
+    /// 证书与私钥，设置后以HTTPS提供API
+    pub tls: Option<ApiTls>,
+    /// 是否在 `/api/v1/docs` 提供Swagger UI
+    pub swagger_ui: bool,
+    /// 出口节点主令牌，用于签发/吊销加入令牌，也可直接用于注册；未设置时禁用Bridge模式
+    pub exit_token: Option<String>,
+    /// 出口节点心跳间隔（秒）
+    pub exit_heartbeat_interval: u64,
+    /// 配置文件路径，`PATCH /api/v1/config?persist=true` 写回到这里；未设置时不支持写回
+    pub config_file: Option<PathBuf>,
+    /// API认证，未配置密钥时不要求认证
+    pub auth: ApiAuth,
+    /// gRPC端口，设置后在同一地址上提供gRPC接口（需要 `grpc` 特性）
+    pub grpc_port: Option<u16>,
+    /// 每个客户端IP每秒允许的请求数，为0时不限制
+    pub rate_limit: u32,
+    /// 每个客户端IP允许的突发请求数
+    pub rate_limit_burst: u32,
+    /// 请求体的最大字节数
+    pub max_body_bytes: usize,
+    /// 批量导入的请求体的最大字节数
+    pub max_import_bytes: usize,
+    /// 记录指标历史的间隔（秒），为0时不记录
+    pub stats_interval: u64,
+    /// 指标历史文件，设置后重启时读回；未设置时只保存在内存中
+    pub stats_history_file: Option<PathBuf>,
 }
 
 impl Default for ApiConfig {
@@ -31,6 +88,93 @@ impl Default for ApiConfig {
             bind_address: "127.0.0.1".to_string(),
             bind_port: 3000,
             enable_cors: false,
+            cors_origins: Vec::new(),
+            tls: None,
+            swagger_ui: false,
+            exit_token: None,
+            exit_heartbeat_interval: 30,
+            config_file: None,
+            auth: ApiAuth::default(),
+            grpc_port: None,
+            rate_limit: 50,
+            rate_limit_burst: 100,
+            max_body_bytes: 1024 * 1024,
+            max_import_bytes: 16 * 1024 * 1024,
+            stats_interval: 60,
+            stats_history_file: None,
+        }
+    }
+}
+
+impl ApiConfig {
+    /// 从 `LOKIPOOL_API_*` 等环境变量读取认证、CORS、HTTPS与出口节点设置，其余使用默认值
+    pub fn from_env() -> Self {
+        // 设置LOKIPOOL_API_KEYS（逗号分隔的 权限:密钥）或LOKIPOOL_API_JWT_SECRET后启用API认证
+        let api_keys = std::env::var("LOKIPOOL_API_KEYS").unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .filter_map(|spec| ApiKey::parse(spec).map_err(|e| error!("忽略无效的API密钥: {}", e)).ok())
+            .collect();
+        let auth = ApiAuth {
+            keys: api_keys,
+            jwt_secret: std::env::var("LOKIPOOL_API_JWT_SECRET").ok().filter(|s| !s.is_empty()),
+            protect_reads: std::env::var("LOKIPOOL_API_PROTECT_READS").is_ok_and(|v| v == "1" || v == "true"),
+        };
+
+        // 设置LOKIPOOL_API_CORS_ORIGINS（逗号分隔，`*` 表示任意来源）后允许跨域访问
+        let cors_origins = std::env::var("LOKIPOOL_API_CORS_ORIGINS").ok();
+        let enable_cors = cors_origins.is_some();
+        let cors_origins = cors_origins.unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty() && *origin != "*")
+            .map(str::to_string)
+            .collect();
+
+        // 同时设置LOKIPOOL_API_TLS_CERT与LOKIPOOL_API_TLS_KEY后以HTTPS提供API
+        let tls = match (std::env::var_os("LOKIPOOL_API_TLS_CERT"), std::env::var_os("LOKIPOOL_API_TLS_KEY")) {
+            (Some(cert), Some(key)) => Some(ApiTls { cert_path: cert.into(), key_path: key.into() }),
+            (None, None) => None,
+            _ => {
+                error!("LOKIPOOL_API_TLS_CERT与LOKIPOOL_API_TLS_KEY需要同时设置，使用HTTP");
+                None
+            }
+        };
+
+        let defaults = Self::default();
+        Self {
+            // 设置LOKIPOOL_EXIT_TOKEN后允许出口节点注册
+            exit_token: std::env::var("LOKIPOOL_EXIT_TOKEN").ok().filter(|t| !t.is_empty()),
+            auth,
+            enable_cors,
+            cors_origins,
+            tls,
+            // 设置LOKIPOOL_API_SWAGGER_UI=1后在 /api/v1/docs 提供Swagger UI
+            swagger_ui: std::env::var("LOKIPOOL_API_SWAGGER_UI").is_ok_and(|v| v == "1" || v == "true"),
+            // 设置LOKIPOOL_API_GRPC_PORT后同时提供gRPC接口
+            grpc_port: env_number("LOKIPOOL_API_GRPC_PORT"),
+            // LOKIPOOL_API_RATE_LIMIT为0时不限制请求频率
+            rate_limit: env_number("LOKIPOOL_API_RATE_LIMIT").unwrap_or(defaults.rate_limit),
+            rate_limit_burst: env_number("LOKIPOOL_API_RATE_BURST").unwrap_or(defaults.rate_limit_burst),
+            max_body_bytes: env_number("LOKIPOOL_API_MAX_BODY").unwrap_or(defaults.max_body_bytes),
+            max_import_bytes: env_number("LOKIPOOL_API_MAX_IMPORT_BODY").unwrap_or(defaults.max_import_bytes),
+            // LOKIPOOL_API_STATS_INTERVAL为0时不记录指标历史，设置LOKIPOOL_API_STATS_HISTORY后保存到文件
+            stats_interval: env_number("LOKIPOOL_API_STATS_INTERVAL").unwrap_or(defaults.stats_interval),
+            stats_history_file: std::env::var_os("LOKIPOOL_API_STATS_HISTORY").filter(|path| !path.is_empty()).map(PathBuf::from),
+            ..defaults
+        }
+    }
+}
+
+/// 读取数值环境变量，无法解析时给出警告并忽略
+fn env_number<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    match value.trim().parse() {
+        Ok(number) => Some(number),
+        Err(_) => {
+            warn!("忽略无效的{}: {}", name, value);
+            None
         }
     }
 }
@@ -38,8 +182,22 @@ impl Default for ApiConfig {
 /// API Server状态
 #[derive(Clone)]
 pub struct ApiState {
-    pool: Arc<Pool>,
-    config: Arc<Config>,
+    pool: PoolHandle,
+    /// 生效的配置，运行时设置修改后同步更新
+    config: Arc<RwLock<Config>>,
+    api_config: Arc<ApiConfig>,
+    exits: ExitRegistry,
+    jobs: JobRegistry,
+    events: EventHistory,
+    leases: LeaseRegistry,
+    /// 同一进程中的SOCKS5监听端口，单独运行API时为空
+    listeners: Option<Arc<dyn ListenerControl>>,
+    /// 各客户端IP的请求频率
+    limiter: RateLimiter,
+    /// 同一进程中进行中的SOCKS5连接，单独运行API时为空
+    connections: Option<Arc<dyn ConnectionControl>>,
+    /// 定期记录的代理池指标
+    history: StatsHistory,
 }
 
 /// API服务器
@@ -50,74 +208,371 @@ pub struct ApiServer {
 
 impl ApiServer {
     /// 创建新的API服务器
-    pub fn new(pool: Pool, config: Config, api_config: ApiConfig) -> Self {
+    pub fn new(pool: impl Into<PoolHandle>, config: Config, api_config: ApiConfig) -> Self {
+        let history = match &api_config.stats_history_file {
+            Some(path) => StatsHistory::open(path).unwrap_or_else(|e| {
+                error!("读取指标历史 {} 失败，只在内存中记录: {}", path.display(), e);
+                StatsHistory::default()
+            }),
+            None => StatsHistory::default(),
+        };
         Self {
-            config: api_config,
             state: ApiState {
-                pool: Arc::new(pool),
-                config: Arc::new(config),
+                pool: pool.into(),
+                config: Arc::new(RwLock::new(config)),
+                api_config: Arc::new(api_config.clone()),
+                exits: ExitRegistry::default(),
+                jobs: JobRegistry::default(),
+                events: EventHistory::default(),
+                leases: LeaseRegistry::default(),
+                listeners: None,
+                connections: None,
+                limiter: RateLimiter::default(),
+                history,
             },
+            config: api_config,
         }
     }
 
+    /// 与同一进程中的SOCKS5服务一起运行时，允许通过API控制其监听端口
+    pub fn with_listeners(mut self, listeners: Arc<dyn ListenerControl>) -> Self {
+        self.state.listeners = Some(listeners);
+        self
+    }
+
+    /// 与同一进程中的SOCKS5服务一起运行时，允许通过API查看与关闭进行中的连接
+    pub fn with_connections(mut self, connections: Arc<dyn ConnectionControl>) -> Self {
+        self.state.connections = Some(connections);
+        self
+    }
+
     /// 运行API服务器
     pub async fn run(&self) -> anyhow::Result<()> {
         let addr = format!("{}:{}", self.config.bind_address, self.config.bind_port);
         let socket_addr: SocketAddr = addr.parse()?;
-        
+        let listener = tokio::net::TcpListener::bind(socket_addr).await?;
+        self.run_with_listener(listener).await
+    }
+
+    /// 在已绑定的监听器上运行API服务器，忽略配置中的监听地址
+    pub async fn run_with_listener(&self, listener: tokio::net::TcpListener) -> anyhow::Result<()> {
+        let addr = listener.local_addr()?;
+
         // 创建路由
         let app = Router::new()
             .route("/", get(|| async { "LokiPool API Server" }))
-            .route("/api/v1/proxies", get(get_proxies))
-            .route("/api/v1/proxies/:id", get(get_proxy))
+            .route("/proxy.pac", get(get_pac))
+            .route("/api/v1/proxies", get(proxies::list_proxies).post(proxies::add_proxy))
+            .route("/api/v1/proxies/import", post(proxies::import_proxies).layer(DefaultBodyLimit::max(self.config.max_import_bytes)))
+            .route("/api/v1/proxies/test-all", post(jobs::test_all))
+            .route("/api/v1/proxies/:id", get(proxies::get_proxy).patch(proxies::patch_proxy).delete(proxies::delete_proxy))
+            .route("/api/v1/proxies/:id/test", post(jobs::test_proxy))
+            .route("/api/v1/jobs", get(jobs::list_jobs))
+            .route("/api/v1/jobs/:id", get(jobs::get_job))
+            .route("/api/v1/events/sse", get(events::sse))
+            .route("/api/v1/lease", post(leases::acquire))
+            .route("/api/v1/lease/:id", axum::routing::delete(leases::release))
             .route("/api/v1/stats", get(get_stats))
+            .route("/api/v1/stats/history", get(history::get_history))
+            .route("/api/v1/listeners", get(listeners::list_listeners))
+            .route("/api/v1/listeners/:port/start", post(listeners::start_listener))
+            .route("/api/v1/listeners/:port/stop", post(listeners::stop_listener))
+            .route("/api/v1/listeners/:port/restart", post(listeners::restart_listener))
+            .route("/api/v1/listeners/:port/strategy", axum::routing::put(listeners::set_listener_strategy))
+            .route("/api/v1/connections", get(connections::list_connections))
+            .route("/api/v1/connections/:id", axum::routing::delete(connections::close_connection))
+            .route("/api/v1/config", get(settings::get_config).put(settings::put_config).patch(settings::patch_config))
+            .route("/api/v1/loglevel", get(settings::get_log_level).put(settings::put_log_level))
+            .route("/api/v1/sources", get(get_sources))
+            .route("/api/v1/traffic", get(get_traffic).delete(clear_traffic))
+            .route("/api/v1/blacklist", get(blacklist::list_blacklist))
+            .route("/api/v1/blacklist/:id", post(blacklist::blacklist_proxy).delete(blacklist::reinstate_proxy))
+            .route("/api/v1/blocklist", get(get_blocklist).delete(clear_blocklist))
+            .route("/api/v1/blocklist/:key", axum::routing::delete(unblock))
+            .route("/api/v1/exits", get(exits::list_exits).post(exits::register_exit))
+            .route("/api/v1/exits/tokens", get(exits::list_tokens).post(exits::issue_token))
+            .route("/api/v1/exits/tokens/:token", axum::routing::delete(exits::revoke_token))
+            .route("/api/v1/exits/:id/heartbeat", post(exits::heartbeat_exit))
+            .route("/api/v1/exits/:id", axum::routing::delete(exits::deregister_exit))
+            .route("/api/v1/openapi.json", get(openapi::openapi_json))
+            .route_layer(axum::middleware::from_fn_with_state(self.state.clone(), auth::require))
+            // 频率限制在认证之前，未认证的请求同样计数
+            .layer(axum::middleware::from_fn_with_state(self.state.clone(), limits::rate_limit))
+            .layer(DefaultBodyLimit::max(self.config.max_body_bytes))
             .with_state(self.state.clone());
+        // Swagger UI只是加载 `/api/v1/openapi.json` 的静态页面，不经过认证层
+        let app = match self.config.swagger_ui {
+            true => app.route("/api/v1/docs", get(openapi::swagger_ui)),
+            false => app,
+        };
+        let app = match self.config.enable_cors {
+            true => app.layer(self.cors_layer()),
+            false => app,
+        };
         
+        self.state.events.record_from(&self.state.pool);
+        self.state.leases.start_sweeper();
+        if self.config.stats_interval > 0 {
+            history::start_sampler(self.state.pool.clone(), self.state.history.clone(), std::time::Duration::from_secs(self.config.stats_interval));
+        }
+
+        if self.config.auth.is_enabled() {
+            info!("API认证已启用: {} 个API密钥, JWT {}, 读取请求{}",
+                self.config.auth.keys.len(),
+                if self.config.auth.jwt_secret.is_some() { "已启用" } else { "未启用" },
+                if self.config.auth.protect_reads { "需要认证" } else { "无需认证" });
+        }
+
+        if self.config.rate_limit > 0 {
+            info!("API请求频率限制: 每个客户端每秒 {} 个请求，突发 {} 个", self.config.rate_limit, self.config.rate_limit_burst);
+        }
+
+        if self.config.exit_token.is_some() {
+            exits::start_exit_health_check(
+                self.state.pool.clone(),
+                self.state.exits.clone(),
+                self.config.exit_heartbeat_interval,
+            );
+            info!("Bridge模式已启用，出口节点可通过 /api/v1/exits 注册");
+        }
+
+        if let Some(port) = self.config.grpc_port {
+            self.start_grpc(port)?;
+        }
+        
+        if let Some(tls) = &self.config.tls {
+            let acceptor = tls.acceptor()?;
+            info!("API服务器启动在: https://{}", addr);
+            return tls::serve(listener, acceptor, app).await;
+        }
+
         info!("API服务器启动在: {}", addr);
         
         // 启动服务器
-        axum::Server::bind(&socket_addr)
-            .serve(app.into_make_service())
+        axum::Server::from_tcp(listener.into_std()?)?
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             .await?;
             
         Ok(())
     }
-}
 
-/// 获取所有代理
-async fn get_proxies(axum::extract::State(state): axum::extract::State<ApiState>) -> Json<Vec<ProxyInfo>> {
-    // 这里应该实现获取所有代理的逻辑
-    // 下面是一个简单的示例
-    Json(vec![])
-}
+    /// 在后台提供gRPC接口，与HTTP接口共享状态与认证
+    #[cfg(feature = "grpc")]
+    fn start_grpc(&self, port: u16) -> anyhow::Result<()> {
+        let addr: SocketAddr = format!("{}:{}", self.config.bind_address, port).parse()?;
+        let state = self.state.clone();
+        lokipool_core::spawn_logged(format!("gRPC :{}", port), async move {
+            if let Err(e) = grpc::serve(addr, state).await {
+                error!("gRPC :{} 运行出错: {}", port, e);
+            }
+        });
+        Ok(())
+    }
+
+    #[cfg(not(feature = "grpc"))]
+    fn start_grpc(&self, port: u16) -> anyhow::Result<()> {
+        warn!("未启用grpc特性，忽略gRPC端口 {}", port);
+        Ok(())
+    }
 
-/// 获取单个代理
-async fn get_proxy(
-    axum::extract::State(state): axum::extract::State<ApiState>, 
-    axum::extract::Path(id): axum::extract::Path<String>
-) -> Result<Json<ProxyInfo>, StatusCode> {
-    // 这里应该实现获取单个代理的逻辑
-    // 下面是一个简单的示例
-    Err(StatusCode::NOT_FOUND)
+    /// 按配置的来源允许跨域访问，忽略无法解析的来源
+    fn cors_layer(&self) -> CorsLayer {
+        let origins: Vec<HeaderValue> = self.config.cors_origins.iter()
+            .filter_map(|origin| match origin.parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    warn!("忽略无效的CORS来源: {}", origin);
+                    None
+                }
+            })
+            .collect();
+        let allow_origin = match self.config.cors_origins.is_empty() {
+            true => AllowOrigin::any(),
+            false => AllowOrigin::list(origins),
+        };
+        info!("已启用CORS，允许的来源: {}",
+            if self.config.cors_origins.is_empty() { "任意".to_string() } else { self.config.cors_origins.join(", ") });
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(Any)
+            .allow_headers(Any)
+    }
 }
 
-/// 获取统计信息
-async fn get_stats(axum::extract::State(state): axum::extract::State<ApiState>) -> Json<Stats> {
-    // 这里应该实现获取统计信息的逻辑
-    // 下面是一个简单的示例
+/// 获取统计信息，`?detail=true` 时附带每个代理的使用统计
+async fn get_stats(
+    axum::extract::State(state): axum::extract::State<ApiState>,
+    axum::extract::Query(query): axum::extract::Query<StatsQuery>,
+) -> Json<Stats> {
+    let metrics = state.pool.metrics().await;
+    let proxies = match query.detail {
+        true => Some(state.pool.get_all_proxies()
+            .await
+            .into_iter()
+            .map(|proxy| ProxyStats {
+                address: format!("{}:{}", proxy.info.host, proxy.info.port),
+                status: proxy.status,
+                latency: proxy.info.last_latency,
+                usage: proxy.usage_stats(),
+                id: proxy.id,
+            })
+            .collect()),
+        false => None,
+    };
+
     Json(Stats {
-        total_proxies: 0,
-        available_proxies: 0,
-        total_requests: 0,
-        average_latency: 0.0,
+        total_proxies: metrics.total,
+        available_proxies: metrics.status.available,
+        total_requests: metrics.connections.total_connections,
+        active_connections: metrics.connections.active_connections,
+        bytes_up: metrics.connections.bytes_up,
+        bytes_down: metrics.connections.bytes_down,
+        average_latency: metrics.latency.mean.unwrap_or(0.0),
+        task_panics: lokipool_core::task_panics(),
+        metrics,
+        proxies,
     })
 }
 
+/// 统计查询参数
+#[derive(Debug, Deserialize, JsonSchema)]
+struct StatsQuery {
+    #[serde(default)]
+    detail: bool,
+}
+
+/// 获取各代理来源的代理数与最近一次同步结果
+async fn get_sources(axum::extract::State(state): axum::extract::State<ApiState>) -> Json<Vec<SourceStatus>> {
+    Json(state.pool.source_status().await)
+}
+
+/// 按客户端与目标累计的流量，`?limit=N` 时每张表只返回前N行
+async fn get_traffic(
+    axum::extract::State(state): axum::extract::State<ApiState>,
+    axum::extract::Query(query): axum::extract::Query<TrafficQuery>,
+) -> Json<TrafficReport> {
+    let mut report = state.pool.traffic().report();
+    if let Some(limit) = query.limit {
+        report.truncate(limit);
+    }
+    Json(report)
+}
+
+/// 清空流量统计
+async fn clear_traffic(axum::extract::State(state): axum::extract::State<ApiState>) -> StatusCode {
+    state.pool.traffic().clear();
+    StatusCode::NO_CONTENT
+}
+
+/// 流量查询参数
+#[derive(Debug, Deserialize, JsonSchema)]
+struct TrafficQuery {
+    limit: Option<usize>,
+}
+
+/// 按当前配置生成的PAC文件
+///
+/// 代理地址取 `?host=`，未指定时取请求的Host头，即客户端访问API所用的地址；`?port=` 选择SOCKS5端口。
+async fn get_pac(
+    axum::extract::State(state): axum::extract::State<ApiState>,
+    axum::extract::Query(query): axum::extract::Query<PacQuery>,
+    headers: axum::http::HeaderMap,
+) -> Result<([(axum::http::header::HeaderName, &'static str); 1], String), (StatusCode, String)> {
+    let config = state.config.read().unwrap().clone();
+    let host = query.host
+        .or_else(|| headers.get(axum::http::header::HOST).and_then(|host| host.to_str().ok()).map(strip_port))
+        .filter(|host| !host.is_empty() && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':')))
+        .unwrap_or_else(|| config.socks_server.bind_address.clone());
+    lokipool_core::pac::render(&config, &host, query.port)
+        .map(|pac| ([(axum::http::header::CONTENT_TYPE, "application/x-ns-proxy-autoconfig")], pac))
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))
+}
+
+/// 去掉Host头中的端口与IPv6地址的方括号
+fn strip_port(host: &str) -> String {
+    match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default().to_string(),
+        None => host.split(':').next().unwrap_or_default().to_string(),
+    }
+}
+
+/// PAC查询参数
+#[derive(Debug, Deserialize, JsonSchema)]
+struct PacQuery {
+    host: Option<String>,
+    port: Option<u16>,
+}
+
+/// 获取永久黑名单
+async fn get_blocklist(axum::extract::State(state): axum::extract::State<ApiState>) -> Json<Vec<BlockedProxy>> {
+    Json(state.pool.blocklist_entries()
+        .into_iter()
+        .map(|(key, entry)| BlockedProxy { key, entry })
+        .collect())
+}
+
+/// 清空永久黑名单
+async fn clear_blocklist(axum::extract::State(state): axum::extract::State<ApiState>) -> Json<ClearedBlocklist> {
+    Json(ClearedBlocklist { cleared: state.pool.clear_blocklist() })
+}
+
+/// 清空永久黑名单的结果
+#[derive(Debug, Serialize, JsonSchema)]
+struct ClearedBlocklist {
+    /// 移除的条目数
+    cleared: usize,
+}
+
+/// 从永久黑名单移除单个条目
+async fn unblock(
+    axum::extract::State(state): axum::extract::State<ApiState>,
+    axum::extract::Path(key): axum::extract::Path<String>
+) -> StatusCode {
+    if state.pool.unblock(&key) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// 永久黑名单条目
+#[derive(Debug, Serialize, JsonSchema)]
+struct BlockedProxy {
+    /// `host:port` 的哈希
+    key: String,
+    #[serde(flatten)]
+    entry: BlockEntry,
+}
+
 /// 统计信息
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 struct Stats {
     total_proxies: usize,
     available_proxies: usize,
     total_requests: u64,
+    /// 正在转发的连接数
+    active_connections: usize,
+    /// 经代理池转发的上下行字节数
+    bytes_up: u64,
+    bytes_down: u64,
+    /// 可用代理的平均延迟（毫秒），没有可用代理时为0
     average_latency: f64,
+    /// 后台任务panic的次数
+    task_panics: u64,
+    /// 状态分布、延迟分位数与各策略的选择次数
+    metrics: PoolMetrics,
+    /// 各代理的使用计数，只在 `?detail=true` 时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxies: Option<Vec<ProxyStats>>,
+}
+
+/// 单个代理的使用统计
+#[derive(Debug, Serialize, JsonSchema)]
+struct ProxyStats {
+    id: String,
+    address: String,
+    status: ProxyStatus,
+    latency: Option<u64>,
+    #[serde(flatten)]
+    usage: UsageStats,
 }
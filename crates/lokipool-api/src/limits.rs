@@ -0,0 +1,81 @@
+//! 请求频率限制
+//!
+//! 每个客户端IP一个令牌桶，每秒补充 `rate_limit` 个令牌，最多积攒 `rate_limit_burst` 个，
+//! 令牌不足的请求直接应答429并在 `Retry-After` 中给出需要等待的秒数，不进入认证与处理。
+//! 请求体大小由 `DefaultBodyLimit` 按 `max_body_bytes`（批量导入为 `max_import_bytes`）限制，超出时应答413。
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tracing::debug;
+
+use crate::ApiState;
+
+/// 记录的客户端超过这个数量时清理令牌已经补满的客户端
+pub const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// 各客户端IP的令牌桶，克隆后共享同一份数据
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimiter {
+    /// 在 `now` 时刻取一个令牌，不足时返回补充一个令牌需要的时间
+    pub fn take(&self, client: IpAddr, per_second: u32, burst: u32, now: Instant) -> Result<(), Duration> {
+        let rate = f64::from(per_second);
+        let capacity = f64::from(burst.max(1));
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&client) {
+            buckets.retain(|_, bucket| bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < capacity);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket { tokens: capacity, updated: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+    }
+
+    /// 当前记录的客户端数
+    pub fn tracked_clients(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+}
+
+/// 按客户端IP限制请求频率，`rate_limit` 为0时不限制
+pub async fn rate_limit<B>(
+    State(state): State<ApiState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let config = &state.api_config;
+    if config.rate_limit == 0 {
+        return next.run(request).await;
+    }
+    match state.limiter.take(remote.ip(), config.rate_limit, config.rate_limit_burst, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            debug!("来自 {} 的API请求过于频繁", remote.ip());
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, (wait.as_secs_f64().ceil() as u64).max(1).to_string())],
+                "请求过于频繁，请稍后重试".to_string(),
+            ).into_response()
+        }
+    }
+}
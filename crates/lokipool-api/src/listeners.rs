@@ -0,0 +1,68 @@
+//! SOCKS5监听端口的控制接口
+//!
+//! API与SOCKS5服务运行在同一进程（`lokipool run` 设置了 `[api_server]`）时，
+//! 可以查看各监听端口的连接数，启停或重启单个端口，修改端口的选择策略。
+//! 单独运行的 `lokipool-api` 没有监听端口可控制，这些接口应答503。
+
+use std::sync::Arc;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+use lokipool_core::{ListenerControl, ListenerError, ListenerInfo, SelectionStrategy};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::ApiState;
+
+type ApiError = (StatusCode, String);
+
+fn control(state: &ApiState) -> Result<&Arc<dyn ListenerControl>, ApiError> {
+    state.listeners.as_ref()
+        .ok_or((StatusCode::SERVICE_UNAVAILABLE, "API未与SOCKS5服务运行在同一进程，无法控制监听端口".to_string()))
+}
+
+fn status(e: ListenerError) -> ApiError {
+    let code = match e {
+        ListenerError::NotFound(_) => StatusCode::NOT_FOUND,
+        ListenerError::AlreadyRunning(_) | ListenerError::NotRunning(_) => StatusCode::CONFLICT,
+        ListenerError::Bind(..) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (code, e.to_string())
+}
+
+/// 全部监听端口及其连接数
+pub async fn list_listeners(State(state): State<ApiState>) -> Result<Json<Vec<ListenerInfo>>, ApiError> {
+    Ok(Json(control(&state)?.listeners().await))
+}
+
+/// 启动已停止的监听端口
+pub async fn start_listener(State(state): State<ApiState>, Path(port): Path<u16>) -> Result<Json<ListenerInfo>, ApiError> {
+    control(&state)?.start(port).await.map(Json).map_err(status)
+}
+
+/// 停止监听端口，进行中的连接结束（或关闭等待期结束）后应答
+pub async fn stop_listener(State(state): State<ApiState>, Path(port): Path<u16>) -> Result<Json<ListenerInfo>, ApiError> {
+    control(&state)?.stop(port).await.map(Json).map_err(status)
+}
+
+/// 重启监听端口
+pub async fn restart_listener(State(state): State<ApiState>, Path(port): Path<u16>) -> Result<Json<ListenerInfo>, ApiError> {
+    control(&state)?.restart(port).await.map(Json).map_err(status)
+}
+
+/// 监听端口使用的选择策略
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StrategyBinding {
+    /// 为null时改用代理池的策略
+    strategy: Option<SelectionStrategy>,
+}
+
+/// 修改监听端口的选择策略，运行中的端口重启后应用
+pub async fn set_listener_strategy(
+    State(state): State<ApiState>,
+    Path(port): Path<u16>,
+    Json(binding): Json<StrategyBinding>,
+) -> Result<Json<ListenerInfo>, ApiError> {
+    control(&state)?.set_strategy(port, binding.strategy).await.map(Json).map_err(status)
+}
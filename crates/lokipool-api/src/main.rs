@@ -3,6 +3,7 @@ use lokipool_core::{Config, Pool, PoolOptions, init_logger};
 use lokipool_api::{ApiServer, ApiConfig};
 use tracing::{info, error};
 use std::path::Path;
+use std::time::Duration;
 
 const VERSION: &str = "v0.1.0";
 
@@ -19,7 +20,19 @@ async fn main() -> Result<()> {
         match Config::from_file(config_path) {
             Ok(cfg) => {
                 info!("配置已从 {} 加载", config_path.display());
-                cfg
+                // 叠加 lokipool tune 生成的推荐值
+                let overlay = Config::overlay_path(config_path);
+                match overlay.exists().then(|| cfg.with_overlay(&overlay)) {
+                    Some(Ok(tuned)) => {
+                        info!("已应用调优覆盖文件 {}", overlay.display());
+                        tuned
+                    }
+                    Some(Err(e)) => {
+                        error!("忽略无效的调优覆盖文件 {}: {}", overlay.display(), e);
+                        cfg
+                    }
+                    None => cfg,
+                }
             }
             Err(e) => {
                 error!("加载配置失败: {}", e);
@@ -40,11 +53,23 @@ async fn main() -> Result<()> {
     // 创建池选项
     let pool_options = PoolOptions::from_config(&config);
     
-    // 创建代理池
+    // 创建代理池，并同步代理文件中的代理
     let pool = Pool::new_with_proxies(config.proxies.clone(), pool_options);
+    let proxy_file = Path::new(&config.proxy.proxy_file);
+    match pool.sync_file(proxy_file).await {
+        Ok(report) => info!("代理文件 {}: {}", proxy_file.display(), report),
+        Err(e) => error!("读取代理文件 {} 失败: {}", proxy_file.display(), e),
+    }
+    pool.start_auto_test();
+    if config.proxy.proxy_file_watch_interval > 0 {
+        pool.watch_file(proxy_file.to_path_buf(), Duration::from_secs(config.proxy.proxy_file_watch_interval));
+    }
     
-    // 创建API配置
-    let api_config = ApiConfig::default();
+    // 认证、CORS、HTTPS等设置来自 LOKIPOOL_API_* 环境变量
+    let api_config = ApiConfig {
+        config_file: Some(config_path.to_path_buf()),
+        ..ApiConfig::from_env()
+    };
     
     // 创建并运行API服务器
     let api_server = ApiServer::new(pool, config, api_config);
@@ -0,0 +1,311 @@
+//! OpenAPI描述
+//!
+//! `GET /api/v1/openapi.json` 返回由请求与应答类型生成的OpenAPI 3.1文档，可用于生成客户端SDK；
+//! 启用 `swagger_ui` 时 `GET /api/v1/docs` 提供浏览文档与调试接口的Swagger UI页面。
+//! 新增接口时在 `build` 中登记，类型的字段说明取自文档注释。
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{Html, Json};
+use lokipool_core::{Config, ConnectionEntry, ListenerInfo, PoolEvent, ProxyConfig, SourceStatus, TrafficReport};
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, SchemaGenerator};
+use serde_json::{json, Map, Value};
+
+use crate::blacklist::{BlacklistRequest, SidelinedProxy};
+use crate::exits::{AgentAuth, ExitAgent, IssueTokenRequest, JoinToken, RegisterRequest, RegisterResponse};
+use crate::history::{HistoryQuery, StatsHistoryReport};
+use crate::jobs::Job;
+use crate::leases::{LeaseGrant, LeaseReport, LeaseRequest};
+use crate::listeners::StrategyBinding;
+use crate::proxies::{ImportQuery, ImportSummary, ProxyEntry, ProxyPage, ProxyPatch, ProxyQuery};
+use crate::settings::{ConfigPatch, ConfigUpdate, EffectiveConfig, LogLevel, PatchQuery};
+use crate::{ApiConfig, ApiState, BlockedProxy, ClearedBlocklist, PacQuery, Stats, StatsQuery, TrafficQuery};
+
+/// 正在登记的文档
+struct Spec {
+    generator: SchemaGenerator,
+    paths: Map<String, Value>,
+}
+
+/// 一个接口，各方法直接修改 `Spec` 中对应的条目
+struct Operation<'a> {
+    spec: &'a mut Spec,
+    path: String,
+    method: &'static str,
+}
+
+impl Spec {
+    fn new() -> Self {
+        let settings = SchemaSettings::draft2020_12().with(|settings| {
+            settings.definitions_path = "/components/schemas".into();
+            settings.meta_schema = None;
+        });
+        Self { generator: settings.into_generator(), paths: Map::new() }
+    }
+
+    /// 登记接口，axum风格的 `:id` 路径参数转换为 `{id}` 并作为必填的字符串参数
+    fn operation(&mut self, method: &'static str, path: &str, summary: &str) -> Operation<'_> {
+        let mut parameters = Vec::new();
+        let path = path.split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => {
+                    parameters.push(json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } }));
+                    format!("{{{}}}", name)
+                }
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let operation = json!({ "summary": summary, "parameters": parameters, "responses": {} });
+        let item = self.paths.entry(path.clone()).or_insert_with(|| json!({}));
+        item[method] = operation;
+        Operation { spec: self, path, method }
+    }
+
+    fn get(&mut self, path: &str, summary: &str) -> Operation<'_> {
+        self.operation("get", path, summary)
+    }
+
+    fn post(&mut self, path: &str, summary: &str) -> Operation<'_> {
+        self.operation("post", path, summary)
+    }
+
+    fn put(&mut self, path: &str, summary: &str) -> Operation<'_> {
+        self.operation("put", path, summary)
+    }
+
+    fn patch(&mut self, path: &str, summary: &str) -> Operation<'_> {
+        self.operation("patch", path, summary)
+    }
+
+    fn delete(&mut self, path: &str, summary: &str) -> Operation<'_> {
+        self.operation("delete", path, summary)
+    }
+}
+
+impl Operation<'_> {
+    fn value(&mut self) -> &mut Value {
+        &mut self.spec.paths[&self.path][self.method]
+    }
+
+    /// 查询参数，逐个取自 `T` 的字段
+    fn query<T: JsonSchema>(mut self) -> Self {
+        let schema = self.spec.generator.root_schema_for::<T>().to_value();
+        let required: Vec<&str> = schema.get("required")
+            .and_then(Value::as_array)
+            .map(|names| names.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        let mut parameters = Vec::new();
+        for (name, property) in schema.get("properties").and_then(Value::as_object).into_iter().flatten() {
+            let mut property = property.clone();
+            let description = property.as_object_mut().and_then(|property| property.remove("description"));
+            let mut parameter = json!({ "name": name, "in": "query", "required": required.contains(&name.as_str()), "schema": property });
+            if let Some(description) = description {
+                parameter["description"] = description;
+            }
+            parameters.push(parameter);
+        }
+        if let Some(existing) = self.value()["parameters"].as_array_mut() {
+            existing.extend(parameters);
+        }
+        self
+    }
+
+    /// JSON请求体
+    fn body<T: JsonSchema>(mut self) -> Self {
+        let schema = self.spec.generator.subschema_for::<T>().to_value();
+        self.value()["requestBody"] = json!({ "required": true, "content": { "application/json": { "schema": schema } } });
+        self
+    }
+
+    /// 可以省略的JSON请求体
+    fn optional_body<T: JsonSchema>(mut self) -> Self {
+        self = self.body::<T>();
+        self.value()["requestBody"]["required"] = json!(false);
+        self
+    }
+
+    /// 文本请求体，可以是任一给定的格式
+    fn text_body(mut self, content_types: &[&str]) -> Self {
+        let content: Map<String, Value> = content_types.iter()
+            .map(|content_type| (content_type.to_string(), json!({ "schema": { "type": "string" } })))
+            .collect();
+        self.value()["requestBody"] = json!({ "required": true, "content": content });
+        self
+    }
+
+    /// 带JSON应答体的应答
+    fn json<T: JsonSchema>(self, status: StatusCode) -> Self {
+        let schema = self.spec.generator.subschema_for::<T>().to_value();
+        self.respond(status, Some(("application/json", schema)))
+    }
+
+    /// 不带应答体或应答体为错误说明的应答
+    fn status(self, status: StatusCode) -> Self {
+        self.respond(status, None)
+    }
+
+    /// Server-Sent Events应答，每条 `data` 是一个 `T`
+    fn event_stream<T: JsonSchema>(self) -> Self {
+        let schema = self.spec.generator.subschema_for::<T>().to_value();
+        self.respond(StatusCode::OK, Some(("text/event-stream", schema)))
+    }
+
+    /// 以给定格式返回文本的应答
+    fn text(self, status: StatusCode, content_type: &str) -> Self {
+        self.respond(status, Some((content_type, json!({ "type": "string" }))))
+    }
+
+    /// 不经过API认证层的接口
+    fn public(mut self) -> Self {
+        self.value()["security"] = json!([]);
+        self
+    }
+
+    fn respond(mut self, status: StatusCode, content: Option<(&str, Value)>) -> Self {
+        let mut response = json!({ "description": status.canonical_reason().unwrap_or("") });
+        if let Some((content_type, schema)) = content {
+            response["content"] = json!({ content_type: { "schema": schema } });
+        }
+        self.value()["responses"][status.as_str()] = response;
+        self
+    }
+}
+
+/// 生成完整的OpenAPI文档
+pub fn build(api_config: &ApiConfig) -> Value {
+    let mut spec = Spec::new();
+
+    spec.get("/proxy.pac", "按当前配置生成的PAC文件").query::<PacQuery>()
+        .text(StatusCode::OK, "application/x-ns-proxy-autoconfig").status(StatusCode::NOT_FOUND).public();
+
+    spec.get("/api/v1/proxies", "列出代理，支持筛选、排序与分页").query::<ProxyQuery>()
+        .json::<ProxyPage>(StatusCode::OK);
+    spec.post("/api/v1/proxies", "添加代理，池中已有同一上游时合并元数据").body::<ProxyConfig>()
+        .json::<ProxyEntry>(StatusCode::CREATED).json::<ProxyEntry>(StatusCode::OK)
+        .status(StatusCode::BAD_REQUEST).status(StatusCode::CONFLICT);
+    spec.post("/api/v1/proxies/import", "批量导入代理，按Content-Type识别格式").query::<ImportQuery>()
+        .text_body(&["text/plain", "application/json", "application/toml"])
+        .json::<ImportSummary>(StatusCode::OK).status(StatusCode::BAD_REQUEST);
+    spec.post("/api/v1/proxies/test-all", "在后台测试全部代理").json::<Job>(StatusCode::ACCEPTED);
+    spec.get("/api/v1/proxies/:id", "获取单个代理").json::<ProxyEntry>(StatusCode::OK).status(StatusCode::NOT_FOUND);
+    spec.patch("/api/v1/proxies/:id", "修改代理的权重、位置标签与认证信息").body::<ProxyPatch>()
+        .json::<ProxyEntry>(StatusCode::OK).status(StatusCode::NOT_FOUND).status(StatusCode::CONFLICT);
+    spec.delete("/api/v1/proxies/:id", "移除代理").status(StatusCode::NO_CONTENT).status(StatusCode::NOT_FOUND);
+    spec.post("/api/v1/proxies/:id/test", "在后台测试单个代理").json::<Job>(StatusCode::ACCEPTED).status(StatusCode::NOT_FOUND);
+
+    spec.get("/api/v1/jobs", "列出最近的测试任务，不含逐个代理的结果").json::<Vec<Job>>(StatusCode::OK);
+    spec.get("/api/v1/jobs/:id", "查看测试任务的进度与结果").json::<Job>(StatusCode::OK).status(StatusCode::NOT_FOUND);
+
+    spec.get("/api/v1/events/sse", "以Server-Sent Events推送代理池事件，每条data是一个事件")
+        .event_stream::<PoolEvent>();
+
+    spec.post("/api/v1/lease", "借出一个代理").optional_body::<LeaseRequest>()
+        .json::<LeaseGrant>(StatusCode::CREATED).status(StatusCode::BAD_REQUEST).status(StatusCode::SERVICE_UNAVAILABLE);
+    spec.delete("/api/v1/lease/:id", "归还代理，可报告使用结果").query::<LeaseReport>()
+        .status(StatusCode::NO_CONTENT).status(StatusCode::NOT_FOUND);
+
+    // 只有与SOCKS5服务运行在同一进程时可用，否则应答503
+    spec.get("/api/v1/listeners", "SOCKS5监听端口及其连接数").json::<Vec<ListenerInfo>>(StatusCode::OK)
+        .status(StatusCode::SERVICE_UNAVAILABLE);
+    for (action, summary) in [("start", "启动已停止的监听端口"), ("stop", "停止监听端口，等待进行中的连接结束"), ("restart", "重启监听端口")] {
+        spec.post(&format!("/api/v1/listeners/:port/{}", action), summary).json::<ListenerInfo>(StatusCode::OK)
+            .status(StatusCode::NOT_FOUND).status(StatusCode::CONFLICT).status(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    spec.put("/api/v1/listeners/:port/strategy", "修改监听端口的选择策略，运行中的端口重启后应用").body::<StrategyBinding>()
+        .json::<ListenerInfo>(StatusCode::OK).status(StatusCode::NOT_FOUND).status(StatusCode::SERVICE_UNAVAILABLE);
+    spec.get("/api/v1/connections", "进行中的SOCKS5连接").json::<Vec<ConnectionEntry>>(StatusCode::OK)
+        .status(StatusCode::SERVICE_UNAVAILABLE);
+    spec.delete("/api/v1/connections/:id", "强制关闭进行中的连接").status(StatusCode::NO_CONTENT)
+        .status(StatusCode::NOT_FOUND).status(StatusCode::SERVICE_UNAVAILABLE);
+
+    spec.get("/api/v1/stats", "代理池统计").query::<StatsQuery>().json::<Stats>(StatusCode::OK);
+    spec.get("/api/v1/stats/history", "按时间段汇总的可用代理数、平均延迟与请求量").query::<HistoryQuery>()
+        .json::<StatsHistoryReport>(StatusCode::OK).status(StatusCode::BAD_REQUEST);
+    spec.get("/api/v1/config", "生效的配置，密码已隐去").json::<EffectiveConfig>(StatusCode::OK);
+    spec.put("/api/v1/config", "以完整配置替换生效的配置并写回配置文件").body::<Config>()
+        .json::<ConfigUpdate>(StatusCode::OK).status(StatusCode::BAD_REQUEST);
+    spec.patch("/api/v1/config", "修改可在运行时生效的设置").query::<PatchQuery>().body::<ConfigPatch>()
+        .json::<EffectiveConfig>(StatusCode::OK).status(StatusCode::BAD_REQUEST);
+    spec.get("/api/v1/loglevel", "当前的日志过滤器").json::<LogLevel>(StatusCode::OK);
+    spec.put("/api/v1/loglevel", "修改日志过滤器，立即生效").body::<LogLevel>()
+        .json::<LogLevel>(StatusCode::OK).status(StatusCode::BAD_REQUEST);
+    spec.get("/api/v1/sources", "各代理来源的代理数与最近一次同步结果").json::<Vec<SourceStatus>>(StatusCode::OK);
+    spec.get("/api/v1/traffic", "按客户端与目标累计的流量").query::<TrafficQuery>().json::<TrafficReport>(StatusCode::OK);
+    spec.delete("/api/v1/traffic", "清空流量统计").status(StatusCode::NO_CONTENT);
+    spec.get("/api/v1/blacklist", "临时拉黑与隔离观察中的代理及其到期时间").json::<Vec<SidelinedProxy>>(StatusCode::OK);
+    spec.post("/api/v1/blacklist/:id", "手动把代理加入临时黑名单").optional_body::<BlacklistRequest>()
+        .json::<SidelinedProxy>(StatusCode::OK).status(StatusCode::BAD_REQUEST).status(StatusCode::NOT_FOUND);
+    spec.delete("/api/v1/blacklist/:id", "提前恢复代理，移出临时黑名单并结束隔离观察")
+        .status(StatusCode::NO_CONTENT).status(StatusCode::NOT_FOUND);
+    spec.get("/api/v1/blocklist", "永久黑名单").json::<Vec<BlockedProxy>>(StatusCode::OK);
+    spec.delete("/api/v1/blocklist", "清空永久黑名单").json::<ClearedBlocklist>(StatusCode::OK);
+    spec.delete("/api/v1/blocklist/:key", "从永久黑名单移除单个条目").status(StatusCode::NO_CONTENT).status(StatusCode::NOT_FOUND);
+
+    // 出口节点接口使用各自的令牌认证
+    spec.get("/api/v1/exits", "列出出口节点，需要出口节点主令牌").json::<Vec<ExitAgent>>(StatusCode::OK)
+        .status(StatusCode::UNAUTHORIZED).public();
+    spec.post("/api/v1/exits", "注册出口节点，需要主令牌或加入令牌").body::<RegisterRequest>()
+        .json::<RegisterResponse>(StatusCode::OK).status(StatusCode::UNAUTHORIZED).public();
+    spec.get("/api/v1/exits/tokens", "列出加入令牌，需要主令牌").json::<Vec<JoinToken>>(StatusCode::OK)
+        .status(StatusCode::UNAUTHORIZED).public();
+    spec.post("/api/v1/exits/tokens", "签发加入令牌，需要主令牌").optional_body::<IssueTokenRequest>()
+        .json::<JoinToken>(StatusCode::OK).status(StatusCode::UNAUTHORIZED).public();
+    spec.delete("/api/v1/exits/tokens/:token", "吊销加入令牌，需要主令牌").status(StatusCode::NO_CONTENT)
+        .status(StatusCode::NOT_FOUND).public();
+    spec.post("/api/v1/exits/:id/heartbeat", "出口节点心跳").body::<AgentAuth>()
+        .status(StatusCode::OK).status(StatusCode::UNAUTHORIZED).public();
+    spec.delete("/api/v1/exits/:id", "注销出口节点").optional_body::<AgentAuth>()
+        .status(StatusCode::NO_CONTENT).status(StatusCode::UNAUTHORIZED).public();
+
+    spec.get("/api/v1/openapi.json", "本文档").json::<Value>(StatusCode::OK);
+
+    let mut document = json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "LokiPool API",
+            "description": "LokiPool SOCKS5代理池的管理接口",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": spec.paths,
+        "components": {
+            "schemas": spec.generator.take_definitions(true),
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer", "description": "静态API密钥或HS256签名的JWT" },
+                "api_key": { "type": "apiKey", "in": "header", "name": "X-API-Key" },
+            },
+        },
+    });
+    if api_config.auth.is_enabled() {
+        document["security"] = json!([{ "bearer": [] }, { "api_key": [] }]);
+    }
+    document
+}
+
+/// 返回OpenAPI文档
+pub async fn openapi_json(State(state): State<ApiState>) -> Json<Value> {
+    Json(build(&state.api_config))
+}
+
+/// Swagger UI页面，脚本与样式从CDN加载
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI)
+}
+
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+  <meta charset="utf-8">
+  <title>LokiPool API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/api/v1/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
@@ -0,0 +1,380 @@
+//! 代理管理
+//!
+//! `GET /api/v1/proxies` 按状态、位置标签与成功率筛选代理池中的代理，排序后分页返回。
+//! `POST /api/v1/proxies` 在运行时添加代理，`PATCH` 与 `DELETE /api/v1/proxies/:id` 修改或移除单个代理。
+//! `POST /api/v1/proxies/import` 批量导入 `host:port` 列表、JSON数组或TOML的 `[[proxies]]`。
+//! 返回的条目不含代理的用户名与密码。
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::Json;
+use chrono::{DateTime, Utc};
+use lokipool_core::{parse_list, Proxy, ProxyConfig, ProxySource, ProxyStatus, UsageStats};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::jobs::{Job, JobKind};
+use crate::ApiState;
+
+/// 列表查询参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ProxyQuery {
+    /// 只返回该状态的代理
+    pub status: Option<ProxyStatus>,
+    /// 只返回位置标签为该值的代理（不区分大小写）；代理没有单独的国家字段，`country` 同样按位置标签匹配
+    #[serde(alias = "tag", alias = "country")]
+    pub location: Option<String>,
+    /// 只返回成功率不低于该值（0.0-1.0）的代理
+    pub min_success_rate: Option<f64>,
+    /// 排序字段
+    #[serde(default)]
+    pub sort: ProxySort,
+    /// 排序方向
+    #[serde(default)]
+    pub order: SortOrder,
+    /// 跳过的条目数
+    #[serde(default)]
+    pub offset: usize,
+    /// 最多返回的条目数，不设置时返回全部
+    pub limit: Option<usize>,
+}
+
+/// 排序字段
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxySort {
+    /// 按 `host:port`
+    #[default]
+    Address,
+    /// 按最近一次测得的延迟，未测试的代理视为最慢
+    Latency,
+    /// 按成功率
+    SuccessRate,
+    /// 按最近一次测试的时间，从未测试的排在最前
+    LastChecked,
+    /// 按当前活跃连接数
+    Connections,
+}
+
+/// 排序方向
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// 一页代理
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ProxyPage {
+    /// 代理池中的代理总数
+    pub total: usize,
+    /// 符合筛选条件的代理数
+    pub matched: usize,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub proxies: Vec<ProxyEntry>,
+}
+
+/// 列表中的一个代理
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ProxyEntry {
+    pub id: String,
+    /// `host:port`
+    pub address: String,
+    pub proxy_type: String,
+    /// 位置标签
+    pub location: Option<String>,
+    /// 选择权重
+    pub weight: u32,
+    pub status: ProxyStatus,
+    /// 最近一次测得的延迟（毫秒）
+    pub latency: Option<u64>,
+    /// 成功率（0.0-1.0）
+    pub success_rate: f64,
+    /// 最近一次测试的时间
+    pub last_checked: Option<DateTime<Utc>>,
+    /// 列出该代理的来源
+    pub sources: Vec<ProxySource>,
+    /// 是否支持UDP ASSOCIATE，尚未用于UDP时为null
+    pub udp: Option<bool>,
+    #[serde(flatten)]
+    pub usage: UsageStats,
+}
+
+impl From<&Proxy> for ProxyEntry {
+    fn from(proxy: &Proxy) -> Self {
+        Self {
+            id: proxy.id.clone(),
+            address: format!("{}:{}", proxy.info.host, proxy.info.port),
+            proxy_type: proxy.info.proxy_type.clone(),
+            location: proxy.info.location.clone(),
+            weight: proxy.info.weight,
+            status: proxy.status,
+            latency: proxy.info.last_latency,
+            success_rate: proxy.info.success_rate,
+            last_checked: proxy.last_tested.or(proxy.info.last_checked).map(|at| at.wall()),
+            sources: proxy.sources.iter().copied().collect(),
+            udp: proxy.udp,
+            usage: proxy.usage_stats(),
+        }
+    }
+}
+
+impl ProxyQuery {
+    fn matches(&self, entry: &ProxyEntry) -> bool {
+        self.status.is_none_or(|status| entry.status == status)
+            && self.location.as_ref().is_none_or(|location| {
+                entry.location.as_ref().is_some_and(|tag| tag.eq_ignore_ascii_case(location))
+            })
+            && self.min_success_rate.is_none_or(|rate| entry.success_rate >= rate)
+    }
+
+    fn compare(&self, a: &ProxyEntry, b: &ProxyEntry) -> Ordering {
+        let ordering = match self.sort {
+            ProxySort::Address => a.address.cmp(&b.address),
+            ProxySort::Latency => a.latency.unwrap_or(u64::MAX).cmp(&b.latency.unwrap_or(u64::MAX)),
+            ProxySort::SuccessRate => a.success_rate.total_cmp(&b.success_rate),
+            ProxySort::LastChecked => a.last_checked.cmp(&b.last_checked),
+            ProxySort::Connections => a.usage.active_connections.cmp(&b.usage.active_connections),
+        };
+        // 排序字段相同时按地址排，翻页时顺序稳定
+        let ordering = ordering.then_with(|| a.address.cmp(&b.address));
+        match self.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+}
+
+/// 修改代理的请求，未列出的字段保持不变
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyPatch {
+    /// 选择权重
+    pub weight: Option<u32>,
+    /// 位置标签，空字符串表示清除
+    #[serde(alias = "tag")]
+    pub location: Option<String>,
+    /// 用户名，空字符串表示不再认证（同时清除密码）
+    pub username: Option<String>,
+    /// 密码，空字符串表示清除
+    pub password: Option<String>,
+}
+
+/// 导入参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ImportQuery {
+    /// 导入后在后台测试新加入的代理
+    #[serde(default)]
+    pub test: bool,
+}
+
+/// 导入结果
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct ImportSummary {
+    /// 新加入的代理数
+    pub added: usize,
+    /// 池中已有或在本次导入中重复出现的条目数
+    pub duplicate: usize,
+    /// 无法解析的条目数
+    pub invalid: usize,
+    /// 因永久黑名单或容量上限未能加入的条目数
+    pub rejected: usize,
+    /// 新加入代理的ID
+    pub ids: Vec<String>,
+    /// 无法解析或未能加入的条目及原因
+    pub errors: Vec<ImportError>,
+    /// `?test=true` 时测试新代理的任务
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<Job>,
+}
+
+/// 未能导入的条目
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ImportError {
+    /// 文本的行号或数组的下标，从1开始
+    pub entry: usize,
+    pub reason: String,
+}
+
+/// 添加前的基本检查
+pub(crate) fn validate(config: &ProxyConfig) -> Result<(), String> {
+    if config.host.is_empty() || config.port == 0 {
+        return Err("host 不能为空，port 必须大于0".to_string());
+    }
+    Ok(())
+}
+
+/// 条目序号与解析结果
+type ParsedEntry = (usize, Result<ProxyConfig, String>);
+
+/// 按请求的Content-Type解析导入的条目，单个条目的错误不影响其他条目
+fn parse_import(headers: &HeaderMap, body: &str) -> Result<Vec<ParsedEntry>, String> {
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    if content_type.contains("json") {
+        let entries: Vec<serde_json::Value> = serde_json::from_str(body).map_err(|e| format!("JSON应为代理数组: {}", e))?;
+        return Ok(entries.into_iter()
+            .enumerate()
+            .map(|(at, entry)| (at + 1, serde_json::from_value(entry).map_err(|e| e.to_string())))
+            .collect());
+    }
+    if content_type.contains("toml") {
+        #[derive(Deserialize)]
+        struct Document {
+            #[serde(default)]
+            proxies: Vec<toml::Value>,
+        }
+        let document: Document = toml::from_str(body).map_err(|e| format!("TOML格式错误: {}", e))?;
+        return Ok(document.proxies.into_iter()
+            .enumerate()
+            .map(|(at, entry)| (at + 1, entry.try_into().map_err(|e: toml::de::Error| e.to_string())))
+            .collect());
+    }
+    // 其余按内容识别每行一个地址、CSV或clash订阅YAML
+    Ok(parse_list(body))
+}
+
+/// 空字符串表示清除
+fn non_empty(value: String) -> Option<String> {
+    (!value.is_empty()).then_some(value)
+}
+
+/// 筛选、排序并分页列出代理
+pub async fn list_proxies(State(state): State<ApiState>, Query(query): Query<ProxyQuery>) -> Json<ProxyPage> {
+    let proxies = state.pool.get_all_proxies().await;
+    let total = proxies.len();
+    let mut entries: Vec<ProxyEntry> = proxies.iter()
+        .map(ProxyEntry::from)
+        .filter(|entry| query.matches(entry))
+        .collect();
+    entries.sort_by(|a, b| query.compare(a, b));
+    let matched = entries.len();
+    let proxies = entries.into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+    Json(ProxyPage { total, matched, offset: query.offset, limit: query.limit, proxies })
+}
+
+/// 获取单个代理
+pub async fn get_proxy(State(state): State<ApiState>, Path(id): Path<String>) -> Result<Json<ProxyEntry>, StatusCode> {
+    state.pool.get_all_proxies().await
+        .iter()
+        .find(|proxy| proxy.id == id)
+        .map(|proxy| Json(ProxyEntry::from(proxy)))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// 添加代理，新加入时应答201，池中已有同一上游时合并元数据并应答200
+///
+/// 新代理处于未测试状态，通过定期测试后才会被选用。
+pub async fn add_proxy(
+    State(state): State<ApiState>,
+    Json(config): Json<ProxyConfig>,
+) -> Result<(StatusCode, Json<ProxyEntry>), (StatusCode, String)> {
+    validate(&config).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let key = config.canonical_key();
+    let existed = state.pool.get_all_proxies().await.iter().any(|proxy| proxy.canonical_key() == key);
+    let address = format!("{}:{}", config.host, config.port);
+    let id = state.pool.add_config(config).await.map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+    let proxy = state.pool.get_all_proxies().await
+        .into_iter()
+        .find(|proxy| proxy.id == id)
+        .ok_or_else(|| (StatusCode::CONFLICT, format!("代理 {} 刚加入即被移除", address)))?;
+    if !existed {
+        info!("通过API添加代理 {}", address);
+    }
+    let status = if existed { StatusCode::OK } else { StatusCode::CREATED };
+    Ok((status, Json(ProxyEntry::from(&proxy))))
+}
+
+/// 修改代理的权重、位置标签或认证信息，状态与统计保持不变
+pub async fn patch_proxy(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(patch): Json<ProxyPatch>,
+) -> Result<Json<ProxyEntry>, (StatusCode, String)> {
+    let edited = state.pool.edit(&id, |info| {
+        if let Some(weight) = patch.weight {
+            info.weight = weight;
+        }
+        if let Some(location) = patch.location {
+            info.location = non_empty(location);
+        }
+        if let Some(username) = patch.username {
+            info.username = non_empty(username);
+            if info.username.is_none() {
+                info.password = None;
+            }
+        }
+        if let Some(password) = patch.password {
+            info.password = non_empty(password);
+        }
+    }).await.map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+    edited
+        .map(|proxy| Json(ProxyEntry::from(&proxy)))
+        .ok_or((StatusCode::NOT_FOUND, format!("代理不存在: {}", id)))
+}
+
+/// 从池中移除代理；来自代理文件或配置文件的代理会在该来源下次同步时重新加入
+pub async fn delete_proxy(State(state): State<ApiState>, Path(id): Path<String>) -> StatusCode {
+    match state.pool.remove(&id).await {
+        Some(proxy) => {
+            info!("通过API移除代理 {}:{}", proxy.info.host, proxy.info.port);
+            StatusCode::NO_CONTENT
+        }
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+/// 批量导入代理，池中已有的上游计为重复而不合并元数据
+pub async fn import_proxies(
+    State(state): State<ApiState>,
+    Query(query): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<ImportSummary>, (StatusCode, String)> {
+    let entries = parse_import(&headers, &body).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let mut known: HashSet<String> = state.pool.get_all_proxies().await.iter().map(Proxy::canonical_key).collect();
+    let mut summary = ImportSummary::default();
+    for (entry, parsed) in entries {
+        let config = match parsed.and_then(|config| validate(&config).map(|_| config)) {
+            Ok(config) => config,
+            Err(reason) => {
+                summary.invalid += 1;
+                summary.errors.push(ImportError { entry, reason });
+                continue;
+            }
+        };
+        if !known.insert(config.canonical_key()) {
+            summary.duplicate += 1;
+            continue;
+        }
+        match state.pool.add_config(config).await {
+            Ok(id) => {
+                summary.added += 1;
+                summary.ids.push(id);
+            }
+            Err(e) => {
+                summary.rejected += 1;
+                summary.errors.push(ImportError { entry, reason: e.to_string() });
+            }
+        }
+    }
+    info!("通过API导入代理: 新增 {} 个, 重复 {} 个, 无效 {} 个, 拒绝 {} 个",
+        summary.added, summary.duplicate, summary.invalid, summary.rejected);
+
+    if query.test && !summary.ids.is_empty() {
+        let added: Vec<Proxy> = state.pool.get_all_proxies().await
+            .into_iter()
+            .filter(|proxy| summary.ids.contains(&proxy.id))
+            .collect();
+        summary.job = Some(state.jobs.start(state.pool.clone(), JobKind::TestImported, added));
+    }
+    Ok(Json(summary))
+}
@@ -0,0 +1,470 @@
+//! 运行时配置
+//!
+//! `GET /api/v1/config` 返回生效的配置（代理与SOCKS5账户的密码已隐去），
+//! `PATCH /api/v1/config` 修改白名单内可在运行时生效的设置，
+//! 带 `?persist=true` 时同时写回配置文件，只改动对应的键，保留文件中的注释与格式。
+//! `PUT /api/v1/config` 以校验后的完整配置替换生效的配置并写回配置文件，代理池的选项与 `[[proxies]]` 立即生效，
+//! 其余改动（监听地址、测试URL等）在重启后生效，应答中的 `restart_required` 列出这些键。
+//! `PUT /api/v1/loglevel` 单独修改日志过滤器，排查问题时无需带 `RUST_LOG` 重启而丢失现场。
+
+use std::path::Path;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::Json;
+use lokipool_core::{write_atomic, Config, PoolOptions, ProxySource, SelectionStrategy, SyncReport};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Map;
+use toml_edit::{DocumentMut, Item, Table, Value};
+use tracing::info;
+
+use crate::ApiState;
+
+/// 代替密码输出的占位符
+const REDACTED: &str = "******";
+
+/// 生效的配置
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EffectiveConfig {
+    #[serde(flatten)]
+    config: Config,
+    /// 当前的日志过滤器
+    log_level: Option<String>,
+}
+
+impl EffectiveConfig {
+    fn new(config: &Config) -> Self {
+        let mut config = config.clone();
+        for proxy in &mut config.proxies {
+            if proxy.password.is_some() {
+                proxy.password = Some(REDACTED.to_string());
+            }
+        }
+        for account in &mut config.socks_server.accounts {
+            account.password = REDACTED.to_string();
+        }
+        Self { config, log_level: lokipool_core::log_filter() }
+    }
+}
+
+/// 可在运行时修改的设置，未列出的字段会被拒绝
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigPatch {
+    /// 代理选择策略
+    strategy: Option<SelectionStrategy>,
+    /// 定期测试间隔（秒）
+    health_check_interval: Option<u64>,
+    /// 单个上游代理的最大并发连接数（0表示不限制）
+    max_conns_per_proxy: Option<usize>,
+    /// 可用代理数量下限（0表示禁用）
+    min_available: Option<usize>,
+    /// 单个代理最多承担的请求比例（0表示不限制）
+    max_share: Option<f64>,
+    /// 连续连接失败多少次后临时拉黑（0表示禁用）
+    blacklist_after_failures: Option<u32>,
+    /// 黑名单冷却时间（秒）
+    blacklist_duration: Option<u64>,
+    /// 熔断器打开后进入半开状态的时间（秒）
+    circuit_open_duration: Option<u64>,
+    /// 恢复后的隔离观察时长（秒，0表示直接恢复为可用）
+    quarantine_period: Option<u64>,
+    /// 日志过滤器，语法与 `RUST_LOG` 相同；只在运行时生效，不写回配置文件
+    log_level: Option<String>,
+}
+
+impl ConfigPatch {
+    fn validate(&self) -> Result<(), String> {
+        if self.health_check_interval == Some(0) {
+            return Err("health_check_interval 必须大于0".to_string());
+        }
+        if self.max_share.is_some_and(|share| !(0.0..1.0).contains(&share)) {
+            return Err("max_share 必须在 [0, 1) 之间".to_string());
+        }
+        Ok(())
+    }
+
+    /// 应用到配置
+    fn apply(&self, config: &mut Config) {
+        let proxy = &mut config.proxy;
+        if let Some(strategy) = self.strategy {
+            proxy.strategy = strategy;
+        }
+        if let Some(interval) = self.health_check_interval {
+            proxy.health_check_interval = interval;
+        }
+        if let Some(max_conns) = self.max_conns_per_proxy {
+            proxy.max_conns_per_proxy = max_conns;
+        }
+        if let Some(min_available) = self.min_available {
+            proxy.min_available = min_available;
+        }
+        if let Some(max_share) = self.max_share {
+            proxy.max_share = max_share;
+        }
+        if let Some(failures) = self.blacklist_after_failures {
+            proxy.blacklist_after_failures = failures;
+        }
+        if let Some(duration) = self.blacklist_duration {
+            proxy.blacklist_duration = duration;
+        }
+        if let Some(duration) = self.circuit_open_duration {
+            proxy.circuit_open_duration = duration;
+        }
+        if let Some(period) = self.quarantine_period {
+            proxy.quarantine_period = period;
+        }
+    }
+
+    /// 需要写回配置文件 `[proxy]` 表的键值
+    fn proxy_entries(&self) -> Vec<(&'static str, Value)> {
+        let mut entries = Vec::new();
+        if let Some(strategy) = self.strategy {
+            entries.push(("strategy", strategy.to_string().into()));
+        }
+        if let Some(interval) = self.health_check_interval {
+            entries.push(("health_check_interval", (interval as i64).into()));
+        }
+        if let Some(max_conns) = self.max_conns_per_proxy {
+            entries.push(("max_conns_per_proxy", (max_conns as i64).into()));
+        }
+        if let Some(min_available) = self.min_available {
+            entries.push(("min_available", (min_available as i64).into()));
+        }
+        if let Some(max_share) = self.max_share {
+            entries.push(("max_share", max_share.into()));
+        }
+        if let Some(failures) = self.blacklist_after_failures {
+            entries.push(("blacklist_after_failures", (failures as i64).into()));
+        }
+        if let Some(duration) = self.blacklist_duration {
+            entries.push(("blacklist_duration", (duration as i64).into()));
+        }
+        if let Some(duration) = self.circuit_open_duration {
+            entries.push(("circuit_open_duration", (duration as i64).into()));
+        }
+        if let Some(period) = self.quarantine_period {
+            entries.push(("quarantine_period", (period as i64).into()));
+        }
+        entries
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct PatchQuery {
+    /// 是否写回配置文件
+    #[serde(default)]
+    persist: bool,
+}
+
+/// 获取生效的配置
+pub async fn get_config(State(state): State<ApiState>) -> Json<EffectiveConfig> {
+    Json(EffectiveConfig::new(&state.config.read().unwrap()))
+}
+
+/// 修改运行时设置，返回修改后的配置
+pub async fn patch_config(
+    State(state): State<ApiState>,
+    Query(query): Query<PatchQuery>,
+    Json(patch): Json<ConfigPatch>,
+) -> Result<Json<EffectiveConfig>, (StatusCode, String)> {
+    patch.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let persist_to = match (query.persist, &state.api_config.config_file) {
+        (false, _) => None,
+        (true, Some(path)) => Some(path.clone()),
+        (true, None) => return Err((StatusCode::BAD_REQUEST, "未指定配置文件，无法写回".to_string())),
+    };
+
+    if let Some(directives) = &patch.log_level {
+        apply_log_filter(directives)?;
+    }
+
+    let effective = {
+        let mut config = state.config.write().unwrap();
+        patch.apply(&mut config);
+        let proxy = &config.proxy;
+        state.pool.update_options(|options| {
+            options.strategy = proxy.strategy;
+            options.test_interval = proxy.health_check_interval;
+            options.max_conns_per_proxy = proxy.max_conns_per_proxy;
+            options.min_available = proxy.min_available;
+            options.max_share = proxy.max_share;
+            options.blacklist_after_failures = proxy.blacklist_after_failures;
+            options.blacklist_duration = proxy.blacklist_duration;
+            options.circuit_open_duration = proxy.circuit_open_duration;
+            options.quarantine_period = proxy.quarantine_period;
+        });
+        EffectiveConfig::new(&config)
+    };
+    info!("运行时配置已更新: {:?}", patch);
+
+    if let Some(path) = persist_to {
+        persist(&path, &patch.proxy_entries())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("写回配置文件失败: {}", e)))?;
+        info!("运行时配置已写回 {}", path.display());
+    }
+
+    Ok(Json(effective))
+}
+
+/// 替换配置后无需重启即生效的键，即代理池的选项与配置文件中的代理
+const HOT_KEYS: &[&str] = &[
+    "max_connections",
+    "proxies",
+    "proxy.health_check_interval",
+    "proxy.strategy",
+    "proxy.max_conns_per_proxy",
+    "proxy.blacklist_after_failures",
+    "proxy.blacklist_duration",
+    "proxy.circuit_failure_threshold",
+    "proxy.circuit_open_duration",
+    "proxy.quarantine_period",
+    "proxy.quarantine_traffic_ratio",
+    "proxy.quarantine_max_error_rate",
+    "proxy.evict_after_failures",
+    "proxy.evict_min_failing_duration",
+    "proxy.dead_list_file",
+    "proxy.honeypot_check",
+    "proxy.min_available",
+    "proxy.retest_on_low_capacity",
+    "proxy.rotate_after_requests",
+    "proxy.rotate_after_secs",
+    "proxy.rotate_cooldown",
+    "proxy.probation_period",
+    "proxy.probation_traffic_ratio",
+    "proxy.probation_max_error_rate",
+    "proxy.max_share",
+    "proxy.fairness_window",
+    "proxy.retry_concurrency",
+    "proxy.retry_backoff",
+    "proxy.retry_backoff_max",
+    "proxy.snapshot_file",
+    "proxy.interactive_reserve",
+    "proxy.race_candidates",
+];
+
+/// 替换配置的结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ConfigUpdate {
+    /// 替换后生效的配置
+    config: EffectiveConfig,
+    /// 是否已写回配置文件
+    persisted: bool,
+    /// `[[proxies]]` 有变化时的同步结果
+    proxies: Option<SyncReport>,
+    /// 已改动但要重启才生效的键，如 `socks_server.bind_port`
+    restart_required: Vec<String>,
+}
+
+/// 校验完整配置中的取值范围
+fn validate(config: &Config) -> Result<(), String> {
+    let proxy = &config.proxy;
+    if config.max_connections == 0 {
+        return Err("max_connections 必须大于0".to_string());
+    }
+    if proxy.health_check_interval == 0 {
+        return Err("proxy.health_check_interval 必须大于0".to_string());
+    }
+    if proxy.shards == 0 {
+        return Err("proxy.shards 必须大于0".to_string());
+    }
+    if !(0.0..1.0).contains(&proxy.max_share) {
+        return Err("proxy.max_share 必须在 [0, 1) 之间".to_string());
+    }
+    let ratios = [
+        ("proxy.quarantine_traffic_ratio", proxy.quarantine_traffic_ratio),
+        ("proxy.quarantine_max_error_rate", proxy.quarantine_max_error_rate),
+        ("proxy.probation_traffic_ratio", proxy.probation_traffic_ratio),
+        ("proxy.probation_max_error_rate", proxy.probation_max_error_rate),
+    ];
+    if let Some((key, _)) = ratios.iter().find(|(_, ratio)| !(0.0..=1.0).contains(ratio)) {
+        return Err(format!("{} 必须在 [0, 1] 之间", key));
+    }
+    if config.test_urls.is_empty() {
+        return Err("test_urls 不能为空".to_string());
+    }
+    Ok(())
+}
+
+/// 把 `GET /api/v1/config` 中隐去的密码换回当前配置中的值，找不到对应的代理或账户时返回错误
+fn restore_passwords(config: &mut Config, current: &Config) -> Result<(), String> {
+    for proxy in &mut config.proxies {
+        if proxy.password.as_deref() != Some(REDACTED) {
+            continue;
+        }
+        let old = current.proxies.iter()
+            .find(|old| old.host == proxy.host && old.port == proxy.port && old.username == proxy.username)
+            .ok_or_else(|| format!("代理 {}:{} 的密码是占位符，但当前配置中没有该代理", proxy.host, proxy.port))?;
+        proxy.password = old.password.clone();
+    }
+    for account in &mut config.socks_server.accounts {
+        if account.password != REDACTED {
+            continue;
+        }
+        let old = current.socks_server.accounts.iter()
+            .find(|old| old.username == account.username)
+            .ok_or_else(|| format!("账户 {} 的密码是占位符，但当前配置中没有该账户", account.username))?;
+        account.password = old.password.clone();
+    }
+    Ok(())
+}
+
+/// 两份配置中取值不同的键，表内逐键比较，形如 `proxy.strategy`
+fn changed_keys(old: &Config, new: &Config) -> Vec<String> {
+    fn walk(prefix: &str, old: &Map<String, serde_json::Value>, new: &Map<String, serde_json::Value>, changed: &mut Vec<String>) {
+        let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+            match (old.get(key), new.get(key)) {
+                (Some(serde_json::Value::Object(old)), Some(serde_json::Value::Object(new))) => walk(&path, old, new, changed),
+                (old, new) if old != new => changed.push(path),
+                _ => {}
+            }
+        }
+    }
+    let mut changed = Vec::new();
+    if let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) = (serde_json::to_value(old), serde_json::to_value(new)) {
+        walk("", &old, &new, &mut changed);
+    }
+    changed
+}
+
+/// 以完整配置替换生效的配置，写回配置文件并立即应用代理池的选项
+pub async fn put_config(
+    State(state): State<ApiState>,
+    Json(mut config): Json<Config>,
+) -> Result<Json<ConfigUpdate>, (StatusCode, String)> {
+    let current = state.config.read().unwrap().clone();
+    restore_passwords(&mut config, &current).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    validate(&config).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let changed = changed_keys(&current, &config);
+    let persisted = match &state.api_config.config_file {
+        Some(path) => {
+            persist_config(path, &config)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("写回配置文件失败: {}", e)))?;
+            info!("配置已写回 {}", path.display());
+            true
+        }
+        None => false,
+    };
+
+    // 分片数量与永久黑名单文件只在创建代理池时使用
+    let fresh = PoolOptions::from_config(&config);
+    state.pool.update_options(|options| {
+        *options = PoolOptions {
+            auto_test: options.auto_test,
+            shards: options.shards,
+            blocklist_file: options.blocklist_file.clone(),
+            ..fresh
+        };
+    });
+    let proxies = match changed.iter().any(|key| key == "proxies") {
+        true => Some(state.pool.sync_source(ProxySource::Config, config.proxies.clone()).await),
+        false => None,
+    };
+    let restart_required: Vec<String> = changed.iter()
+        .filter(|key| !HOT_KEYS.contains(&key.as_str()))
+        .cloned()
+        .collect();
+
+    let effective = {
+        let mut current = state.config.write().unwrap();
+        *current = config;
+        EffectiveConfig::new(&current)
+    };
+    info!("配置已替换，改动 {} 个键，其中 {} 个需要重启后生效", changed.len(), restart_required.len());
+    Ok(Json(ConfigUpdate { config: effective, persisted, proxies, restart_required }))
+}
+
+/// 把完整配置写回配置文件：逐键更新已有的值，删除不再存在的键，保留原有的注释与顺序
+fn persist_config(path: &Path, config: &Config) -> anyhow::Result<()> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut document: DocumentMut = content.parse()?;
+    let updated: DocumentMut = toml::to_string(config)?.parse()?;
+    merge_table(document.as_table_mut(), updated.as_table());
+    write_atomic(path, document.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// 把 `updated` 合并进 `base`，两边都是表时递归合并，值沿用原有的前后缀
+fn merge_table(base: &mut Table, updated: &Table) {
+    let stale: Vec<String> = base.iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !updated.contains_key(key))
+        .collect();
+    for key in stale {
+        base.remove(&key);
+    }
+    for (key, item) in updated.iter() {
+        match (base.get_mut(key), item) {
+            (Some(Item::Table(base)), Item::Table(updated)) => merge_table(base, updated),
+            (Some(Item::Value(old)), Item::Value(value)) => {
+                let mut value = value.clone();
+                *value.decor_mut() = old.decor().clone();
+                *old = value;
+            }
+            _ => {
+                base.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+/// 把 `[proxy]` 表中的键写回配置文件，保留原有的注释
+fn persist(path: &Path, entries: &[(&str, Value)]) -> anyhow::Result<()> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut document: DocumentMut = content.parse()?;
+    let proxy = document.entry("proxy")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_mut()
+        .ok_or_else(|| anyhow::anyhow!("配置文件中的 proxy 不是表"))?;
+
+    for (key, value) in entries {
+        let mut value = value.clone();
+        // 沿用原值的前后缀，行尾注释得以保留
+        if let Some(old) = proxy.get(key).and_then(Item::as_value) {
+            *value.decor_mut() = old.decor().clone();
+        }
+        proxy.insert(key, Item::Value(value));
+    }
+
+    write_atomic(path, document.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// 日志过滤器
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LogLevel {
+    /// 过滤器，语法与 `RUST_LOG` 相同，如 `debug` 或 `lokipool_core=trace,info`
+    filter: String,
+}
+
+/// 获取当前的日志过滤器
+pub async fn get_log_level() -> Result<Json<LogLevel>, StatusCode> {
+    lokipool_core::log_filter()
+        .map(|filter| Json(LogLevel { filter }))
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+/// 修改日志过滤器，立即生效
+pub async fn put_log_level(Json(level): Json<LogLevel>) -> Result<Json<LogLevel>, (StatusCode, String)> {
+    apply_log_filter(&level.filter)?;
+    Ok(Json(level))
+}
+
+fn apply_log_filter(directives: &str) -> Result<(), (StatusCode, String)> {
+    lokipool_core::set_log_filter(directives).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    info!("日志级别已修改为 {}", directives);
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! API的HTTPS支持
+//!
+//! 配置了证书与私钥（PEM格式）时，`ApiServer::run` 在同一端口上只接受TLS连接。
+//! 证书文件可以包含完整的证书链，私钥支持PKCS#8、PKCS#1与SEC1格式。
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{anyhow, Context};
+use axum::extract::ConnectInfo;
+use axum::{Extension, Router};
+use hyper::server::conn::Http;
+use lokipool_core::accept_backoff;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tower::Layer;
+use tracing::{debug, warn};
+
+/// TLS握手的最长时间，超时的连接直接关闭，避免只建立TCP连接不握手的客户端长期占用连接
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 证书与私钥路径
+#[derive(Debug, Clone)]
+pub struct ApiTls {
+    /// PEM格式的证书（链）
+    pub cert_path: PathBuf,
+    /// PEM格式的私钥
+    pub key_path: PathBuf,
+}
+
+impl ApiTls {
+    /// 读取证书与私钥
+    pub fn acceptor(&self) -> anyhow::Result<TlsAcceptor> {
+        let certs = rustls_pemfile::certs(&mut open(&self.cert_path)?)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("读取证书 {} 失败", self.cert_path.display()))?;
+        if certs.is_empty() {
+            return Err(anyhow!("证书文件 {} 中没有证书", self.cert_path.display()));
+        }
+        let key = rustls_pemfile::private_key(&mut open(&self.key_path)?)
+            .with_context(|| format!("读取私钥 {} 失败", self.key_path.display()))?
+            .ok_or_else(|| anyhow!("私钥文件 {} 中没有私钥", self.key_path.display()))?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .context("证书与私钥不匹配")?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+fn open(path: &Path) -> anyhow::Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("无法打开 {}", path.display()))?;
+    Ok(BufReader::new(file))
+}
+
+/// 在TLS连接上提供路由，握手失败或超时的连接直接丢弃
+///
+/// 接受连接出错时只记录日志并继续，文件描述符耗尽时稍等片刻再接受，避免空转。
+pub(crate) async fn serve(listener: TcpListener, acceptor: TlsAcceptor, app: Router) -> anyhow::Result<()> {
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("接受HTTPS连接失败: {}", e);
+                accept_backoff(&e).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        // 与明文服务一样提供对端地址，出口节点注册依赖它
+        let service = Extension(ConnectInfo(remote)).layer(app.clone());
+        tokio::spawn(async move {
+            let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    debug!("来自 {} 的TLS握手失败: {}", remote, e);
+                    return;
+                }
+                Err(_) => {
+                    debug!("来自 {} 的TLS握手超时", remote);
+                    return;
+                }
+            };
+            if let Err(e) = Http::new().serve_connection(stream, service).with_upgrades().await {
+                debug!("来自 {} 的HTTPS连接出错: {}", remote, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,137 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use lokipool_api::auth::{ApiAuth, ApiKey, Scope};
+use lokipool_api::{ApiConfig, ApiServer};
+use lokipool_core::time::wall_now;
+use lokipool_core::{Config, Pool, PoolOptions};
+use ring::hmac;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+const SECRET: &str = "jwt-shared-secret";
+
+/// 用 `secret` 签名的JWT，`alg` 只写入头部，签名始终为HS256
+fn jwt(alg: &str, claims: Value, secret: &str) -> String {
+    let header = URL_SAFE_NO_PAD.encode(json!({ "alg": alg, "typ": "JWT" }).to_string());
+    let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+    let signed = format!("{}.{}", header, claims);
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(hmac::sign(&key, signed.as_bytes())))
+}
+
+fn auth(protect_reads: bool) -> ApiAuth {
+    ApiAuth {
+        keys: vec![ApiKey::parse("read:reader").unwrap(), ApiKey::parse("admin:root").unwrap()],
+        jwt_secret: Some(SECRET.to_string()),
+        protect_reads,
+    }
+}
+
+/// 在随机端口上启动API服务器，返回其地址
+async fn start_server(auth: ApiAuth) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let pool = Pool::new_with_proxies(Vec::new(), PoolOptions::default()).handle();
+    let api = ApiServer::new(pool, Config::default(), ApiConfig {
+        auth,
+        exit_token: Some("exit-master".to_string()),
+        ..ApiConfig::default()
+    });
+    tokio::spawn(async move { api.run_with_listener(listener).await });
+    format!("http://{}", addr)
+}
+
+#[test]
+fn static_keys_map_to_their_scope() {
+    let auth = auth(false);
+    assert_eq!(auth.scope_of("reader"), Some(Scope::Read));
+    assert_eq!(auth.scope_of("root"), Some(Scope::Admin));
+    assert_eq!(auth.scope_of("roo"), None);
+    assert_eq!(auth.scope_of(""), None);
+    assert!(ApiKey::parse("owner:x").is_err());
+    assert!(ApiKey::parse("admin:").is_err());
+}
+
+#[test]
+fn jwt_requires_hs256_and_valid_signature() {
+    let auth = auth(false);
+    let claims = json!({ "scope": "admin" });
+    assert_eq!(auth.scope_of(&jwt("HS256", claims.clone(), SECRET)), Some(Scope::Admin));
+    assert_eq!(auth.scope_of(&jwt("HS512", claims.clone(), SECRET)), None);
+    assert_eq!(auth.scope_of(&jwt("none", claims.clone(), SECRET)), None);
+    assert_eq!(auth.scope_of(&jwt("HS256", claims.clone(), "other-secret")), None);
+
+    // 篡改声明后签名不再匹配
+    let token = jwt("HS256", json!({ "scope": "read" }), SECRET);
+    let mut parts: Vec<&str> = token.split('.').collect();
+    let forged = URL_SAFE_NO_PAD.encode(claims.to_string());
+    parts[1] = &forged;
+    assert_eq!(auth.scope_of(&parts.join(".")), None);
+}
+
+#[test]
+fn jwt_honours_exp_and_nbf() {
+    let auth = auth(false);
+    let now = wall_now().timestamp();
+    assert_eq!(auth.scope_of(&jwt("HS256", json!({ "scope": "admin", "exp": now - 10 }), SECRET)), None);
+    assert_eq!(auth.scope_of(&jwt("HS256", json!({ "scope": "admin", "nbf": now + 600 }), SECRET)), None);
+    assert_eq!(auth.scope_of(&jwt("HS256", json!({ "scope": "admin", "nbf": now - 10, "exp": now + 600 }), SECRET)), Some(Scope::Admin));
+}
+
+#[test]
+fn jwt_without_scope_is_read_only() {
+    let auth = auth(false);
+    assert_eq!(auth.scope_of(&jwt("HS256", json!({ "sub": "dashboard" }), SECRET)), Some(Scope::Read));
+}
+
+#[tokio::test]
+async fn writes_require_admin_scope() {
+    let base = start_server(auth(false)).await;
+    let client = reqwest::Client::new();
+    let add = |key: Option<&str>| {
+        let request = client.post(format!("{}/api/v1/proxies", base)).json(&json!({ "host": "10.0.0.1", "port": 1080 }));
+        match key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+        .send()
+    };
+
+    let response = add(None).await.unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    assert_eq!(add(Some("wrong")).await.unwrap().status(), 401);
+    assert_eq!(add(Some("reader")).await.unwrap().status(), 403);
+    assert!(add(Some("root")).await.unwrap().status().is_success());
+
+    // 未开启 protect_reads 时读取不需要凭据
+    assert_eq!(client.get(format!("{}/api/v1/stats", base)).send().await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn protect_reads_requires_any_valid_credential() {
+    let base = start_server(auth(true)).await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/v1/stats", base);
+
+    assert_eq!(client.get(&url).send().await.unwrap().status(), 401);
+    assert_eq!(client.get(&url).header("x-api-key", "reader").send().await.unwrap().status(), 200);
+    let token = jwt("HS256", json!({}), SECRET);
+    assert_eq!(client.get(&url).bearer_auth(token).send().await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn exits_and_pac_bypass_api_auth() {
+    let base = start_server(auth(true)).await;
+    let client = reqwest::Client::new();
+
+    // 出口节点接口使用出口令牌，不接受也不要求API密钥
+    let exits = client.get(format!("{}/api/v1/exits", base)).bearer_auth("exit-master").send().await.unwrap();
+    assert_eq!(exits.status(), 200);
+    let exits = client.get(format!("{}/api/v1/exits/tokens", base)).bearer_auth("root").send().await.unwrap();
+    assert_eq!(exits.status(), 401);
+    assert!(exits.headers().get("www-authenticate").is_none());
+
+    let pac = client.get(format!("{}/proxy.pac", base)).send().await.unwrap();
+    assert_ne!(pac.status(), 401);
+}
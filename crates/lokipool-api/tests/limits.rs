@@ -0,0 +1,126 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+use lokipool_api::limits::{RateLimiter, MAX_TRACKED_CLIENTS};
+use lokipool_api::{ApiConfig, ApiServer};
+use lokipool_core::{Config, Pool, PoolOptions};
+use tokio::net::TcpListener;
+
+const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+/// 在随机端口上启动API服务器，返回其地址
+async fn start_server(config: ApiConfig) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let pool = Pool::new_with_proxies(Vec::new(), PoolOptions::default()).handle();
+    let api = ApiServer::new(pool, Config::default(), config);
+    tokio::spawn(async move { api.run_with_listener(listener).await });
+    format!("http://{}", addr)
+}
+
+#[test]
+fn burst_is_available_immediately() {
+    let limiter = RateLimiter::default();
+    let now = Instant::now();
+    for _ in 0..5 {
+        assert!(limiter.take(CLIENT, 1, 5, now).is_ok());
+    }
+    assert!(limiter.take(CLIENT, 1, 5, now).is_err());
+    // 各客户端的令牌桶互不影响
+    assert!(limiter.take(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)), 1, 5, now).is_ok());
+}
+
+#[test]
+fn tokens_refill_at_rate_up_to_burst() {
+    let limiter = RateLimiter::default();
+    let start = Instant::now();
+    for _ in 0..2 {
+        limiter.take(CLIENT, 10, 2, start).unwrap();
+    }
+    assert!(limiter.take(CLIENT, 10, 2, start).is_err());
+
+    // 每秒10个令牌，100ms补充一个
+    let later = start + Duration::from_millis(100);
+    assert!(limiter.take(CLIENT, 10, 2, later).is_ok());
+    assert!(limiter.take(CLIENT, 10, 2, later).is_err());
+
+    // 空闲再久也只积攒 `burst` 个
+    let idle = later + Duration::from_secs(60);
+    assert!(limiter.take(CLIENT, 10, 2, idle).is_ok());
+    assert!(limiter.take(CLIENT, 10, 2, idle).is_ok());
+    assert!(limiter.take(CLIENT, 10, 2, idle).is_err());
+}
+
+#[test]
+fn wait_is_time_until_next_token() {
+    let limiter = RateLimiter::default();
+    let start = Instant::now();
+    limiter.take(CLIENT, 4, 1, start).unwrap();
+    assert_eq!(limiter.take(CLIENT, 4, 1, start), Err(Duration::from_millis(250)));
+
+    let wait = limiter.take(CLIENT, 4, 1, start + Duration::from_millis(100)).unwrap_err();
+    assert!((wait.as_secs_f64() - 0.15).abs() < 1e-6, "{:?}", wait);
+}
+
+#[test]
+fn full_buckets_are_evicted_when_tracking_too_many_clients() {
+    let limiter = RateLimiter::default();
+    let start = Instant::now();
+    for index in 0..MAX_TRACKED_CLIENTS as u32 {
+        limiter.take(IpAddr::V4(Ipv4Addr::from(0x0A00_0000 + index)), 1, 1, start).unwrap();
+    }
+    assert_eq!(limiter.tracked_clients(), MAX_TRACKED_CLIENTS);
+
+    // 令牌尚未补满的客户端不清理，否则会绕过限制
+    let soon = start + Duration::from_millis(500);
+    limiter.take(CLIENT, 1, 1, soon).unwrap();
+    assert_eq!(limiter.tracked_clients(), MAX_TRACKED_CLIENTS + 1);
+    assert!(limiter.take(IpAddr::V4(Ipv4Addr::from(0x0A00_0000)), 1, 1, soon).is_err());
+
+    // 补满后再有新客户端时全部清理
+    let later = start + Duration::from_secs(2);
+    limiter.take(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 3)), 1, 1, later).unwrap();
+    assert_eq!(limiter.tracked_clients(), 1);
+}
+
+#[tokio::test]
+async fn too_many_requests_get_429_with_retry_after() {
+    let base = start_server(ApiConfig { rate_limit: 1, rate_limit_burst: 2, ..ApiConfig::default() }).await;
+    let client = reqwest::Client::new();
+    for _ in 0..2 {
+        assert_eq!(client.get(&base).send().await.unwrap().status(), 200);
+    }
+    let limited = client.get(&base).send().await.unwrap();
+    assert_eq!(limited.status(), 429);
+    assert_eq!(limited.headers()["retry-after"], "1");
+}
+
+#[tokio::test]
+async fn zero_rate_limit_disables_limiting() {
+    let base = start_server(ApiConfig { rate_limit: 0, rate_limit_burst: 1, ..ApiConfig::default() }).await;
+    let client = reqwest::Client::new();
+    for _ in 0..5 {
+        assert_eq!(client.get(&base).send().await.unwrap().status(), 200);
+    }
+}
+
+#[tokio::test]
+async fn oversized_bodies_get_413() {
+    let base = start_server(ApiConfig { max_body_bytes: 1024, max_import_bytes: 8192, ..ApiConfig::default() }).await;
+    let client = reqwest::Client::new();
+
+    let add = client.post(format!("{}/api/v1/proxies", base))
+        .header("content-type", "application/json")
+        .body(format!(r#"{{"host": "10.0.0.1", "port": 1080, "location": "{}"}}"#, "x".repeat(2048)))
+        .send().await.unwrap();
+    assert_eq!(add.status(), 413);
+
+    // 批量导入使用单独的上限
+    let import = |lines: usize| client.post(format!("{}/api/v1/proxies/import", base))
+        .header("content-type", "text/plain")
+        .body("10.0.0.1:1080\n".repeat(lines))
+        .send();
+    let within = import(4096 / 14).await.unwrap();
+    assert!(within.status().is_success(), "{}", within.status());
+    assert_eq!(import(16384 / 14).await.unwrap().status(), 413);
+}
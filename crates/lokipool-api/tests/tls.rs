@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use lokipool_api::tls::ApiTls;
+use lokipool_api::{ApiConfig, ApiServer};
+use lokipool_core::{Config, Pool, PoolOptions};
+use tokio::net::TcpListener;
+
+/// 测试用的自签名证书，CN与SAN为localhost/127.0.0.1
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+fn localhost_tls() -> ApiTls {
+    ApiTls { cert_path: fixture("localhost.crt"), key_path: fixture("localhost.key") }
+}
+
+/// 在随机端口上启动HTTPS API服务器，只允许 `origins` 跨域访问，返回其地址
+async fn start_server(origins: &[&str]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let pool = Pool::new_with_proxies(Vec::new(), PoolOptions::default()).handle();
+    let api = ApiServer::new(pool, Config::default(), ApiConfig {
+        tls: Some(localhost_tls()),
+        enable_cors: true,
+        cors_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+        ..ApiConfig::default()
+    });
+    tokio::spawn(async move { api.run_with_listener(listener).await });
+    format!("https://{}", addr)
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder().danger_accept_invalid_certs(true).build().unwrap()
+}
+
+#[test]
+fn acceptor_reports_missing_or_invalid_files() {
+    assert!(localhost_tls().acceptor().is_ok());
+
+    let missing = ApiTls { cert_path: fixture("missing.crt"), key_path: fixture("localhost.key") };
+    assert!(missing.acceptor().err().unwrap().to_string().contains("missing.crt"));
+
+    // 证书文件中没有私钥
+    let no_key = ApiTls { cert_path: fixture("localhost.crt"), key_path: fixture("localhost.crt") };
+    assert!(no_key.acceptor().is_err());
+}
+
+#[tokio::test]
+async fn serves_over_tls_only() {
+    let base = start_server(&[]).await;
+    let response = client().get(&base).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "LokiPool API Server");
+
+    // 明文请求在TLS握手阶段被丢弃，服务器继续接受后续连接
+    let plain = base.replacen("https://", "http://", 1);
+    assert!(client().get(&plain).send().await.is_err());
+    assert_eq!(client().get(&base).send().await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn cors_allows_only_listed_origins() {
+    let base = start_server(&["https://dash.example"]).await;
+    let client = client();
+
+    let allowed = client.get(&base).header("origin", "https://dash.example").send().await.unwrap();
+    assert_eq!(allowed.headers()["access-control-allow-origin"], "https://dash.example");
+
+    let denied = client.get(&base).header("origin", "https://evil.example").send().await.unwrap();
+    assert!(denied.headers().get("access-control-allow-origin").is_none());
+
+    let preflight = client.request(reqwest::Method::OPTIONS, format!("{}/api/v1/proxies", base))
+        .header("origin", "https://dash.example")
+        .header("access-control-request-method", "POST")
+        .send().await.unwrap();
+    assert_eq!(preflight.headers()["access-control-allow-origin"], "https://dash.example");
+}
+
+#[tokio::test]
+async fn cors_allows_any_origin_without_list() {
+    let base = start_server(&[]).await;
+    let response = client().get(&base).header("origin", "https://anywhere.example").send().await.unwrap();
+    assert_eq!(response.headers()["access-control-allow-origin"], "*");
+}
@@ -0,0 +1,540 @@
+//! 非交互的子命令
+//!
+//! 每个命令读取 `--config` 指定的配置文件及其中的代理文件，执行完即退出；
+//! 给出 `--endpoint` 时 `list`、`test`、`next`、`stats`、`add`、`remove` 改为操作运行中的实例，见 `remote` 模块。
+//! `--json` 时标准输出只有一个JSON值，便于脚本处理；`list`、`test`、`stats` 还可以用 `--output csv` 输出CSV。
+//! 返回值表示命令是否成功，决定退出码。
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{anyhow, bail, Result};
+use colored::*;
+use lokipool_core::deprecation;
+use chrono::{DateTime, SecondsFormat, Utc};
+use lokipool_core::{write_atomic, Acl, Config, Pool, PoolMetrics, PoolOptions, PortPolicy, ProxyConfig, ProxyStatus};
+use serde::Serialize;
+use tracing::info;
+
+use crate::output::{emit, write_stdout, Cell, OutputFormat, Table, Tone};
+use crate::remote::{address_id, Remote, RemoteProxy};
+
+/// 各子命令共用的选项
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// 配置文件路径
+    pub config: PathBuf,
+    /// 输出格式
+    pub output: OutputFormat,
+    /// 表格是否使用颜色
+    pub colored: bool,
+    /// 操作的运行中实例，为None时使用本地配置
+    pub remote: Option<Remote>,
+}
+
+impl Options {
+    fn json(&self) -> bool {
+        self.output == OutputFormat::Json
+    }
+}
+
+/// 代理的来源
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Origin {
+    /// 配置文件的 `proxies`
+    Config,
+    /// `proxy.proxy_file` 指定的代理文件
+    File,
+}
+
+impl Origin {
+    fn label(self) -> &'static str {
+        match self {
+            Origin::Config => "config",
+            Origin::File => "file",
+        }
+    }
+}
+
+/// `list` 输出的一行
+#[derive(Debug, Serialize)]
+struct ProxyRow {
+    /// `host:port`，`remove` 以此指定代理
+    id: String,
+    #[serde(rename = "type")]
+    proxy_type: String,
+    location: Option<String>,
+    origin: Origin,
+}
+
+/// `test` 输出的一行
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestRow {
+    pub id: String,
+    pub success: bool,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// `next` 的结果，本地模式没有租约
+#[derive(Debug, Serialize)]
+struct NextRow {
+    id: String,
+    /// 含认证信息的代理地址
+    url: String,
+    location: Option<String>,
+    latency_ms: Option<u64>,
+    /// 远程模式下的租约ID
+    lease: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// `config validate` 的结果
+#[derive(Debug, Serialize)]
+struct Validation {
+    path: String,
+    valid: bool,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// 代理在命令行中的标识：`host:port`，IPv6地址加方括号
+pub fn proxy_id(proxy: &ProxyConfig) -> String {
+    match proxy.host.contains(':') {
+        true => format!("[{}]:{}", proxy.host, proxy.port),
+        false => format!("{}:{}", proxy.host, proxy.port),
+    }
+}
+
+/// 读取配置文件，文件不存在时使用默认配置
+pub fn load_config(path: &Path) -> Result<Config> {
+    if !path.exists() {
+        info!("{} 不存在，使用默认配置", path.display());
+        return Ok(Config::default());
+    }
+    Config::from_file(path).map_err(|e| anyhow!("加载配置 {} 失败: {}", path.display(), e))
+}
+
+/// 由配置文件与代理文件中的代理组成代理池
+pub async fn load_pool(config: &Config) -> Result<Pool> {
+    let pool = Pool::new_with_proxies(config.proxies.clone(), PoolOptions::from_config(config));
+    let proxy_file = Path::new(&config.proxy.proxy_file);
+    pool.sync_file(proxy_file).await
+        .map_err(|e| anyhow!("读取代理文件 {} 失败: {}", proxy_file.display(), e))?;
+    Ok(pool)
+}
+
+/// 代理文件中的代理，文件不存在时为空
+fn file_proxies(config: &Config) -> Result<Vec<ProxyConfig>> {
+    let proxy_file = Path::new(&config.proxy.proxy_file);
+    if !proxy_file.exists() {
+        return Ok(Vec::new());
+    }
+    ProxyConfig::load_list(proxy_file).map_err(|e| anyhow!("读取代理文件 {} 失败: {}", proxy_file.display(), e))
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    write_stdout(&format!("{}\n", serde_json::to_string_pretty(value)?))
+}
+
+/// 测试代理后持续健康检查并监视代理文件，直到收到Ctrl-C
+pub async fn run(options: &Options) -> Result<bool> {
+    let config = load_config(&options.config)?;
+    let pool = load_pool(&config).await?;
+    let results = pool.test_all().await;
+    let available = results.iter().filter(|(_, result)| result.success).count();
+    println!("{} 个代理中 {} 个可用，每 {} 秒重新测试，按Ctrl-C退出",
+        results.len(), available.to_string().green().bold(), config.proxy.health_check_interval);
+
+    // 两个后台任务都在代理池关闭时结束
+    pool.start_auto_test();
+    if config.proxy.proxy_file_watch_interval > 0 {
+        pool.watch_file(PathBuf::from(&config.proxy.proxy_file), Duration::from_secs(config.proxy.proxy_file_watch_interval));
+    }
+    tokio::signal::ctrl_c().await?;
+
+    let report = pool.shutdown(Duration::from_secs(config.proxy.test_timeout)).await?;
+    if let Some(path) = report.snapshot_file {
+        println!("代理池状态已保存到 {}", path.display());
+    }
+    Ok(true)
+}
+
+/// 测试所有代理，至少一个可用时成功
+pub async fn test(options: &Options) -> Result<bool> {
+    let rows = test_rows(options).await?;
+    let succeeded = rows.iter().filter(|row| row.success).count();
+
+    emit(options.output, options.colored, &rows, || {
+        let mut table = Table::new(&[("id", "代理"), ("success", "结果"), ("latency_ms", "延迟(ms)"), ("error", "错误")]);
+        for row in &rows {
+            table.row(vec![
+                Cell::new(&row.id),
+                match row.success {
+                    true => Cell::new("ok").tone(Tone::Good),
+                    false => Cell::new("failed").tone(Tone::Bad),
+                },
+                Cell::new(row.latency_ms.map(|latency| latency.to_string()).unwrap_or_default()),
+                Cell::new(row.error.as_deref().unwrap_or_default()).tone(Tone::Dim),
+            ]);
+        }
+        table
+    })?;
+    if options.output == OutputFormat::Table {
+        println!("共 {} 个代理: {} 个可用, {} 个不可用", rows.len(), succeeded, rows.len() - succeeded);
+    }
+    Ok(succeeded > 0)
+}
+
+/// 测试所有代理并返回各代理的结果，本地模式每次重新读取配置与代理文件
+pub async fn test_rows(options: &Options) -> Result<Vec<TestRow>> {
+    Ok(match &options.remote {
+        Some(remote) => remote.test_all().await?
+            .into_iter()
+            .map(|result| TestRow {
+                id: address_id(&result.address),
+                success: result.success,
+                latency_ms: result.latency,
+                error: result.error,
+            })
+            .collect(),
+        None => {
+            let config = load_config(&options.config)?;
+            let pool = load_pool(&config).await?;
+            pool.test_all().await
+                .into_iter()
+                .map(|(proxy, result)| TestRow {
+                    id: proxy_id(&proxy),
+                    success: result.success,
+                    latency_ms: result.latency,
+                    error: result.error,
+                })
+                .collect()
+        }
+    })
+}
+
+/// 列出配置文件与代理文件中的代理，不做测试；远程模式下列出实例中的代理及其状态
+pub async fn list(options: &Options) -> Result<bool> {
+    if let Some(remote) = &options.remote {
+        return list_remote(options, remote).await;
+    }
+    let config = load_config(&options.config)?;
+    let mut rows: Vec<ProxyRow> = Vec::new();
+    let sources = [(Origin::Config, config.proxies.clone()), (Origin::File, file_proxies(&config)?)];
+    for (origin, proxies) in sources {
+        for proxy in proxies {
+            let id = proxy_id(&proxy);
+            if rows.iter().any(|row| row.id == id) {
+                continue;
+            }
+            rows.push(ProxyRow { id, proxy_type: proxy.proxy_type, location: proxy.location, origin });
+        }
+    }
+
+    emit(options.output, options.colored, &rows, || {
+        let mut table = Table::new(&[("id", "代理"), ("type", "类型"), ("location", "位置"), ("origin", "来源")]);
+        for row in &rows {
+            table.row(vec![
+                Cell::new(&row.id).tone(Tone::Strong),
+                Cell::new(&row.proxy_type),
+                Cell::new(row.location.as_deref().unwrap_or_default()),
+                Cell::new(row.origin.label()).tone(Tone::Dim),
+            ]);
+        }
+        table
+    })?;
+    Ok(true)
+}
+
+async fn list_remote(options: &Options, remote: &Remote) -> Result<bool> {
+    let proxies = remote.proxies().await?;
+    emit(options.output, options.colored, &proxies, || {
+        let mut table = Table::new(&[
+            ("id", "代理"), ("type", "类型"), ("location", "位置"), ("status", "状态"), ("latency_ms", "延迟(ms)"), ("success_rate", "成功率"),
+        ]);
+        for proxy in &proxies {
+            table.row(vec![
+                Cell::new(address_id(&proxy.address)).tone(Tone::Strong),
+                Cell::new(&proxy.proxy_type),
+                Cell::new(proxy.location.as_deref().unwrap_or_default()),
+                status_cell(proxy),
+                Cell::new(proxy.latency.map(|latency| latency.to_string()).unwrap_or_default()),
+                Cell::new(format!("{:.2}", proxy.success_rate)),
+            ]);
+        }
+        table
+    })?;
+    Ok(true)
+}
+
+fn status_cell(proxy: &RemoteProxy) -> Cell {
+    let tone = match proxy.status {
+        ProxyStatus::Available | ProxyStatus::InUse => Tone::Good,
+        ProxyStatus::Failed => Tone::Bad,
+        ProxyStatus::Quarantined => Tone::Warn,
+        ProxyStatus::Untested | ProxyStatus::Unknown => Tone::Dim,
+    };
+    Cell::new(proxy.status).tone(tone)
+}
+
+/// 测试所有代理后给出选择策略选中的可用代理；远程模式下向实例借出一个代理，租约到期后自动归还
+pub async fn next(options: &Options) -> Result<bool> {
+    let row = match &options.remote {
+        Some(remote) => {
+            let lease = remote.lease().await?;
+            NextRow {
+                id: ProxyConfig::parse(&lease.url).map(|proxy| proxy_id(&proxy)).unwrap_or_else(|_| lease.proxy_id.clone()),
+                url: lease.url,
+                location: lease.location,
+                latency_ms: None,
+                lease: Some(lease.id),
+                expires_at: Some(lease.expires_at),
+            }
+        }
+        None => {
+            let config = load_config(&options.config)?;
+            let pool = load_pool(&config).await?;
+            pool.test_all().await;
+            let Some(proxy) = pool.get_available().await else {
+                bail!("没有可用代理");
+            };
+            let proxy_config = proxy.to_config();
+            NextRow {
+                id: proxy_id(&proxy_config),
+                url: proxy_config.to_address(),
+                location: proxy_config.location,
+                latency_ms: proxy.info.last_latency,
+                lease: None,
+                expires_at: None,
+            }
+        }
+    };
+
+    emit(options.output, options.colored, &row, || {
+        let mut table = Table::new(&[("id", "代理"), ("url", "地址"), ("latency_ms", "延迟(ms)"), ("lease", "租约"), ("expires_at", "到期时间")]);
+        table.row(vec![
+            Cell::new(&row.id).tone(Tone::Strong),
+            Cell::new(&row.url),
+            Cell::new(row.latency_ms.map(|latency| latency.to_string()).unwrap_or_default()),
+            Cell::new(row.lease.as_deref().unwrap_or_default()).tone(Tone::Dim),
+            Cell::new(row.expires_at.map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true)).unwrap_or_default()).tone(Tone::Dim),
+        ]);
+        table
+    })?;
+    Ok(true)
+}
+
+/// 测试所有代理后输出代理池指标；远程模式下直接取实例的指标，不触发测试
+pub async fn stats(options: &Options) -> Result<bool> {
+    let metrics = match &options.remote {
+        Some(remote) => remote.metrics().await?,
+        None => {
+            let config = load_config(&options.config)?;
+            let pool = load_pool(&config).await?;
+            pool.test_all().await;
+            pool.metrics().await
+        }
+    };
+    emit(options.output, options.colored, &metrics, || metrics_table(&metrics))?;
+    Ok(true)
+}
+
+/// 每项指标一行，延迟为空时留空
+fn metrics_table(metrics: &PoolMetrics) -> Table {
+    let status = &metrics.status;
+    let latency = &metrics.latency;
+    let millis = |value: Option<u64>| value.map(|value| value.to_string()).unwrap_or_default();
+    let rows = [
+        ("total", metrics.total.to_string(), Tone::Strong),
+        ("available", status.available.to_string(), Tone::Good),
+        ("in_use", status.in_use.to_string(), Tone::Plain),
+        ("failed", status.failed.to_string(), Tone::Bad),
+        ("untested", status.untested.to_string(), Tone::Dim),
+        ("quarantined", status.quarantined.to_string(), Tone::Warn),
+        ("blacklisted", status.blacklisted.to_string(), Tone::Warn),
+        ("latency_mean_ms", latency.mean.map(|mean| format!("{:.1}", mean)).unwrap_or_default(), Tone::Plain),
+        ("latency_p50_ms", millis(latency.p50), Tone::Plain),
+        ("latency_p90_ms", millis(latency.p90), Tone::Plain),
+        ("latency_p99_ms", millis(latency.p99), Tone::Plain),
+    ];
+    let mut table = Table::new(&[("metric", "指标"), ("value", "数值")]);
+    for (metric, value, tone) in rows {
+        table.row(vec![Cell::new(metric), Cell::new(value).tone(tone)]);
+    }
+    table
+}
+
+/// 测试并把代理追加到代理文件，`skip_test` 时不测试直接添加
+pub async fn add(options: &Options, address: &str, skip_test: bool) -> Result<bool> {
+    if let Some(remote) = &options.remote {
+        return add_remote(options, remote, address, skip_test).await;
+    }
+    let config = load_config(&options.config)?;
+    let proxy = ProxyConfig::parse(address)?;
+    let id = proxy_id(&proxy);
+    let existing = file_proxies(&config)?;
+    if config.proxies.iter().chain(&existing).any(|known| known.canonical_key() == proxy.canonical_key()) {
+        bail!("代理 {} 已存在", id);
+    }
+
+    let latency = if skip_test {
+        None
+    } else {
+        let pool = Pool::new_with_proxies(vec![proxy.clone()], PoolOptions::from_config(&config));
+        match pool.test_all().await.pop() {
+            Some((_, result)) if result.success => result.latency,
+            Some((_, result)) => bail!("代理 {} 测试失败，未添加（可使用 --no-test 跳过测试）: {}", id, result.error.unwrap_or_default()),
+            None => bail!("代理 {} 未能测试", id),
+        }
+    };
+
+    let proxy_file = Path::new(&config.proxy.proxy_file);
+    let mut content = if proxy_file.exists() { std::fs::read_to_string(proxy_file)? } else { String::new() };
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&proxy.to_address());
+    content.push('\n');
+    write_atomic(proxy_file, content.as_bytes())?;
+
+    if options.json() {
+        print_json(&serde_json::json!({ "id": id, "latency_ms": latency, "file": proxy_file.display().to_string() }))?;
+    } else {
+        match latency {
+            Some(latency) => println!("{} 已添加 {} - {}ms", "✓".green().bold(), id, latency),
+            None => println!("{} 已添加 {}（未测试）", "✓".green().bold(), id),
+        }
+    }
+    Ok(true)
+}
+
+/// 加入实例后由实例测试，测试失败时再移除
+async fn add_remote(options: &Options, remote: &Remote, address: &str, skip_test: bool) -> Result<bool> {
+    let proxy = ProxyConfig::parse(address)?;
+    let id = proxy_id(&proxy);
+    let Some(added) = remote.add(&proxy).await? else {
+        bail!("代理 {} 已存在", id);
+    };
+
+    let latency = if skip_test {
+        None
+    } else {
+        match remote.test(&added.id).await? {
+            Some(result) if result.success => result.latency,
+            result => {
+                remote.remove(&added.id).await?;
+                let error = result.and_then(|result| result.error).unwrap_or_default();
+                bail!("代理 {} 测试失败，未添加（可使用 --no-test 跳过测试）: {}", id, error);
+            }
+        }
+    };
+
+    if options.json() {
+        print_json(&serde_json::json!({ "id": id, "latency_ms": latency, "endpoint": remote.endpoint() }))?;
+    } else {
+        match latency {
+            Some(latency) => println!("{} 已添加 {} 到 {} - {}ms", "✓".green().bold(), id, remote.endpoint(), latency),
+            None => println!("{} 已添加 {} 到 {}（未测试）", "✓".green().bold(), id, remote.endpoint()),
+        }
+    }
+    Ok(true)
+}
+
+/// 从代理文件中移除代理，配置文件中定义的代理需要编辑配置文件；远程模式下从实例中移除
+pub async fn remove(options: &Options, id: &str) -> Result<bool> {
+    let target = proxy_id(&ProxyConfig::parse(id)?);
+    if let Some(remote) = &options.remote {
+        let proxies = remote.proxies().await?;
+        let Some(proxy) = proxies.iter().find(|proxy| address_id(&proxy.address) == target) else {
+            bail!("{} 中没有代理 {}", remote.endpoint(), target);
+        };
+        remote.remove(&proxy.id).await?;
+        if options.json() {
+            print_json(&serde_json::json!({ "id": target, "removed": 1 }))?;
+        } else {
+            println!("{} 已从 {} 移除 {}", "✓".green().bold(), remote.endpoint(), target);
+        }
+        return Ok(true);
+    }
+
+    let config = load_config(&options.config)?;
+    if config.proxies.iter().any(|proxy| proxy_id(proxy) == target) {
+        bail!("代理 {} 定义在配置文件 {} 中，请直接编辑配置文件", target, options.config.display());
+    }
+
+    let proxy_file = Path::new(&config.proxy.proxy_file);
+    let content = if proxy_file.exists() { std::fs::read_to_string(proxy_file)? } else { String::new() };
+    let mut removed = 0;
+    let kept: Vec<&str> = content.lines()
+        .filter(|line| {
+            let matches = ProxyConfig::parse(line).is_ok_and(|proxy| proxy_id(&proxy) == target);
+            removed += matches as usize;
+            !matches
+        })
+        .collect();
+    if removed == 0 {
+        bail!("代理文件 {} 中没有代理 {}", proxy_file.display(), target);
+    }
+    let mut content = kept.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    write_atomic(proxy_file, content.as_bytes())?;
+
+    if options.json() {
+        print_json(&serde_json::json!({ "id": target, "removed": removed }))?;
+    } else {
+        println!("{} 已从 {} 移除 {}", "✓".green().bold(), proxy_file.display(), target);
+    }
+    Ok(true)
+}
+
+/// 严格检查配置文件能否解析、代理文件能否读取、访问控制与端口策略是否有效
+pub async fn validate_config(options: &Options) -> Result<bool> {
+    let path = options.config.as_path();
+    let mut validation = Validation { path: path.display().to_string(), valid: true, errors: Vec::new(), warnings: Vec::new() };
+
+    // 不使用 `Config::from_file` 的宽松解析，格式错误的配置项在这里报告为错误
+    let parsed = std::fs::read_to_string(path)
+        .map_err(|e| format!("无法读取 {}: {}", path.display(), e))
+        .and_then(|content| content.parse::<toml::Table>().map_err(|e| format!("TOML语法错误: {}", e)))
+        .and_then(|mut table| {
+            let deprecated = deprecation::migrate(&mut table);
+            if !deprecated.is_empty() {
+                let keys: Vec<&str> = deprecated.iter().map(|deprecated| deprecated.key).collect();
+                validation.warnings.push(format!("废弃的配置项: {}（启动时会自动迁移）", keys.join(", ")));
+            }
+            table.try_into::<Config>().map_err(|e| format!("配置格式错误: {}", e))
+        });
+    match parsed {
+        Ok(config) => {
+            if let Err(e) = file_proxies(&config) {
+                validation.errors.push(e.to_string());
+            }
+            if let Err(e) = Acl::from_settings(&config.socks_server.acl) {
+                validation.errors.push(format!("访问控制: {}", e));
+            }
+            if let Err(e) = PortPolicy::from_settings(&config.socks_server.port_policy) {
+                validation.errors.push(format!("端口策略: {}", e));
+            }
+        }
+        Err(e) => validation.errors.push(e),
+    }
+    validation.valid = validation.errors.is_empty();
+
+    if options.json() {
+        print_json(&validation)?;
+    } else {
+        for warning in &validation.warnings {
+            println!("{} {}", "!".yellow().bold(), warning);
+        }
+        for error in &validation.errors {
+            println!("{} {}", "✗".red().bold(), error);
+        }
+        if validation.valid {
+            println!("{} {} 有效", "✓".green().bold(), validation.path);
+        }
+    }
+    Ok(validation.valid)
+}
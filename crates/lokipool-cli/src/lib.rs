@@ -2,7 +2,13 @@
 //! 
 //! This library provides the CLI functionality for managing LokiPool.
 
-/// CLI命令枚举
+pub mod commands;
+pub mod output;
+pub mod remote;
+pub mod shell;
+pub mod watch;
+
+/// 交互模式的命令
 #[derive(Debug, Clone)]
 pub enum Command {
     /// 显示所有代理
@@ -17,6 +23,20 @@ pub enum Command {
     Quit,
 }
 
+impl Command {
+    /// 解析一行输入，不是已知命令时返回None
+    pub fn parse(line: &str) -> Option<Self> {
+        match line.trim() {
+            "list" | "ls" => Some(Self::List),
+            "show" => Some(Self::Show),
+            "next" => Some(Self::Next),
+            "test" => Some(Self::Test),
+            "quit" | "exit" => Some(Self::Quit),
+            _ => None,
+        }
+    }
+}
+
 /// CLI配置
 #[derive(Debug, Clone)]
 pub struct CliConfig {
@@ -0,0 +1,149 @@
+//! 命令结果的输出格式
+//!
+//! `table` 按显示宽度对齐各列，彩色只用于表格且遵循 `CliConfig.colored_output`；
+//! `csv` 以各列的键作为表头，不含颜色与单位，可直接导入表格软件；`json` 输出命令自己的结果结构，便于用jq处理。
+
+use std::fmt::Write as _;
+use std::io::{ErrorKind, Write as _};
+use anyhow::Result;
+use clap::ValueEnum;
+use colored::*;
+use serde::Serialize;
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// 对齐的表格
+    #[default]
+    Table,
+    /// JSON
+    Json,
+    /// 带表头的CSV
+    Csv,
+}
+
+/// 单元格的颜色
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tone {
+    #[default]
+    Plain,
+    Good,
+    Bad,
+    Warn,
+    Dim,
+    Strong,
+}
+
+/// 一个单元格
+#[derive(Debug, Clone, Default)]
+pub struct Cell {
+    text: String,
+    tone: Tone,
+}
+
+impl Cell {
+    pub fn new(text: impl ToString) -> Self {
+        Self { text: text.to_string(), tone: Tone::Plain }
+    }
+
+    pub fn tone(mut self, tone: Tone) -> Self {
+        self.tone = tone;
+        self
+    }
+
+    fn paint(&self, padded: String) -> String {
+        match self.tone {
+            Tone::Plain => padded,
+            Tone::Good => padded.green().to_string(),
+            Tone::Bad => padded.red().to_string(),
+            Tone::Warn => padded.yellow().to_string(),
+            Tone::Dim => padded.dimmed().to_string(),
+            Tone::Strong => padded.bold().to_string(),
+        }
+    }
+}
+
+/// 表格：每列有CSV中使用的键与表格中显示的标题
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    columns: Vec<(&'static str, &'static str)>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    /// `columns` 为 (键, 标题)
+    pub fn new(columns: &[(&'static str, &'static str)]) -> Self {
+        Self { columns: columns.to_vec(), rows: Vec::new() }
+    }
+
+    pub fn row(&mut self, cells: Vec<Cell>) {
+        debug_assert_eq!(cells.len(), self.columns.len());
+        self.rows.push(cells);
+    }
+
+    /// 按显示宽度对齐的表格，`colored` 为false时不含颜色
+    pub fn render(&self, colored: bool) -> String {
+        let mut widths: Vec<usize> = self.columns.iter().map(|(_, title)| console::measure_text_width(title)).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(console::measure_text_width(&cell.text));
+            }
+        }
+
+        let mut output = String::new();
+        let header: Vec<String> = self.columns.iter().zip(&widths)
+            .map(|((_, title), width)| {
+                let padded = console::pad_str(title, *width, console::Alignment::Left, None).into_owned();
+                if colored { padded.bold().to_string() } else { padded }
+            })
+            .collect();
+        let _ = writeln!(output, "{}", header.join("  ").trim_end());
+        for row in &self.rows {
+            let cells: Vec<String> = row.iter().zip(&widths)
+                .map(|(cell, width)| {
+                    let padded = console::pad_str(&cell.text, *width, console::Alignment::Left, None).into_owned();
+                    if colored { cell.paint(padded) } else { padded }
+                })
+                .collect();
+            let _ = writeln!(output, "{}", cells.join("  ").trim_end());
+        }
+        output
+    }
+
+    /// 以键为表头的CSV
+    pub fn to_csv(&self) -> String {
+        let mut output = String::new();
+        let keys: Vec<String> = self.columns.iter().map(|(key, _)| csv_field(key)).collect();
+        let _ = writeln!(output, "{}", keys.join(","));
+        for row in &self.rows {
+            let cells: Vec<String> = row.iter().map(|cell| csv_field(&cell.text)).collect();
+            let _ = writeln!(output, "{}", cells.join(","));
+        }
+        output
+    }
+}
+
+/// 含逗号、引号或换行的字段加引号，其中的引号加倍
+fn csv_field(text: &str) -> String {
+    match text.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
+
+/// 写到标准输出，读取端提前关闭（如管道到 `head`）时不报错
+pub fn write_stdout(text: &str) -> Result<()> {
+    match std::io::stdout().lock().write_all(text.as_bytes()) {
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// 按格式输出结果：`json` 序列化 `value`，其余格式由 `table` 生成
+pub fn emit<T: Serialize>(format: OutputFormat, colored: bool, value: &T, table: impl FnOnce() -> Table) -> Result<()> {
+    match format {
+        OutputFormat::Json => write_stdout(&format!("{}\n", serde_json::to_string_pretty(value)?)),
+        OutputFormat::Csv => write_stdout(&table().to_csv()),
+        OutputFormat::Table => write_stdout(&table().render(colored)),
+    }
+}
@@ -0,0 +1,181 @@
+//! 通过HTTP API操作运行中的实例
+//!
+//! 给出 `--endpoint` 时，`list`、`test`、`next`、`stats`、`add`、`remove` 不读取本地配置、不建立自己的代理池，
+//! 而是调用运行中LokiPool的REST API，同一个实例可以在其他机器上管理。`--api-key` 以 `x-api-key` 头发送。
+
+use std::time::Duration;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use lokipool_core::{PoolMetrics, ProxyConfig, ProxyStatus};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// 单个请求的超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 轮询测试任务的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 运行中实例的API
+#[derive(Debug, Clone)]
+pub struct Remote {
+    endpoint: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+/// 实例中的一个代理
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteProxy {
+    /// 实例内的代理ID，每次启动都会变化
+    pub id: String,
+    /// `host:port`
+    pub address: String,
+    pub proxy_type: String,
+    pub location: Option<String>,
+    pub status: ProxyStatus,
+    /// 最近一次测得的延迟（毫秒）
+    pub latency: Option<u64>,
+    pub success_rate: f64,
+}
+
+/// 把API给出的 `host:port` 转为与本地命令相同的标识，IPv6地址加方括号
+pub fn address_id(address: &str) -> String {
+    match address.rsplit_once(':') {
+        Some((host, port)) if host.contains(':') && !host.starts_with('[') => format!("[{}]:{}", host, port),
+        _ => address.to_string(),
+    }
+}
+
+/// 测试任务中一个代理的结果
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobResult {
+    pub proxy_id: String,
+    /// `host:port`
+    pub address: String,
+    pub success: bool,
+    /// 延迟（毫秒）
+    pub latency: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// 借出的代理
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaseGrant {
+    /// 租约ID，`DELETE /api/v1/lease/:id` 归还
+    pub id: String,
+    pub proxy_id: String,
+    /// 含认证信息的代理URL
+    pub url: String,
+    pub location: Option<String>,
+    /// 租约时长（秒）
+    pub ttl: u64,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct ProxyPage {
+    proxies: Vec<RemoteProxy>,
+}
+
+#[derive(Deserialize)]
+struct Job {
+    id: String,
+    state: String,
+    results: Vec<JobResult>,
+}
+
+#[derive(Deserialize)]
+struct Stats {
+    metrics: PoolMetrics,
+}
+
+impl Remote {
+    /// `endpoint` 为API的根地址，如 `http://127.0.0.1:3000`
+    pub fn new(endpoint: &str, api_key: Option<String>) -> Result<Self> {
+        if !(endpoint.starts_with("http://") || endpoint.starts_with("https://")) {
+            bail!("无效的API地址: {}（应以 http:// 或 https:// 开头）", endpoint);
+        }
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        Ok(Self { endpoint: endpoint.trim_end_matches('/').to_string(), api_key, client })
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// 发送请求，应答不是2xx时返回带应答内容的错误
+    async fn send<B: Serialize>(&self, method: Method, path: &str, body: Option<&B>) -> Result<(StatusCode, String)> {
+        let url = format!("{}{}", self.endpoint, path);
+        let mut request = self.client.request(method, &url);
+        if let Some(key) = &self.api_key {
+            request = request.header("x-api-key", key);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send().await.map_err(|e| anyhow!("连接 {} 失败: {}", self.endpoint, e))?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            bail!("请求 {} 失败 ({}): {}", url, status, text.trim());
+        }
+        Ok((status, text))
+    }
+
+    async fn request<T: DeserializeOwned>(&self, method: Method, path: &str) -> Result<T> {
+        let (_, body) = self.send(method, path, None::<&()>).await?;
+        serde_json::from_str(&body).map_err(|e| anyhow!("无法解析 {} 的应答: {}", path, e))
+    }
+
+    /// 实例中的全部代理，按地址排序
+    pub async fn proxies(&self) -> Result<Vec<RemoteProxy>> {
+        Ok(self.request::<ProxyPage>(Method::GET, "/api/v1/proxies").await?.proxies)
+    }
+
+    /// 代理池指标
+    pub async fn metrics(&self) -> Result<PoolMetrics> {
+        Ok(self.request::<Stats>(Method::GET, "/api/v1/stats").await?.metrics)
+    }
+
+    /// 让实例测试全部代理并等待结果
+    pub async fn test_all(&self) -> Result<Vec<JobResult>> {
+        let job = self.request(Method::POST, "/api/v1/proxies/test-all").await?;
+        self.wait(job).await
+    }
+
+    /// 让实例测试一个代理并等待结果
+    pub async fn test(&self, id: &str) -> Result<Option<JobResult>> {
+        let job = self.request(Method::POST, &format!("/api/v1/proxies/{}/test", id)).await?;
+        Ok(self.wait(job).await?.pop())
+    }
+
+    async fn wait(&self, mut job: Job) -> Result<Vec<JobResult>> {
+        while job.state == "running" {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            job = self.request(Method::GET, &format!("/api/v1/jobs/{}", job.id)).await?;
+        }
+        Ok(job.results)
+    }
+
+    /// 借出一个可用代理，到期后由实例自动收回
+    pub async fn lease(&self) -> Result<LeaseGrant> {
+        self.request(Method::POST, "/api/v1/lease").await
+    }
+
+    /// 添加代理，实例中已有时返回None
+    pub async fn add(&self, proxy: &ProxyConfig) -> Result<Option<RemoteProxy>> {
+        let (status, body) = self.send(Method::POST, "/api/v1/proxies", Some(proxy)).await?;
+        if status != StatusCode::CREATED {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&body)?))
+    }
+
+    /// 按实例内的代理ID移除代理
+    pub async fn remove(&self, id: &str) -> Result<()> {
+        self.send(Method::DELETE, &format!("/api/v1/proxies/{}", id), None::<&()>).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+//! 交互模式
+//!
+//! 启动时测试全部代理，之后逐行读取 `list`、`show`、`next`、`test`、`quit` 命令，
+//! 输入结束（Ctrl-D）与 `quit` 一样保存状态后退出。
+
+use std::io::Write;
+use std::time::Duration;
+use anyhow::Result;
+use colored::*;
+use lokipool_core::{Pool, Proxy, ProxyStatus};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::commands::{load_config, load_pool, proxy_id, Options};
+use crate::output::{Cell, Table, Tone};
+use crate::{CliConfig, Command};
+
+const BANNER: &str = r#"
+╦  ╔═╗╦╔═╦╔═╗╔═╗╔═╗╦  
+║  ║ ║╠╩╗║╠═╝║ ║║ ║║  
+╩═╝╚═╝╩ ╩╩╩  ╚═╝╚═╝╩═╝
+"#;
+
+/// 运行交互模式，直到 `quit` 或输入结束
+pub async fn run(options: &Options, cli: &CliConfig) -> Result<bool> {
+    if cli.show_banner {
+        println!("{} v{}", BANNER, env!("CARGO_PKG_VERSION"));
+    }
+    let config = load_config(&options.config)?;
+    let pool = load_pool(&config).await?;
+    test_all(&pool).await;
+    let mut current = pool.get_available().await;
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("{} ", ">".cyan().bold());
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next_line().await? else {
+            println!();
+            break;
+        };
+        match Command::parse(&line) {
+            Some(Command::List) => list(&pool, current.as_ref(), cli.colored_output).await,
+            Some(Command::Show) => match &current {
+                Some(proxy) => println!("当前代理: {}", describe(proxy)),
+                None => println!("{}", "没有可用代理".yellow()),
+            },
+            Some(Command::Next) => {
+                current = pool.get_available().await;
+                match &current {
+                    Some(proxy) => println!("切换到: {}", describe(proxy)),
+                    None => println!("{}", "没有可用代理".yellow()),
+                }
+            }
+            Some(Command::Test) => {
+                test_all(&pool).await;
+                current = pool.get_available().await;
+            }
+            Some(Command::Quit) => break,
+            None if line.trim().is_empty() => {}
+            None => println!("未知命令: {}（可用: list, show, next, test, quit）", line.trim()),
+        }
+    }
+
+    pool.shutdown(Duration::from_secs(config.proxy.test_timeout)).await?;
+    Ok(true)
+}
+
+fn describe(proxy: &Proxy) -> String {
+    match proxy.info.last_latency {
+        Some(latency) => format!("{} - {}ms", proxy_id(&proxy.to_config()), latency),
+        None => proxy_id(&proxy.to_config()),
+    }
+}
+
+/// 测试所有代理并输出结果
+async fn test_all(pool: &Pool) {
+    let results = pool.test_all().await;
+    for (proxy, result) in &results {
+        match result.success {
+            true => println!("{} {} - {}ms", "✓".green().bold(), proxy_id(proxy), result.latency.unwrap_or(0)),
+            false => println!("{} {} - {}", "✗".red().bold(), proxy_id(proxy), result.error.as_deref().unwrap_or("未知错误")),
+        }
+    }
+    let available = results.iter().filter(|(_, result)| result.success).count();
+    println!("共 {} 个代理: {} 个可用", results.len(), available);
+}
+
+/// 按延迟列出可用代理，标出当前代理
+async fn list(pool: &Pool, current: Option<&Proxy>, colored: bool) {
+    let mut proxies: Vec<Proxy> = pool.get_all_proxies().await
+        .into_iter()
+        .filter(|proxy| proxy.status == ProxyStatus::Available)
+        .collect();
+    if proxies.is_empty() {
+        println!("{}", "没有可用代理".yellow());
+        return;
+    }
+    proxies.sort_by_key(|proxy| proxy.info.last_latency.unwrap_or(u64::MAX));
+    let mut table = Table::new(&[("current", ""), ("rank", "#"), ("address", "代理"), ("latency_ms", "延迟(ms)")]);
+    for (index, proxy) in proxies.iter().enumerate() {
+        let is_current = current.is_some_and(|current| current.id == proxy.id);
+        table.row(vec![
+            Cell::new(if is_current { "*" } else { "" }).tone(Tone::Good),
+            Cell::new(index + 1),
+            Cell::new(proxy_id(&proxy.to_config())).tone(if is_current { Tone::Strong } else { Tone::Plain }),
+            Cell::new(proxy.info.last_latency.map(|latency| latency.to_string()).unwrap_or_default()),
+        ]);
+    }
+    print!("{}", table.render(colored));
+}
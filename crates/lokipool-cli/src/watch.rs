@@ -0,0 +1,146 @@
+//! 持续测试
+//!
+//! `test --watch` 每隔 `--interval` 秒测试一次全部代理，直到Ctrl-C。表格格式在终端中原地重绘一张紧凑的状态表，
+//! 除本轮结果外还给出各代理在已进行各轮中的可用率；`json` 每轮输出一行JSON，`csv` 只输出一次表头，
+//! 每行带轮次与时间。输出被重定向时不清屏，各轮依次追加，便于在CI中或写入文件后观察。
+
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::time::Duration;
+use anyhow::Result;
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::commands::{test_rows, Options, TestRow};
+use crate::output::{write_stdout, Cell, OutputFormat, Table, Tone};
+
+/// 一轮测试的结果，`json` 格式下每轮输出一行
+#[derive(Debug, Clone, Serialize)]
+pub struct Round {
+    /// 从1开始的轮次
+    pub round: u64,
+    pub at: DateTime<Utc>,
+    pub total: usize,
+    pub available: usize,
+    pub results: Vec<TestRow>,
+}
+
+/// 各轮结果的累计，按代理统计可用的轮数
+#[derive(Debug, Clone, Default)]
+pub struct Watch {
+    rounds: u64,
+    /// 代理ID -> (可用轮数, 参与轮数)
+    history: HashMap<String, (u64, u64)>,
+}
+
+impl Watch {
+    /// 记录一轮结果，结果按代理ID排序，重绘时各行位置不变
+    pub fn record(&mut self, mut results: Vec<TestRow>, at: DateTime<Utc>) -> Round {
+        self.rounds += 1;
+        results.sort_by(|a, b| a.id.cmp(&b.id));
+        for row in &results {
+            let (available, total) = self.history.entry(row.id.clone()).or_default();
+            *available += row.success as u64;
+            *total += 1;
+        }
+        Round {
+            round: self.rounds,
+            at,
+            total: results.len(),
+            available: results.iter().filter(|row| row.success).count(),
+            results,
+        }
+    }
+
+    /// 代理在参与的各轮中可用的比例（0-1），没有记录时为None
+    pub fn availability(&self, id: &str) -> Option<f64> {
+        self.history.get(id).map(|(available, total)| *available as f64 / (*total).max(1) as f64)
+    }
+
+    /// 本轮的状态表
+    pub fn table(&self, round: &Round) -> Table {
+        let mut table = Table::new(&[("id", "代理"), ("success", "结果"), ("latency_ms", "延迟(ms)"), ("availability", "可用率"), ("error", "错误")]);
+        for row in &round.results {
+            let availability = self.availability(&row.id).unwrap_or(0.0);
+            table.row(vec![
+                Cell::new(&row.id),
+                match row.success {
+                    true => Cell::new("ok").tone(Tone::Good),
+                    false => Cell::new("failed").tone(Tone::Bad),
+                },
+                Cell::new(row.latency_ms.map(|latency| latency.to_string()).unwrap_or_default()),
+                Cell::new(format!("{:.0}%", availability * 100.0)).tone(match availability {
+                    a if a >= 1.0 => Tone::Good,
+                    a if a < 0.5 => Tone::Bad,
+                    _ => Tone::Warn,
+                }),
+                Cell::new(row.error.as_deref().unwrap_or_default()).tone(Tone::Dim),
+            ]);
+        }
+        table
+    }
+
+    /// 本轮的CSV行，第一轮带表头
+    pub fn csv(&self, round: &Round) -> String {
+        let mut table = Table::new(&[("round", "round"), ("at", "at"), ("id", "id"), ("success", "success"), ("latency_ms", "latency_ms"), ("error", "error")]);
+        let at = round.at.to_rfc3339_opts(SecondsFormat::Secs, true);
+        for row in &round.results {
+            table.row(vec![
+                Cell::new(round.round),
+                Cell::new(&at),
+                Cell::new(&row.id),
+                Cell::new(row.success),
+                Cell::new(row.latency_ms.map(|latency| latency.to_string()).unwrap_or_default()),
+                Cell::new(row.error.as_deref().unwrap_or_default()),
+            ]);
+        }
+        let csv = table.to_csv();
+        match round.round {
+            1 => csv,
+            _ => csv.split_once('\n').map(|(_, rows)| rows.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+/// 每隔 `interval` 测试一次全部代理并输出，直到Ctrl-C；最后一轮至少一个代理可用时成功
+pub async fn run(options: &Options, interval: Duration) -> Result<bool> {
+    let mut watch = Watch::default();
+    let redraw = options.output == OutputFormat::Table && std::io::stdout().is_terminal();
+    let mut healthy = false;
+    loop {
+        tokio::select! {
+            rows = test_rows(options) => match rows {
+                Ok(rows) => {
+                    let round = watch.record(rows, Utc::now());
+                    healthy = round.available > 0;
+                    show(&watch, &round, options, interval, redraw)?;
+                }
+                // 一轮失败（如实例暂时不可达）不结束监视
+                Err(e) => eprintln!("第 {} 轮测试失败: {}", watch.rounds + 1, e),
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    Ok(healthy)
+}
+
+fn show(watch: &Watch, round: &Round, options: &Options, interval: Duration, redraw: bool) -> Result<()> {
+    match options.output {
+        OutputFormat::Json => write_stdout(&format!("{}\n", serde_json::to_string(round)?)),
+        OutputFormat::Csv => write_stdout(&watch.csv(round)),
+        OutputFormat::Table => {
+            if redraw {
+                console::Term::stdout().clear_screen()?;
+            } else if round.round > 1 {
+                println!();
+            }
+            println!("第 {} 轮 {} - {}/{} 个代理可用，{} 秒后重新测试，按Ctrl-C退出",
+                round.round, round.at.with_timezone(&Local).format("%H:%M:%S"), round.available, round.total, interval.as_secs());
+            write_stdout(&watch.table(round).render(options.colored))
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use lokipool_cli::output::{Cell, Table, Tone};
+
+fn table() -> Table {
+    let mut table = Table::new(&[("id", "代理"), ("location", "位置"), ("note", "备注")]);
+    table.row(vec![Cell::new("1.2.3.4:1080"), Cell::new("东京"), Cell::new("a,\"b\"").tone(Tone::Bad)]);
+    table.row(vec![Cell::new("[::1]:80"), Cell::new(""), Cell::new("")]);
+    table
+}
+
+#[test]
+fn table_columns_are_aligned_by_display_width() {
+    let rendered = table().render(false);
+    let lines: Vec<&str> = rendered.lines().collect();
+    assert_eq!(lines, [
+        "代理          位置  备注",
+        "1.2.3.4:1080  东京  a,\"b\"",
+        "[::1]:80",
+    ]);
+}
+
+#[test]
+fn csv_uses_keys_and_quotes_special_fields() {
+    assert_eq!(table().to_csv(), "id,location,note\n1.2.3.4:1080,东京,\"a,\"\"b\"\"\"\n[::1]:80,,\n");
+}
@@ -0,0 +1,107 @@
+use lokipool_api::auth::{ApiAuth, ApiKey};
+use lokipool_api::{ApiConfig, ApiServer};
+use lokipool_cli::remote::{address_id, Remote};
+use lokipool_core::{Config, Pool, PoolHandle, PoolOptions, ProxyConfig, ProxyStatus};
+use tokio::net::TcpListener;
+
+const API_KEY: &str = "cli-admin-key";
+
+/// 在随机端口上启动要求API密钥的API服务器，返回其地址与代理池
+async fn start_server(proxies: Vec<ProxyConfig>) -> (String, PoolHandle) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let pool = Pool::new_with_proxies(proxies, PoolOptions::default()).handle();
+    let api = ApiServer::new(pool.clone(), Config::default(), ApiConfig {
+        auth: ApiAuth {
+            keys: vec![ApiKey::parse(&format!("admin:{}", API_KEY)).unwrap()],
+            jwt_secret: None,
+            protect_reads: true,
+        },
+        ..ApiConfig::default()
+    });
+    tokio::spawn(async move { api.run_with_listener(listener).await });
+    (format!("http://{}", addr), pool)
+}
+
+#[test]
+fn api_addresses_match_local_proxy_ids() {
+    assert_eq!(address_id("1.2.3.4:1080"), "1.2.3.4:1080");
+    assert_eq!(address_id("2001:db8::1:1081"), "[2001:db8::1]:1081");
+    assert_eq!(address_id("[2001:db8::1]:1081"), "[2001:db8::1]:1081");
+}
+
+#[test]
+fn endpoint_must_be_http() {
+    assert_eq!(Remote::new("http://10.0.0.5:3000/", None).unwrap().endpoint(), "http://10.0.0.5:3000");
+    assert!(Remote::new("10.0.0.5:3000", None).is_err());
+}
+
+#[tokio::test]
+async fn api_key_is_sent_as_header() {
+    let (endpoint, _pool) = start_server(vec![ProxyConfig::parse("10.0.0.1:1080").unwrap()]).await;
+
+    let proxies = Remote::new(&endpoint, Some(API_KEY.to_string())).unwrap().proxies().await.unwrap();
+    assert_eq!(proxies.len(), 1);
+    assert_eq!(proxies[0].address, "10.0.0.1:1080");
+
+    // 应答不是2xx时错误信息带上状态码
+    let error = Remote::new(&endpoint, None).unwrap().proxies().await.unwrap_err().to_string();
+    assert!(error.contains("401"), "{}", error);
+    let error = Remote::new(&endpoint, Some("wrong".to_string())).unwrap().metrics().await.unwrap_err().to_string();
+    assert!(error.contains("401"), "{}", error);
+}
+
+#[tokio::test]
+async fn add_list_and_remove() {
+    let (endpoint, pool) = start_server(Vec::new()).await;
+    let remote = Remote::new(&endpoint, Some(API_KEY.to_string())).unwrap();
+
+    let added = remote.add(&ProxyConfig::parse("10.0.0.2:1080").unwrap()).await.unwrap().unwrap();
+    assert_eq!(added.address, "10.0.0.2:1080");
+    // 实例中已有的代理不重复添加
+    assert!(remote.add(&ProxyConfig::parse("10.0.0.2:1080").unwrap()).await.unwrap().is_none());
+
+    let listed = remote.proxies().await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, added.id);
+    assert_eq!(remote.metrics().await.unwrap().total, 1);
+
+    remote.remove(&added.id).await.unwrap();
+    assert!(pool.get_all_proxies().await.is_empty());
+    let error = remote.remove(&added.id).await.unwrap_err().to_string();
+    assert!(error.contains("404"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_waits_for_job_to_finish() {
+    let proxies = vec![ProxyConfig::parse("10.0.0.3:1080").unwrap(), ProxyConfig::parse("10.0.0.4:1080").unwrap()];
+    let (endpoint, pool) = start_server(proxies).await;
+    let remote = Remote::new(&endpoint, Some(API_KEY.to_string())).unwrap();
+
+    // 任务创建时仍在运行，结果来自轮询到的完成状态
+    let results = remote.test_all().await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result.success && result.latency.is_some()));
+    assert!(pool.get_all_proxies().await.iter().all(|proxy| proxy.status == ProxyStatus::Available));
+
+    let id = pool.get_all_proxies().await[0].id.clone();
+    let result = remote.test(&id).await.unwrap().unwrap();
+    assert_eq!(result.proxy_id, id);
+    let error = remote.test("no-such-proxy").await.unwrap_err().to_string();
+    assert!(error.contains("404"), "{}", error);
+}
+
+#[tokio::test]
+async fn lease_needs_an_available_proxy() {
+    let (endpoint, _pool) = start_server(vec![ProxyConfig::parse("10.0.0.5:1080").unwrap()]).await;
+    let remote = Remote::new(&endpoint, Some(API_KEY.to_string())).unwrap();
+
+    // 未测试的代理不借出
+    let error = remote.lease().await.unwrap_err().to_string();
+    assert!(error.contains("503"), "{}", error);
+
+    remote.test_all().await.unwrap();
+    let lease = remote.lease().await.unwrap();
+    assert!(lease.url.ends_with("10.0.0.5:1080"), "{}", lease.url);
+    assert!(lease.ttl > 0);
+}
@@ -0,0 +1,53 @@
+use chrono::{TimeZone, Utc};
+use lokipool_cli::commands::TestRow;
+use lokipool_cli::watch::Watch;
+
+fn row(id: &str, latency: Option<u64>) -> TestRow {
+    TestRow {
+        id: id.to_string(),
+        success: latency.is_some(),
+        latency_ms: latency,
+        error: latency.is_none().then(|| "连接被拒绝".to_string()),
+    }
+}
+
+#[test]
+fn availability_accumulates_across_rounds() {
+    let mut watch = Watch::default();
+    let at = Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+    watch.record(vec![row("2.2.2.2:1080", Some(80)), row("1.1.1.1:1080", Some(40))], at);
+    let round = watch.record(vec![row("2.2.2.2:1080", None), row("1.1.1.1:1080", Some(45))], at);
+
+    assert_eq!(round.round, 2);
+    assert_eq!((round.available, round.total), (1, 2));
+    assert_eq!(watch.availability("1.1.1.1:1080"), Some(1.0));
+    assert_eq!(watch.availability("2.2.2.2:1080"), Some(0.5));
+    assert_eq!(watch.availability("3.3.3.3:1080"), None);
+
+    // 行按代理ID排序，重绘时位置不变
+    let rendered = watch.table(&round).render(false);
+    let lines: Vec<&str> = rendered.lines().collect();
+    assert!(lines[1].starts_with("1.1.1.1:1080") && lines[1].contains("100%"));
+    assert!(lines[2].starts_with("2.2.2.2:1080") && lines[2].contains("50%"));
+}
+
+#[test]
+fn csv_header_is_written_once() {
+    let mut watch = Watch::default();
+    let at = Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+    let first = watch.record(vec![row("1.1.1.1:1080", Some(40))], at);
+    assert_eq!(watch.csv(&first), "round,at,id,success,latency_ms,error\n1,2026-01-02T03:04:05Z,1.1.1.1:1080,true,40,\n");
+    let second = watch.record(vec![row("1.1.1.1:1080", None)], at);
+    assert_eq!(watch.csv(&second), "2,2026-01-02T03:04:05Z,1.1.1.1:1080,false,,连接被拒绝\n");
+}
+
+#[test]
+fn json_round_is_a_single_line() {
+    let mut watch = Watch::default();
+    let round = watch.record(vec![row("1.1.1.1:1080", Some(40))], Utc::now());
+    let line = serde_json::to_string(&round).unwrap();
+    assert!(!line.contains('\n'));
+    let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(value["round"], 1);
+    assert_eq!(value["results"][0]["latency_ms"], 40);
+}
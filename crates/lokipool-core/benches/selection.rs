@@ -0,0 +1,73 @@
+//! 大规模代理池的并发选择吞吐量
+//!
+//! 一万个代理下，多个任务并发调用 `get_available` 并上报连接结果，对比单分片与多分片存储。
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lokipool_core::{Pool, PoolHandle, PoolOptions, ProxyConfig, ProxyStatus};
+use tokio::runtime::Runtime;
+
+const PROXIES: usize = 10_000;
+const OPS_PER_TASK: usize = 64;
+
+fn build_pool(runtime: &Runtime, shards: usize) -> Pool {
+    let pool = Pool::new(PoolOptions {
+        max_size: PROXIES,
+        shards,
+        ..PoolOptions::default()
+    });
+    let configs = (0..PROXIES)
+        .map(|i| ProxyConfig {
+            host: format!("10.{}.{}.{}", i / 65536, (i / 256) % 256, i % 256),
+            port: 1080,
+            username: None,
+            password: None,
+            location: None,
+            proxy_type: "socks5".to_string(),
+            weight: 1,
+        })
+        .collect();
+
+    runtime.block_on(async {
+        let ids = pool.replace_all(configs).await.expect("代理数量未超过上限");
+        // 四分之一的代理测试失败，不参与选择
+        for (i, id) in ids.iter().enumerate() {
+            let status = if i % 4 == 0 { ProxyStatus::Failed } else { ProxyStatus::Available };
+            pool.update_status(id, status).await;
+        }
+    });
+    pool
+}
+
+fn get_available(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    let mut group = c.benchmark_group("get_available");
+
+    for shards in [1, 16] {
+        let pool = PoolHandle::from(build_pool(&runtime, shards));
+        for tasks in [1, 8, 32] {
+            group.throughput(Throughput::Elements((tasks * OPS_PER_TASK) as u64));
+            group.bench_with_input(BenchmarkId::new(format!("shards={}", shards), tasks), &tasks, |b, &tasks| {
+                b.iter(|| runtime.block_on(async {
+                    let workers: Vec<_> = (0..tasks)
+                        .map(|_| {
+                            let pool = pool.clone();
+                            tokio::spawn(async move {
+                                for _ in 0..OPS_PER_TASK {
+                                    let proxy = pool.get_available().await.expect("池中有可用代理");
+                                    pool.report_connection(&proxy.id, true).await;
+                                }
+                            })
+                        })
+                        .collect();
+                    for worker in workers {
+                        worker.await.unwrap();
+                    }
+                }));
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, get_available);
+criterion_main!(benches);
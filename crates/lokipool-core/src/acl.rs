@@ -0,0 +1,126 @@
+//! 本地监听端口的访问控制
+//!
+//! 客户端按来源地址段过滤，目标按主机与端口过滤。每一类都有允许与拒绝两个列表：
+//! 命中拒绝列表的一律拒绝，允许列表为空时不限制，否则必须命中其中一条。
+
+use std::net::IpAddr;
+use std::str::FromStr;
+use crate::cidr::IpNet;
+use crate::config::AclSettings;
+use crate::error::{Error, Result};
+
+/// 目标主机的匹配方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum HostPattern {
+    /// `*`，匹配任意主机
+    Any,
+    /// IP地址或地址段，只匹配以IP给出的目标
+    Net(IpNet),
+    /// 完整域名，不区分大小写
+    Domain(String),
+    /// `*.example.com`，匹配 `example.com` 的所有子域名，保存为小写
+    Suffix(String),
+}
+
+/// 目标规则：`主机[:端口]`，主机可以是 `*`、域名、`*.` 开头的域名后缀、IP或地址段，
+/// IPv6地址带端口时写作 `[::1]:443`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DestinationRule {
+    pub(crate) host: HostPattern,
+    pub(crate) port: Option<u16>,
+}
+
+impl DestinationRule {
+    /// 目标是否命中规则；域名不做解析，域名规则不匹配以IP给出的目标，反之亦然
+    pub fn matches(&self, host: &str, port: u16) -> bool {
+        if self.port.is_some_and(|p| p != port) {
+            return false;
+        }
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        match &self.host {
+            HostPattern::Any => true,
+            HostPattern::Net(net) => host.parse::<IpAddr>().is_ok_and(|ip| net.contains(ip)),
+            HostPattern::Domain(domain) => host.eq_ignore_ascii_case(domain),
+            HostPattern::Suffix(suffix) => host.to_ascii_lowercase()
+                .strip_suffix(suffix.as_str())
+                .is_some_and(|rest| rest.ends_with('.')),
+        }
+    }
+}
+
+impl FromStr for DestinationRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Configuration(format!("无效的目标规则: {}", s));
+        let s = s.trim();
+        // 方括号内是IPv6地址；不带方括号且有多个冒号时整体是IPv6地址，没有端口
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (host, rest) = rest.split_once(']').ok_or_else(invalid)?;
+            match rest {
+                "" => (host, None),
+                _ => (host, Some(rest.strip_prefix(':').ok_or_else(invalid)?)),
+            }
+        } else if s.matches(':').count() == 1 {
+            let (host, port) = s.split_once(':').ok_or_else(invalid)?;
+            (host, Some(port))
+        } else {
+            (s, None)
+        };
+        let port = port.map(|port| port.parse::<u16>().map_err(|_| invalid())).transpose()?;
+
+        let host = match host {
+            "" => return Err(invalid()),
+            "*" => HostPattern::Any,
+            _ if host.contains('/') || host.parse::<IpAddr>().is_ok() => HostPattern::Net(host.parse()?),
+            _ => match host.strip_prefix("*.") {
+                Some(suffix) if !suffix.is_empty() => HostPattern::Suffix(suffix.to_ascii_lowercase()),
+                Some(_) => return Err(invalid()),
+                None => HostPattern::Domain(host.to_string()),
+            },
+        };
+        Ok(Self { host, port })
+    }
+}
+
+/// 访问控制列表，默认不限制任何客户端与目标
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Acl {
+    pub allow_clients: Vec<IpNet>,
+    pub deny_clients: Vec<IpNet>,
+    pub allow_destinations: Vec<DestinationRule>,
+    pub deny_destinations: Vec<DestinationRule>,
+}
+
+impl Acl {
+    /// 解析配置中的规则，任一条无效时返回错误
+    pub fn from_settings(settings: &AclSettings) -> Result<Self> {
+        fn parse<T: FromStr<Err = Error>>(rules: &[String]) -> Result<Vec<T>> {
+            rules.iter().map(|rule| rule.parse()).collect()
+        }
+        Ok(Self {
+            allow_clients: parse(&settings.allow_clients)?,
+            deny_clients: parse(&settings.deny_clients)?,
+            allow_destinations: parse(&settings.allow_destinations)?,
+            deny_destinations: parse(&settings.deny_destinations)?,
+        })
+    }
+
+    /// 没有任何规则
+    pub fn is_empty(&self) -> bool {
+        self.allow_clients.is_empty() && self.deny_clients.is_empty()
+            && self.allow_destinations.is_empty() && self.deny_destinations.is_empty()
+    }
+
+    /// 是否允许该地址的客户端连接
+    pub fn permits_client(&self, ip: IpAddr) -> bool {
+        !self.deny_clients.iter().any(|net| net.contains(ip))
+            && (self.allow_clients.is_empty() || self.allow_clients.iter().any(|net| net.contains(ip)))
+    }
+
+    /// 是否允许连接该目标
+    pub fn permits_destination(&self, host: &str, port: u16) -> bool {
+        !self.deny_destinations.iter().any(|rule| rule.matches(host, port))
+            && (self.allow_destinations.is_empty() || self.allow_destinations.iter().any(|rule| rule.matches(host, port)))
+    }
+}
@@ -0,0 +1,46 @@
+//! 监听端口的代理亲和模式
+//!
+//! 默认每个连接都交给选择策略，延迟最低等策略可能让连续的连接落在同一个代理上。
+//! 轮换模式让每个连接都换一个与上一个连接不同的代理，固定模式让同一客户端始终使用同一个代理，
+//! 两种模式都在选择策略给出的候选中挑选，固定的代理不可用时改用策略选出的下一个代理并重新固定。
+
+use std::fmt;
+use std::str::FromStr;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// 连接与上游代理的对应方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyAffinity {
+    /// 每个连接单独按选择策略选择
+    #[default]
+    Strategy,
+    /// 每个连接使用与上一个连接不同的代理
+    Rotate,
+    /// 同一客户端IP始终使用同一个代理
+    Pin,
+}
+
+impl fmt::Display for ProxyAffinity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyAffinity::Strategy => write!(f, "strategy"),
+            ProxyAffinity::Rotate => write!(f, "rotate"),
+            ProxyAffinity::Pin => write!(f, "pin"),
+        }
+    }
+}
+
+impl FromStr for ProxyAffinity {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strategy" => Ok(ProxyAffinity::Strategy),
+            "rotate" => Ok(ProxyAffinity::Rotate),
+            "pin" => Ok(ProxyAffinity::Pin),
+            other => Err(crate::error::Error::Configuration(format!("未知的代理亲和模式: {}", other))),
+        }
+    }
+}
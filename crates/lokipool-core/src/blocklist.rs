@@ -0,0 +1,139 @@
+//! 永久黑名单，记录出现永久性错误的代理
+//!
+//! 与 `blacklist_after_failures` 的临时黑名单不同，这里的条目不会自动过期，
+//! 并以 `host:port` 的哈希持久化，避免从订阅重新导入时反复添加和测试已知的坏代理。
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use crate::error::{Error, Result};
+use crate::file_writer::write_atomic;
+use crate::time::wall_now;
+
+/// 永久性错误的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, thiserror::Error)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockReason {
+    /// 上游要求认证，但没有配置可用的凭据
+    #[error("上游代理要求认证")]
+    AuthRequired,
+    /// 上游返回了被篡改或不符合协议的响应
+    #[error("上游代理返回了被篡改的响应")]
+    Tampering,
+    /// 上游存在恶意行为
+    #[error("上游代理存在恶意行为")]
+    Malicious,
+}
+
+/// 永久黑名单条目
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BlockEntry {
+    /// 加入原因
+    pub reason: BlockReason,
+    /// 加入时间
+    pub blocked_at: DateTime<Utc>,
+}
+
+/// 永久黑名单，键为 `host:port` 的SHA-256哈希
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    path: Option<PathBuf>,
+    entries: HashMap<String, BlockEntry>,
+}
+
+impl Blocklist {
+    /// 打开黑名单文件，文件不存在或无法解析时从空列表开始
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let entries = match &path {
+            Some(path) if path.exists() => Self::load(path).unwrap_or_else(|e| {
+                warn!("读取永久黑名单 {} 失败: {}", path.display(), e);
+                HashMap::new()
+            }),
+            _ => HashMap::new(),
+        };
+        Self { path, entries }
+    }
+
+    /// 条目相同、只保存在内存中的副本，修改不会写回文件
+    pub fn detached(&self) -> Self {
+        Self { path: None, entries: self.entries.clone() }
+    }
+
+    fn load(path: &Path) -> Result<HashMap<String, BlockEntry>> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    /// 计算代理地址的黑名单键
+    pub fn key(host: &str, port: u16) -> String {
+        let host = host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+        format!("{:x}", Sha256::digest(format!("{}:{}", host, port)))
+    }
+
+    /// 代理地址是否在黑名单中
+    pub fn contains(&self, host: &str, port: u16) -> bool {
+        self.entries.contains_key(&Self::key(host, port))
+    }
+
+    /// 加入黑名单，返回是否为新条目
+    pub fn insert(&mut self, host: &str, port: u16, reason: BlockReason) -> bool {
+        let entry = BlockEntry { reason, blocked_at: wall_now() };
+        let added = self.entries.insert(Self::key(host, port), entry).is_none();
+        self.persist();
+        added
+    }
+
+    /// 按键移除条目
+    pub fn remove(&mut self, key: &str) -> bool {
+        let removed = self.entries.remove(key).is_some();
+        if removed {
+            self.persist();
+        }
+        removed
+    }
+
+    /// 清空黑名单，返回移除的条目数
+    pub fn clear(&mut self) -> usize {
+        let count = self.entries.len();
+        self.entries.clear();
+        self.persist();
+        count
+    }
+
+    /// 所有条目，按加入时间排序
+    pub fn entries(&self) -> Vec<(String, BlockEntry)> {
+        let mut entries: Vec<_> = self.entries.iter()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        entries.sort_by_key(|(_, entry)| entry.blocked_at);
+        entries
+    }
+
+    /// 条目数量
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 原子地写回文件
+    pub(crate) fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let written = serde_json::to_string_pretty(&self.entries)
+            .map_err(|e| Error::Serialization(e.to_string()))
+            .and_then(|content| Ok(write_atomic(path, content.as_bytes())?));
+        if let Err(e) = written {
+            warn!("保存永久黑名单 {} 失败: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! 直连列表
+//!
+//! 局域网、本机等目标经上游代理反而无法访问，命中直连规则的目标由本机直接连接。
+//! 规则写法与访问控制列表的目标规则相同；也可以在代理池没有可用代理时让所有目标改为直连。
+
+use crate::acl::DestinationRule;
+use crate::config::BypassSettings;
+use crate::error::Result;
+
+/// 直连规则，默认所有目标都经上游代理
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bypass {
+    pub destinations: Vec<DestinationRule>,
+    /// 没有可用代理时直接连接目标
+    pub when_pool_empty: bool,
+}
+
+impl Bypass {
+    /// 解析配置中的规则，任一条无效时返回错误
+    pub fn from_settings(settings: &BypassSettings) -> Result<Self> {
+        Ok(Self {
+            destinations: settings.destinations.iter().map(|rule| rule.parse()).collect::<Result<_>>()?,
+            when_pool_empty: settings.when_pool_empty,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.destinations.is_empty() && !self.when_pool_empty
+    }
+
+    /// 目标是否命中直连规则
+    pub fn matches(&self, host: &str, port: u16) -> bool {
+        self.destinations.iter().any(|rule| rule.matches(host, port))
+    }
+}
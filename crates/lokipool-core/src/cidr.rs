@@ -0,0 +1,61 @@
+//! IP地址段
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// IP地址段，如 `192.168.0.0/16`；不带前缀长度时表示单个地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    pub(crate) addr: IpAddr,
+    pub(crate) prefix: u8,
+}
+
+impl IpNet {
+    /// 地址是否在地址段内，IPv4映射的IPv6地址按IPv4处理
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            v4 => v4,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => Self::masked(u32::from(net).into(), u32::from(ip).into(), self.prefix, 32),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => Self::masked(u128::from(net), u128::from(ip), self.prefix, 128),
+            _ => false,
+        }
+    }
+
+    /// 比较两个地址的前 `prefix` 位
+    fn masked(net: u128, ip: u128, prefix: u8, bits: u8) -> bool {
+        if prefix == 0 {
+            return true;
+        }
+        let shift = bits - prefix;
+        net >> shift == ip >> shift
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::error::Error::Configuration(format!("无效的地址段: {}", s));
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().map_err(|_| invalid())?)),
+            None => (s.trim(), None),
+        };
+        let addr: IpAddr = addr.trim_start_matches('[').trim_end_matches(']').parse().map_err(|_| invalid())?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        if prefix > bits {
+            return Err(invalid());
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
@@ -0,0 +1,97 @@
+//! 基于实际流量的单代理熔断器
+//!
+//! 连续连接失败达到阈值后熔断器打开，代理立即退出选择；经过 `open_duration`
+//! 后进入半开状态，只放行一个试探连接，成功则关闭，失败则重新打开。
+
+use std::time::{Duration, Instant};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// 熔断器状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// 正常放行
+    #[default]
+    Closed,
+    /// 熔断中，不参与选择
+    Open,
+    /// 试探中，只放行一个连接
+    HalfOpen,
+}
+
+/// 熔断器
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreaker {
+    state: CircuitState,
+    /// 关闭状态下连续失败的次数
+    failures: u32,
+    /// 最近一次打开的时间
+    opened_at: Option<Instant>,
+    /// 半开状态下试探连接的开始时间
+    trial_started: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// 当前状态
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    /// 打开时长已满时转入半开状态，返回状态是否变化
+    pub fn refresh(&mut self, open_duration: Duration) -> bool {
+        if self.state == CircuitState::Open
+            && self.opened_at.is_some_and(|at| at.elapsed() >= open_duration)
+        {
+            self.state = CircuitState::HalfOpen;
+            self.trial_started = None;
+            return true;
+        }
+        false
+    }
+
+    /// 是否允许选择该代理
+    ///
+    /// 半开状态下已有试探连接时不再放行，试探连接超过 `open_duration` 未报告结果则允许重新试探。
+    pub fn allows(&self, open_duration: Duration) -> bool {
+        match self.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => false,
+            CircuitState::HalfOpen => self.trial_started.is_none_or(|at| at.elapsed() >= open_duration),
+        }
+    }
+
+    /// 代理被选中时调用，半开状态下记录试探连接
+    pub fn on_selected(&mut self) {
+        if self.state == CircuitState::HalfOpen {
+            self.trial_started = Some(Instant::now());
+        }
+    }
+
+    /// 记录一次连接结果，返回状态是否变化
+    pub fn record(&mut self, success: bool, threshold: u32) -> bool {
+        let old = self.state;
+        match (self.state, success) {
+            (_, true) => {
+                self.state = CircuitState::Closed;
+                self.failures = 0;
+            }
+            (CircuitState::HalfOpen, false) => self.open(),
+            (CircuitState::Closed, false) => {
+                self.failures += 1;
+                if threshold > 0 && self.failures >= threshold {
+                    self.open();
+                }
+            }
+            (CircuitState::Open, false) => {}
+        }
+        self.state != old
+    }
+
+    fn open(&mut self) {
+        self.state = CircuitState::Open;
+        self.failures = 0;
+        self.opened_at = Some(Instant::now());
+        self.trial_started = None;
+    }
+}
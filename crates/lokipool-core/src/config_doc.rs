@@ -0,0 +1,139 @@
+//! 由配置结构体生成JSON Schema与带注释的示例配置，保证文档与代码一致
+
+use std::fmt::Write;
+use serde_json::Value;
+use crate::config::{Config, ProxyConfig};
+
+impl Config {
+    /// 生成配置文件的JSON Schema
+    pub fn json_schema() -> Value {
+        schemars::schema_for!(Config).to_value()
+    }
+
+    /// 生成带注释的完整示例配置，取值均为默认值
+    pub fn example_toml() -> String {
+        let schema = Self::json_schema();
+        let mut example = Config::default();
+        example.proxies.push(ProxyConfig {
+            location: Some("Local".to_string()),
+            ..ProxyConfig::parse("socks5://127.0.0.1:1080").expect("示例代理地址有效")
+        });
+        let values = toml::Value::try_from(&example).expect("默认配置可以序列化为TOML");
+
+        let mut out = String::from("# LokiPool 配置文件\n# 由 `lokipool config example` 生成\n");
+        if let (Some(table), Some(node)) = (values.as_table(), SchemaNode::new(&schema, &schema)) {
+            write_table(&mut out, &schema, &node, table, "");
+        }
+        out
+    }
+}
+
+/// 解析 `$ref` 后的Schema节点
+struct SchemaNode<'a> {
+    schema: &'a Value,
+    description: Option<&'a str>,
+}
+
+impl<'a> SchemaNode<'a> {
+    fn new(root: &'a Value, schema: &'a Value) -> Option<Self> {
+        let description = schema.get("description").and_then(Value::as_str);
+        let resolved = match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => root.pointer(reference.trim_start_matches('#'))?,
+            None => schema,
+        };
+        Some(Self {
+            schema: resolved,
+            description: description.or_else(|| resolved.get("description").and_then(Value::as_str)),
+        })
+    }
+
+    fn property(&self, root: &'a Value, name: &str) -> Option<Self> {
+        Self::new(root, self.schema.get("properties")?.get(name)?)
+    }
+
+    fn property_names(&self) -> Vec<&'a str> {
+        self.schema
+            .get("properties")
+            .and_then(Value::as_object)
+            .map(|props| props.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// 数组元素的Schema
+    fn items(&self, root: &'a Value) -> Option<Self> {
+        Self::new(root, self.schema.get("items")?)
+    }
+
+    /// 可选字段没有默认值时使用的占位值
+    fn placeholder(&self) -> &'static str {
+        let types = match self.schema.get("type") {
+            Some(Value::String(t)) => vec![t.as_str()],
+            Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if types.contains(&"integer") || types.contains(&"number") {
+            "0"
+        } else if types.contains(&"boolean") {
+            "false"
+        } else {
+            "\"\""
+        }
+    }
+}
+
+fn write_comment(out: &mut String, description: Option<&str>) {
+    for line in description.unwrap_or_default().lines() {
+        let _ = writeln!(out, "# {}", line.trim());
+    }
+}
+
+/// 先写出标量字段，再写出子表与表数组
+fn write_table(out: &mut String, root: &Value, node: &SchemaNode, values: &toml::Table, path: &str) {
+    let mut tables = Vec::new();
+
+    for name in node.property_names() {
+        let Some(prop) = node.property(root, name) else {
+            continue;
+        };
+        match values.get(name) {
+            Some(toml::Value::Table(_)) => tables.push((name, prop)),
+            Some(toml::Value::Array(items)) if !items.is_empty() && items.iter().all(toml::Value::is_table) && prop.items(root).is_some() => {
+                tables.push((name, prop))
+            }
+            Some(value) => {
+                out.push('\n');
+                write_comment(out, prop.description);
+                let _ = writeln!(out, "{} = {}", name, value);
+            }
+            None => {
+                out.push('\n');
+                write_comment(out, prop.description);
+                let _ = writeln!(out, "# {} = {}", name, prop.placeholder());
+            }
+        }
+    }
+
+    for (name, prop) in tables {
+        let full_name = if path.is_empty() { name.to_string() } else { format!("{}.{}", path, name) };
+        match values.get(name) {
+            Some(toml::Value::Table(table)) => {
+                out.push('\n');
+                write_comment(out, prop.description);
+                let _ = writeln!(out, "[{}]", full_name);
+                write_table(out, root, &prop, table, &full_name);
+            }
+            Some(toml::Value::Array(items)) => {
+                let Some(item_node) = prop.items(root) else {
+                    continue;
+                };
+                out.push('\n');
+                write_comment(out, prop.description);
+                for item in items.iter().filter_map(toml::Value::as_table) {
+                    let _ = writeln!(out, "[[{}]]", full_name);
+                    write_table(out, root, &item_node, item, &full_name);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! 进行中的连接的查看与关闭
+//!
+//! 运行SOCKS5服务的进程实现 `ConnectionControl`，API等控制面通过它列出正在转发的连接，
+//! 强制关闭占用代理过久或流量异常的单个连接。
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::lane::TrafficClass;
+
+/// 一个进行中的连接
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ConnectionEntry {
+    /// 进程内唯一的连接编号
+    pub id: u64,
+    /// 接受连接的监听端口
+    pub port: u16,
+    /// 客户端地址
+    pub client: String,
+    pub class: TrafficClass,
+    /// 请求的目标 `host:port`，握手完成前为空
+    pub target: Option<String>,
+    /// 使用的上游代理 `host:port`，直接连接或尚未选定时为空
+    pub proxy: Option<String>,
+    /// 接受连接的时间
+    pub opened_at: DateTime<Utc>,
+    /// 连接已存在的毫秒数
+    pub age_ms: u64,
+    /// 客户端发往目标的字节数
+    pub bytes_up: u64,
+    /// 目标发往客户端的字节数
+    pub bytes_down: u64,
+}
+
+/// 进行中的连接的控制接口
+pub trait ConnectionControl: Send + Sync {
+    /// 全部进行中的连接，按接受的先后排列
+    fn connections(&self) -> Vec<ConnectionEntry>;
+
+    /// 强制关闭连接，连接不存在或已经结束时返回false
+    fn close(&self, id: u64) -> bool;
+}
@@ -0,0 +1,119 @@
+//! 连接日志
+//!
+//! 每个转发结束的连接写一行JSON（客户端、目标、使用的代理、时长、双向字节数与结果），
+//! 与控制台的tracing输出互不影响，可以直接用 `jq` 等工具分析。
+//! 事件日志为了节省空间做了压缩并混有代理池事件，连接日志则是未压缩的纯文本，便于交给其他系统采集。
+//! 每批记录写入时重新打开文件，外部轮转工具移走文件后会自动新建。
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+use crate::event_log::ConnectionSummary;
+
+/// 写入队列容量，队列满时丢弃新的记录，不阻塞转发
+const QUEUE_CAPACITY: usize = 4096;
+
+/// 单批最多的记录数
+const MAX_BATCH: usize = 1024;
+
+/// 连接日志中的一行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionRecord {
+    /// 连接结束的时间
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub summary: ConnectionSummary,
+}
+
+enum Command {
+    Record(ConnectionRecord),
+    Flush(oneshot::Sender<io::Result<()>>),
+}
+
+/// 连接日志的写入句柄，所有克隆共享同一个后台写入任务
+#[derive(Debug, Clone)]
+pub struct ConnectionLog {
+    path: PathBuf,
+    tx: mpsc::Sender<Command>,
+}
+
+impl ConnectionLog {
+    /// 启动写入任务，必须在tokio运行时中调用
+    pub fn spawn<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(Self::run(path.clone(), rx));
+        Self { path, tx }
+    }
+
+    /// 日志文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 记录一个结束的连接，写入队列已满时丢弃
+    pub fn record(&self, summary: &ConnectionSummary) {
+        let record = ConnectionRecord { at: Utc::now(), summary: summary.clone() };
+        if self.tx.try_send(Command::Record(record)).is_err() {
+            debug!("连接日志写入队列已满，丢弃记录");
+        }
+    }
+
+    /// 写出已提交的记录并等待完成
+    pub async fn flush(&self) -> io::Result<()> {
+        let stopped = || io::Error::new(io::ErrorKind::BrokenPipe, "连接日志写入任务已停止");
+        let (done, result) = oneshot::channel();
+        self.tx.send(Command::Flush(done)).await.map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+
+    /// 取出队列中已有的记录成批写入，没有新记录时等待
+    async fn run(path: PathBuf, mut rx: mpsc::Receiver<Command>) {
+        while let Some(command) = rx.recv().await {
+            let mut lines = Vec::new();
+            let mut waiters = Vec::new();
+            let mut next = Some(command);
+            let mut count = 0;
+            while let Some(command) = next.take() {
+                match command {
+                    Command::Record(record) => {
+                        if serde_json::to_writer(&mut lines, &record).is_ok() {
+                            lines.push(b'\n');
+                        }
+                        count += 1;
+                    }
+                    Command::Flush(done) => waiters.push(done),
+                }
+                if count < MAX_BATCH {
+                    next = rx.try_recv().ok();
+                }
+            }
+
+            let task_path = path.clone();
+            let written = tokio::task::spawn_blocking(move || append(&task_path, &lines))
+                .await
+                .unwrap_or_else(|e| Err(io::Error::other(e)));
+            if let Err(e) = &written {
+                warn!("写入连接日志 {} 失败: {}", path.display(), e);
+            }
+            for done in waiters {
+                let _ = done.send(written.as_ref().map(|_| ()).map_err(|e| io::Error::new(e.kind(), e.to_string())));
+            }
+        }
+    }
+}
+
+/// 把一批记录追加到日志文件，目录不存在时创建
+fn append(path: &Path, lines: &[u8]) -> io::Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(lines)
+}
@@ -0,0 +1,135 @@
+//! 已废弃配置项的检测与迁移
+
+use tracing::warn;
+
+/// 已废弃的配置项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeprecatedKey {
+    /// 废弃的配置路径，如 `server.bind_host`
+    pub key: &'static str,
+    /// 替代的配置路径，没有替代项时为None
+    pub replacement: Option<&'static str>,
+    /// 取值是否可以原样迁移到替代项
+    pub migrate_value: bool,
+    /// 补充说明
+    pub note: &'static str,
+}
+
+impl std::fmt::Display for DeprecatedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.replacement {
+            Some(replacement) => write!(f, "配置项 `{}` 已废弃，请改用 `{}`", self.key, replacement)?,
+            None => write!(f, "配置项 `{}` 已废弃且不再生效", self.key)?,
+        }
+        if !self.note.is_empty() {
+            write!(f, "（{}）", self.note)?;
+        }
+        Ok(())
+    }
+}
+
+/// 所有已废弃的配置项
+pub const DEPRECATED_KEYS: &[DeprecatedKey] = &[
+    DeprecatedKey {
+        key: "server.bind_host",
+        replacement: Some("socks_server.bind_address"),
+        migrate_value: true,
+        note: "",
+    },
+    DeprecatedKey {
+        key: "server.bind_port",
+        replacement: Some("socks_server.bind_port"),
+        migrate_value: true,
+        note: "",
+    },
+    DeprecatedKey {
+        key: "server.max_connections",
+        replacement: Some("max_connections"),
+        migrate_value: true,
+        note: "",
+    },
+    DeprecatedKey {
+        key: "proxy.auto_switch",
+        replacement: Some("proxy.strategy"),
+        migrate_value: false,
+        note: "每次连接都会按选择策略挑选代理",
+    },
+    DeprecatedKey {
+        key: "proxy.switch_interval",
+        replacement: Some("proxy.strategy"),
+        migrate_value: false,
+        note: "每次连接都会按选择策略挑选代理",
+    },
+    DeprecatedKey {
+        key: "log.show_error_log",
+        replacement: None,
+        migrate_value: false,
+        note: "错误日志由 RUST_LOG 环境变量控制",
+    },
+];
+
+fn split_key(key: &str) -> (Option<&str>, &str) {
+    match key.split_once('.') {
+        Some((table, name)) => (Some(table), name),
+        None => (None, key),
+    }
+}
+
+fn get<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    match split_key(key) {
+        (Some(section), name) => table.get(section)?.as_table()?.get(name),
+        (None, name) => table.get(name),
+    }
+}
+
+fn remove(table: &mut toml::Table, key: &str) -> Option<toml::Value> {
+    match split_key(key) {
+        (Some(section), name) => {
+            let section_table = table.get_mut(section)?.as_table_mut()?;
+            let value = section_table.remove(name);
+            if section_table.is_empty() {
+                table.remove(section);
+            }
+            value
+        }
+        (None, name) => table.remove(name),
+    }
+}
+
+fn insert(table: &mut toml::Table, key: &str, value: toml::Value) {
+    match split_key(key) {
+        (Some(section), name) => {
+            let section_table = table
+                .entry(section)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let Some(section_table) = section_table.as_table_mut() {
+                section_table.insert(name.to_string(), value);
+            }
+        }
+        (None, name) => {
+            table.insert(name.to_string(), value);
+        }
+    }
+}
+
+/// 查找配置中使用的废弃项
+pub fn find_deprecated(table: &toml::Table) -> Vec<&'static DeprecatedKey> {
+    DEPRECATED_KEYS.iter().filter(|d| get(table, d.key).is_some()).collect()
+}
+
+/// 将废弃项迁移到替代项（替代项已设置时以替代项为准），返回发现的废弃项
+pub fn migrate(table: &mut toml::Table) -> Vec<&'static DeprecatedKey> {
+    let found = find_deprecated(table);
+    for deprecated in &found {
+        warn!("{}", deprecated);
+        let Some(value) = remove(table, deprecated.key) else {
+            continue;
+        };
+        if let (true, Some(replacement)) = (deprecated.migrate_value, deprecated.replacement) {
+            if get(table, replacement).is_none() {
+                insert(table, replacement, value);
+            }
+        }
+    }
+    found
+}
@@ -0,0 +1,90 @@
+//! DNS转发
+//!
+//! 本机的DNS查询经代理池中的代理发往上游解析器，避免域名解析绕过代理泄露访问记录。
+//! 这里只处理与转发方式无关的部分：转发方式的选择与DNS报文的最小解析。
+
+use std::fmt;
+use std::str::FromStr;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// DNS报文头长度
+pub const HEADER_LENGTH: usize = 12;
+
+/// 查询经代理发往上游的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DnsTransport {
+    /// 经代理的CONNECT发送DNS-over-HTTPS请求，所有SOCKS5代理都支持
+    #[default]
+    Doh,
+    /// 经代理的UDP ASSOCIATE发送普通DNS查询，只能使用支持UDP的代理
+    Udp,
+}
+
+impl fmt::Display for DnsTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsTransport::Doh => write!(f, "doh"),
+            DnsTransport::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+impl FromStr for DnsTransport {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "doh" => Ok(DnsTransport::Doh),
+            "udp" => Ok(DnsTransport::Udp),
+            other => Err(crate::error::Error::Configuration(format!("未知的DNS转发方式: {}", other))),
+        }
+    }
+}
+
+/// 报文头中的查询ID，报文不完整时返回None
+pub fn message_id(message: &[u8]) -> Option<u16> {
+    message.get(..2).map(|id| u16::from_be_bytes([id[0], id[1]]))
+}
+
+/// 按查询生成SERVFAIL应答，转发失败时告知客户端而不是让它等到超时；不是查询报文时返回None
+///
+/// 应答保留查询的ID、操作码与问题部分，去掉其余记录。
+pub fn servfail(query: &[u8]) -> Option<Vec<u8>> {
+    if query.len() < HEADER_LENGTH || query[2] & 0x80 != 0 {
+        return None;
+    }
+    let question_end = question_end(query)?;
+    let mut response = query[..question_end].to_vec();
+    // QR=1，保留操作码与RD；RA=1，RCODE=2
+    response[2] = 0x80 | (query[2] & 0x79);
+    response[3] = 0x80 | 0x02;
+    // 只保留问题数，其余记录数清零
+    response[6..HEADER_LENGTH].fill(0);
+    Some(response)
+}
+
+/// 问题部分结束的位置
+fn question_end(message: &[u8]) -> Option<usize> {
+    let count = u16::from_be_bytes([message[4], message[5]]);
+    let mut at = HEADER_LENGTH;
+    for _ in 0..count {
+        loop {
+            let len = *message.get(at)? as usize;
+            at += 1;
+            match len {
+                0 => break,
+                // 压缩指针占两个字节，之后名字结束
+                l if l & 0xc0 == 0xc0 => {
+                    at += 1;
+                    break;
+                }
+                l => at += l,
+            }
+        }
+        // QTYPE与QCLASS
+        at += 4;
+    }
+    (at <= message.len()).then_some(at)
+}
@@ -33,6 +33,9 @@ pub enum Error {
     /// 序列化错误
     #[error("Serialization error: {0}")]
     Serialization(String),
+    /// 使用了废弃的配置项
+    #[error("Deprecated configuration keys: {0}")]
+    Deprecated(String),
 }
 
 // 移除手动实现的 Display 和 std::error::Error trait
@@ -0,0 +1,53 @@
+use crate::circuit::CircuitState;
+use crate::honeypot::Threat;
+use crate::proxy::ProxyStatus;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// 事件通道容量，订阅者落后超过该数量的事件时会收到 `RecvError::Lagged`
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// 代理池事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PoolEvent {
+    /// 代理被加入池中
+    ProxyAdded {
+        id: String,
+        host: String,
+        port: u16,
+    },
+    /// 代理被移出池
+    ProxyRemoved {
+        id: String,
+    },
+    /// 代理状态发生变化
+    StatusChanged {
+        id: String,
+        old: ProxyStatus,
+        new: ProxyStatus,
+    },
+    /// 代理的熔断器状态发生变化
+    CircuitChanged {
+        id: String,
+        state: CircuitState,
+    },
+    /// 检测到恶意代理，代理已被加入永久黑名单
+    ThreatDetected {
+        id: String,
+        host: String,
+        port: u16,
+        threat: Threat,
+    },
+    /// 可用代理数量低于 `min_available`
+    LowCapacity {
+        available: usize,
+        min_available: usize,
+    },
+    /// 代理完成一次测试
+    TestCompleted {
+        id: String,
+        success: bool,
+        latency: Option<u64>,
+    },
+}
@@ -0,0 +1,318 @@
+//! 压缩事件日志
+//!
+//! 把代理池事件与SOCKS连接摘要按NDJSON格式追加到zstd压缩文件，不依赖数据库也能离线分析。
+//! 记录先在内存中攒批，每批压缩成一个独立的zstd帧追加到文件末尾。帧之间互不依赖，
+//! 进程异常退出时最多丢失尚未写出的一批，已写入的部分可以用 `zstd -dc` 直接解压。
+//! 文件超过大小上限后轮转为 `<文件>.1`、`<文件>.2`……，编号越大越旧，只保留 `keep` 个旧文件。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+use crate::event::PoolEvent;
+use crate::lane::TrafficClass;
+use crate::pool::Pool;
+
+/// 写入队列容量，队列满时丢弃新的连接摘要，不阻塞转发
+const QUEUE_CAPACITY: usize = 4096;
+
+/// 单批最多的记录数，达到后立即写出
+const MAX_BATCH: usize = 1024;
+
+/// zstd压缩级别
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// 事件日志选项
+#[derive(Debug, Clone)]
+pub struct EventLogOptions {
+    /// 当前日志文件路径
+    pub path: PathBuf,
+    /// 单个文件压缩后的大小上限（字节），超过后轮转
+    pub max_bytes: u64,
+    /// 保留的旧文件数，为0时轮转直接丢弃旧内容
+    pub keep: usize,
+    /// 攒批的最长时间
+    pub flush_interval: Duration,
+}
+
+impl EventLogOptions {
+    /// 默认选项：64MB轮转，保留5个旧文件，每秒写出一次
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: 64 * 1024 * 1024,
+            keep: 5,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// 一次SOCKS连接的摘要
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionSummary {
+    /// 客户端地址
+    pub client: String,
+    /// 目标地址，`host:port`
+    pub target: String,
+    /// 使用的上游代理，未分配到代理时为None
+    pub proxy: Option<String>,
+    pub class: TrafficClass,
+    /// 是否成功建立到目标的连接
+    pub success: bool,
+    /// 客户端发往目标的字节数
+    pub bytes_up: u64,
+    /// 目标发往客户端的字节数
+    pub bytes_down: u64,
+    /// 从接受连接到连接结束的时长（毫秒）
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+/// 日志记录的内容
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LogEntry {
+    /// 代理池事件
+    Pool { event: PoolEvent },
+    /// 连接摘要
+    Connection(ConnectionSummary),
+}
+
+/// 事件日志中的一行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub entry: LogEntry,
+}
+
+impl EventRecord {
+    /// 以当前时间创建记录
+    pub fn now(entry: LogEntry) -> Self {
+        Self { at: Utc::now(), entry }
+    }
+}
+
+enum Command {
+    Record(EventRecord),
+    Flush(oneshot::Sender<io::Result<()>>),
+}
+
+/// 事件日志的写入句柄
+///
+/// 所有克隆共享同一个后台写入任务，最后一个句柄释放后写入任务写出剩余记录并退出。
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    path: PathBuf,
+    tx: mpsc::Sender<Command>,
+}
+
+impl EventLog {
+    /// 启动写入任务，必须在tokio运行时中调用
+    pub fn spawn(options: EventLogOptions) -> Self {
+        let path = options.path.clone();
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(Self::run(Arc::new(options), rx));
+        Self { path, tx }
+    }
+
+    /// 当前日志文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 订阅代理池事件并写入日志，代理池关闭时停止
+    pub fn follow(&self, pool: &Pool) -> JoinHandle<()> {
+        let mut events = pool.subscribe();
+        let tx = self.tx.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        let record = EventRecord::now(LogEntry::Pool { event });
+                        if tx.send(Command::Record(record)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("事件日志落后，跳过了 {} 个代理池事件", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        pool.lifecycle.track(&task);
+        task
+    }
+
+    /// 记录一次连接的摘要，写入队列已满时丢弃
+    pub fn record_connection(&self, summary: ConnectionSummary) {
+        let record = EventRecord::now(LogEntry::Connection(summary));
+        if self.tx.try_send(Command::Record(record)).is_err() {
+            debug!("事件日志写入队列已满，丢弃连接摘要");
+        }
+    }
+
+    /// 立即写出已提交的记录并等待落盘
+    pub async fn flush(&self) -> io::Result<()> {
+        let (done, result) = oneshot::channel();
+        self.tx.send(Command::Flush(done)).await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "事件日志写入任务已停止"))?;
+        result.await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "事件日志写入任务已停止"))?
+    }
+
+    async fn run(options: Arc<EventLogOptions>, mut rx: mpsc::Receiver<Command>) {
+        let mut batch = Vec::new();
+        // 批中第一条记录到达后开始计时，到期时写出
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let command = match deadline {
+                Some(at) => match tokio::time::timeout_at(at, rx.recv()).await {
+                    Ok(command) => command,
+                    Err(_) => {
+                        let _ = Self::write_batch(&options, &mut batch).await;
+                        deadline = None;
+                        continue;
+                    }
+                },
+                None => rx.recv().await,
+            };
+            match command {
+                Some(Command::Record(record)) => {
+                    if batch.is_empty() {
+                        deadline = Some(Instant::now() + options.flush_interval);
+                    }
+                    batch.push(record);
+                    if batch.len() >= MAX_BATCH {
+                        let _ = Self::write_batch(&options, &mut batch).await;
+                        deadline = None;
+                    }
+                }
+                Some(Command::Flush(done)) => {
+                    let _ = done.send(Self::write_batch(&options, &mut batch).await);
+                    deadline = None;
+                }
+                None => {
+                    let _ = Self::write_batch(&options, &mut batch).await;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// 把当前批写成一个zstd帧，失败时记录日志并丢弃这一批
+    async fn write_batch(options: &Arc<EventLogOptions>, batch: &mut Vec<EventRecord>) -> io::Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let records = std::mem::take(batch);
+        let task_options = Arc::clone(options);
+        let written = tokio::task::spawn_blocking(move || append(&task_options, &records))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+        if let Err(e) = &written {
+            warn!("写入事件日志 {} 失败: {}", options.path.display(), e);
+        }
+        written
+    }
+}
+
+/// 压缩一批记录并追加到日志文件，必要时先轮转
+fn append(options: &EventLogOptions, records: &[EventRecord]) -> io::Result<()> {
+    let mut lines = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut lines, record)?;
+        lines.push(b'\n');
+    }
+    let frame = zstd::encode_all(lines.as_slice(), COMPRESSION_LEVEL)?;
+
+    let size = fs::metadata(&options.path).map(|m| m.len()).unwrap_or(0);
+    if size > 0 && size + frame.len() as u64 > options.max_bytes {
+        rotate(&options.path, options.keep)?;
+    }
+    if let Some(dir) = options.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&options.path)?;
+    file.write_all(&frame)?;
+    file.sync_data()
+}
+
+/// 第 `index` 个旧文件的路径，`.1` 是最近一次轮转出的文件
+pub fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// 轮转：`<文件>.N` 依次后移一位，超出 `keep` 的删除，当前文件变为 `<文件>.1`
+fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    let oldest = rotated_path(path, keep);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for index in (1..keep).rev() {
+        let from = rotated_path(path, index);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, index + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))
+}
+
+/// 日志的所有文件（含轮转出的旧文件），从旧到新排列
+pub fn log_files(path: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = (1..)
+        .map(|index| rotated_path(path, index))
+        .take_while(|file| file.exists())
+        .collect();
+    files.reverse();
+    if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    files
+}
+
+/// 从字节偏移 `offset` 开始读取完整的帧，返回其中的记录与读到的位置
+///
+/// 末尾尚未写完（或进程异常退出时被截断）的帧不会被读取，持续跟踪日志时下次从返回的位置继续。
+pub fn read_from(path: &Path, offset: u64) -> io::Result<(Vec<EventRecord>, u64)> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+
+    let mut records = Vec::new();
+    let mut consumed = 0;
+    while let Some(size) = zstd::zstd_safe::find_frame_compressed_size(&data[consumed..])
+        .ok()
+        .filter(|&size| size > 0 && consumed + size <= data.len())
+    {
+        let lines = zstd::decode_all(&data[consumed..consumed + size])?;
+        for line in lines.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+            records.push(serde_json::from_slice(line)?);
+        }
+        consumed += size;
+    }
+    Ok((records, offset + consumed as u64))
+}
+
+/// 按时间顺序读取日志及其旧文件中的全部记录
+pub fn read_all(path: &Path) -> io::Result<Vec<EventRecord>> {
+    let mut records = Vec::new();
+    for file in log_files(path) {
+        records.extend(read_from(&file, 0)?.0);
+    }
+    Ok(records)
+}
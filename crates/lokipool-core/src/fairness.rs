@@ -0,0 +1,104 @@
+//! 流量公平性：限制单个代理承担的请求份额
+//!
+//! 每个代理一个令牌桶：桶容量为滚动窗口内全池请求数乘以份额上限，
+//! 补充速度为全池请求速率乘以份额上限，每次被选中消耗一个令牌。
+//! 令牌不足的代理暂时跳过，因此长期来看没有代理承担超过上限的请求；
+//! 所有候选都没有令牌时仍然放行，公平性策略不会让连接失败。
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// 单个代理的令牌桶
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// 公平性策略的内部状态
+#[derive(Debug)]
+pub(crate) struct FairnessState {
+    max_share: f64,
+    window: Duration,
+    /// 窗口内全池每次选择的时间
+    requests: VecDeque<Instant>,
+    buckets: HashMap<String, Bucket>,
+    pruned_at: Instant,
+}
+
+impl FairnessState {
+    /// 桶容量，至少为1以免请求很少时所有代理都被跳过
+    fn capacity(&self) -> f64 {
+        (self.requests.len() as f64 * self.max_share).max(1.0)
+    }
+
+    /// 每秒补充的令牌数
+    fn refill_rate(&self) -> f64 {
+        self.requests.len() as f64 / self.window.as_secs_f64() * self.max_share
+    }
+
+    /// 代理当前可用的令牌数，不修改状态
+    fn tokens(&self, id: &str, now: Instant) -> f64 {
+        let capacity = self.capacity();
+        self.buckets.get(id).map_or(capacity, |bucket| {
+            let refilled = self.refill_rate() * now.duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + refilled).min(capacity)
+        })
+    }
+
+    /// 代理是否还有份额可以承担本次请求
+    pub(crate) fn allows(&self, id: &str) -> bool {
+        self.tokens(id, Instant::now()) >= 1.0
+    }
+
+    /// 记录代理被选中一次
+    pub(crate) fn record(&mut self, id: &str) {
+        let now = Instant::now();
+        let tokens = self.tokens(id, now);
+        self.buckets.insert(id.to_string(), Bucket { tokens: tokens - 1.0, updated: now });
+        self.requests.push_back(now);
+        self.expire(now);
+    }
+
+    /// 丢弃窗口之外的请求，并定期清理已经补满的桶
+    fn expire(&mut self, now: Instant) {
+        while self.requests.front().is_some_and(|at| now.duration_since(*at) > self.window) {
+            self.requests.pop_front();
+        }
+        if now.duration_since(self.pruned_at) > self.window {
+            let window = self.window;
+            self.buckets.retain(|_, bucket| now.duration_since(bucket.updated) <= window);
+            self.pruned_at = now;
+        }
+    }
+}
+
+/// 限制单个代理在滚动窗口内承担的请求份额
+#[derive(Debug)]
+pub(crate) struct FairnessGuard {
+    state: Mutex<FairnessState>,
+}
+
+impl FairnessGuard {
+    /// 创建公平性策略，`max_share` 不在 (0, 1) 之间时表示不限制，返回None
+    pub(crate) fn new(max_share: f64, window: Duration) -> Option<Self> {
+        if !(max_share > 0.0 && max_share < 1.0) {
+            return None;
+        }
+        Some(Self {
+            state: Mutex::new(FairnessState {
+                max_share,
+                window: window.max(Duration::from_secs(1)),
+                requests: VecDeque::new(),
+                buckets: HashMap::new(),
+                pruned_at: Instant::now(),
+            }),
+        })
+    }
+
+    /// 锁定状态，选择与记录在同一次加锁中完成
+    pub(crate) fn lock(&self) -> MutexGuard<'_, FairnessState> {
+        self.state.lock().unwrap()
+    }
+}
@@ -0,0 +1,104 @@
+//! 代理列表文件的原子写回
+//!
+//! 所有写入都先写到同目录下的临时文件并 `fsync`，再通过重命名替换目标文件，
+//! 进程崩溃时文件要么是旧内容，要么是完整的新内容，不会被截断。
+//! `ProxyFileWriter` 用单个后台任务串行处理写入请求，积压的请求只保留最新的一次。
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+/// 原子地替换文件内容
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    tmp.write_all(contents)?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+enum Command {
+    Write(Vec<String>),
+    Flush(oneshot::Sender<io::Result<()>>),
+}
+
+/// 代理列表文件的单写者
+#[derive(Debug, Clone)]
+pub struct ProxyFileWriter {
+    path: PathBuf,
+    tx: mpsc::UnboundedSender<Command>,
+}
+
+impl ProxyFileWriter {
+    /// 启动写入任务，必须在tokio运行时中调用
+    pub fn spawn<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(Self::run(path.clone(), rx));
+        Self { path, tx }
+    }
+
+    /// 目标文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 提交一次写入，每行一个代理地址
+    pub fn write(&self, lines: Vec<String>) {
+        if self.tx.send(Command::Write(lines)).is_err() {
+            warn!("代理文件写入任务已停止，丢弃对 {} 的更新", self.path.display());
+        }
+    }
+
+    /// 等待此前提交的写入全部落盘，返回最后一次写入的结果
+    pub async fn flush(&self) -> io::Result<()> {
+        let (done, result) = oneshot::channel();
+        self.tx.send(Command::Flush(done))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "代理文件写入任务已停止"))?;
+        result.await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "代理文件写入任务已停止"))?
+    }
+
+    async fn run(path: PathBuf, mut rx: mpsc::UnboundedReceiver<Command>) {
+        let mut last_result = Ok(());
+        while let Some(command) = rx.recv().await {
+            let mut pending = None;
+            let mut waiters = Vec::new();
+            let mut next = Some(command);
+            // 合并积压的请求，只写最新的内容
+            while let Some(command) = next {
+                match command {
+                    Command::Write(lines) => pending = Some(lines),
+                    Command::Flush(done) => waiters.push(done),
+                }
+                next = rx.try_recv().ok();
+            }
+
+            if let Some(lines) = pending {
+                let target = path.clone();
+                let written = tokio::task::spawn_blocking(move || write_atomic(&target, lines.join("\n").as_bytes()))
+                    .await
+                    .unwrap_or_else(|e| Err(io::Error::other(e)));
+                if let Err(e) = &written {
+                    warn!("更新代理文件 {} 失败: {}", path.display(), e);
+                }
+                last_result = written;
+            }
+
+            for done in waiters {
+                let result = match &last_result {
+                    Ok(()) => Ok(()),
+                    Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+                };
+                let _ = done.send(result);
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! 恶意代理（蜜罐）检测
+//!
+//! 这些启发式规则只针对明显的恶意行为：握手回复之前主动推送数据、
+//! 替换HTTPS证书、把请求重定向到疑似钓鱼的登录页面。命中后代理会被立即加入永久黑名单。
+
+use std::net::IpAddr;
+use std::time::Duration;
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::proxy::Proxy;
+
+/// 重定向地址中常见于凭据钓鱼页面的关键词
+const CREDENTIAL_KEYWORDS: &[&str] = &[
+    "login", "signin", "sign-in", "logon", "account", "verify", "password", "credential", "wallet",
+];
+
+/// 检测到的恶意行为
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, thiserror::Error)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Threat {
+    /// 在握手回复之外主动推送了数据
+    #[error("握手回复之前收到了未经请求的数据")]
+    UnsolicitedData,
+    /// HTTPS证书校验失败，可能被中间人替换
+    #[error("访问 {url} 时证书被替换")]
+    CertificateSwap { url: String },
+    /// 重定向到了疑似凭据钓鱼的站点
+    #[error("访问 {url} 时被重定向到 {location}")]
+    PhishingRedirect { url: String, location: String },
+}
+
+/// 通过代理访问测试URL，检查证书替换与可疑重定向
+///
+/// 连接超时等普通失败不视为恶意，返回None。
+pub async fn inspect(proxy: &Proxy, url: &str, timeout: Duration) -> Option<Threat> {
+    let requested = Url::parse(url).ok()?;
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(proxy.url()).ok()?)
+        .redirect(Policy::none())
+        .timeout(timeout)
+        .build()
+        .ok()?;
+
+    let response = match client.get(requested.clone()).send().await {
+        Ok(response) => response,
+        Err(e) if requested.scheme() == "https" && is_certificate_error(&e) => {
+            return Some(Threat::CertificateSwap { url: url.to_string() });
+        }
+        Err(_) => return None,
+    };
+
+    if !response.status().is_redirection() {
+        return None;
+    }
+    let location = response.headers().get(reqwest::header::LOCATION)?.to_str().ok()?;
+    let target = requested.join(location).ok()?;
+    is_phishing_redirect(&requested, &target).then(|| Threat::PhishingRedirect {
+        url: url.to_string(),
+        location: target.to_string(),
+    })
+}
+
+/// 错误链中是否包含证书校验失败
+fn is_certificate_error(error: &reqwest::Error) -> bool {
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+    while let Some(err) = source {
+        if err.to_string().to_ascii_lowercase().contains("certificate") {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// 跨站重定向到IP地址或带凭据关键词的地址时视为钓鱼
+fn is_phishing_redirect(requested: &Url, target: &Url) -> bool {
+    let (Some(from), Some(to)) = (requested.host_str(), target.host_str()) else {
+        return false;
+    };
+    if same_site(from, to) {
+        return false;
+    }
+
+    let to_ip = to.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().is_ok();
+    let address = format!("{}{}", to, target.path()).to_ascii_lowercase();
+    to_ip || CREDENTIAL_KEYWORDS.iter().any(|keyword| address.contains(keyword))
+}
+
+/// 两个主机是否属于同一站点（按最后两级域名比较）
+fn same_site(a: &str, b: &str) -> bool {
+    let site = |host: &str| {
+        let labels: Vec<&str> = host.trim_end_matches('.').rsplit('.').take(2).collect();
+        labels.join(".").to_ascii_lowercase()
+    };
+    site(a) == site(b)
+}
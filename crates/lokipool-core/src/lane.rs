@@ -0,0 +1,42 @@
+//! 流量类别（优先级通道）
+//!
+//! 交互流量（浏览器、SSH等）对延迟敏感，批量流量（下载、爬虫）更看重吞吐。
+//! 启用 `interactive_reserve` 后，延迟最低的若干个代理只留给交互流量，批量流量使用其余代理，
+//! 避免大流量挤占低延迟代理。某一类别的代理都不可用时退回到全部代理。
+
+use std::fmt;
+use std::str::FromStr;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// 连接的流量类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficClass {
+    /// 延迟敏感的交互流量，优先使用保留的低延迟代理
+    #[default]
+    Interactive,
+    /// 批量流量，避开保留给交互流量的代理
+    Bulk,
+}
+
+impl fmt::Display for TrafficClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrafficClass::Interactive => write!(f, "interactive"),
+            TrafficClass::Bulk => write!(f, "bulk"),
+        }
+    }
+}
+
+impl FromStr for TrafficClass {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interactive" => Ok(TrafficClass::Interactive),
+            "bulk" => Ok(TrafficClass::Bulk),
+            other => Err(crate::error::Error::Configuration(format!("未知的流量类别: {}", other))),
+        }
+    }
+}
@@ -4,26 +4,114 @@
 
 // 导出模块
 pub mod config;
+pub mod deprecation;
+mod config_doc;
 pub mod error;
 pub mod pool;
 pub mod proxy;
 pub mod tester;
 pub mod proxy_pool;
+pub mod strategy;
+pub mod event;
+pub mod snapshot;
+pub mod mirror;
+pub mod blocklist;
+pub mod circuit;
+pub mod honeypot;
+pub mod file_writer;
+pub mod supervisor;
+pub mod time;
+pub mod source;
+pub mod lane;
+pub mod affinity;
+pub mod cidr;
+pub mod acl;
+pub mod bypass;
+pub mod proxy_protocol;
+pub mod dns;
+pub mod pac;
+pub mod port_policy;
+pub mod traffic;
+pub mod sticky;
+pub mod quota;
+pub mod event_log;
+pub mod connection_log;
+pub mod metrics;
+pub mod listener;
+pub mod connection;
+pub mod stats_history;
+pub mod proxy_list;
+pub mod secret;
+mod shard;
+mod fairness;
+mod lifecycle;
+#[cfg(feature = "middleware")]
+pub mod middleware;
 
 // 从模块导出核心类型
-pub use config::{Config, ProxyConfig};
+pub use config::{AclSettings, ApiServerSettings, BypassSettings, Config, DnsServerSettings, HealthServerSettings, HttpServerSettings, ListenerSettings, PortPolicySettings, PortRuleSettings, ProxyConfig, QuotaSettings, SocksAccount, WarmPoolSettings};
 pub use error::{Error, Result};
-pub use pool::{Pool, PoolManager, PoolOptions};
-pub use proxy::{Proxy, ProxyInfo, ProxyStatus};
+pub use pool::{Pool, PoolHandle, PoolManager, PoolOptions, RetrySummary, ShutdownReport};
+pub use proxy::{ConnectionGuard, Proxy, ProxyInfo, ProxyStatus, ProxyUsage, UsageStats};
 pub use tester::{Tester, TestOptions, TestResult};
 pub use proxy_pool::{ProxyPool, ProxyEntry};
+pub use strategy::SelectionStrategy;
+pub use event::PoolEvent;
+pub use snapshot::PoolSnapshot;
+pub use mirror::{MirrorOptions, MirrorStats, TrafficMirror};
+pub use blocklist::{BlockEntry, BlockReason, Blocklist};
+pub use circuit::{CircuitBreaker, CircuitState};
+pub use honeypot::Threat;
+pub use file_writer::{write_atomic, ProxyFileWriter};
+pub use supervisor::{spawn_logged, supervise, task_panics, Backoff};
+pub use time::Stamp;
+pub use source::{ProxySource, SourceStatus, SyncReport};
+pub use lane::TrafficClass;
+pub use affinity::ProxyAffinity;
+pub use cidr::IpNet;
+pub use acl::{Acl, DestinationRule};
+pub use bypass::Bypass;
+pub use dns::DnsTransport;
+pub use port_policy::{PolicyAction, PortPolicy, PortRange, PortRule, Transport};
+pub use sticky::StickyTargets;
+pub use quota::{ClientUsage, QuotaExceeded, QuotaLimits, QuotaPermit, QuotaTable};
+pub use traffic::{TrafficAccounting, TrafficEntry, TrafficReport, TrafficStats};
+pub use connection_log::{ConnectionLog, ConnectionRecord};
+pub use event_log::{ConnectionSummary, EventLog, EventLogOptions, EventRecord, LogEntry};
+pub use metrics::{LatencyPercentiles, PoolMetrics, SelectionCounts, StatusCounts};
+pub use secret::constant_time_eq;
+pub use listener::{accept_backoff, ListenerControl, ListenerError, ListenerInfo, ListenerState};
+pub use connection::{ConnectionControl, ConnectionEntry};
+pub use stats_history::{StatsBucket, StatsHistory, StatsSample};
+pub use proxy_list::{parse_list, ListEntry, ListFormat};
+
+/// 运行时修改日志过滤器的句柄，`init_logger` 之后可用
+static LOG_FILTER: std::sync::OnceLock<
+    tracing_subscriber::reload::Handle<tracing_subscriber::EnvFilter, tracing_subscriber::fmt::Formatter>,
+> = std::sync::OnceLock::new();
 
 /// Initialize the logger with default settings
 pub fn init_logger() {
     use tracing_subscriber::{fmt, EnvFilter};
     
-    fmt()
+    let builder = fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .with_target(true)
-        .init();
+        .with_filter_reloading();
+    let _ = LOG_FILTER.set(builder.reload_handle());
+    builder.init();
+}
+
+/// 在运行时替换日志过滤器，语法与 `RUST_LOG` 相同，如 `debug` 或 `lokipool_core=trace,info`
+pub fn set_log_filter(directives: &str) -> Result<()> {
+    let filter = tracing_subscriber::EnvFilter::try_new(directives)
+        .map_err(|e| Error::Configuration(format!("无效的日志级别 {}: {}", directives, e)))?;
+    let handle = LOG_FILTER.get()
+        .ok_or_else(|| Error::Configuration("日志尚未初始化".to_string()))?;
+    handle.reload(filter).map_err(|e| Error::Other(e.to_string()))
+}
+
+/// 当前的日志过滤器，日志尚未初始化时返回None
+pub fn log_filter() -> Option<String> {
+    LOG_FILTER.get()?.with_current(|filter| filter.to_string()).ok()
 }
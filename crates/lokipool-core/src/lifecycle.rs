@@ -0,0 +1,84 @@
+//! 代理池的生命周期：登记后台任务与进行中的测试，供关闭时排空
+//!
+//! 关闭信号发出后不再开始新的测试，常驻任务在下一次等待时自行退出；
+//! 进行中的测试放在阻塞线程池里，无法中途取消，只能等待它们结束或超时。
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// 后台任务与进行中测试的登记
+#[derive(Debug)]
+pub(crate) struct Lifecycle {
+    /// 关闭信号
+    closing: watch::Sender<bool>,
+    /// 进行中的测试数
+    tests: watch::Sender<usize>,
+    /// 代理池启动的后台任务
+    tasks: Mutex<Vec<AbortHandle>>,
+}
+
+impl Lifecycle {
+    pub(crate) fn new() -> Self {
+        Self {
+            closing: watch::channel(false).0,
+            tests: watch::channel(0).0,
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 是否已经开始关闭
+    pub(crate) fn is_closing(&self) -> bool {
+        *self.closing.borrow()
+    }
+
+    /// 等待关闭信号
+    pub(crate) async fn closed(&self) {
+        let _ = self.closing.subscribe().wait_for(|closing| *closing).await;
+    }
+
+    /// 登记后台任务，顺带清理已经结束的任务
+    pub(crate) fn track<T>(&self, task: &JoinHandle<T>) {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(task.abort_handle());
+    }
+
+    /// 开始一次测试，已经开始关闭时返回None
+    pub(crate) fn begin_test(&self) -> Option<TestPermit<'_>> {
+        self.tests.send_modify(|tests| *tests += 1);
+        let permit = TestPermit { lifecycle: self };
+        // 先计数再检查，关闭流程看到计数为0后不会再有测试开始
+        (!self.is_closing()).then_some(permit)
+    }
+
+    /// 发出关闭信号并在 `timeout` 内等待进行中的测试结束，之后取消仍在运行的后台任务
+    ///
+    /// 返回超时时仍未结束的测试数。
+    pub(crate) async fn close(&self, timeout: Duration) -> usize {
+        self.closing.send_replace(true);
+        let mut tests = self.tests.subscribe();
+        let drained = tokio::time::timeout(timeout, async {
+            let _ = tests.wait_for(|tests| *tests == 0).await;
+        })
+        .await;
+        let interrupted = if drained.is_ok() { 0 } else { *self.tests.borrow() };
+
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+        interrupted
+    }
+}
+
+/// 进行中的一次测试，释放时计数减一
+pub(crate) struct TestPermit<'a> {
+    lifecycle: &'a Lifecycle,
+}
+
+impl Drop for TestPermit<'_> {
+    fn drop(&mut self) {
+        self.lifecycle.tests.send_modify(|tests| *tests -= 1);
+    }
+}
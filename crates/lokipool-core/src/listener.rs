@@ -0,0 +1,86 @@
+//! 监听端口的运行时控制
+//!
+//! 运行SOCKS5服务的进程实现 `ListenerControl`，API等控制面通过它查看监听端口、
+//! 启停或重启单个端口以及修改端口使用的选择策略，不需要重启整个进程。
+//! 各监听循环接受连接出错时也在这里统一处理，见 [`accept_backoff`]。
+
+use std::io;
+use std::time::Duration;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::strategy::SelectionStrategy;
+
+/// 文件描述符耗尽等资源错误后暂停接受连接的时间
+pub const RESOURCE_BACKOFF: Duration = Duration::from_millis(100);
+
+/// 监听端口的运行状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ListenerState {
+    /// 正在运行，监听循环panic后等待重启期间也算运行中
+    Running,
+    /// 已停止或未能绑定端口
+    Stopped,
+}
+
+/// 监听端口的状态与连接数
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ListenerInfo {
+    /// 监听端口，同时作为端口的标识
+    pub port: u16,
+    /// 监听地址
+    pub bind_addresses: Vec<String>,
+    pub state: ListenerState,
+    /// 端口自己的选择策略，为空时使用代理池的策略
+    pub strategy: Option<SelectionStrategy>,
+    /// 进行中的连接数，停止后仍在等待结束的连接也计算在内
+    pub active_connections: usize,
+    /// 进程启动以来接受的连接数
+    pub total_connections: u64,
+}
+
+/// 控制监听端口失败的原因
+#[derive(Debug, thiserror::Error)]
+pub enum ListenerError {
+    #[error("没有监听端口 {0}")]
+    NotFound(u16),
+    #[error("监听端口 {0} 已在运行")]
+    AlreadyRunning(u16),
+    #[error("监听端口 {0} 未在运行")]
+    NotRunning(u16),
+    #[error("无法绑定监听端口 {0}: {1}")]
+    Bind(u16, String),
+}
+
+/// 监听端口的控制接口
+#[async_trait]
+pub trait ListenerControl: Send + Sync {
+    /// 全部监听端口，按配置顺序排列
+    async fn listeners(&self) -> Vec<ListenerInfo>;
+
+    /// 启动已停止的端口
+    async fn start(&self, port: u16) -> Result<ListenerInfo, ListenerError>;
+
+    /// 停止接受新连接，等待进行中的连接结束（最长 `drain_timeout_secs`）后返回
+    async fn stop(&self, port: u16) -> Result<ListenerInfo, ListenerError>;
+
+    /// 停止后重新启动，已停止的端口直接启动
+    async fn restart(&self, port: u16) -> Result<ListenerInfo, ListenerError>;
+
+    /// 修改端口的选择策略，为空时改用代理池的策略；运行中的端口会重启以应用新策略
+    async fn set_strategy(&self, port: u16, strategy: Option<SelectionStrategy>) -> Result<ListenerInfo, ListenerError>;
+}
+
+/// 进程或系统的文件描述符耗尽（EMFILE/ENFILE）或内存不足，稍后重试可能恢复
+pub fn is_resource_error(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(23 | 24)) || e.kind() == io::ErrorKind::OutOfMemory
+}
+
+/// 接受连接失败后调用：资源错误时等待 [`RESOURCE_BACKOFF`] 再继续，避免监听循环空转占满CPU
+pub async fn accept_backoff(e: &io::Error) {
+    if is_resource_error(e) {
+        tokio::time::sleep(RESOURCE_BACKOFF).await;
+    }
+}
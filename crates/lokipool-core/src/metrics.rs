@@ -0,0 +1,150 @@
+//! 代理池指标快照
+//!
+//! `Pool::metrics` 在一次读锁内汇总各状态的代理数、可用代理的延迟分位数、连接计数与各选择策略的选择次数，
+//! API的 `/stats` 与监控导出共用同一份数据，不必各自遍历代理列表。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::proxy::{Proxy, ProxyStatus, UsageStats};
+use crate::strategy::SelectionStrategy;
+
+/// 各状态的代理数
+///
+/// 前六项按 `ProxyStatus` 划分，合计等于代理总数；其余各项是叠加在状态之上的限制，与前者重叠。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct StatusCounts {
+    pub available: usize,
+    pub in_use: usize,
+    pub failed: usize,
+    pub untested: usize,
+    pub unknown: usize,
+    pub quarantined: usize,
+    /// 处于临时黑名单中
+    pub blacklisted: usize,
+    /// 熔断中
+    pub circuit_open: usize,
+    /// 轮换冷却中
+    pub retired: usize,
+    /// 新代理试用期
+    pub probation: usize,
+}
+
+impl StatusCounts {
+    fn record(&mut self, proxy: &Proxy) {
+        match proxy.status {
+            ProxyStatus::Available => self.available += 1,
+            ProxyStatus::InUse => self.in_use += 1,
+            ProxyStatus::Failed => self.failed += 1,
+            ProxyStatus::Untested => self.untested += 1,
+            ProxyStatus::Unknown => self.unknown += 1,
+            ProxyStatus::Quarantined => self.quarantined += 1,
+        }
+        self.blacklisted += proxy.is_blacklisted() as usize;
+        self.circuit_open += proxy.circuit_open() as usize;
+        self.retired += proxy.is_retired() as usize;
+        self.probation += proxy.on_probation() as usize;
+    }
+}
+
+/// 延迟分布（毫秒），没有样本时各分位数为None
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LatencyPercentiles {
+    /// 参与统计的代理数
+    pub samples: usize,
+    pub mean: Option<f64>,
+    pub p50: Option<u64>,
+    pub p90: Option<u64>,
+    pub p99: Option<u64>,
+}
+
+impl LatencyPercentiles {
+    /// 按最近秩法计算分位数
+    pub fn from_samples(mut samples: Vec<u64>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let rank = |p: f64| samples[((p * samples.len() as f64).ceil() as usize).clamp(1, samples.len()) - 1];
+        Self {
+            samples: samples.len(),
+            mean: Some(samples.iter().sum::<u64>() as f64 / samples.len() as f64),
+            p50: Some(rank(0.50)),
+            p90: Some(rank(0.90)),
+            p99: Some(rank(0.99)),
+        }
+    }
+}
+
+/// 各选择策略选出代理的次数，按选择时生效的策略计数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SelectionCounts {
+    pub lowest_latency: u64,
+    pub round_robin: u64,
+    pub random: u64,
+    pub weighted_random: u64,
+}
+
+/// 选择次数计数器，由代理池在每次成功选出代理时更新
+#[derive(Debug, Default)]
+pub(crate) struct SelectionCounters([AtomicU64; 4]);
+
+impl SelectionCounters {
+    fn slot(strategy: SelectionStrategy) -> usize {
+        match strategy {
+            SelectionStrategy::LowestLatency => 0,
+            SelectionStrategy::RoundRobin => 1,
+            SelectionStrategy::Random => 2,
+            SelectionStrategy::WeightedRandom => 3,
+        }
+    }
+
+    pub(crate) fn record(&self, strategy: SelectionStrategy) {
+        self.0[Self::slot(strategy)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> SelectionCounts {
+        let [lowest_latency, round_robin, random, weighted_random] = self.0.each_ref().map(|count| count.load(Ordering::Relaxed));
+        SelectionCounts { lowest_latency, round_robin, random, weighted_random }
+    }
+
+    /// 当前计数的独立副本
+    pub(crate) fn copy(&self) -> Self {
+        Self(self.0.each_ref().map(|count| AtomicU64::new(count.load(Ordering::Relaxed))))
+    }
+}
+
+/// 代理池指标快照
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PoolMetrics {
+    /// 代理总数
+    pub total: usize,
+    pub status: StatusCounts,
+    /// 可用代理最近一次测试的延迟
+    pub latency: LatencyPercentiles,
+    /// 所有代理的连接与流量计数之和
+    pub connections: UsageStats,
+    pub selections: SelectionCounts,
+}
+
+impl PoolMetrics {
+    pub(crate) fn collect<'a>(proxies: impl Iterator<Item = &'a Proxy>, selections: SelectionCounts) -> Self {
+        let mut metrics = Self { selections, ..Self::default() };
+        let mut latencies = Vec::new();
+        for proxy in proxies {
+            metrics.total += 1;
+            metrics.status.record(proxy);
+            if proxy.status == ProxyStatus::Available {
+                latencies.extend(proxy.info.last_latency);
+            }
+            let usage = proxy.usage_stats();
+            metrics.connections.active_connections += usage.active_connections;
+            metrics.connections.total_connections += usage.total_connections;
+            metrics.connections.bytes_up += usage.bytes_up;
+            metrics.connections.bytes_down += usage.bytes_down;
+            metrics.connections.connect_failures += usage.connect_failures;
+        }
+        metrics.latency = LatencyPercentiles::from_samples(latencies);
+        metrics
+    }
+}
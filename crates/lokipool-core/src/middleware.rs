@@ -0,0 +1,129 @@
+//! reqwest中间件：每个请求轮换代理
+//!
+//! 启用 `middleware` 特性后可用。中间件在每次请求前从代理池选择代理，
+//! 同一域名在冷却时间内不会重复使用同一个代理，并把请求结果回报给代理池，
+//! 让连接失败的代理自动进入黑名单。
+//!
+//! ```no_run
+//! # use lokipool_core::{Pool, PoolOptions};
+//! # use lokipool_core::middleware::RotationMiddleware;
+//! # use std::time::Duration;
+//! # async fn demo(pool: Pool) -> Result<(), reqwest_middleware::Error> {
+//! let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+//!     .with(RotationMiddleware::new(pool).with_domain_cooldown(Duration::from_secs(30)))
+//!     .build();
+//! let resp = client.get("https://example.com").send().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+use http::Extensions;
+use tracing::debug;
+
+use crate::pool::PoolHandle;
+use crate::proxy::{ConnectionGuard, Proxy};
+
+/// 每请求轮换代理的中间件
+///
+/// 该中间件直接通过所选代理发送请求，因此需要放在中间件链的最后。
+pub struct RotationMiddleware {
+    pool: PoolHandle,
+    domain_cooldown: Duration,
+    request_timeout: Duration,
+    /// 每个代理对应的HTTP客户端，复用连接
+    clients: Mutex<HashMap<String, reqwest::Client>>,
+    /// (域名, 代理ID) 最近一次使用时间
+    last_used: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl RotationMiddleware {
+    /// 使用默认设置创建中间件（域名冷却10秒，请求超时30秒）
+    pub fn new(pool: impl Into<PoolHandle>) -> Self {
+        Self {
+            pool: pool.into(),
+            domain_cooldown: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(30),
+            clients: Mutex::new(HashMap::new()),
+            last_used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 设置同一域名复用同一代理的冷却时间
+    pub fn with_domain_cooldown(mut self, cooldown: Duration) -> Self {
+        self.domain_cooldown = cooldown;
+        self
+    }
+
+    /// 设置单个请求的超时时间
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// 为目标域名选择代理，优先选择不在冷却期内的代理
+    async fn select(&self, domain: &str) -> Option<(Proxy, ConnectionGuard)> {
+        let now = Instant::now();
+        let cooling: Vec<String> = {
+            let mut last_used = self.last_used.lock().unwrap();
+            last_used.retain(|_, used| now.duration_since(*used) < self.domain_cooldown);
+            last_used.keys()
+                .filter(|(used_domain, _)| used_domain == domain)
+                .map(|(_, id)| id.clone())
+                .collect()
+        };
+
+        let selected = match self.pool.acquire_filtered(|p| !cooling.contains(&p.id)).await {
+            Some(selected) => Some(selected),
+            None => self.pool.acquire().await,
+        };
+        if let Some((proxy, _)) = &selected {
+            self.last_used.lock().unwrap().insert((domain.to_string(), proxy.id.clone()), now);
+        }
+        selected
+    }
+
+    fn client_for(&self, proxy: &Proxy) -> reqwest_middleware::Result<reqwest::Client> {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&proxy.id) {
+            return Ok(client.clone());
+        }
+
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all(proxy.url())?)
+            .timeout(self.request_timeout)
+            .build()?;
+        clients.insert(proxy.id.clone(), client.clone());
+        Ok(client)
+    }
+}
+
+#[async_trait]
+impl Middleware for RotationMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        _extensions: &mut Extensions,
+        _next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let domain = req.url().host_str().unwrap_or_default().to_string();
+        let (proxy, _conn_guard) = self.select(&domain).await.ok_or_else(|| {
+            reqwest_middleware::Error::middleware(crate::error::Error::Other("没有可用的代理".to_string()))
+        })?;
+        debug!("请求 {} 使用代理 {}:{}", domain, proxy.info.host, proxy.info.port);
+
+        let client = self.client_for(&proxy)?;
+        let result = client.execute(req).await;
+
+        // 代理认证失败同样视为代理故障
+        let success = matches!(&result, Ok(resp) if resp.status() != StatusCode::PROXY_AUTHENTICATION_REQUIRED);
+        self.pool.report_connection(&proxy.id, success).await;
+
+        Ok(result?)
+    }
+}
@@ -0,0 +1,181 @@
+//! 流量镜像，用于在真实负载下对比评估候选代理
+//!
+//! 按 `sample_rate` 抽样实际的代理选择，抽中时对配置的测试URL发送HEAD请求，
+//! 同时经过被选中的代理与一个候选代理（试用期、隔离期或未测试的代理），
+//! 记录两者的成功率与延迟。镜像请求与真实流量相互独立，不会影响客户端连接。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use rand::seq::IndexedRandom;
+use serde::Serialize;
+use tracing::debug;
+use crate::pool::PoolHandle;
+use crate::proxy::{Proxy, ProxyStatus};
+use crate::supervisor::spawn_logged;
+
+/// 流量镜像选项
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    /// 抽样比例（0.0-1.0）
+    pub sample_rate: f64,
+    /// 镜像请求的目标URL，仅发送HEAD请求
+    pub urls: Vec<String>,
+    /// 单个镜像请求的超时
+    pub timeout: Duration,
+}
+
+/// 候选代理与基准代理的对比统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MirrorStats {
+    /// 候选代理地址
+    pub candidate: String,
+    /// 镜像样本数
+    pub samples: u64,
+    /// 候选代理成功次数
+    pub candidate_successes: u64,
+    /// 基准代理（被选中的代理）成功次数
+    pub baseline_successes: u64,
+    /// 候选代理成功请求的总延迟（毫秒）
+    pub candidate_latency_total: u64,
+    /// 基准代理成功请求的总延迟（毫秒）
+    pub baseline_latency_total: u64,
+}
+
+impl MirrorStats {
+    /// 候选代理成功率
+    pub fn candidate_success_rate(&self) -> f64 {
+        ratio(self.candidate_successes, self.samples)
+    }
+
+    /// 基准代理成功率
+    pub fn baseline_success_rate(&self) -> f64 {
+        ratio(self.baseline_successes, self.samples)
+    }
+
+    /// 候选代理平均延迟（毫秒）
+    pub fn candidate_avg_latency(&self) -> Option<u64> {
+        self.candidate_latency_total.checked_div(self.candidate_successes)
+    }
+
+    /// 基准代理平均延迟（毫秒）
+    pub fn baseline_avg_latency(&self) -> Option<u64> {
+        self.baseline_latency_total.checked_div(self.baseline_successes)
+    }
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+/// 流量镜像
+#[derive(Clone)]
+pub struct TrafficMirror {
+    pool: PoolHandle,
+    options: MirrorOptions,
+    /// 按候选代理ID记录的对比统计
+    stats: Arc<Mutex<HashMap<String, MirrorStats>>>,
+}
+
+impl TrafficMirror {
+    /// 创建流量镜像
+    pub fn new(pool: impl Into<PoolHandle>, options: MirrorOptions) -> Self {
+        Self {
+            pool: pool.into(),
+            options,
+            stats: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 观察一次代理选择，抽中时在后台发起镜像请求
+    pub fn observe(&self, chosen: &Proxy) {
+        if self.options.urls.is_empty() || !rand::rng().random_bool(self.options.sample_rate.clamp(0.0, 1.0)) {
+            return;
+        }
+        let Some(url) = self.options.urls.choose(&mut rand::rng()).cloned() else {
+            return;
+        };
+
+        let mirror = self.clone();
+        let baseline = chosen.clone();
+        spawn_logged("流量镜像", async move {
+            let Some(candidate) = mirror.pick_candidate(&baseline).await else {
+                return;
+            };
+            let timeout = mirror.options.timeout;
+            let (baseline_latency, candidate_latency) = futures::join!(
+                probe(&baseline, &url, timeout),
+                probe(&candidate, &url, timeout),
+            );
+            debug!("镜像请求 {}: 基准 {}:{} {:?}ms, 候选 {}:{} {:?}ms", url,
+                baseline.info.host, baseline.info.port, baseline_latency,
+                candidate.info.host, candidate.info.port, candidate_latency);
+
+            let mut stats = mirror.stats.lock().unwrap();
+            let entry = stats.entry(candidate.id.clone()).or_insert_with(|| MirrorStats {
+                candidate: format!("{}:{}", candidate.info.host, candidate.info.port),
+                ..MirrorStats::default()
+            });
+            entry.samples += 1;
+            if let Some(latency) = baseline_latency {
+                entry.baseline_successes += 1;
+                entry.baseline_latency_total += latency;
+            }
+            if let Some(latency) = candidate_latency {
+                entry.candidate_successes += 1;
+                entry.candidate_latency_total += latency;
+            }
+        });
+    }
+
+    /// 当前所有候选代理的对比统计，按样本数降序
+    pub fn report(&self) -> Vec<MirrorStats> {
+        let mut report: Vec<MirrorStats> = self.stats.lock().unwrap().values().cloned().collect();
+        report.sort_by_key(|s| std::cmp::Reverse(s.samples));
+        report
+    }
+
+    /// 优先选择尚未进入正常轮换的代理作为候选
+    async fn pick_candidate(&self, chosen: &Proxy) -> Option<Proxy> {
+        let others: Vec<Proxy> = self.pool.get_all_proxies()
+            .await
+            .into_iter()
+            .filter(|p| p.id != chosen.id)
+            .collect();
+        let pending: Vec<&Proxy> = others.iter()
+            .filter(|p| {
+                p.on_probation()
+                    || matches!(p.status, ProxyStatus::Quarantined | ProxyStatus::Untested | ProxyStatus::Unknown)
+            })
+            .collect();
+
+        let mut rng = rand::rng();
+        match pending.choose(&mut rng) {
+            Some(candidate) => Some((*candidate).clone()),
+            None => others.iter()
+                .filter(|p| p.status == ProxyStatus::Available)
+                .collect::<Vec<_>>()
+                .choose(&mut rng)
+                .map(|p| (*p).clone()),
+        }
+    }
+}
+
+/// 通过代理发送HEAD请求，成功时返回延迟（毫秒）
+async fn probe(proxy: &Proxy, url: &str, timeout: Duration) -> Option<u64> {
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(proxy.url()).ok()?)
+        .timeout(timeout)
+        .build()
+        .ok()?;
+
+    let start = Instant::now();
+    let response = client.head(url).send().await.ok()?;
+    let latency = start.elapsed().as_millis() as u64;
+    (response.status().is_success() || response.status().is_redirection()).then_some(latency)
+}
@@ -0,0 +1,93 @@
+//! PAC文件生成
+//!
+//! 按配置生成 `proxy.pac`：命中直连列表的目标返回 `DIRECT`，其余目标交给本机的SOCKS5端口，
+//! 配置了HTTP代理时再把它列为不支持SOCKS的客户端的后备。客户端机器只需设置一个自动代理配置地址。
+//! 直连规则尽量按LokiPool自己的匹配方式翻译：IP规则只匹配以IP给出的目标，不触发DNS解析；
+//! PAC没有通用的IPv6地址段判断，IPv6地址段规则只写成注释，对应的目标仍经代理转发。
+
+use std::fmt::Write;
+use std::net::IpAddr;
+use crate::acl::{DestinationRule, HostPattern};
+use crate::bypass::Bypass;
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// 从URL中取出端口，没有写明时按协议取默认端口
+const URL_PORT: &str = r#"function urlPort(url) {
+    var m = url.match(/^[a-z][a-z0-9+.-]*:\/\/(?:[^\/@]*@)?(\[[^\]]*\]|[^\/:?#]*)(?::(\d+))?/i);
+    if (m && m[2]) return parseInt(m[2], 10);
+    return url.substring(0, 6).toLowerCase() == "https:" ? 443 : 80;
+}
+
+function isIpv4(h) {
+    return /^\d+\.\d+\.\d+\.\d+$/.test(h);
+}
+"#;
+
+/// 生成PAC文件
+///
+/// `proxy_host` 为客户端访问本机使用的地址；`port` 选择使用的SOCKS5端口，必须是主端口或额外监听端口之一，
+/// 不指定时使用主端口。
+pub fn render(config: &Config, proxy_host: &str, port: Option<u16>) -> Result<String> {
+    let socks = &config.socks_server;
+    let port = port.unwrap_or(socks.bind_port);
+    if port != socks.bind_port && !socks.listeners.iter().any(|listener| listener.bind_port == port) {
+        return Err(Error::Configuration(format!("没有监听端口 {}", port)));
+    }
+    let bypass = Bypass::from_settings(&socks.bypass)?;
+
+    let proxy_host = match proxy_host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => format!("[{}]", ip),
+        _ => proxy_host.to_string(),
+    };
+    let mut route = format!("SOCKS5 {host}:{port}; SOCKS {host}:{port}", host = proxy_host, port = port);
+    if let Some(http_port) = config.http_server.bind_port {
+        let _ = write!(route, "; PROXY {}:{}", proxy_host, http_port);
+    }
+
+    let mut pac = String::from("// 由LokiPool按当前配置生成，修改配置后重新获取\n");
+    pac.push_str(URL_PORT);
+    pac.push_str("\nfunction FindProxyForURL(url, host) {\n    var h = host.toLowerCase();\n");
+    if bypass.destinations.iter().any(|rule| rule.port.is_some()) {
+        pac.push_str("    var port = urlPort(url);\n");
+    }
+    for (text, rule) in socks.bypass.destinations.iter().zip(&bypass.destinations) {
+        match condition(rule) {
+            Some(condition) => {
+                let _ = writeln!(pac, "    if ({}) return \"DIRECT\";", condition);
+            }
+            None => {
+                let _ = writeln!(pac, "    // 无法在PAC中表示的直连规则: {}", text.trim());
+            }
+        }
+    }
+    let _ = write!(pac, "    return {};\n}}\n", quote(&route));
+    Ok(pac)
+}
+
+/// 直连规则对应的PAC条件表达式，无法表示时为None
+fn condition(rule: &DestinationRule) -> Option<String> {
+    let host = match &rule.host {
+        HostPattern::Any => "true".to_string(),
+        HostPattern::Domain(domain) => format!("h == {}", quote(&domain.to_ascii_lowercase())),
+        HostPattern::Suffix(suffix) => format!("dnsDomainIs(h, {})", quote(&format!(".{}", suffix))),
+        HostPattern::Net(net) => match net.addr {
+            IpAddr::V4(addr) if net.prefix >= 32 => format!("h == \"{}\"", addr),
+            IpAddr::V4(addr) => {
+                let mask = u32::MAX.checked_shl(32 - net.prefix as u32).unwrap_or(0);
+                format!("isIpv4(h) && isInNet(h, \"{}\", \"{}\")", addr, std::net::Ipv4Addr::from(mask))
+            }
+            IpAddr::V6(addr) if net.prefix >= 128 => format!("h == \"{}\" || h == \"[{}]\"", addr, addr),
+            IpAddr::V6(_) => return None,
+        },
+    };
+    Some(match rule.port {
+        Some(port) => format!("({}) && port == {}", host, port),
+        None => host,
+    })
+}
+
+/// 写成JavaScript字符串字面量
+fn quote(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
@@ -1,9 +1,33 @@
-use crate::proxy::{Proxy, ProxyStatus};
+use crate::proxy::{canonical_key, ConnectionGuard, Proxy, ProxyInfo, ProxyStatus};
 use crate::error::Result;
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
 use crate::tester::{Tester, TestOptions, TestResult};
 use crate::config::ProxyConfig;
+use crate::strategy::SelectionStrategy;
+use crate::event::{PoolEvent, EVENT_CHANNEL_CAPACITY};
+use crate::snapshot::PoolSnapshot;
+use crate::blocklist::{BlockEntry, BlockReason, Blocklist};
+use crate::traffic::TrafficAccounting;
+use crate::circuit::CircuitState;
+use crate::honeypot::{self, Threat};
+use crate::supervisor::{spawn_logged, supervise, Backoff};
+use crate::fairness::FairnessGuard;
+use crate::lifecycle::Lifecycle;
+use crate::lane::TrafficClass;
+use crate::source::{self, ProxySource, SourceStatus, SyncRecord, SyncReport};
+use crate::shard::{Insert, ProxyShards, SelectionIndex, ShardsWrite, Tier, DEFAULT_SHARDS};
+use crate::metrics::{PoolMetrics, SelectionCounters};
+use tokio::sync::broadcast;
+use arc_swap::{ArcSwap, ArcSwapOption, Guard};
+use futures::StreamExt;
+use serde::Serialize;
 
 /// 代理池选项配置
 #[derive(Debug, Clone)]
@@ -14,6 +38,70 @@ pub struct PoolOptions {
     pub auto_test: bool,
     /// 测试间隔（秒）
     pub test_interval: u64,
+    /// 代理选择策略
+    pub strategy: SelectionStrategy,
+    /// 单个上游代理的最大并发连接数（0表示不限制）
+    pub max_conns_per_proxy: usize,
+    /// 连续连接失败多少次后自动加入黑名单（0表示禁用）
+    pub blacklist_after_failures: u32,
+    /// 自动黑名单冷却时间（秒）
+    pub blacklist_duration: u64,
+    /// 连续连接失败多少次后打开熔断器（0表示禁用）
+    pub circuit_failure_threshold: u32,
+    /// 熔断器打开后进入半开状态的时间（秒）
+    pub circuit_open_duration: u64,
+    /// 恢复后的隔离观察时长（秒，0表示直接恢复为可用）
+    pub quarantine_period: u64,
+    /// 隔离期代理接收的流量比例
+    pub quarantine_traffic_ratio: f64,
+    /// 隔离期允许的最大错误率，超过则重新标记为失败
+    pub quarantine_max_error_rate: f64,
+    /// 连续多少次健康检查失败后移除代理（0表示禁用）
+    pub evict_after_failures: u32,
+    /// 代理至少持续失败多久（秒）才会被移除
+    pub evict_min_failing_duration: u64,
+    /// 被移除代理追加写入的文件
+    pub dead_list_file: Option<PathBuf>,
+    /// 永久黑名单文件，未设置时黑名单仅保存在内存中
+    pub blocklist_file: Option<PathBuf>,
+    /// 恶意代理检测访问的URL，为空时不检测
+    pub screen_urls: Vec<String>,
+    /// 恶意代理检测的请求超时（秒）
+    pub screen_timeout: u64,
+    /// 可用代理数量下限，低于该值时发布 `LowCapacity` 事件（0表示禁用）
+    pub min_available: usize,
+    /// 可用代理不足时是否立即重新测试失败的代理
+    pub retest_on_low_capacity: bool,
+    /// 单个代理连续分配多少个请求后强制轮换（0表示不限制）
+    pub rotate_after_requests: u64,
+    /// 单个代理连续使用多久（秒）后强制轮换（0表示不限制）
+    pub rotate_after_secs: u64,
+    /// 强制轮换后的冷却时间（秒）
+    pub rotate_cooldown: u64,
+    /// 新代理的试用期时长（秒，0表示禁用）
+    pub probation_period: u64,
+    /// 试用期代理接收的流量比例
+    pub probation_traffic_ratio: f64,
+    /// 试用期允许的最大错误率，超过则转入隔离观察
+    pub probation_max_error_rate: f64,
+    /// 代理存储的分片数量
+    pub shards: usize,
+    /// 单个代理在滚动窗口内最多承担的请求比例（0表示不限制）
+    pub max_share: f64,
+    /// 公平性统计的滚动窗口（秒）
+    pub fairness_window: u64,
+    /// 测试全部代理与重试失败代理时的最大并发测试数
+    pub retry_concurrency: usize,
+    /// 代理重试失败后的初始退避时间（秒），之后每次连续失败翻倍
+    pub retry_backoff: u64,
+    /// 重试退避时间上限（秒）
+    pub retry_backoff_max: u64,
+    /// 关闭代理池时保存状态快照的文件，未设置时不保存
+    pub snapshot_file: Option<PathBuf>,
+    /// 保留给交互流量的低延迟代理数量（0表示不区分流量类别）
+    pub interactive_reserve: usize,
+    /// 同时向前两个候选代理发起连接，使用先完成握手的一个并取消另一个
+    pub race_candidates: bool,
 }
 
 impl Default for PoolOptions {
@@ -22,6 +110,38 @@ impl Default for PoolOptions {
             max_size: 100,
             auto_test: true,
             test_interval: 300, // 5分钟
+            strategy: SelectionStrategy::default(),
+            max_conns_per_proxy: 0,
+            blacklist_after_failures: 3,
+            blacklist_duration: 300,
+            circuit_failure_threshold: 2,
+            circuit_open_duration: 15,
+            quarantine_period: 600,
+            quarantine_traffic_ratio: 0.1,
+            quarantine_max_error_rate: 0.2,
+            evict_after_failures: 0,
+            evict_min_failing_duration: 1800,
+            dead_list_file: None,
+            blocklist_file: None,
+            screen_urls: Vec::new(),
+            screen_timeout: 10,
+            min_available: 0,
+            retest_on_low_capacity: true,
+            rotate_after_requests: 0,
+            rotate_after_secs: 0,
+            rotate_cooldown: 300,
+            probation_period: 300,
+            probation_traffic_ratio: 0.2,
+            probation_max_error_rate: 0.2,
+            shards: DEFAULT_SHARDS,
+            max_share: 0.0,
+            fairness_window: 60,
+            retry_concurrency: 8,
+            retry_backoff: 30,
+            retry_backoff_max: 1800,
+            snapshot_file: None,
+            interactive_reserve: 0,
+            race_candidates: false,
         }
     }
 }
@@ -32,170 +152,1439 @@ impl PoolOptions {
         Self {
             max_size: config.max_connections,
             auto_test: true, // 默认启用自动测试
-            test_interval: 300, // 默认5分钟
+            test_interval: config.proxy.health_check_interval,
+            strategy: config.proxy.strategy,
+            max_conns_per_proxy: config.proxy.max_conns_per_proxy,
+            blacklist_after_failures: config.proxy.blacklist_after_failures,
+            blacklist_duration: config.proxy.blacklist_duration,
+            circuit_failure_threshold: config.proxy.circuit_failure_threshold,
+            circuit_open_duration: config.proxy.circuit_open_duration,
+            quarantine_period: config.proxy.quarantine_period,
+            quarantine_traffic_ratio: config.proxy.quarantine_traffic_ratio,
+            quarantine_max_error_rate: config.proxy.quarantine_max_error_rate,
+            evict_after_failures: config.proxy.evict_after_failures,
+            evict_min_failing_duration: config.proxy.evict_min_failing_duration,
+            dead_list_file: config.proxy.dead_list_file.as_ref().map(PathBuf::from),
+            blocklist_file: config.proxy.blocklist_file.as_ref().map(PathBuf::from),
+            screen_urls: if config.proxy.honeypot_check { config.test_urls.clone() } else { Vec::new() },
+            screen_timeout: config.proxy.test_timeout,
+            min_available: config.proxy.min_available,
+            retest_on_low_capacity: config.proxy.retest_on_low_capacity,
+            rotate_after_requests: config.proxy.rotate_after_requests,
+            rotate_after_secs: config.proxy.rotate_after_secs,
+            rotate_cooldown: config.proxy.rotate_cooldown,
+            probation_period: config.proxy.probation_period,
+            probation_traffic_ratio: config.proxy.probation_traffic_ratio,
+            probation_max_error_rate: config.proxy.probation_max_error_rate,
+            shards: config.proxy.shards,
+            max_share: config.proxy.max_share,
+            fairness_window: config.proxy.fairness_window,
+            retry_concurrency: config.proxy.retry_concurrency,
+            retry_backoff: config.proxy.retry_backoff,
+            retry_backoff_max: config.proxy.retry_backoff_max,
+            snapshot_file: config.proxy.snapshot_file.as_ref().map(PathBuf::from),
+            interactive_reserve: config.proxy.interactive_reserve,
+            race_candidates: config.proxy.race_candidates,
         }
     }
 }
 
 /// 代理池，用于存储和管理代理
-#[derive(Debug, Clone)]
+///
+/// 代理池本身不实现 `Clone`：需要在多个任务间共享时使用 `handle`，需要独立副本时使用 `deep_clone`。
+#[derive(Debug)]
 pub struct Pool {
-    proxies: Arc<Mutex<HashMap<String, Proxy>>>,
-    options: PoolOptions,
+    /// 分片的代理存储，选择时只在需要修改代理状态的情况下才获取写锁
+    proxies: Arc<ProxyShards>,
+    /// 当前生效的选项，部分选项可在运行时通过 `update_options` 修改
+    options: Arc<ArcSwap<PoolOptions>>,
+    /// 选择计数，用于按比例把流量分给隔离期代理
+    selections: Arc<AtomicU64>,
+    /// 各选择策略选出代理的次数
+    selection_counts: Arc<SelectionCounters>,
+    /// 轮询策略的游标
+    rr_cursor: Arc<AtomicUsize>,
+    /// 轮询策略上次选中的代理ID，索引选择从它之后继续
+    rr_last: Arc<Mutex<String>>,
+    /// 事件广播通道
+    events: broadcast::Sender<PoolEvent>,
+    /// 是否处于可用代理不足状态，用于避免重复告警
+    low_capacity: Arc<AtomicBool>,
+    /// 出现永久性错误的代理
+    blocklist: Arc<Mutex<Blocklist>>,
+    /// 单个代理的流量份额限制，未启用时为None
+    fairness: Arc<ArcSwapOption<FairnessGuard>>,
+    /// 重试失败代理时持有，保证同一时间只有一轮重试
+    retrying: Arc<tokio::sync::Mutex<()>>,
+    /// 后台任务与进行中的测试，关闭时排空
+    pub(crate) lifecycle: Arc<Lifecycle>,
+    /// 各来源最近一次同步的记录
+    sync_records: Arc<Mutex<HashMap<ProxySource, SyncRecord>>>,
+    /// 按客户端与目标累计的流量
+    traffic: Arc<TrafficAccounting>,
+    /// 该句柄使用的选择策略，为None时使用选项中的策略
+    strategy: Option<SelectionStrategy>,
+}
+
+/// 代理池的共享句柄
+///
+/// 所有句柄指向同一份代理池状态，克隆只增加一次引用计数，可以直接传给各个任务；
+/// 通过 `Deref` 调用 `Pool` 的全部方法。
+#[derive(Debug, Clone)]
+pub struct PoolHandle(Arc<Pool>);
+
+impl std::ops::Deref for PoolHandle {
+    type Target = Pool;
+
+    fn deref(&self) -> &Pool {
+        &self.0
+    }
+}
+
+impl From<Pool> for PoolHandle {
+    fn from(pool: Pool) -> Self {
+        Self(Arc::new(pool))
+    }
+}
+
+/// 一轮失败代理重试的结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RetrySummary {
+    /// 本轮重新测试的代理数
+    pub attempted: usize,
+    /// 测试成功、已恢复的代理数
+    pub recovered: usize,
+    /// 仍然失败、进入下一次退避的代理数
+    pub failed: usize,
+    /// 仍在退避期内、本轮跳过的代理数
+    pub deferred: usize,
 }
 
 impl Pool {
     /// 创建新的代理池
     pub fn new(options: PoolOptions) -> Self {
         Self {
-            proxies: Arc::new(Mutex::new(HashMap::new())),
-            options,
+            proxies: Arc::new(ProxyShards::new(options.shards)),
+            selections: Arc::new(AtomicU64::new(0)),
+            selection_counts: Arc::new(SelectionCounters::default()),
+            rr_cursor: Arc::new(AtomicUsize::new(0)),
+            rr_last: Arc::new(Mutex::new(String::new())),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            low_capacity: Arc::new(AtomicBool::new(false)),
+            blocklist: Arc::new(Mutex::new(Blocklist::open(options.blocklist_file.clone()))),
+            fairness: Arc::new(ArcSwapOption::new(Self::fairness_guard(&options))),
+            retrying: Arc::new(tokio::sync::Mutex::new(())),
+            lifecycle: Arc::new(Lifecycle::new()),
+            sync_records: Arc::new(Mutex::new(HashMap::new())),
+            traffic: Arc::new(TrafficAccounting::default()),
+            options: Arc::new(ArcSwap::from_pointee(options)),
+            strategy: None,
+        }
+    }
+
+    /// 共享同一份状态的句柄，通过任一句柄的修改对所有句柄可见
+    pub fn handle(&self) -> PoolHandle {
+        PoolHandle(Arc::new(self.share()))
+    }
+
+    /// 指向同一份状态的另一个 `Pool`，只复制引用计数
+    fn share(&self) -> Self {
+        Self {
+            proxies: Arc::clone(&self.proxies),
+            options: Arc::clone(&self.options),
+            selections: Arc::clone(&self.selections),
+            selection_counts: Arc::clone(&self.selection_counts),
+            rr_cursor: Arc::clone(&self.rr_cursor),
+            rr_last: Arc::clone(&self.rr_last),
+            events: self.events.clone(),
+            low_capacity: Arc::clone(&self.low_capacity),
+            blocklist: Arc::clone(&self.blocklist),
+            fairness: Arc::clone(&self.fairness),
+            retrying: Arc::clone(&self.retrying),
+            lifecycle: Arc::clone(&self.lifecycle),
+            sync_records: Arc::clone(&self.sync_records),
+            traffic: Arc::clone(&self.traffic),
+            strategy: self.strategy,
         }
     }
 
-    /// 从代理配置列表创建代理池
+    /// 共享同一份状态、但按给定策略选择代理的句柄，用于让不同的监听端口使用不同的选择策略
+    ///
+    /// 轮询策略的位置与其他句柄分开记录。
+    pub fn with_strategy(&self, strategy: SelectionStrategy) -> PoolHandle {
+        PoolHandle(Arc::new(Self {
+            strategy: Some(strategy),
+            rr_last: Arc::new(Mutex::new(String::new())),
+            ..self.share()
+        }))
+    }
+
+    /// 与当前代理池完全独立的副本
+    ///
+    /// 代理的状态、使用计数与黑名单都被复制，之后双方的修改互不影响。
+    /// 副本的黑名单只保存在内存中，关闭时也不保存状态快照，不会覆盖原代理池的文件；
+    /// 副本没有事件订阅者，公平性统计、按客户端与目标的流量统计、正在进行的重试与后台任务也不会被复制。
+    pub async fn deep_clone(&self) -> Pool {
+        let options = PoolOptions { snapshot_file: None, ..PoolOptions::clone(&self.opts()) };
+        let copy = Self {
+            proxies: Arc::new(ProxyShards::new(options.shards)),
+            selections: Arc::new(AtomicU64::new(self.selections.load(Ordering::Relaxed))),
+            selection_counts: Arc::new(self.selection_counts.copy()),
+            rr_cursor: Arc::new(AtomicUsize::new(self.rr_cursor.load(Ordering::Relaxed))),
+            rr_last: Arc::new(Mutex::new(self.rr_last.lock().unwrap().clone())),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            low_capacity: Arc::new(AtomicBool::new(self.low_capacity.load(Ordering::SeqCst))),
+            blocklist: Arc::new(Mutex::new(self.blocklist.lock().unwrap().detached())),
+            fairness: Arc::new(ArcSwapOption::new(Self::fairness_guard(&options))),
+            retrying: Arc::new(tokio::sync::Mutex::new(())),
+            lifecycle: Arc::new(Lifecycle::new()),
+            sync_records: Arc::new(Mutex::new(self.sync_records.lock().unwrap().clone())),
+            traffic: Arc::new(TrafficAccounting::default()),
+            options: Arc::new(ArcSwap::from_pointee(options)),
+            strategy: self.strategy,
+        };
+        {
+            let source = self.proxies.read_all().await;
+            let mut target = copy.proxies.try_write_all().expect("新建的代理池没有其他持有者");
+            for proxy in source.iter() {
+                target.insert(proxy.detached());
+            }
+        }
+        copy
+    }
+
+    /// 当前生效的选项
+    pub fn options(&self) -> Arc<PoolOptions> {
+        self.options.load_full()
+    }
+
+    /// 在运行时修改选项，之后的选择、检测与重试立即使用新值
+    ///
+    /// 分片数量、黑名单文件等只在创建时使用的选项修改后不会生效。
+    /// 份额上限或统计窗口变化时，公平性统计从零开始。
+    pub fn update_options(&self, f: impl FnOnce(&mut PoolOptions)) {
+        let old = self.options();
+        let mut options = PoolOptions::clone(&old);
+        f(&mut options);
+        if options.max_share != old.max_share || options.fairness_window != old.fairness_window {
+            self.fairness.store(Self::fairness_guard(&options));
+        }
+        self.options.store(Arc::new(options));
+    }
+
+    /// 按选项创建公平性策略，未启用时为None
+    fn fairness_guard(options: &PoolOptions) -> Option<Arc<FairnessGuard>> {
+        FairnessGuard::new(options.max_share, Duration::from_secs(options.fairness_window)).map(Arc::new)
+    }
+
+    /// 读取当前选项，不能跨越await持有
+    fn opts(&self) -> Guard<Arc<PoolOptions>> {
+        self.options.load()
+    }
+
+    /// 当前句柄的选择策略
+    pub fn strategy(&self) -> SelectionStrategy {
+        self.strategy.unwrap_or(self.opts().strategy)
+    }
+
+    /// 订阅代理池事件
+    pub fn subscribe(&self) -> broadcast::Receiver<PoolEvent> {
+        self.events.subscribe()
+    }
+
+    /// 发布事件，没有订阅者时直接丢弃
+    fn emit(&self, event: PoolEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// 状态与之前不同时发布状态变化事件
+    fn emit_status_change(&self, proxy: &Proxy, old: ProxyStatus) {
+        if proxy.status != old {
+            self.emit(PoolEvent::StatusChanged {
+                id: proxy.id.clone(),
+                old,
+                new: proxy.status,
+            });
+        }
+    }
+
+    /// 从代理配置列表创建代理池，列表中的代理属于配置文件来源
     pub fn new_with_proxies(proxies: Vec<crate::config::ProxyConfig>, options: PoolOptions) -> Self {
         let pool = Self::new(options);
         
-        for proxy_config in proxies {
-            let proxy = Proxy::new(
-                proxy_config.host,
-                proxy_config.port,
-                proxy_config.username,
-                proxy_config.password,
-            );
-            
-            // 忽略添加失败的情况
-            let _ = pool.add(proxy);
-        }
+        let report = {
+            let mut map = pool.proxies.try_write_all().expect("新建的代理池没有其他持有者");
+            pool.sync_locked(&mut map, ProxySource::Config, proxies)
+        };
+        pool.record_sync(ProxySource::Config, None, Ok(report));
         
         pool
     }
 
-    /// 添加代理到池中
-    pub fn add(&self, proxy: Proxy) -> Result<()> {
-        let mut proxies = self.proxies.lock().unwrap();
-        if proxies.len() >= self.options.max_size {
+    /// 添加代理到池中，返回池中对应代理的ID
+    ///
+    /// 规范键（地址、端口、类型、用户名）相同的代理已存在时不会重复添加，而是合并元数据并返回已有代理的ID。
+    /// 未标记来源的代理视为运行时添加，不会被文件来源的同步移除。
+    pub async fn add(&self, mut proxy: Proxy) -> Result<String> {
+        if proxy.sources.is_empty() {
+            proxy.sources.insert(ProxySource::Api);
+        }
+        if self.is_blocked(&proxy.info.host, proxy.info.port) {
+            return Err(crate::error::Error::Other(format!(
+                "Proxy {}:{} is blocklisted", proxy.info.host, proxy.info.port)));
+        }
+        // 只锁住新代理所在的分片，查重使用选择索引中的规范键
+        loop {
+            let event = PoolEvent::ProxyAdded {
+                id: proxy.id.clone(),
+                host: proxy.info.host.clone(),
+                port: proxy.info.port,
+            };
+            let id = proxy.id.clone();
+            match self.proxies.insert(proxy, self.opts().max_size).await {
+                Insert::Added => {
+                    self.emit(event);
+                    return Ok(id);
+                }
+                Insert::Existing(existing, new) => {
+                    debug!("代理 {}:{} 已存在，合并元数据", new.info.host, new.info.port);
+                    if self.proxies.update(&existing, |proxy| proxy.merge_from(&new)).await.is_some() {
+                        return Ok(existing);
+                    }
+                    // 已有的代理刚被移除，重新尝试插入
+                    proxy = *new;
+                }
+                Insert::Full => return Err(crate::error::Error::Other("Pool size limit reached".to_string())),
+            }
+        }
+    }
+
+    /// 在已持有写锁的情况下添加代理，调用方负责确认规范键相同的代理不存在
+    fn add_locked(&self, proxies: &mut ShardsWrite<'_>, proxy: Proxy) -> Result<String> {
+        if self.is_blocked(&proxy.info.host, proxy.info.port) {
+            return Err(crate::error::Error::Other(format!(
+                "Proxy {}:{} is blocklisted", proxy.info.host, proxy.info.port)));
+        }
+        if proxies.len() >= self.opts().max_size {
             return Err(crate::error::Error::Other("Pool size limit reached".to_string()));
         }
-        proxies.insert(proxy.id.clone(), proxy);
-        Ok(())
+        let event = PoolEvent::ProxyAdded {
+            id: proxy.id.clone(),
+            host: proxy.info.host.clone(),
+            port: proxy.info.port,
+        };
+        let id = proxy.id.clone();
+        proxies.insert(proxy);
+        self.emit(event);
+        Ok(id)
     }
 
-    /// 获取可用代理
-    pub fn get_available(&self) -> Option<Proxy> {
-        let proxies = self.proxies.lock().unwrap();
-        proxies.values()
-            .filter(|p| p.status == ProxyStatus::Available)
-            .min_by_key(|p| p.latency)
-            .cloned()
+    /// 检查可用代理数量，低于 `min_available` 时告警并按需重新测试失败的代理
+    ///
+    /// 计数来自选择索引，调用前必须释放所有分片锁，使索引反映最新的修改。
+    fn check_capacity(&self) {
+        let min_available = self.opts().min_available;
+        if min_available == 0 {
+            return;
+        }
+
+        let available = self.proxies.index().available();
+        if available >= min_available {
+            if self.low_capacity.swap(false, Ordering::SeqCst) {
+                info!("可用代理数量已恢复: {} (下限 {})", available, min_available);
+            }
+            return;
+        }
+        if self.low_capacity.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        warn!("可用代理数量不足: {} (下限 {})", available, min_available);
+        self.emit(PoolEvent::LowCapacity { available, min_available });
+
+        if self.opts().retest_on_low_capacity
+            && !self.lifecycle.is_closing()
+            && tokio::runtime::Handle::try_current().is_ok()
+        {
+            let pool = self.handle();
+            let task = spawn_logged("可用代理不足重测", async move {
+                pool.retry_connections().await;
+            });
+            self.lifecycle.track(&task);
+        }
     }
 
-    /// 获取所有代理，用于调试
-    pub fn get_all_proxies(&self) -> Vec<Proxy> {
-        let proxies = self.proxies.lock().unwrap();
-        proxies.values().cloned().collect()
+    /// 按配置添加代理，返回池中对应代理的ID
+    ///
+    /// 新代理处于未测试状态，需要立即可用时可随后调用 `test_proxy`。
+    pub async fn add_config(&self, config: ProxyConfig) -> Result<String> {
+        self.add(Proxy::from_config(config)).await
     }
 
-    /// 测试所有代理
-    pub async fn test_all(&self) -> Vec<(ProxyConfig, TestResult)> {
-        let mut results = Vec::new();
-        let tester = Tester::new(TestOptions::default());
-        
-        // 获取锁并修改代理状态
-        let mut proxies_lock = self.proxies.lock().unwrap();
-        
-        for (_, proxy) in proxies_lock.iter_mut() {
-            // 克隆代理用于测试
-            let mut proxy_clone = proxy.clone();
-            
-            match tester.test_proxy(&mut proxy_clone) {
-                Ok(result) => {
-                    // 将测试结果应用回原始代理
-                    if result.success {
-                        proxy.update_status_and_latency(ProxyStatus::Available, result.latency);
-                    } else {
-                        proxy.update_status_and_latency(ProxyStatus::Failed, None);
+    /// 用新的配置列表替换池中所有代理，返回新加入代理的ID
+    ///
+    /// 地址与用户名相同的代理会保留原有的状态与统计，其余代理被移除，不区分来源；
+    /// 新加入的代理属于配置文件来源。只需要更新某个来源时使用 `sync_source`。
+    pub async fn replace_all(&self, configs: Vec<ProxyConfig>) -> Result<Vec<String>> {
+        self.install(configs).await.map(|(_, added)| added)
+    }
+
+    /// 整体换入新的代理列表，规则与 `replace_all` 相同
+    ///
+    /// 新的分片内容在锁外构建好，再在所有分片的写锁内一次换入，并发的选择只会看到替换前或替换后的完整列表，
+    /// 不会遇到空的或只更新了一部分的池；持有写锁的时间只用于换入与沿用保留代理的最新状态。
+    pub async fn swap(&self, configs: Vec<ProxyConfig>) -> Result<SyncReport> {
+        self.install(configs).await.map(|(report, _)| report)
+    }
+
+    async fn install(&self, configs: Vec<ProxyConfig>) -> Result<(SyncReport, Vec<String>)> {
+        if configs.len() > self.opts().max_size {
+            return Err(crate::error::Error::Other("Pool size limit reached".to_string()));
+        }
+        let mut report = SyncReport::default();
+
+        let configs: Vec<ProxyConfig> = {
+            let blocklist = self.blocklist.lock().unwrap();
+            configs.into_iter()
+                .filter(|config| {
+                    let blocked = blocklist.contains(&config.host, config.port);
+                    if blocked {
+                        debug!("代理 {}:{} 在永久黑名单中，跳过", config.host, config.port);
+                        report.rejected += 1;
                     }
-                    
-                    // 创建 ProxyConfig 用于返回结果
-                    let config = ProxyConfig {
+                    !blocked
+                })
+                .collect()
+        };
+
+        // 在锁外按当前快照构建新的列表
+        let mut current: HashMap<String, Proxy> = self.proxies.read_all().await
+            .iter()
+            .map(|proxy| (proxy.canonical_key(), proxy.clone()))
+            .collect();
+        let mut seen = HashSet::new();
+        let mut retained = HashMap::new();
+        let mut added = Vec::new();
+        let mut next = Vec::with_capacity(configs.len());
+        for config in configs {
+            let key = config.canonical_key();
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+            let proxy = match current.remove(&key) {
+                Some(mut proxy) => {
+                    proxy.update_from_config(config.clone());
+                    retained.insert(proxy.id.clone(), config);
+                    proxy
+                }
+                None => {
+                    let mut proxy = Proxy::from_config(config);
+                    proxy.sources.insert(ProxySource::Config);
+                    added.push(PoolEvent::ProxyAdded {
+                        id: proxy.id.clone(),
                         host: proxy.info.host.clone(),
                         port: proxy.info.port,
-                        username: proxy.info.username.clone(),
-                        password: proxy.info.password.clone(),
-                        location: proxy.info.location.clone(),
-                        proxy_type: proxy.info.proxy_type.clone(),
-                    };
-                    
-                    results.push((config, result));
-                },
+                    });
+                    proxy
+                }
+            };
+            next.push(proxy);
+        }
+        let partitioned = self.proxies.partition(next);
+
+        let mut proxies = self.proxies.write_all().await;
+        let mut removed = Vec::new();
+        for old in proxies.install(partitioned) {
+            match (proxies.get_mut(&old.id), retained.remove(&old.id)) {
+                // 快照之后保留的代理可能又被测试或使用过，沿用最新的状态
+                (Some(slot), Some(config)) => {
+                    *slot = old;
+                    slot.update_from_config(config);
+                    report.existing += 1;
+                }
+                _ => removed.push(PoolEvent::ProxyRemoved { id: old.id }),
+            }
+        }
+        let total = proxies.len();
+        drop(proxies);
+
+        report.added = added.len();
+        report.removed = removed.len();
+        let added_ids = added.iter()
+            .filter_map(|event| match event {
+                PoolEvent::ProxyAdded { id, .. } => Some(id.clone()),
+                _ => None,
+            })
+            .collect();
+        for event in added.into_iter().chain(removed) {
+            self.emit(event);
+        }
+
+        info!("代理列表已替换: 共 {} 个代理，新增 {} 个", total, report.added);
+        self.check_capacity();
+        Ok((report, added_ids))
+    }
+
+    /// 按来源的最新列表同步代理
+    ///
+    /// 列表中的代理加入池中或更新元数据；之前属于该来源、如今不在列表中的代理失去该来源，
+    /// 不再属于任何来源时被移除。其他来源的代理不受影响。
+    pub async fn sync_source(&self, source: ProxySource, configs: Vec<ProxyConfig>) -> SyncReport {
+        let report = {
+            let mut proxies = self.proxies.write_all().await;
+            self.sync_locked(&mut proxies, source, configs)
+        };
+        self.check_capacity();
+        self.record_sync(source, None, Ok(report));
+        report
+    }
+
+    /// 读取代理列表文件并同步文件来源，文件不存在时视为空列表
+    ///
+    /// 读取或解析失败时保留现有的代理，只记录失败原因。
+    pub async fn sync_file(&self, path: &Path) -> Result<SyncReport> {
+        let origin = Some(path.display().to_string());
+        let configs = if path.exists() { ProxyConfig::load_list(path) } else { Ok(Vec::new()) };
+        let configs = match configs {
+            Ok(configs) => configs,
+            Err(e) => {
+                self.record_sync(ProxySource::File, origin, Err(e.to_string()));
+                return Err(e);
+            }
+        };
+
+        let report = {
+            let mut proxies = self.proxies.write_all().await;
+            self.sync_locked(&mut proxies, ProxySource::File, configs)
+        };
+        self.check_capacity();
+        self.record_sync(ProxySource::File, origin, Ok(report));
+        Ok(report)
+    }
+
+    /// 在已持有写锁的情况下同步来源
+    fn sync_locked(&self, proxies: &mut ShardsWrite<'_>, source: ProxySource, configs: Vec<ProxyConfig>) -> SyncReport {
+        let mut report = SyncReport::default();
+        let mut ids: HashMap<String, String> = proxies.iter()
+            .map(|proxy| (proxy.canonical_key(), proxy.id.clone()))
+            .collect();
+        let mut listed = std::collections::HashSet::new();
+
+        for config in configs {
+            let key = config.canonical_key();
+            if !listed.insert(key.clone()) {
+                continue;
+            }
+            if let Some(proxy) = ids.get(&key).and_then(|id| proxies.get_mut(id)) {
+                proxy.update_from_config(config);
+                proxy.sources.insert(source);
+                report.existing += 1;
+                continue;
+            }
+            let mut proxy = Proxy::from_config(config);
+            proxy.sources.insert(source);
+            match self.add_locked(proxies, proxy) {
+                Ok(id) => {
+                    ids.insert(key, id);
+                    report.added += 1;
+                }
                 Err(e) => {
-                    // 更新代理状态为失败
-                    proxy.update_status(ProxyStatus::Failed);
-                    
-                    // 创建失败的测试结果
-                    let result = TestResult {
-                        proxy_id: proxy.id.clone(),
-                        success: false,
-                        latency: None,
-                        error: Some(e.to_string()),
-                        timestamp: chrono::Utc::now(),
-                    };
-                    
-                    // 创建 ProxyConfig 用于返回结果
-                    let config = ProxyConfig {
-                        host: proxy.info.host.clone(),
-                        port: proxy.info.port,
-                        username: proxy.info.username.clone(),
-                        password: proxy.info.password.clone(),
-                        location: proxy.info.location.clone(),
-                        proxy_type: proxy.info.proxy_type.clone(),
-                    };
-                    
-                    results.push((config, result));
+                    debug!("来源 {} 的代理未能加入: {}", source, e);
+                    report.rejected += 1;
                 }
             }
         }
-        
-        results
+
+        let stale: Vec<String> = proxies.iter()
+            .filter(|proxy| proxy.sources.contains(&source) && !listed.contains(&proxy.canonical_key()))
+            .map(|proxy| proxy.id.clone())
+            .collect();
+        for id in stale {
+            let Some(proxy) = proxies.get_mut(&id) else { continue };
+            proxy.sources.remove(&source);
+            if !proxy.sources.is_empty() {
+                report.released += 1;
+                continue;
+            }
+            if let Some(proxy) = proxies.remove(&id) {
+                debug!("代理 {}:{} 已不在来源 {} 中，移除", proxy.info.host, proxy.info.port, source);
+                self.emit(PoolEvent::ProxyRemoved { id });
+                report.removed += 1;
+            }
+        }
+
+        report
     }
 
-    // 添加自动重试功能，遇到失败连接时
-    pub async fn retry_connections(&self) -> bool {
-        let mut any_updated = false;
-        let mut proxies_lock = self.proxies.lock().unwrap();
-        
-        // 检查是否有失败的代理需要重试
-        let mut failed_proxies: Vec<String> = Vec::new();
-        for (id, proxy) in proxies_lock.iter() {
+    /// 记录来源的同步结果，未给出位置时沿用之前的位置
+    fn record_sync(&self, source: ProxySource, origin: Option<String>, outcome: std::result::Result<SyncReport, String>) {
+        let mut records = self.sync_records.lock().unwrap();
+        let record = records.entry(source).or_default();
+        if origin.is_some() {
+            record.origin = origin;
+        }
+        record.at = Some(crate::time::Stamp::now());
+        match outcome {
+            Ok(report) => {
+                record.report = Some(report);
+                record.error = None;
+            }
+            Err(e) => record.error = Some(e),
+        }
+    }
+
+    /// 各来源的代理数与最近一次同步的结果
+    pub async fn source_status(&self) -> Vec<SourceStatus> {
+        let mut counts: HashMap<ProxySource, usize> = HashMap::new();
+        for proxy in self.proxies.read_all().await.iter() {
+            for source in &proxy.sources {
+                *counts.entry(*source).or_default() += 1;
+            }
+        }
+        let records = self.sync_records.lock().unwrap();
+        ProxySource::ALL.iter()
+            .map(|source| SourceStatus::new(
+                *source,
+                counts.get(source).copied().unwrap_or_default(),
+                records.get(source).cloned().unwrap_or_default(),
+            ))
+            .collect()
+    }
+
+    /// 从池中移除代理，返回被移除的代理
+    pub async fn remove(&self, id: &str) -> Option<Proxy> {
+        let removed = self.proxies.remove(id).await;
+        if removed.is_some() {
+            self.emit(PoolEvent::ProxyRemoved { id: id.to_string() });
+            self.check_capacity();
+        }
+        removed
+    }
+
+    /// 修改代理的元数据（权重、位置标签、认证信息），返回修改后的代理，代理不存在时返回None
+    ///
+    /// 状态与统计保持不变，`f` 不应修改 `ProxyInfo` 中的状态与测试结果。
+    /// 修改后与池中另一个代理的规范键相同时拒绝修改，代理保持原样。
+    pub async fn edit(&self, id: &str, f: impl FnOnce(&mut ProxyInfo)) -> Result<Option<Proxy>> {
+        let mut proxies = self.proxies.write_all().await;
+        let Some(proxy) = proxies.get_mut(id) else {
+            return Ok(None);
+        };
+        let mut info = proxy.info.clone();
+        f(&mut info);
+        let key = canonical_key(&info.host, info.port, &info.proxy_type, info.username.as_deref());
+        if proxies.iter().any(|p| p.id != id && p.canonical_key() == key) {
+            return Err(crate::error::Error::Other(format!(
+                "Proxy {}:{} already exists with the same credentials", info.host, info.port)));
+        }
+        let proxy = proxies.get_mut(id).expect("持有写锁期间代理不会被移除");
+        proxy.info = info;
+        Ok(Some(proxy.clone()))
+    }
+
+    /// 将代理移出池并加入永久黑名单，代理不存在时返回false
+    pub async fn block(&self, id: &str, reason: BlockReason) -> bool {
+        let Some(proxy) = self.remove(id).await else {
+            return false;
+        };
+        warn!("代理 {}:{} 已加入永久黑名单: {}", proxy.info.host, proxy.info.port, reason);
+        self.blocklist.lock().unwrap().insert(&proxy.info.host, proxy.info.port, reason);
+        true
+    }
+
+    /// 报告检测到的恶意行为，代理会被立即加入永久黑名单并发布告警事件
+    pub async fn report_threat(&self, id: &str, threat: Threat) -> bool {
+        let Some((host, port)) = self.proxies.get(id).await
+            .map(|p| (p.info.host, p.info.port))
+        else {
+            return false;
+        };
+        warn!("检测到恶意代理 {}:{}: {}", host, port, threat);
+        self.emit(PoolEvent::ThreatDetected { id: id.to_string(), host, port, threat });
+        self.block(id, BlockReason::Malicious).await
+    }
+
+    /// 通过 `screen_urls` 检测所有代理，返回被拉黑的代理ID及原因
+    pub async fn screen(&self) -> Vec<(String, Threat)> {
+        let options = self.options();
+        if options.screen_urls.is_empty() {
+            return Vec::new();
+        }
+        let timeout = Duration::from_secs(options.screen_timeout);
+        let proxies = self.get_all_proxies().await;
+        let screen_urls = &options.screen_urls;
+        let checks = proxies.iter().map(|proxy| async move {
+            for url in screen_urls {
+                if let Some(threat) = honeypot::inspect(proxy, url, timeout).await {
+                    return Some((proxy.id.clone(), threat));
+                }
+            }
+            None
+        });
+
+        let detected: Vec<(String, Threat)> = futures::future::join_all(checks)
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        for (id, threat) in &detected {
+            self.report_threat(id, threat.clone()).await;
+        }
+        detected
+    }
+
+    /// 按客户端与目标累计的流量
+    pub fn traffic(&self) -> &TrafficAccounting {
+        &self.traffic
+    }
+
+    /// 代理地址是否在永久黑名单中
+    pub fn is_blocked(&self, host: &str, port: u16) -> bool {
+        self.blocklist.lock().unwrap().contains(host, port)
+    }
+
+    /// 永久黑名单的所有条目，键为地址哈希
+    pub fn blocklist_entries(&self) -> Vec<(String, BlockEntry)> {
+        self.blocklist.lock().unwrap().entries()
+    }
+
+    /// 按地址哈希移除永久黑名单条目
+    pub fn unblock(&self, key: &str) -> bool {
+        self.blocklist.lock().unwrap().remove(key)
+    }
+
+    /// 清空永久黑名单，返回移除的条目数
+    pub fn clear_blocklist(&self) -> usize {
+        self.blocklist.lock().unwrap().clear()
+    }
+
+    /// 更新指定代理的状态，代理不存在时返回false
+    pub async fn update_status(&self, id: &str, status: ProxyStatus) -> bool {
+        let updated = self.proxies.update(id, |proxy| {
+            let old = proxy.status;
+            proxy.update_status(status);
+            self.emit_status_change(proxy, old);
+        }).await.is_some();
+        if updated {
+            self.check_capacity();
+        }
+        updated
+    }
+
+    /// 将代理加入黑名单，冷却期内不会被选中，代理不存在时返回false
+    pub async fn blacklist(&self, id: &str, duration: Duration) -> bool {
+        let updated = self.proxies.update(id, |proxy| proxy.blacklist(duration)).await.is_some();
+        if updated {
+            self.check_capacity();
+        }
+        updated
+    }
+
+    /// 提前将代理移出黑名单
+    pub async fn unblacklist(&self, id: &str) -> bool {
+        self.proxies.update(id, |proxy| {
+            proxy.blacklisted_until = None;
+            proxy.consecutive_failures = 0;
+        }).await.is_some()
+    }
+
+    /// 恢复被临时拉黑或处于隔离观察的代理：移出黑名单、清零连续失败次数，隔离中的代理直接恢复为可用，代理不存在时返回false
+    pub async fn reinstate(&self, id: &str) -> bool {
+        let updated = self.proxies.update(id, |proxy| {
+            proxy.blacklisted_until = None;
+            proxy.consecutive_failures = 0;
+            if proxy.status == ProxyStatus::Quarantined {
+                let old = proxy.status;
+                proxy.update_status(ProxyStatus::Available);
+                self.emit_status_change(proxy, old);
+            }
+        }).await.is_some();
+        if updated {
+            self.check_capacity();
+        }
+        updated
+    }
+
+    /// 记录代理是否支持UDP ASSOCIATE，代理不存在时返回false
+    pub async fn set_udp_support(&self, id: &str, supported: bool) -> bool {
+        self.proxies.update(id, |proxy| proxy.udp = Some(supported)).await.is_some()
+    }
+
+    /// 报告一次实际连接的结果
+    ///
+    /// 连续失败达到 `blacklist_after_failures` 次后代理会被临时加入黑名单，
+    /// 返回本次报告是否触发了黑名单。
+    pub async fn report_connection(&self, id: &str, success: bool) -> bool {
+        let Some(blacklisted) = self.proxies.update(id, |proxy| self.record_outcome(proxy, success)).await else {
+            return false;
+        };
+        self.check_capacity();
+        blacklisted
+    }
+
+    /// 在分片锁内记录连接结果，返回是否触发了黑名单
+    fn record_outcome(&self, proxy: &mut Proxy, success: bool) -> bool {
+        if !success {
+            proxy.usage.connect_failures.fetch_add(1, Ordering::Relaxed);
+        }
+        if proxy.breaker.record(success, self.opts().circuit_failure_threshold) {
+            self.on_circuit_change(proxy);
+        }
+        let blacklisted = proxy.record_connection(
+            success,
+            self.opts().blacklist_after_failures,
+            Duration::from_secs(self.opts().blacklist_duration),
+        );
+        if blacklisted {
+            warn!("代理 {}:{} 连续连接失败，加入黑名单 {} 秒",
+                proxy.info.host, proxy.info.port, self.opts().blacklist_duration);
+        }
+        self.settle_quarantine(proxy);
+        self.settle_probation(proxy);
+        blacklisted
+    }
+
+    /// 测试成功后更新代理状态
+    ///
+    /// 从失败中恢复的代理先进入隔离观察期，首次通过测试的新代理进入试用期。
+    fn mark_recovered(&self, proxy: &mut Proxy, latency: Option<u64>) {
+        let old = proxy.status;
+        let recovering = matches!(proxy.status, ProxyStatus::Failed | ProxyStatus::Quarantined);
+        let is_new = matches!(proxy.status, ProxyStatus::Untested | ProxyStatus::Unknown);
+        if recovering && self.opts().quarantine_period > 0 {
             if proxy.status == ProxyStatus::Failed {
-                failed_proxies.push(id.clone());
+                proxy.enter_quarantine();
+            }
+            proxy.update_status_and_latency(ProxyStatus::Quarantined, latency);
+        } else {
+            proxy.update_status_and_latency(ProxyStatus::Available, latency);
+            if is_new && self.opts().probation_period > 0 {
+                proxy.enter_probation();
             }
         }
-        
-        // 如果有失败的代理，则尝试重新测试
-        if !failed_proxies.is_empty() {
-            let tester = Tester::new(TestOptions::default());
-            
-            for id in failed_proxies {
-                if let Some(proxy) = proxies_lock.get_mut(&id) {
-                    let mut proxy_clone = proxy.clone();
-                    if let Ok(result) = tester.test_proxy(&mut proxy_clone) {
-                        if result.success {
-                            proxy.update_status_and_latency(ProxyStatus::Available, result.latency);
-                            any_updated = true;
-                        }
+        self.emit_status_change(proxy, old);
+    }
+
+    /// 试用期结束后根据实际错误率决定转为正式代理或转入隔离观察
+    fn settle_probation(&self, proxy: &mut Proxy) {
+        let Some(probation) = &proxy.probation else {
+            return;
+        };
+        if probation.since.elapsed() < Duration::from_secs(self.opts().probation_period) {
+            return;
+        }
+
+        let error_rate = probation.error_rate();
+        if error_rate <= self.opts().probation_max_error_rate {
+            info!("代理 {}:{} 通过试用期 (错误率 {:.1}%)，加入正常轮换",
+                proxy.info.host, proxy.info.port, error_rate * 100.0);
+            proxy.probation = None;
+        } else {
+            warn!("代理 {}:{} 试用期错误率过高 ({:.1}%)，转入隔离观察",
+                proxy.info.host, proxy.info.port, error_rate * 100.0);
+            let old = proxy.status;
+            proxy.enter_quarantine();
+            self.emit_status_change(proxy, old);
+        }
+    }
+
+    /// 观察期结束后根据实际错误率决定晋升为可用或重新标记为失败
+    fn settle_quarantine(&self, proxy: &mut Proxy) {
+        let Some(quarantine) = &proxy.quarantine else {
+            return;
+        };
+        if quarantine.since.elapsed() < Duration::from_secs(self.opts().quarantine_period) {
+            return;
+        }
+
+        let error_rate = quarantine.error_rate();
+        let old = proxy.status;
+        if error_rate <= self.opts().quarantine_max_error_rate {
+            info!("代理 {}:{} 通过隔离观察 (错误率 {:.1}%)，恢复全部流量",
+                proxy.info.host, proxy.info.port, error_rate * 100.0);
+            proxy.update_status(ProxyStatus::Available);
+        } else {
+            warn!("代理 {}:{} 隔离期错误率过高 ({:.1}%)，重新标记为失败",
+                proxy.info.host, proxy.info.port, error_rate * 100.0);
+            proxy.update_status(ProxyStatus::Failed);
+        }
+        self.emit_status_change(proxy, old);
+    }
+
+    /// 获取可用代理
+    ///
+    /// 按 `strategy` 从可用代理中选择；隔离期代理按 `quarantine_traffic_ratio` 的比例接收流量，
+    /// 没有可用代理时也会退而使用隔离期代理。
+    pub async fn get_available(&self) -> Option<Proxy> {
+        self.get_available_filtered(|_| true).await
+    }
+
+    /// 获取满足额外条件的可用代理，选择规则与 `get_available` 相同
+    pub async fn get_available_filtered<F>(&self, filter: F) -> Option<Proxy>
+    where
+        F: Fn(&Proxy) -> bool,
+    {
+        if let Some(selected) = self.select_indexed(&filter) {
+            return selected;
+        }
+        let mut proxies = self.proxies.write_all().await;
+        self.select_locked(&mut proxies, filter)
+    }
+
+    /// 按流量类别获取可用代理
+    ///
+    /// 交互流量只从延迟最低的 `interactive_reserve` 个代理中选择，批量流量只从其余代理中选择；
+    /// 对应的代理都不可用时退回到全部代理。未启用保留时与 `get_available` 相同。
+    pub async fn get_available_for(&self, class: TrafficClass) -> Option<Proxy> {
+        if let Some(reserved) = self.reserved_for_interactive() {
+            let lane = |proxy: &Proxy| reserved.contains(&proxy.id) == (class == TrafficClass::Interactive);
+            if let Some(proxy) = self.get_available_filtered(lane).await {
+                return Some(proxy);
+            }
+            debug!("{} 流量没有可用的专属代理，使用全部代理", class);
+        }
+        self.get_available().await
+    }
+
+    /// 按流量类别与额外条件选择代理并占用一个连接名额，类别的处理与 `get_available_for` 相同
+    pub async fn acquire_for<F>(&self, class: TrafficClass, filter: F) -> Option<(Proxy, ConnectionGuard)>
+    where
+        F: Fn(&Proxy) -> bool,
+    {
+        if let Some(reserved) = self.reserved_for_interactive() {
+            let lane = |proxy: &Proxy| reserved.contains(&proxy.id) == (class == TrafficClass::Interactive) && filter(proxy);
+            if let Some(selected) = self.acquire_filtered(lane).await {
+                return Some(selected);
+            }
+            debug!("{} 流量没有可用的专属代理，使用全部代理", class);
+        }
+        self.acquire_filtered(filter).await
+    }
+
+    /// 保留给交互流量的代理ID：正常轮换层级中延迟最低、未被拉黑或冷却的代理，未启用时返回None
+    fn reserved_for_interactive(&self) -> Option<HashSet<String>> {
+        let reserve = self.opts().interactive_reserve;
+        if reserve == 0 {
+            return None;
+        }
+        let index = self.proxies.index();
+        let reserved = index.by_latency(Tier::Regular)
+            .filter(|proxy| !proxy.is_blacklisted() && !proxy.is_retired())
+            .take(reserve)
+            .map(|proxy| proxy.id.clone())
+            .collect();
+        Some(reserved)
+    }
+
+    /// 选择代理并占用一个连接名额，连接结束时释放返回的 `ConnectionGuard`
+    ///
+    /// 达到 `max_conns_per_proxy` 上限的代理会被跳过，转而选择下一个候选代理。
+    pub async fn acquire(&self) -> Option<(Proxy, ConnectionGuard)> {
+        self.acquire_filtered(|_| true).await
+    }
+
+    /// 按额外条件选择代理并占用一个连接名额
+    pub async fn acquire_filtered<F>(&self, filter: F) -> Option<(Proxy, ConnectionGuard)>
+    where
+        F: Fn(&Proxy) -> bool,
+    {
+        if let Some(selected) = self.select_indexed(&filter) {
+            let proxy = selected?;
+            let guard = ConnectionGuard::new(Arc::clone(&proxy.usage));
+            return Some((proxy, guard));
+        }
+        // 选择与计数在同一组写锁内完成，避免并发连接越过上限
+        let mut proxies = self.proxies.write_all().await;
+        let proxy = self.select_locked(&mut proxies, filter)?;
+        let guard = ConnectionGuard::new(Arc::clone(&proxy.usage));
+        Some((proxy, guard))
+    }
+
+    /// 不需要修改代理状态时直接从选择索引中挑选，需要写锁时返回None
+    ///
+    /// 最低延迟与轮询策略沿索引的有序结构取第一个合格的代理，复杂度为O(log n)；
+    /// 随机类策略仍需收集一个层级的全部候选。
+    fn select_indexed<F>(&self, filter: &F) -> Option<Option<Proxy>>
+    where
+        F: Fn(&Proxy) -> bool,
+    {
+        let index = self.proxies.index();
+        if self.selection_needs_write(&index) {
+            return None;
+        }
+        let selected = match self.strategy() {
+            SelectionStrategy::LowestLatency => self.choose(|tier| index.by_latency(tier), true, filter).cloned(),
+            SelectionStrategy::RoundRobin => {
+                let mut last = self.rr_last.lock().unwrap();
+                let selected = self.choose(|tier| index.after(tier, &last), true, filter).cloned();
+                if let Some(proxy) = &selected {
+                    last.clone_from(&proxy.id);
+                }
+                selected
+            }
+            SelectionStrategy::Random | SelectionStrategy::WeightedRandom => {
+                self.choose(|tier| index.by_id(tier), false, filter).cloned()
+            }
+        };
+        Some(selected)
+    }
+
+    /// 本次选择是否需要修改代理状态
+    ///
+    /// 并发上限、强制轮换、试用期、隔离观察与熔断都需要在选择时更新状态，
+    /// 都未启用时直接从选择索引中挑选，不需要获取任何分片锁。
+    fn selection_needs_write(&self, index: &SelectionIndex) -> bool {
+        self.opts().max_conns_per_proxy > 0
+            || self.opts().rotate_after_requests > 0
+            || self.opts().rotate_after_secs > 0
+            || index.needs_write()
+    }
+
+    /// 在已持有所有分片写锁的情况下选择代理
+    fn select_locked<F>(&self, proxies: &mut ShardsWrite<'_>, filter: F) -> Option<Proxy>
+    where
+        F: Fn(&Proxy) -> bool,
+    {
+        let open_duration = Duration::from_secs(self.opts().circuit_open_duration);
+        for proxy in proxies.iter_mut() {
+            self.settle_quarantine(proxy);
+            self.settle_probation(proxy);
+            if self.rotation_due(proxy) {
+                self.retire(proxy);
+            }
+            if proxy.breaker.refresh(open_duration) {
+                self.on_circuit_change(proxy);
+            }
+        }
+
+        let id = self.choose(|tier| proxies.iter().filter(move |p| tier.matches(p)), false, &filter)?.id.clone();
+        let proxy = proxies.get_mut(&id)?;
+        self.record_rotation_usage(proxy);
+        proxy.breaker.on_selected();
+        Some(proxy.clone())
+    }
+
+    /// 按层级与策略选出代理，不修改代理状态
+    ///
+    /// `members` 返回某一层级的代理；`ordered` 表示其已经按策略的优先顺序排列，
+    /// 此时取第一个合格的代理即可，不必收集全部候选。
+    fn choose<'a, I, F>(&self, members: impl Fn(Tier) -> I, ordered: bool, filter: &F) -> Option<&'a Proxy>
+    where
+        I: Iterator<Item = &'a Proxy>,
+        F: Fn(&Proxy) -> bool,
+    {
+        let open_duration = Duration::from_secs(self.opts().circuit_open_duration);
+        let max_conns = self.opts().max_conns_per_proxy;
+        let cursor = self.rr_cursor.fetch_add(1, Ordering::Relaxed);
+        let fairness_guard = self.fairness.load();
+        let mut fairness = fairness_guard.as_ref().map(|guard| guard.lock());
+        let best = |tier: Tier, fair: bool| {
+            let mut eligible = members(tier)
+                .filter(|p| !p.is_blacklisted() && !p.is_retired())
+                .filter(|p| p.breaker.allows(open_duration))
+                .filter(|p| max_conns == 0 || p.active_connections() < max_conns)
+                .filter(|p| !fair || fairness.as_ref().is_none_or(|state| state.allows(&p.id)))
+                .filter(|p| filter(p));
+            if ordered {
+                return eligible.next();
+            }
+            let candidates: Vec<&Proxy> = eligible.collect();
+            self.strategy().select(&candidates, cursor)
+        };
+
+        // 隔离期与试用期代理按各自比例轮到优先选择，其余时候作为后备
+        let selection = self.selections.fetch_add(1, Ordering::Relaxed);
+        let turn = |ratio: f64| ratio > 0.0 && selection.is_multiple_of((1.0 / ratio).round().max(1.0) as u64);
+        let order = if turn(self.opts().quarantine_traffic_ratio) {
+            [Tier::Quarantined, Tier::Regular, Tier::Probation]
+        } else if turn(self.opts().probation_traffic_ratio) {
+            [Tier::Probation, Tier::Regular, Tier::Quarantined]
+        } else {
+            [Tier::Regular, Tier::Probation, Tier::Quarantined]
+        };
+        let mut selected = order.into_iter().find_map(|tier| best(tier, true));
+        if selected.is_none() && fairness.is_some() {
+            // 所有候选都已用完份额时不拒绝请求，按原有规则选择
+            debug!("所有候选代理都超过流量份额上限，忽略公平性限制");
+            selected = order.into_iter().find_map(|tier| best(tier, false));
+        }
+        if let (Some(state), Some(proxy)) = (fairness.as_mut(), selected) {
+            state.record(&proxy.id);
+        }
+        if selected.is_some() {
+            self.selection_counts.record(self.strategy());
+        }
+        selected
+    }
+
+    /// 熔断器状态变化时记录日志并发布事件
+    fn on_circuit_change(&self, proxy: &Proxy) {
+        let state = proxy.breaker.state();
+        match state {
+            CircuitState::Open => warn!("代理 {}:{} 连接持续失败，熔断 {} 秒",
+                proxy.info.host, proxy.info.port, self.opts().circuit_open_duration),
+            CircuitState::HalfOpen => debug!("代理 {}:{} 熔断结束，放行试探连接", proxy.info.host, proxy.info.port),
+            CircuitState::Closed => info!("代理 {}:{} 试探连接成功，恢复正常", proxy.info.host, proxy.info.port),
+        }
+        self.emit(PoolEvent::CircuitChanged { id: proxy.id.clone(), state });
+    }
+
+    /// 判断代理是否已达到强制轮换条件
+    fn rotation_due(&self, proxy: &Proxy) -> bool {
+        let by_requests = self.opts().rotate_after_requests > 0
+            && proxy.rotation_requests >= self.opts().rotate_after_requests;
+        let by_time = self.opts().rotate_after_secs > 0
+            && proxy.rotation_started.is_some_and(|since| {
+                since.elapsed() >= Duration::from_secs(self.opts().rotate_after_secs)
+            });
+        by_requests || by_time
+    }
+
+    /// 让代理进入轮换冷却
+    fn retire(&self, proxy: &mut Proxy) {
+        info!("代理 {}:{} 本轮已分配 {} 个请求，轮换冷却 {} 秒",
+            proxy.info.host, proxy.info.port, proxy.rotation_requests, self.opts().rotate_cooldown);
+        proxy.retire(Duration::from_secs(self.opts().rotate_cooldown));
+    }
+
+    /// 记录一次分配，达到请求数上限后立即进入冷却
+    fn record_rotation_usage(&self, proxy: &mut Proxy) {
+        if self.opts().rotate_after_requests == 0 && self.opts().rotate_after_secs == 0 {
+            return;
+        }
+        proxy.rotation_started.get_or_insert_with(Instant::now);
+        proxy.rotation_requests += 1;
+        if self.rotation_due(proxy) {
+            self.retire(proxy);
+        }
+    }
+
+    /// 当前的指标快照：各状态的代理数、可用代理的延迟分位数、连接计数与各策略的选择次数
+    pub async fn metrics(&self) -> PoolMetrics {
+        let proxies = self.proxies.read_all().await;
+        PoolMetrics::collect(proxies.iter(), self.selection_counts.snapshot())
+    }
+
+    /// 将所有代理的状态、延迟与成功率保存到快照文件
+    pub async fn save_snapshot<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        let snapshot = {
+            let proxies = self.proxies.read_all().await;
+            PoolSnapshot::capture(proxies.iter())
+        };
+        snapshot.save(path)
+    }
+
+    /// 从快照文件恢复地址匹配的代理的状态，返回快照及恢复的代理ID
+    pub async fn load_snapshot<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(PoolSnapshot, Vec<String>)> {
+        let snapshot = PoolSnapshot::load(path)?;
+        let restored = snapshot.apply(self.proxies.write_all().await.iter_mut());
+        self.check_capacity();
+        Ok((snapshot, restored))
+    }
+
+    /// 获取所有代理，用于调试
+    pub async fn get_all_proxies(&self) -> Vec<Proxy> {
+        self.proxies.read_all().await.iter().cloned().collect()
+    }
+
+    /// 测试所有代理
+    ///
+    /// 测试在锁外并发进行，并发数受 `retry_concurrency` 限制，完成后再获取写锁应用结果，测试期间代理选择不受影响。
+    pub async fn test_all(&self) -> Vec<(ProxyConfig, TestResult)> {
+        let Some(_permit) = self.lifecycle.begin_test() else {
+            debug!("代理池正在关闭，跳过测试");
+            return Vec::new();
+        };
+        let targets = self.get_all_proxies().await;
+        let concurrency = self.opts().retry_concurrency.max(1);
+        let outcomes: Vec<_> = futures::stream::iter(targets.into_iter().map(Self::run_test))
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+        let mut proxies_lock = self.proxies.write_all().await;
+        let results = outcomes
+            .into_iter()
+            .filter_map(|(id, outcome)| {
+                let proxy = proxies_lock.get_mut(&id)?;
+                Some((proxy.to_config(), self.apply_test(proxy, outcome)))
+            })
+            .collect();
+        self.evict_dead_locked(&mut proxies_lock);
+        drop(proxies_lock);
+        self.check_capacity();
+        results
+    }
+
+    /// 测试单个代理，代理不存在或代理池正在关闭时返回None
+    pub async fn test_proxy(&self, id: &str) -> Option<TestResult> {
+        let _permit = self.lifecycle.begin_test()?;
+        let target = self.proxies.get(id).await?;
+        let (_, outcome) = Self::run_test(target).await;
+
+        let result = self.proxies.update(id, |proxy| self.apply_test(proxy, outcome)).await?;
+        self.check_capacity();
+        Some(result)
+    }
+
+    /// 在阻塞线程池中测试代理的副本，不持有任何锁
+    async fn run_test(mut proxy: Proxy) -> (String, Result<TestResult>) {
+        let id = proxy.id.clone();
+        let outcome = tokio::task::spawn_blocking(move || {
+            Tester::new(TestOptions::default()).test_proxy(&mut proxy)
+        })
+        .await
+        .unwrap_or_else(|e| Err(crate::error::Error::Test(e.to_string())));
+        (id, outcome)
+    }
+
+    /// 将测试结果应用到代理状态
+    fn apply_test(&self, proxy: &mut Proxy, outcome: Result<TestResult>) -> TestResult {
+        let old = proxy.status;
+
+        let result = match outcome {
+            Ok(result) => {
+                if result.success {
+                    proxy.consecutive_test_failures = 0;
+                    proxy.failing_since = None;
+                    proxy.retry_after = None;
+                    self.mark_recovered(proxy, result.latency);
+                } else {
+                    Self::record_test_failure(proxy);
+                    proxy.update_status_and_latency(ProxyStatus::Failed, None);
+                    self.emit_status_change(proxy, old);
+                }
+                result
+            },
+            Err(e) => {
+                // 更新代理状态为失败
+                Self::record_test_failure(proxy);
+                proxy.update_status(ProxyStatus::Failed);
+                self.emit_status_change(proxy, old);
+
+                // 创建失败的测试结果
+                TestResult {
+                    proxy_id: proxy.id.clone(),
+                    success: false,
+                    latency: None,
+                    error: Some(e.to_string()),
+                    timestamp: crate::time::wall_now(),
+                }
+            }
+        };
+
+        self.emit(PoolEvent::TestCompleted {
+            id: proxy.id.clone(),
+            success: result.success,
+            latency: result.latency,
+        });
+        result
+    }
+
+    /// 记录一次健康检查失败
+    fn record_test_failure(proxy: &mut Proxy) {
+        proxy.consecutive_test_failures += 1;
+        proxy.failing_since.get_or_insert_with(Instant::now);
+    }
+
+    /// 移除持续失败的代理，并追加记录到 `dead_list_file`
+    fn evict_dead_locked(&self, proxies: &mut ShardsWrite<'_>) {
+        let threshold = self.opts().evict_after_failures;
+        if threshold == 0 {
+            return;
+        }
+
+        let min_duration = Duration::from_secs(self.opts().evict_min_failing_duration);
+        let dead: Vec<String> = proxies.iter()
+            .filter(|p| p.consecutive_test_failures >= threshold)
+            .filter(|p| p.failing_since.is_some_and(|since| since.elapsed() >= min_duration))
+            .map(|p| p.id.clone())
+            .collect();
+        if dead.is_empty() {
+            return;
+        }
+
+        let mut evicted = Vec::with_capacity(dead.len());
+        for id in dead {
+            if let Some(proxy) = proxies.remove(&id) {
+                warn!("代理 {}:{} 连续 {} 次健康检查失败，已从池中移除",
+                    proxy.info.host, proxy.info.port, proxy.consecutive_test_failures);
+                self.emit(PoolEvent::ProxyRemoved { id });
+                evicted.push(proxy);
+            }
+        }
+
+        if let Some(path) = &self.opts().dead_list_file {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| {
+                    evicted.iter().try_for_each(|proxy| writeln!(file, "{}", proxy.url()))
+                });
+            if let Err(e) = written {
+                warn!("写入失效代理列表 {} 失败: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// 启动定期测试，按 `test_interval` 测试所有代理，`auto_test` 关闭期间跳过
+    ///
+    /// 每轮等待前重新读取选项，运行时修改的间隔从下一轮开始生效。
+    pub fn start_auto_test(&self) -> tokio::task::JoinHandle<()> {
+        let pool = self.handle();
+        let task = supervise("代理池定期测试", Backoff::default(), move || {
+            let pool = pool.clone();
+            async move {
+                loop {
+                    let interval = Duration::from_secs(pool.opts().test_interval.max(1));
+                    if tokio::time::timeout(interval, pool.lifecycle.closed()).await.is_ok() {
+                        return;
+                    }
+                    if pool.opts().auto_test {
+                        let results = pool.test_all().await;
+                        debug!("定期测试完成，共 {} 个代理", results.len());
                     }
                 }
             }
+        });
+        self.lifecycle.track(&task);
+        task
+    }
+
+    /// 监视代理列表文件，每隔 `interval` 检查一次，修改时间或大小变化后重新同步文件来源
+    pub fn watch_file(&self, path: PathBuf, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let pool = self.handle();
+        // 在调用时记录文件版本，任务开始运行前发生的修改也会被发现
+        let initial = source::file_version(&path);
+        let task = supervise("代理文件监视", Backoff::default(), move || {
+            let pool = pool.clone();
+            let path = path.clone();
+            async move {
+                let mut version = initial;
+                loop {
+                    if tokio::time::timeout(interval, pool.lifecycle.closed()).await.is_ok() {
+                        return;
+                    }
+                    let current = source::file_version(&path);
+                    if current == version {
+                        continue;
+                    }
+                    version = current;
+                    match pool.sync_file(&path).await {
+                        Ok(report) => info!("代理文件 {} 已变化，重新同步: {}", path.display(), report),
+                        Err(e) => warn!("重新读取代理文件 {} 失败，保留现有代理: {}", path.display(), e),
+                    }
+                }
+            }
+        });
+        self.lifecycle.track(&task);
+        task
+    }
+
+    /// 在后台重试失败的代理，返回的任务在本轮重试结束后给出结果
+    pub fn spawn_retry(&self) -> tokio::task::JoinHandle<Option<RetrySummary>> {
+        let pool = self.handle();
+        let task = tokio::spawn(async move { pool.retry_connections().await });
+        self.lifecycle.track(&task);
+        task
+    }
+
+    /// 重新测试退避期已过的失败代理，已有一轮重试在进行或代理池正在关闭时返回None
+    ///
+    /// 测试在锁外进行，并发数受 `retry_concurrency` 限制；每个结果完成后只锁住所在分片应用。
+    /// 仍然失败的代理按连续失败次数指数退避，退避期内的代理不会被重复测试。
+    pub async fn retry_connections(&self) -> Option<RetrySummary> {
+        let Ok(_running) = self.retrying.try_lock() else {
+            debug!("已有一轮失败代理重试在进行，跳过");
+            return None;
+        };
+        let _permit = self.lifecycle.begin_test()?;
+
+        let now = Instant::now();
+        let mut summary = RetrySummary::default();
+        let due: Vec<Proxy> = self.proxies.read_all().await
+            .iter()
+            .filter(|proxy| proxy.status == ProxyStatus::Failed)
+            .filter(|proxy| {
+                let due = proxy.retry_after.is_none_or(|at| at <= now);
+                summary.deferred += !due as usize;
+                due
+            })
+            .cloned()
+            .collect();
+
+        let concurrency = self.opts().retry_concurrency.max(1);
+        let mut tests = futures::stream::iter(due.into_iter().map(Self::run_test)).buffer_unordered(concurrency);
+        while let Some((id, outcome)) = tests.next().await {
+            let recovered = self.proxies.update(&id, |proxy| {
+                let success = self.apply_test(proxy, outcome).success;
+                if !success {
+                    proxy.retry_after = Some(Instant::now() + self.retry_backoff(proxy.consecutive_test_failures));
+                }
+                success
+            }).await;
+            match recovered {
+                Some(true) => summary.recovered += 1,
+                Some(false) => summary.failed += 1,
+                // 测试期间代理已被移除
+                None => continue,
+            }
+            summary.attempted += 1;
         }
-        
-        any_updated
+
+        if self.opts().evict_after_failures > 0 {
+            self.evict_dead_locked(&mut self.proxies.write_all().await);
+        }
+        self.check_capacity();
+
+        if summary.attempted > 0 {
+            info!("失败代理重试完成: 测试 {} 个, 恢复 {} 个, 仍失败 {} 个, 退避中 {} 个",
+                summary.attempted, summary.recovered, summary.failed, summary.deferred);
+        }
+        Some(summary)
     }
+
+    /// 连续失败 `failures` 次后的重试退避时间
+    fn retry_backoff(&self, failures: u32) -> Duration {
+        let initial = Duration::from_secs(self.opts().retry_backoff);
+        let max = Duration::from_secs(self.opts().retry_backoff_max);
+        initial.saturating_mul(1 << failures.saturating_sub(1).min(16)).min(max)
+    }
+
+    /// 关闭代理池
+    ///
+    /// 不再开始新的测试，在 `timeout` 内等待进行中的测试结束，随后取消定期测试、重试等后台任务，
+    /// 最后写回永久黑名单并保存状态快照（设置了 `snapshot_file` 时）。
+    /// 关闭后代理选择仍然可用，只是状态不会再被测试更新；重复调用是安全的。
+    pub async fn shutdown(&self, timeout: Duration) -> Result<ShutdownReport> {
+        info!("正在关闭代理池...");
+        let interrupted_tests = self.lifecycle.close(timeout).await;
+        if interrupted_tests > 0 {
+            warn!("等待 {:?} 后仍有 {} 个测试未结束，结果将被丢弃", timeout, interrupted_tests);
+        }
+
+        self.blocklist.lock().unwrap().persist();
+        let snapshot_file = self.opts().snapshot_file.clone();
+        if let Some(path) = &snapshot_file {
+            self.save_snapshot(path).await?;
+        }
+
+        Ok(ShutdownReport { interrupted_tests, snapshot_file })
+    }
+}
+
+/// 关闭代理池的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// 超时时仍未结束、结果被丢弃的测试数
+    pub interrupted_tests: usize,
+    /// 保存了状态快照的文件
+    pub snapshot_file: Option<PathBuf>,
 }
 
 /// 代理池管理器，管理多个代理池
+#[derive(Default)]
 pub struct PoolManager {
     pools: HashMap<String, Pool>,
 }
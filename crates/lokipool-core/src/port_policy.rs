@@ -0,0 +1,181 @@
+//! 目标端口策略
+//!
+//! 规则按顺序匹配，第一条命中的规则决定放行还是拒绝，都不命中时使用默认动作。
+//! 典型用法是拒绝SMTP端口，或把默认动作设为拒绝、只放行80与443。
+//! 与访问控制列表同时生效，任一方拒绝即拒绝。
+
+use std::fmt;
+use std::str::FromStr;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::config::PortPolicySettings;
+use crate::error::{Error, Result};
+
+/// 规则命中后的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// 放行
+    #[default]
+    Allow,
+    /// 拒绝
+    Deny,
+}
+
+impl fmt::Display for PolicyAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyAction::Allow => write!(f, "allow"),
+            PolicyAction::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+impl FromStr for PolicyAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "allow" => Ok(PolicyAction::Allow),
+            "deny" => Ok(PolicyAction::Deny),
+            other => Err(Error::Configuration(format!("未知的策略动作: {}", other))),
+        }
+    }
+}
+
+/// 规则适用的传输协议：CONNECT为TCP，UDP ASSOCIATE转发的数据报为UDP
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    /// TCP与UDP
+    #[default]
+    Any,
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Any => write!(f, "any"),
+            Transport::Tcp => write!(f, "tcp"),
+            Transport::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+impl FromStr for Transport {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "any" => Ok(Transport::Any),
+            "tcp" => Ok(Transport::Tcp),
+            "udp" => Ok(Transport::Udp),
+            other => Err(Error::Configuration(format!("未知的传输协议: {}", other))),
+        }
+    }
+}
+
+/// 端口或端口范围，写作 `25` 或 `8000-8999`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortRange {
+    start: u16,
+    end: u16,
+}
+
+impl PortRange {
+    pub fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Configuration(format!("无效的端口范围: {}", s));
+        let (start, end) = s.trim().split_once('-').unwrap_or((s.trim(), s.trim()));
+        let start = start.trim().parse::<u16>().map_err(|_| invalid())?;
+        let end = end.trim().parse::<u16>().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// 一条端口规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortRule {
+    pub action: PolicyAction,
+    pub ports: Vec<PortRange>,
+    pub protocol: Transport,
+    /// 命中时是否记录日志
+    pub log: bool,
+}
+
+impl PortRule {
+    pub fn matches(&self, port: u16, protocol: Transport) -> bool {
+        (self.protocol == Transport::Any || self.protocol == protocol)
+            && self.ports.iter().any(|range| range.contains(port))
+    }
+}
+
+impl fmt::Display for PortRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ports = self.ports.iter().map(ToString::to_string).collect::<Vec<_>>().join(",");
+        write!(f, "{} {} {}", self.action, self.protocol, ports)
+    }
+}
+
+/// 目标端口策略，默认放行所有端口
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortPolicy {
+    pub default_action: PolicyAction,
+    pub rules: Vec<PortRule>,
+}
+
+impl PortPolicy {
+    /// 解析配置中的规则，任一条无效时返回错误
+    pub fn from_settings(settings: &PortPolicySettings) -> Result<Self> {
+        let rules = settings.rules.iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                if rule.ports.is_empty() {
+                    return Err(Error::Configuration(format!("端口策略第 {} 条规则没有端口", index + 1)));
+                }
+                Ok(PortRule {
+                    action: rule.action,
+                    ports: rule.ports.iter().map(|ports| ports.parse()).collect::<Result<_>>()?,
+                    protocol: rule.protocol,
+                    log: rule.log,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { default_action: settings.default_action, rules })
+    }
+
+    /// 没有规则且默认放行，等于不限制
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.default_action == PolicyAction::Allow
+    }
+
+    /// 判定该端口的动作，同时返回决定结果的规则及其序号（从1开始），未命中任何规则时为 `None`
+    pub fn evaluate(&self, port: u16, protocol: Transport) -> (PolicyAction, Option<(usize, &PortRule)>) {
+        match self.rules.iter().enumerate().find(|(_, rule)| rule.matches(port, protocol)) {
+            Some((index, rule)) => (rule.action, Some((index + 1, rule))),
+            None => (self.default_action, None),
+        }
+    }
+}
@@ -1,10 +1,19 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::fmt;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
+use crate::circuit::{CircuitBreaker, CircuitState};
+use crate::config::ProxyConfig;
+use crate::source::ProxySource;
+use crate::time::Stamp;
 
 /// 代理状态枚举
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
 pub enum ProxyStatus {
     /// 可用
     Available,
@@ -13,15 +22,12 @@ pub enum ProxyStatus {
     /// 失败
     Failed,
     /// 未经测试
+    #[default]
     Untested,
     /// 未知状态
     Unknown,
-}
-
-impl Default for ProxyStatus {
-    fn default() -> Self {
-        Self::Untested
-    }
+    /// 恢复后的隔离观察期，仅接收少量流量
+    Quarantined,
 }
 
 impl fmt::Display for ProxyStatus {
@@ -32,6 +38,7 @@ impl fmt::Display for ProxyStatus {
             ProxyStatus::Failed => write!(f, "Failed"),
             ProxyStatus::Untested => write!(f, "Untested"),
             ProxyStatus::Unknown => write!(f, "Unknown"),
+            ProxyStatus::Quarantined => write!(f, "Quarantined"),
         }
     }
 }
@@ -56,9 +63,16 @@ pub struct ProxyInfo {
     /// 成功率 (0.0-1.0)
     pub success_rate: f64,
     /// 最后检查时间
-    pub last_checked: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_checked: Option<Stamp>,
     /// 当前状态
     pub status: ProxyStatus,
+    /// 选择权重，用于加权随机策略
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
 }
 
 impl ProxyInfo {
@@ -75,6 +89,7 @@ impl ProxyInfo {
             success_rate: 0.0,
             last_checked: None,
             status: ProxyStatus::Untested,
+            weight: default_weight(),
         }
     }
 
@@ -85,6 +100,136 @@ impl ProxyInfo {
     }
 }
 
+/// 代理的规范键，键相同的代理视为同一个上游
+pub fn canonical_key(host: &str, port: u16, proxy_type: &str, username: Option<&str>) -> String {
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+    format!("{}://{}@{}:{}", proxy_type.to_ascii_lowercase(), username.unwrap_or_default(), host, port)
+}
+
+/// 观察窗口内的实际流量统计，用于隔离观察期与新代理试用期
+#[derive(Debug, Clone)]
+pub struct TrialWindow {
+    /// 窗口开始时间
+    pub since: Instant,
+    /// 观察期内成功的连接数
+    pub successes: u32,
+    /// 观察期内失败的连接数
+    pub failures: u32,
+}
+
+impl TrialWindow {
+    fn start() -> Self {
+        Self {
+            since: Instant::now(),
+            successes: 0,
+            failures: 0,
+        }
+    }
+
+    fn record(&mut self, success: bool) {
+        if success {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+    }
+
+    /// 观察期内的错误率，没有流量时视为0
+    pub fn error_rate(&self) -> f64 {
+        let total = self.successes + self.failures;
+        if total == 0 {
+            0.0
+        } else {
+            self.failures as f64 / total as f64
+        }
+    }
+}
+
+/// 代理的实时使用情况，在代理的所有克隆之间共享
+#[derive(Debug, Default)]
+pub struct ProxyUsage {
+    /// 当前活跃连接数
+    pub active_connections: AtomicUsize,
+    /// 累计分配的连接数
+    pub total_connections: AtomicU64,
+    /// 客户端发往上游的字节数
+    pub bytes_up: AtomicU64,
+    /// 上游返回给客户端的字节数
+    pub bytes_down: AtomicU64,
+    /// 连接上游失败的次数
+    pub connect_failures: AtomicU64,
+}
+
+impl ProxyUsage {
+    /// 记录上行流量
+    pub fn record_up(&self, bytes: u64) {
+        self.bytes_up.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// 记录下行流量
+    pub fn record_down(&self, bytes: u64) {
+        self.bytes_down.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// 计数相同的独立副本
+    pub fn copy(&self) -> Self {
+        let stats = self.stats();
+        Self {
+            active_connections: AtomicUsize::new(stats.active_connections),
+            total_connections: AtomicU64::new(stats.total_connections),
+            bytes_up: AtomicU64::new(stats.bytes_up),
+            bytes_down: AtomicU64::new(stats.bytes_down),
+            connect_failures: AtomicU64::new(stats.connect_failures),
+        }
+    }
+
+    /// 当前计数的快照
+    pub fn stats(&self) -> UsageStats {
+        UsageStats {
+            active_connections: self.active_connections.load(Ordering::SeqCst),
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            bytes_up: self.bytes_up.load(Ordering::Relaxed),
+            bytes_down: self.bytes_down.load(Ordering::Relaxed),
+            connect_failures: self.connect_failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 代理使用计数的快照
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct UsageStats {
+    /// 当前活跃连接数
+    pub active_connections: usize,
+    /// 累计分配的连接数
+    pub total_connections: u64,
+    /// 客户端发往上游的字节数
+    pub bytes_up: u64,
+    /// 上游返回给客户端的字节数
+    pub bytes_down: u64,
+    /// 连接上游失败的次数
+    pub connect_failures: u64,
+}
+
+/// 连接占用凭证，释放时自动减少代理的活跃连接数
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    usage: Arc<ProxyUsage>,
+}
+
+impl ConnectionGuard {
+    pub(crate) fn new(usage: Arc<ProxyUsage>) -> Self {
+        usage.active_connections.fetch_add(1, Ordering::SeqCst);
+        usage.total_connections.fetch_add(1, Ordering::Relaxed);
+        Self { usage }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.usage.active_connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 /// 代理实现
 #[derive(Debug, Clone)]
 pub struct Proxy {
@@ -97,7 +242,35 @@ pub struct Proxy {
     /// 延迟（毫秒）
     pub latency: u64,
     /// 最后测试时间
-    pub last_tested: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_tested: Option<Stamp>,
+    /// 实际流量中连续失败的次数
+    pub consecutive_failures: u32,
+    /// 黑名单到期时间，期间不参与代理选择
+    pub blacklisted_until: Option<Instant>,
+    /// 隔离观察状态，仅在 `Quarantined` 状态下存在
+    pub quarantine: Option<TrialWindow>,
+    /// 新代理试用期状态，试用期内仅接收有限比例的流量
+    pub probation: Option<TrialWindow>,
+    /// 实时使用情况
+    pub usage: Arc<ProxyUsage>,
+    /// 连续健康检查失败的次数
+    pub consecutive_test_failures: u32,
+    /// 本轮连续失败开始的时间
+    pub failing_since: Option<Instant>,
+    /// 重试退避到期时间，到期前 `retry_connections` 跳过该代理
+    pub retry_after: Option<Instant>,
+    /// 本轮使用开始的时间，用于强制轮换
+    pub rotation_started: Option<Instant>,
+    /// 本轮已分配的请求数
+    pub rotation_requests: u64,
+    /// 轮换冷却到期时间，期间不参与代理选择
+    pub retired_until: Option<Instant>,
+    /// 基于实际流量的熔断器
+    pub breaker: CircuitBreaker,
+    /// 列出该代理的来源，为空的代理视为运行时添加
+    pub sources: BTreeSet<ProxySource>,
+    /// 是否支持UDP ASSOCIATE，首次经该代理转发UDP时得知，此前为None
+    pub udp: Option<bool>,
 }
 
 impl Proxy {
@@ -119,6 +292,7 @@ impl Proxy {
             success_rate: 0.0,
             last_checked: None,
             status: ProxyStatus::Untested,
+            weight: default_weight(),
         };
 
         Self {
@@ -127,9 +301,87 @@ impl Proxy {
             status: ProxyStatus::Unknown,
             latency: u64::MAX,
             last_tested: None,
+            consecutive_failures: 0,
+            blacklisted_until: None,
+            quarantine: None,
+            probation: None,
+            usage: Arc::new(ProxyUsage::default()),
+            consecutive_test_failures: 0,
+            failing_since: None,
+            retry_after: None,
+            rotation_started: None,
+            rotation_requests: 0,
+            retired_until: None,
+            breaker: CircuitBreaker::default(),
+            sources: BTreeSet::new(),
+            udp: None,
         }
     }
 
+    /// 不与原代理共享使用计数的副本，`clone` 得到的副本共享同一份计数
+    pub fn detached(&self) -> Self {
+        Self { usage: Arc::new(self.usage.copy()), ..self.clone() }
+    }
+
+    /// 从代理配置创建代理，初始状态为未测试
+    pub fn from_config(config: ProxyConfig) -> Self {
+        let mut proxy = Self::new(config.host, config.port, config.username, config.password);
+        proxy.info.location = config.location;
+        proxy.info.proxy_type = config.proxy_type;
+        proxy.info.weight = config.weight;
+        proxy.update_status(ProxyStatus::Untested);
+        proxy
+    }
+
+    /// 规范键，用于去重
+    pub fn canonical_key(&self) -> String {
+        canonical_key(&self.info.host, self.info.port, &self.info.proxy_type, self.info.username.as_deref())
+    }
+
+    /// 合并重复代理的元数据，已有的信息优先
+    pub fn merge_from(&mut self, other: &Proxy) {
+        if self.info.password.is_none() {
+            self.info.password = other.info.password.clone();
+        }
+        if self.info.location.is_none() {
+            self.info.location = other.info.location.clone();
+        }
+        self.info.weight = self.info.weight.max(other.info.weight);
+        self.udp = self.udp.or(other.udp);
+        self.sources.extend(&other.sources);
+    }
+
+    /// 按来源提供的配置更新元数据，状态与统计保持不变
+    pub fn update_from_config(&mut self, config: ProxyConfig) {
+        self.info.password = config.password;
+        self.info.location = config.location;
+        self.info.proxy_type = config.proxy_type;
+        self.info.weight = config.weight;
+    }
+
+    /// 转换为代理配置
+    pub fn to_config(&self) -> ProxyConfig {
+        ProxyConfig {
+            host: self.info.host.clone(),
+            port: self.info.port,
+            username: self.info.username.clone(),
+            password: self.info.password.clone(),
+            location: self.info.location.clone(),
+            proxy_type: self.info.proxy_type.clone(),
+            weight: self.info.weight,
+        }
+    }
+
+    /// 当前活跃连接数
+    pub fn active_connections(&self) -> usize {
+        self.usage.active_connections.load(Ordering::SeqCst)
+    }
+
+    /// 使用计数的快照
+    pub fn usage_stats(&self) -> UsageStats {
+        self.usage.stats()
+    }
+
     /// 获取代理URL
     pub fn url(&self) -> String {
         match (&self.info.username, &self.info.password) {
@@ -144,6 +396,28 @@ impl Proxy {
     pub fn update_status(&mut self, status: ProxyStatus) {
         self.status = status;
         self.info.status = status;
+        if status != ProxyStatus::Quarantined {
+            self.quarantine = None;
+        }
+        if status != ProxyStatus::Available {
+            self.probation = None;
+        }
+    }
+
+    /// 进入隔离观察期
+    pub fn enter_quarantine(&mut self) {
+        self.update_status(ProxyStatus::Quarantined);
+        self.quarantine = Some(TrialWindow::start());
+    }
+
+    /// 进入新代理试用期
+    pub fn enter_probation(&mut self) {
+        self.probation = Some(TrialWindow::start());
+    }
+
+    /// 是否处于试用期
+    pub fn on_probation(&self) -> bool {
+        self.probation.is_some()
     }
 
     /// 更新代理状态和延迟
@@ -153,13 +427,63 @@ impl Proxy {
             self.latency = lat;
             self.update_latency(lat);
         }
-        self.last_tested = Some(chrono::Utc::now());
+        self.last_tested = Some(Stamp::now());
+    }
+
+    /// 将代理加入黑名单
+    pub fn blacklist(&mut self, duration: Duration) {
+        self.blacklisted_until = Some(Instant::now() + duration);
+    }
+
+    /// 暂时退出选择，冷却结束后开始新一轮使用
+    pub fn retire(&mut self, cooldown: Duration) {
+        self.retired_until = Some(Instant::now() + cooldown);
+        self.rotation_started = None;
+        self.rotation_requests = 0;
+    }
+
+    /// 是否处于轮换冷却期
+    pub fn is_retired(&self) -> bool {
+        self.retired_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// 熔断器是否处于打开状态
+    pub fn circuit_open(&self) -> bool {
+        self.breaker.state() == CircuitState::Open
+    }
+
+    /// 是否处于黑名单冷却期，冷却期结束后自动恢复
+    pub fn is_blacklisted(&self) -> bool {
+        self.blacklisted_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// 记录一次实际连接结果，返回是否因连续失败达到阈值而被加入黑名单
+    pub fn record_connection(&mut self, success: bool, blacklist_after: u32, duration: Duration) -> bool {
+        if let Some(quarantine) = self.quarantine.as_mut() {
+            quarantine.record(success);
+        }
+        if let Some(probation) = self.probation.as_mut() {
+            probation.record(success);
+        }
+
+        if success {
+            self.consecutive_failures = 0;
+            return false;
+        }
+
+        self.consecutive_failures += 1;
+        if blacklist_after > 0 && self.consecutive_failures >= blacklist_after {
+            self.consecutive_failures = 0;
+            self.blacklist(duration);
+            return true;
+        }
+        false
     }
 
     /// 更新延迟信息
     pub fn update_latency(&mut self, latency_ms: u64) {
         self.info.last_latency = Some(latency_ms);
-        self.info.last_checked = Some(chrono::Utc::now());
+        self.info.last_checked = Some(Stamp::now());
     }
 
     /// 更新成功率
@@ -1,9 +1,9 @@
 // 从根目录的src/proxy_pool.rs复制并修改,以对接core库的其他模块
-use std::fs::{self, File};
+use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 use tokio::sync::RwLock;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 use reqwest::Proxy;
 use tokio::time::timeout;
@@ -12,6 +12,8 @@ use indicatif::{ProgressBar, ProgressStyle};
 use tokio::net::TcpStream;
 use std::net::SocketAddr;
 use crate::config::Config;
+use crate::file_writer::{write_atomic, ProxyFileWriter};
+use crate::supervisor::{supervise, Backoff};
 use std::error::Error as StdError;
 use std::collections::HashSet;
 use tracing::info;
@@ -29,6 +31,8 @@ pub struct ProxyPool {
     current_index: Arc<RwLock<usize>>,
     config: Arc<Config>,
     proxy_file: Arc<String>,
+    /// `proxy_file` 的单写者，首次写入时在运行时中启动
+    writer: Arc<OnceLock<ProxyFileWriter>>,
 }
 
 impl ProxyPool {
@@ -38,9 +42,15 @@ impl ProxyPool {
             current_index: Arc::new(RwLock::new(0)),
             config: Arc::new(config.clone()),
             proxy_file: Arc::new(config.proxy.proxy_file),
+            writer: Arc::new(OnceLock::new()),
         }
     }
 
+    /// 代理文件的写入器，所有对 `proxy_file` 的写入都经过它串行化
+    pub fn writer(&self) -> &ProxyFileWriter {
+        self.writer.get_or_init(|| ProxyFileWriter::spawn(self.proxy_file.as_str()))
+    }
+
     pub fn get_config(&self) -> &Arc<Config> {
         &self.config
     }
@@ -144,7 +154,7 @@ impl ProxyPool {
         pb.finish_with_message("代理测试完成");
 
         // 按延迟排序
-        valid_proxies.sort_by(|a, b| a.latency.cmp(&b.latency));
+        valid_proxies.sort_by_key(|p| p.latency);
 
         // 更新代理列表
         let mut pool = self.proxies.write().await;
@@ -154,7 +164,12 @@ impl ProxyPool {
         let valid_proxies_str: Vec<String> = valid_proxies.iter()
             .map(|p| p.address.clone())
             .collect();
-        fs::write(&path, valid_proxies_str.join("\n"))?;
+        if path.as_ref() == Path::new(self.proxy_file.as_str()) {
+            self.writer().write(valid_proxies_str);
+            self.writer().flush().await?;
+        } else {
+            write_atomic(&path, valid_proxies_str.join("\n").as_bytes())?;
+        }
 
         info!("\n{} {} {}", 
             "测试完成，可用代理:".green().bold(), 
@@ -196,46 +211,50 @@ impl ProxyPool {
     fn start_health_check(&self) {
         let pool = Arc::clone(&self.proxies);
         let config = Arc::clone(&self.config);
-        let proxy_file = Arc::clone(&self.proxy_file);
+        let writer = self.writer().clone();
         
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(config.proxy.health_check_interval)).await;
+        // 健康检查panic后自动重启，避免代理列表从此不再更新
+        supervise("健康检查", Backoff::default(), move || {
+            let pool = Arc::clone(&pool);
+            let config = Arc::clone(&config);
+            let writer = writer.clone();
+            async move {
+                loop {
+                    tokio::time::sleep(Duration::from_secs(config.proxy.health_check_interval)).await;
                 
-                let mut proxies = pool.write().await;
-                let mut i = 0;
+                    let mut proxies = pool.write().await;
+                    let mut i = 0;
 
-                while i < proxies.len() {
-                    let addr = proxies[i].address.clone();
-                    match Self::test_proxy_health(&addr).await {
-                        Ok(latency) => {
-                            proxies[i].latency = latency;
-                            proxies[i].last_check = Instant::now();
-                            proxies[i].fail_count = 0;
-                            i += 1;
-                        }
-                        Err(_) => {
-                            proxies[i].fail_count += 1;
-                            if proxies[i].fail_count >= config.proxy.retry_times {
-                                let removed = proxies.remove(i);
-                                info!("{} {}", "代理失效，已移除:".red().bold(), removed.address);
-                            } else {
+                    while i < proxies.len() {
+                        let addr = proxies[i].address.clone();
+                        match Self::test_proxy_health(&addr).await {
+                            Ok(latency) => {
+                                proxies[i].latency = latency;
+                                proxies[i].last_check = Instant::now();
+                                proxies[i].fail_count = 0;
                                 i += 1;
                             }
+                            Err(_) => {
+                                proxies[i].fail_count += 1;
+                                if proxies[i].fail_count >= config.proxy.retry_times {
+                                    let removed = proxies.remove(i);
+                                    info!("{} {}", "代理失效，已移除:".red().bold(), removed.address);
+                                } else {
+                                    i += 1;
+                                }
+                            }
                         }
                     }
-                }
                 
-                // 重新按延迟排序
-                proxies.sort_by(|a, b| a.latency.cmp(&b.latency));
+                    // 重新按延迟排序
+                    proxies.sort_by_key(|p| p.latency);
 
-                // 更新文件中的代理列表
-                if !proxies.is_empty() {
-                    let valid_proxies_str: Vec<String> = proxies.iter()
-                        .map(|p| p.address.clone())
-                        .collect();
-                    if let Err(e) = fs::write(&*proxy_file, valid_proxies_str.join("\n")) {
-                        eprintln!("{} {}", "更新代理文件失败:".red().bold(), e);
+                    // 更新文件中的代理列表
+                    if !proxies.is_empty() {
+                        let valid_proxies_str: Vec<String> = proxies.iter()
+                            .map(|p| p.address.clone())
+                            .collect();
+                        writer.write(valid_proxies_str);
                     }
                 }
             }
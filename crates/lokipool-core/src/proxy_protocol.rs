@@ -0,0 +1,118 @@
+//! PROXY协议头解析
+//!
+//! 部署在TCP负载均衡之后时，连接的来源地址是负载均衡本身。负载均衡按PROXY协议（v1文本或v2二进制）
+//! 在连接开头写明真实的客户端地址，这里只负责从已收到的字节中解析出协议头，读取与超时由调用方处理。
+//! 只应对负载均衡可达的端口开启，否则客户端可以随意声明来源地址。
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use crate::error::{Error, Result};
+
+/// v1协议头的最大长度，含结尾的CRLF
+pub const MAX_V1_LENGTH: usize = 107;
+
+/// v2协议头的签名
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// v2协议头的固定部分长度
+const V2_FIXED_LENGTH: usize = 16;
+
+/// 解析出的协议头
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+    /// 真实的客户端地址；负载均衡自身的健康检查（LOCAL命令、UNKNOWN协议族）没有地址
+    pub source: Option<SocketAddr>,
+    /// 客户端连接的目标地址
+    pub destination: Option<SocketAddr>,
+    /// 协议头占用的字节数，之后才是客户端的数据
+    pub length: usize,
+}
+
+/// 解析的进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parsed {
+    /// 数据还不完整，至少需要这么多字节才能继续
+    Incomplete(usize),
+    Complete(ProxyHeader),
+}
+
+/// 从连接开头已收到的字节中解析协议头，不是PROXY协议或格式错误时返回错误
+pub fn parse(buf: &[u8]) -> Result<Parsed> {
+    let signature_len = buf.len().min(V2_SIGNATURE.len());
+    if buf[..signature_len] == V2_SIGNATURE[..signature_len] {
+        return parse_v2(buf);
+    }
+    let prefix_len = buf.len().min(6);
+    if buf[..prefix_len] == b"PROXY "[..prefix_len] {
+        return parse_v1(buf);
+    }
+    Err(invalid("连接开头不是PROXY协议头"))
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::Connection(format!("无效的PROXY协议头: {}", reason))
+}
+
+/// `PROXY TCP4 源地址 目标地址 源端口 目标端口\r\n`
+fn parse_v1(buf: &[u8]) -> Result<Parsed> {
+    let searched = &buf[..buf.len().min(MAX_V1_LENGTH)];
+    let Some(end) = searched.windows(2).position(|w| w == b"\r\n") else {
+        return match buf.len() >= MAX_V1_LENGTH {
+            true => Err(invalid("v1协议头过长")),
+            false => Ok(Parsed::Incomplete(buf.len() + 1)),
+        };
+    };
+    let line = std::str::from_utf8(&buf[..end]).map_err(|_| invalid("v1协议头不是ASCII文本"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    let length = end + 2;
+    let (source, destination) = match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => (None, None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] => {
+            let address = |ip: &str, port: &str| -> Result<SocketAddr> {
+                let ip: IpAddr = ip.parse().map_err(|_| invalid("地址格式错误"))?;
+                if ip.is_ipv4() != (*family == "TCP4") {
+                    return Err(invalid("地址与协议族不符"));
+                }
+                let port: u16 = port.parse().map_err(|_| invalid("端口格式错误"))?;
+                Ok(SocketAddr::new(ip, port))
+            };
+            (Some(address(source, source_port)?), Some(address(destination, destination_port)?))
+        }
+        _ => return Err(invalid("v1协议头字段不正确")),
+    };
+    Ok(Parsed::Complete(ProxyHeader { source, destination, length }))
+}
+
+/// 12字节签名、版本与命令、协议族、地址长度，之后是地址与可选的TLV
+fn parse_v2(buf: &[u8]) -> Result<Parsed> {
+    if buf.len() < V2_FIXED_LENGTH {
+        return Ok(Parsed::Incomplete(V2_FIXED_LENGTH));
+    }
+    let version_command = buf[12];
+    if version_command >> 4 != 2 {
+        return Err(invalid("不支持的v2版本"));
+    }
+    let length = V2_FIXED_LENGTH + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if buf.len() < length {
+        return Ok(Parsed::Incomplete(length));
+    }
+    let addresses = &buf[V2_FIXED_LENGTH..length];
+    let (source, destination) = match (version_command & 0x0f, buf[13]) {
+        // LOCAL命令是负载均衡自己发起的连接
+        (0x00, _) => (None, None),
+        (0x01, 0x11 | 0x12) if addresses.len() >= 12 => {
+            let ip = |at: usize| IpAddr::V4(Ipv4Addr::new(addresses[at], addresses[at + 1], addresses[at + 2], addresses[at + 3]));
+            let port = |at: usize| u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+            (Some(SocketAddr::new(ip(0), port(8))), Some(SocketAddr::new(ip(4), port(10))))
+        }
+        (0x01, 0x21 | 0x22) if addresses.len() >= 36 => {
+            let ip = |at: usize| IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(&addresses[at..at + 16]).unwrap()));
+            let port = |at: usize| u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+            (Some(SocketAddr::new(ip(0), port(32))), Some(SocketAddr::new(ip(16), port(34))))
+        }
+        // UNSPEC与UNIX套接字没有可用的IP地址
+        (0x01, 0x00 | 0x31 | 0x32) => (None, None),
+        (0x01, _) => return Err(invalid("v2地址长度与协议族不符")),
+        _ => return Err(invalid("不支持的v2命令")),
+    };
+    Ok(Parsed::Complete(ProxyHeader { source, destination, length }))
+}
@@ -0,0 +1,196 @@
+//! 按客户端IP的配额
+//!
+//! 多人共用一个监听端口时，限制每个客户端IP的并发连接数与每天（UTC日期）的流量，避免一个人占满代理池。
+//! 并发连接在连接开始时占用、结束时释放；流量在连接结束时计入，进行中的连接不会因超出配额被断开，
+//! 超出后该客户端的新连接被拒绝，直到下一个UTC日。当天的流量可以保存到文件，重启后继续计算。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use crate::config::QuotaSettings;
+use crate::error::{Error, Result};
+use crate::file_writer::write_atomic;
+use crate::time::wall_now;
+
+/// 每个客户端的配额，为0的项不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaLimits {
+    /// 并发连接数
+    pub max_connections: usize,
+    /// 每天的上下行字节数之和
+    pub max_bytes_per_day: u64,
+}
+
+impl QuotaLimits {
+    pub fn from_settings(settings: &QuotaSettings) -> Self {
+        Self {
+            max_connections: settings.max_connections,
+            max_bytes_per_day: settings.max_mb_per_day.saturating_mul(1024 * 1024),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_connections == 0 && self.max_bytes_per_day == 0
+    }
+}
+
+/// 超出的配额
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaExceeded {
+    /// 已有这么多个并发连接
+    Connections(usize),
+    /// 今天已经用了这么多字节
+    Bytes(u64),
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaExceeded::Connections(count) => write!(f, "并发连接数已达上限 ({})", count),
+            QuotaExceeded::Bytes(bytes) => write!(f, "今日流量已达上限 ({} 字节)", bytes),
+        }
+    }
+}
+
+/// 一个客户端的用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientUsage {
+    /// 进行中的连接数
+    pub connections: usize,
+    /// 今天已用的字节数
+    pub bytes: u64,
+}
+
+/// 保存到文件的当天用量
+#[derive(Debug, Serialize, Deserialize)]
+struct Persisted {
+    date: NaiveDate,
+    clients: HashMap<IpAddr, u64>,
+}
+
+#[derive(Debug)]
+struct Table {
+    date: NaiveDate,
+    clients: HashMap<IpAddr, ClientUsage>,
+}
+
+impl Table {
+    /// 日期变化后清零流量，没有进行中连接的客户端一并移除
+    fn roll_over(&mut self) {
+        let today = wall_now().date_naive();
+        if self.date != today {
+            self.date = today;
+            self.clients.retain(|_, usage| {
+                usage.bytes = 0;
+                usage.connections > 0
+            });
+        }
+    }
+}
+
+/// 各客户端的用量表，克隆后共享同一份数据，多个监听端口共用时配额合并计算
+#[derive(Debug, Clone)]
+pub struct QuotaTable {
+    table: Arc<Mutex<Table>>,
+}
+
+impl Default for QuotaTable {
+    fn default() -> Self {
+        Self { table: Arc::new(Mutex::new(Table { date: wall_now().date_naive(), clients: HashMap::new() })) }
+    }
+}
+
+impl QuotaTable {
+    /// 检查客户端的配额并占用一个连接名额，返回的凭据在连接结束时释放名额
+    pub fn admit(&self, client: IpAddr, limits: &QuotaLimits) -> std::result::Result<QuotaPermit, QuotaExceeded> {
+        if limits.is_unlimited() {
+            return Ok(QuotaPermit { table: None, client });
+        }
+        let mut table = self.table.lock().unwrap();
+        table.roll_over();
+        let usage = table.clients.entry(client).or_default();
+        if limits.max_connections > 0 && usage.connections >= limits.max_connections {
+            return Err(QuotaExceeded::Connections(usage.connections));
+        }
+        if limits.max_bytes_per_day > 0 && usage.bytes >= limits.max_bytes_per_day {
+            return Err(QuotaExceeded::Bytes(usage.bytes));
+        }
+        usage.connections += 1;
+        Ok(QuotaPermit { table: Some(self.clone()), client })
+    }
+
+    /// 把结束的连接的流量计入客户端今天的用量
+    pub fn record(&self, client: IpAddr, bytes: u64) {
+        let mut table = self.table.lock().unwrap();
+        table.roll_over();
+        table.clients.entry(client).or_default().bytes += bytes;
+    }
+
+    /// 客户端当前的用量
+    pub fn usage(&self, client: IpAddr) -> ClientUsage {
+        let mut table = self.table.lock().unwrap();
+        table.roll_over();
+        table.clients.get(&client).copied().unwrap_or_default()
+    }
+
+    /// 保存当天的流量用量
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let persisted = {
+            let mut table = self.table.lock().unwrap();
+            table.roll_over();
+            Persisted {
+                date: table.date,
+                clients: table.clients.iter().filter(|(_, usage)| usage.bytes > 0).map(|(ip, usage)| (*ip, usage.bytes)).collect(),
+            }
+        };
+        let content = serde_json::to_string_pretty(&persisted).map_err(|e| Error::Serialization(e.to_string()))?;
+        write_atomic(path, content.as_bytes())?;
+        Ok(())
+    }
+
+    /// 读取保存的用量，文件不是今天的时从零开始
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let persisted: Persisted = serde_json::from_str(&content).map_err(|e| Error::Serialization(e.to_string()))?;
+        let quotas = Self::default();
+        {
+            let mut table = quotas.table.lock().unwrap();
+            if persisted.date == table.date {
+                table.clients = persisted.clients.into_iter()
+                    .map(|(ip, bytes)| (ip, ClientUsage { connections: 0, bytes }))
+                    .collect();
+            }
+        }
+        Ok(quotas)
+    }
+
+    fn release(&self, client: IpAddr) {
+        let mut table = self.table.lock().unwrap();
+        if let Some(usage) = table.clients.get_mut(&client) {
+            usage.connections = usage.connections.saturating_sub(1);
+            if usage.connections == 0 && usage.bytes == 0 {
+                table.clients.remove(&client);
+            }
+        }
+    }
+}
+
+/// 占用的连接名额，drop时释放
+#[derive(Debug)]
+pub struct QuotaPermit {
+    table: Option<QuotaTable>,
+    client: IpAddr,
+}
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        if let Some(table) = &self.table {
+            table.release(self.client);
+        }
+    }
+}
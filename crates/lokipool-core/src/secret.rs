@@ -0,0 +1,8 @@
+//! 凭据比较
+//!
+//! SOCKS5账户、API密钥与出口节点令牌都用这里的比较，避免按比较耗时逐字节猜出凭据。
+
+/// 比较两个字节串，耗时只与长度有关
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
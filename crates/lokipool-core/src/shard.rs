@@ -0,0 +1,395 @@
+//! 分片的代理存储
+//!
+//! 代理按ID哈希分散到多个分片，每个分片各自持有一把读写锁，单个代理的状态更新只锁住所在分片。
+//! 只读的选择路径不扫描分片，而是使用合并的选择索引：索引按层级保存候选代理的副本，
+//! 修改代理时如果选择或拨号读取的字段发生变化，就在同一把分片锁内刷新副本并调整它在索引中的位置。
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Bound;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::circuit::CircuitState;
+use crate::proxy::{Proxy, ProxyStatus};
+
+/// 默认分片数量
+pub const DEFAULT_SHARDS: usize = 16;
+
+type Shard = HashMap<String, Proxy>;
+
+/// 选择时的候选层级
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Tier {
+    /// 正常轮换的可用代理
+    Regular,
+    /// 试用期内的新代理
+    Probation,
+    /// 隔离观察期的代理
+    Quarantined,
+}
+
+impl Tier {
+    const ALL: [Tier; 3] = [Tier::Regular, Tier::Probation, Tier::Quarantined];
+
+    pub(crate) fn matches(self, proxy: &Proxy) -> bool {
+        match self {
+            Tier::Regular => proxy.status == ProxyStatus::Available && !proxy.on_probation(),
+            Tier::Probation => proxy.status == ProxyStatus::Available && proxy.on_probation(),
+            Tier::Quarantined => proxy.status == ProxyStatus::Quarantined,
+        }
+    }
+
+    fn slot(self) -> usize {
+        self as usize
+    }
+}
+
+/// 选择结果与索引中副本的指纹
+///
+/// 除影响选择的字段外还包括选择过滤条件与拨号会读取的全部字段，
+/// 只更换密码或位置标签的代理也会刷新索引中的副本。
+fn fingerprint(proxy: &Proxy) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    proxy.id.hash(&mut hasher);
+    let info = &proxy.info;
+    info.host.hash(&mut hasher);
+    info.port.hash(&mut hasher);
+    info.username.hash(&mut hasher);
+    info.password.hash(&mut hasher);
+    info.proxy_type.hash(&mut hasher);
+    info.location.hash(&mut hasher);
+    info.last_latency.hash(&mut hasher);
+    info.success_rate.to_bits().hash(&mut hasher);
+    info.last_checked.hash(&mut hasher);
+    (info.status as u8).hash(&mut hasher);
+    proxy.udp.hash(&mut hasher);
+    (proxy.status as u8).hash(&mut hasher);
+    proxy.latency.hash(&mut hasher);
+    proxy.info.weight.hash(&mut hasher);
+    proxy.blacklisted_until.hash(&mut hasher);
+    proxy.retired_until.hash(&mut hasher);
+    (proxy.breaker.state() as u8).hash(&mut hasher);
+    proxy.probation.is_some().hash(&mut hasher);
+    proxy.quarantine.is_some().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 代理在索引中的位置
+#[derive(Debug)]
+struct Placement {
+    /// 所在层级，不可选的代理为None
+    tier: Option<Tier>,
+    latency: u64,
+    fingerprint: u64,
+    /// 规范键，用于维护键到ID的映射
+    key: String,
+    /// 是否处于隔离、试用或熔断状态
+    stateful: bool,
+}
+
+/// 合并所有分片的选择索引
+///
+/// 每个层级的候选代理按ID保存，另按 `(延迟, ID)` 维护有序集合；
+/// 代理状态或延迟变化时只调整该代理的位置，选择与更新都是O(log n)。
+#[derive(Debug, Default)]
+pub(crate) struct SelectionIndex {
+    placements: HashMap<String, Placement>,
+    by_id: [BTreeMap<String, Proxy>; 3],
+    by_latency: [BTreeSet<(u64, String)>; 3],
+    /// 规范键到代理ID，添加代理时不必扫描全部代理查重
+    keys: HashMap<String, String>,
+    /// 处于隔离、试用或熔断状态的代理数量
+    stateful: usize,
+}
+
+impl SelectionIndex {
+    /// 插入或更新代理，指纹未变化时不做任何事
+    fn upsert(&mut self, proxy: &Proxy) {
+        let fingerprint = fingerprint(proxy);
+        if self.placements.get(&proxy.id).is_some_and(|p| p.fingerprint == fingerprint) {
+            return;
+        }
+        self.remove(&proxy.id);
+
+        let tier = Tier::ALL.into_iter().find(|tier| tier.matches(proxy));
+        let stateful = proxy.quarantine.is_some()
+            || proxy.probation.is_some()
+            || proxy.breaker.state() != CircuitState::Closed;
+        if let Some(tier) = tier {
+            self.by_id[tier.slot()].insert(proxy.id.clone(), proxy.clone());
+            self.by_latency[tier.slot()].insert((proxy.latency, proxy.id.clone()));
+        }
+        self.stateful += stateful as usize;
+        let key = proxy.canonical_key();
+        self.keys.insert(key.clone(), proxy.id.clone());
+        self.placements.insert(proxy.id.clone(), Placement { tier, latency: proxy.latency, fingerprint, key, stateful });
+    }
+
+    fn remove(&mut self, id: &str) {
+        let Some(placement) = self.placements.remove(id) else {
+            return;
+        };
+        if let Some(tier) = placement.tier {
+            self.by_id[tier.slot()].remove(id);
+            self.by_latency[tier.slot()].remove(&(placement.latency, id.to_string()));
+        }
+        self.stateful -= placement.stateful as usize;
+        if self.keys.get(&placement.key).is_some_and(|owner| owner == id) {
+            self.keys.remove(&placement.key);
+        }
+    }
+
+    /// 与全部代理对齐：更新变化的代理并移除已不存在的代理
+    fn sync<'a>(&mut self, proxies: impl Iterator<Item = &'a Proxy>) {
+        let mut present = HashSet::new();
+        for proxy in proxies {
+            self.upsert(proxy);
+            present.insert(proxy.id.as_str());
+        }
+        let stale: Vec<String> = self.placements.keys()
+            .filter(|id| !present.contains(id.as_str()))
+            .cloned()
+            .collect();
+        for id in stale {
+            self.remove(&id);
+        }
+    }
+
+    /// 指定层级的候选代理，按ID排序
+    pub(crate) fn by_id(&self, tier: Tier) -> impl Iterator<Item = &Proxy> {
+        self.by_id[tier.slot()].values()
+    }
+
+    /// 指定层级的候选代理，按延迟升序排列
+    pub(crate) fn by_latency(&self, tier: Tier) -> impl Iterator<Item = &Proxy> {
+        let proxies = &self.by_id[tier.slot()];
+        self.by_latency[tier.slot()].iter().filter_map(|(_, id)| proxies.get(id))
+    }
+
+    /// 指定层级中ID排在 `last` 之后的候选代理，到末尾后从头继续，用于轮询
+    pub(crate) fn after<'a>(&'a self, tier: Tier, last: &'a str) -> impl Iterator<Item = &'a Proxy> {
+        let proxies = &self.by_id[tier.slot()];
+        proxies.range::<str, _>((Bound::Excluded(last), Bound::Unbounded))
+            .chain(proxies.range::<str, _>((Bound::Unbounded, Bound::Included(last))))
+            .map(|(_, proxy)| proxy)
+    }
+
+    /// 规范键相同的代理的ID
+    pub(crate) fn find(&self, key: &str) -> Option<&str> {
+        self.keys.get(key).map(String::as_str)
+    }
+
+    /// 选择时是否需要修改代理状态
+    pub(crate) fn needs_write(&self) -> bool {
+        self.stateful > 0
+    }
+
+    /// 未被黑名单的可用代理数量
+    pub(crate) fn available(&self) -> usize {
+        [Tier::Regular, Tier::Probation]
+            .into_iter()
+            .flat_map(|tier| self.by_id(tier))
+            .filter(|p| !p.is_blacklisted())
+            .count()
+    }
+}
+
+/// 单个代理插入的结果
+pub(crate) enum Insert {
+    Added,
+    /// 已有规范键相同的代理，附带其ID与未插入的代理
+    Existing(String, Box<Proxy>),
+    /// 代理数量已达上限
+    Full,
+}
+
+/// 按ID哈希分片的代理存储
+#[derive(Debug)]
+pub(crate) struct ProxyShards {
+    shards: Box<[RwLock<Shard>]>,
+    /// 选择索引，修改代理时在持有分片锁的情况下同步更新
+    index: std::sync::RwLock<SelectionIndex>,
+}
+
+impl ProxyShards {
+    pub(crate) fn new(count: usize) -> Self {
+        Self {
+            shards: (0..count.max(1)).map(|_| RwLock::new(HashMap::new())).collect(),
+            index: std::sync::RwLock::new(SelectionIndex::default()),
+        }
+    }
+
+    fn slot(&self, id: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// 读取选择索引，不能跨越await持有
+    pub(crate) fn index(&self) -> std::sync::RwLockReadGuard<'_, SelectionIndex> {
+        self.index.read().unwrap()
+    }
+
+    /// 按ID获取代理的副本
+    pub(crate) async fn get(&self, id: &str) -> Option<Proxy> {
+        self.shards[self.slot(id)].read().await.get(id).cloned()
+    }
+
+    /// 在所在分片的写锁内修改单个代理，代理不存在时返回None
+    pub(crate) async fn update<R>(&self, id: &str, f: impl FnOnce(&mut Proxy) -> R) -> Option<R> {
+        let mut shard = self.shards[self.slot(id)].write().await;
+        let proxy = shard.get_mut(id)?;
+        let before = fingerprint(proxy);
+        let result = f(proxy);
+        if fingerprint(proxy) != before {
+            self.index.write().unwrap().upsert(proxy);
+        }
+        Some(result)
+    }
+
+    /// 只锁住所在分片插入单个代理，规范键相同的代理已存在或数量已达 `limit` 时不插入
+    ///
+    /// 查重与插入都在索引的写锁内完成，并发插入同一个代理时只有一个会成功。
+    pub(crate) async fn insert(&self, proxy: Proxy, limit: usize) -> Insert {
+        let mut shard = self.shards[self.slot(&proxy.id)].write().await;
+        let mut index = self.index.write().unwrap();
+        if let Some(id) = index.find(&proxy.canonical_key()) {
+            return Insert::Existing(id.to_string(), Box::new(proxy));
+        }
+        if index.placements.len() >= limit {
+            return Insert::Full;
+        }
+        index.upsert(&proxy);
+        shard.insert(proxy.id.clone(), proxy);
+        Insert::Added
+    }
+
+    /// 移除单个代理
+    pub(crate) async fn remove(&self, id: &str) -> Option<Proxy> {
+        let mut shard = self.shards[self.slot(id)].write().await;
+        let removed = shard.remove(id);
+        if removed.is_some() {
+            self.index.write().unwrap().remove(id);
+        }
+        removed
+    }
+
+    /// 按固定顺序获取所有分片的读锁
+    pub(crate) async fn read_all(&self) -> ShardsRead<'_> {
+        let mut guards = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            guards.push(shard.read().await);
+        }
+        ShardsRead { guards }
+    }
+
+    /// 按固定顺序获取所有分片的写锁
+    pub(crate) async fn write_all(&self) -> ShardsWrite<'_> {
+        let mut guards = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            guards.push(shard.write().await);
+        }
+        ShardsWrite::new(self, guards)
+    }
+
+    /// 在锁外把代理按分片分好，之后用 `ShardsWrite::install` 一次换入
+    pub(crate) fn partition(&self, proxies: impl IntoIterator<Item = Proxy>) -> Partitioned {
+        let mut shards: Vec<Shard> = (0..self.shards.len()).map(|_| HashMap::new()).collect();
+        for proxy in proxies {
+            shards[self.slot(&proxy.id)].insert(proxy.id.clone(), proxy);
+        }
+        Partitioned(shards)
+    }
+
+    /// 立即获取所有分片的写锁，有其他持有者时返回None
+    pub(crate) fn try_write_all(&self) -> Option<ShardsWrite<'_>> {
+        let guards = self.shards.iter().map(|shard| shard.try_write().ok()).collect::<Option<Vec<_>>>()?;
+        Some(ShardsWrite::new(self, guards))
+    }
+}
+
+/// 已按分片分好的代理，只能换入划分它的存储
+pub(crate) struct Partitioned(Vec<Shard>);
+
+/// 所有分片的读锁
+pub(crate) struct ShardsRead<'a> {
+    guards: Vec<RwLockReadGuard<'a, Shard>>,
+}
+
+impl ShardsRead<'_> {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Proxy> {
+        self.guards.iter().flat_map(|shard| shard.values())
+    }
+}
+
+/// 所有分片的写锁，释放时把修改同步到选择索引
+pub(crate) struct ShardsWrite<'a> {
+    owner: &'a ProxyShards,
+    guards: Vec<RwLockWriteGuard<'a, Shard>>,
+    /// 按ID访问过的代理，释放时只重新放置这些代理
+    touched: HashSet<String>,
+    /// 遍历修改或整体换入过，释放时与全部代理对齐
+    resync: bool,
+}
+
+impl<'a> ShardsWrite<'a> {
+    fn new(owner: &'a ProxyShards, guards: Vec<RwLockWriteGuard<'a, Shard>>) -> Self {
+        Self { owner, guards, touched: HashSet::new(), resync: false }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.guards.iter().map(|shard| shard.len()).sum()
+    }
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Proxy> {
+        self.guards.iter().flat_map(|shard| shard.values())
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Proxy> + use<'_, 'a> {
+        self.resync = true;
+        self.guards.iter_mut().flat_map(|shard| shard.values_mut())
+    }
+
+    pub(crate) fn get_mut(&mut self, id: &str) -> Option<&mut Proxy> {
+        let slot = self.owner.slot(id);
+        self.touched.insert(id.to_string());
+        self.guards[slot].get_mut(id)
+    }
+
+    pub(crate) fn insert(&mut self, proxy: Proxy) {
+        let slot = self.owner.slot(&proxy.id);
+        self.touched.insert(proxy.id.clone());
+        self.guards[slot].insert(proxy.id.clone(), proxy);
+    }
+
+    pub(crate) fn remove(&mut self, id: &str) -> Option<Proxy> {
+        let slot = self.owner.slot(id);
+        self.touched.insert(id.to_string());
+        self.guards[slot].remove(id)
+    }
+
+    /// 用分好的代理整体替换所有分片的内容，返回被替换下来的代理
+    pub(crate) fn install(&mut self, partitioned: Partitioned) -> Vec<Proxy> {
+        self.resync = true;
+        self.guards.iter_mut()
+            .zip(partitioned.0)
+            .flat_map(|(guard, shard)| std::mem::replace(&mut **guard, shard).into_values())
+            .collect()
+    }
+}
+
+impl Drop for ShardsWrite<'_> {
+    fn drop(&mut self) {
+        // 只重新放置指纹变化的代理，未变化的代理只需一次哈希比较
+        let mut index = self.owner.index.write().unwrap();
+        if self.resync {
+            index.sync(self.guards.iter().flat_map(|shard| shard.values()));
+            return;
+        }
+        for id in &self.touched {
+            match self.guards[self.owner.slot(id)].get(id) {
+                Some(proxy) => index.upsert(proxy),
+                None => index.remove(id),
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! 代理池状态快照，用于在重启后恢复测试结果
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::error::{Error, Result};
+use crate::file_writer::write_atomic;
+use crate::proxy::{canonical_key, Proxy, ProxyStatus};
+use crate::time::{wall_age, wall_now, Stamp};
+
+/// 代理池快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolSnapshot {
+    /// 保存时间
+    pub saved_at: DateTime<Utc>,
+    /// 各代理的状态
+    pub proxies: Vec<ProxySnapshot>,
+}
+
+/// 单个代理的状态快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxySnapshot {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    #[serde(default = "default_proxy_type")]
+    pub proxy_type: String,
+    pub status: ProxyStatus,
+    /// 延迟（毫秒），未测试时为None
+    pub latency: Option<u64>,
+    pub success_rate: f64,
+    pub last_checked: Option<DateTime<Utc>>,
+}
+
+impl PoolSnapshot {
+    /// 记录代理的当前状态
+    pub(crate) fn capture<'a>(proxies: impl Iterator<Item = &'a Proxy>) -> Self {
+        Self {
+            saved_at: wall_now(),
+            proxies: proxies
+                .map(|proxy| ProxySnapshot {
+                    host: proxy.info.host.clone(),
+                    port: proxy.info.port,
+                    username: proxy.info.username.clone(),
+                    proxy_type: proxy.info.proxy_type.clone(),
+                    status: proxy.status,
+                    latency: proxy.info.last_latency,
+                    success_rate: proxy.info.success_rate,
+                    last_checked: proxy.last_tested.or(proxy.info.last_checked).map(|at| at.wall()),
+                })
+                .collect(),
+        }
+    }
+
+    /// 快照距今的时长，保存时间晚于当前时间（时钟被回拨）时返回None
+    pub fn age(&self) -> Option<Duration> {
+        wall_age(self.saved_at)
+    }
+
+    /// 将快照中的状态应用到地址匹配的代理上，返回恢复的代理ID
+    pub(crate) fn apply<'a>(&self, proxies: impl Iterator<Item = &'a mut Proxy>) -> Vec<String> {
+        let saved: HashMap<_, _> = self.proxies
+            .iter()
+            .map(|p| (canonical_key(&p.host, p.port, &p.proxy_type, p.username.as_deref()), p))
+            .collect();
+
+        let mut restored = Vec::new();
+        for proxy in proxies {
+            let Some(saved) = saved.get(&proxy.canonical_key()) else {
+                continue;
+            };
+
+            if saved.status == ProxyStatus::Quarantined {
+                proxy.enter_quarantine();
+            } else {
+                proxy.update_status(saved.status);
+            }
+            proxy.info.last_latency = saved.latency;
+            proxy.latency = saved.latency.unwrap_or(u64::MAX);
+            proxy.info.success_rate = saved.success_rate;
+            proxy.info.last_checked = saved.last_checked.map(Stamp::from_wall);
+            proxy.last_tested = proxy.info.last_checked;
+            restored.push(proxy.id.clone());
+        }
+        restored
+    }
+
+    /// 保存到JSON文件，原子替换避免中途退出留下损坏的快照
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::Serialization(e.to_string()))?;
+        write_atomic(path, content.as_bytes())?;
+        Ok(())
+    }
+
+    /// 从JSON文件读取
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| Error::Serialization(e.to_string()))
+    }
+}
+
+fn default_proxy_type() -> String {
+    "socks5".to_string()
+}
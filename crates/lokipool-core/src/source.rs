@@ -0,0 +1,106 @@
+//! 代理来源与同步策略
+//!
+//! 代理可以来自配置文件的 `[[proxies]]`、代理列表文件或运行时添加（API、命令行、出口节点注册），
+//! 同一个上游被多个来源列出时只保留一份，并记录它属于哪些来源。
+//! 每个来源只对自己列出的代理负责：重新同步某个来源时，从该来源消失的代理只是失去这个来源，
+//! 不再属于任何来源时才从池中移除。运行时添加的代理不会被任何文件的同步移除，只能显式删除。
+
+use std::fmt;
+use std::path::Path;
+use std::time::SystemTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::time::Stamp;
+
+/// 代理来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxySource {
+    /// 配置文件中的 `[[proxies]]`
+    Config,
+    /// 代理列表文件（`proxy_file`）
+    File,
+    /// 运行时通过API、命令行或出口节点注册添加
+    Api,
+}
+
+impl ProxySource {
+    /// 所有来源
+    pub const ALL: [ProxySource; 3] = [ProxySource::Config, ProxySource::File, ProxySource::Api];
+}
+
+impl fmt::Display for ProxySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProxySource::Config => "config",
+            ProxySource::File => "file",
+            ProxySource::Api => "api",
+        })
+    }
+}
+
+/// 一次来源同步的结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SyncReport {
+    /// 新加入池中的代理数
+    pub added: usize,
+    /// 已在池中、继续保留的代理数
+    pub existing: usize,
+    /// 从来源中消失且不再属于任何来源、已移除的代理数
+    pub removed: usize,
+    /// 从来源中消失但仍属于其他来源、继续保留的代理数
+    pub released: usize,
+    /// 因永久黑名单或容量上限未能加入的代理数
+    pub rejected: usize,
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "新增 {} 个, 保留 {} 个, 移除 {} 个, 由其他来源保留 {} 个, 拒绝 {} 个",
+            self.added, self.existing, self.removed, self.released, self.rejected)
+    }
+}
+
+/// 来源最近一次同步的记录
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SyncRecord {
+    pub(crate) origin: Option<String>,
+    pub(crate) at: Option<Stamp>,
+    pub(crate) report: Option<SyncReport>,
+    pub(crate) error: Option<String>,
+}
+
+/// 单个来源的状态
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SourceStatus {
+    pub source: ProxySource,
+    /// 当前属于该来源的代理数，同时被多个来源列出的代理分别计入
+    pub proxies: usize,
+    /// 来源所在的位置，如代理文件路径
+    pub origin: Option<String>,
+    /// 最近一次同步的时间
+    pub last_sync: Option<Stamp>,
+    /// 最近一次成功同步的结果
+    pub last_report: Option<SyncReport>,
+    /// 最近一次同步失败的原因，之后同步成功时清空
+    pub last_error: Option<String>,
+}
+
+impl SourceStatus {
+    pub(crate) fn new(source: ProxySource, proxies: usize, record: SyncRecord) -> Self {
+        Self {
+            source,
+            proxies,
+            origin: record.origin,
+            last_sync: record.at,
+            last_report: record.report,
+            last_error: record.error,
+        }
+    }
+}
+
+/// 文件的版本（修改时间与大小），文件不存在时为None，用于判断文件是否变化
+pub(crate) fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
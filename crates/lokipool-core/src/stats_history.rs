@@ -0,0 +1,190 @@
+//! 代理池指标的历史记录
+//!
+//! 定期把可用代理数、可用代理的平均延迟与累计连接数记为一个样本，保留最近 `RETENTION` 内的样本，
+//! 按时间窗口与步长汇总成序列，供仪表盘绘制趋势图。设置了文件时每个样本追加为一行JSON，
+//! 启动时读回未过期的样本并重写文件，重启后历史不会中断。
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use crate::error::{Error, Result};
+use crate::file_writer::write_atomic;
+use crate::metrics::PoolMetrics;
+
+/// 样本的保留时长
+pub const RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// 某一时刻的指标
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StatsSample {
+    pub at: DateTime<Utc>,
+    /// 可用代理数
+    pub available: usize,
+    /// 代理总数
+    pub total: usize,
+    /// 可用代理的平均延迟（毫秒）
+    pub average_latency_ms: Option<f64>,
+    /// 累计连接数，移除代理或重启后可能变小
+    pub total_connections: u64,
+}
+
+impl StatsSample {
+    pub fn from_metrics(at: DateTime<Utc>, metrics: &PoolMetrics) -> Self {
+        Self {
+            at,
+            available: metrics.status.available,
+            total: metrics.total,
+            average_latency_ms: metrics.latency.mean,
+            total_connections: metrics.connections.total_connections,
+        }
+    }
+}
+
+/// 一个时间段内的汇总，没有样本时各平均值为空
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct StatsBucket {
+    /// 时间段的开始，按步长对齐
+    pub start: DateTime<Utc>,
+    /// 落在时间段内的样本数
+    pub samples: usize,
+    /// 平均可用代理数
+    pub available_proxies: Option<f64>,
+    /// 可用代理的平均延迟（毫秒）
+    pub average_latency_ms: Option<f64>,
+    /// 时间段内新增的连接数
+    pub requests: u64,
+}
+
+/// 指标样本，克隆后共享同一份数据
+#[derive(Debug, Clone, Default)]
+pub struct StatsHistory {
+    samples: Arc<Mutex<VecDeque<StatsSample>>>,
+    file: Option<Arc<PathBuf>>,
+}
+
+impl StatsHistory {
+    /// 读取文件中未过期的样本并重写文件，之后的样本追加到其中；文件不存在时从空开始
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let cutoff = Utc::now() - chrono::Duration::from_std(RETENTION).unwrap_or_default();
+        let mut samples: Vec<StatsSample> = match fs::read_to_string(path) {
+            Ok(content) => content.lines()
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(|line| match serde_json::from_str::<StatsSample>(line) {
+                    Ok(sample) => Some(sample),
+                    Err(e) => {
+                        warn!("忽略指标历史中无法解析的行: {}", e);
+                        None
+                    }
+                })
+                .filter(|sample| sample.at >= cutoff)
+                .collect(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        samples.sort_by_key(|sample| sample.at);
+
+        let mut content = String::new();
+        for sample in &samples {
+            content.push_str(&serde_json::to_string(sample).map_err(|e| Error::Serialization(e.to_string()))?);
+            content.push('\n');
+        }
+        write_atomic(path, content.as_bytes())?;
+
+        Ok(Self {
+            samples: Arc::new(Mutex::new(samples.into())),
+            file: Some(Arc::new(path.to_path_buf())),
+        })
+    }
+
+    /// 记录一个样本并丢弃过期的样本，设置了文件时同时追加到文件
+    pub fn record(&self, sample: StatsSample) -> Result<()> {
+        {
+            let mut samples = self.samples.lock().unwrap();
+            let cutoff = sample.at - chrono::Duration::from_std(RETENTION).unwrap_or_default();
+            while samples.front().is_some_and(|oldest| oldest.at < cutoff) {
+                samples.pop_front();
+            }
+            samples.push_back(sample);
+        }
+        if let Some(path) = &self.file {
+            let mut line = serde_json::to_string(&sample).map_err(|e| Error::Serialization(e.to_string()))?;
+            line.push('\n');
+            OpenOptions::new().create(true).append(true).open(path.as_path())?.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// 保留的样本数
+    pub fn len(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 把 `end` 之前 `window` 内的样本按 `step` 汇总，时间段的开始按步长对齐，最后一段包含 `end`
+    ///
+    /// 连接数取相邻样本的差，计入后一个样本所在的时间段；累计值变小时视为重新计数，取后一个样本的值。
+    pub fn series(&self, end: DateTime<Utc>, window: Duration, step: Duration) -> Vec<StatsBucket> {
+        let step_ms = (step.as_millis() as i64).max(1);
+        let window = chrono::Duration::from_std(window).unwrap_or_default();
+        let first = (end - window).timestamp_millis().div_euclid(step_ms) * step_ms;
+        let last = end.timestamp_millis().div_euclid(step_ms) * step_ms;
+        let mut buckets: Vec<Accumulator> = (0..=(last - first) / step_ms).map(|_| Accumulator::default()).collect();
+
+        let samples = self.samples.lock().unwrap();
+        let mut previous: Option<&StatsSample> = None;
+        for sample in samples.iter() {
+            if sample.at > end {
+                break;
+            }
+            let offset = sample.at.timestamp_millis() - first;
+            if offset < 0 {
+                previous = Some(sample);
+                continue;
+            }
+            let bucket = &mut buckets[(offset / step_ms) as usize];
+            bucket.samples += 1;
+            bucket.available += sample.available as f64;
+            if let Some(latency) = sample.average_latency_ms {
+                bucket.latency += latency;
+                bucket.latency_samples += 1;
+            }
+            bucket.requests += match previous {
+                Some(previous) if sample.total_connections >= previous.total_connections => sample.total_connections - previous.total_connections,
+                Some(_) => sample.total_connections,
+                None => 0,
+            };
+            previous = Some(sample);
+        }
+
+        buckets.into_iter()
+            .enumerate()
+            .map(|(index, bucket)| StatsBucket {
+                start: DateTime::from_timestamp_millis(first + index as i64 * step_ms).unwrap_or(end),
+                samples: bucket.samples,
+                available_proxies: (bucket.samples > 0).then(|| bucket.available / bucket.samples as f64),
+                average_latency_ms: (bucket.latency_samples > 0).then(|| bucket.latency / bucket.latency_samples as f64),
+                requests: bucket.requests,
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Accumulator {
+    samples: usize,
+    available: f64,
+    latency: f64,
+    latency_samples: usize,
+    requests: u64,
+}
@@ -0,0 +1,61 @@
+//! 按目标固定代理
+//!
+//! 部分网站按来源IP识别访问者，同一目标的连接在一段时间内都经同一个代理出去更不容易被拦截。
+//! 映射从首次分配代理开始计时，到期后下一个连接重新选择代理并开始新的一段时间；
+//! 固定的代理不可用时由调用方改选并覆盖映射。
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 最多保存的目标数，超出后先清理过期的映射，仍然超出时清空
+const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Debug)]
+struct Entry {
+    proxy_id: String,
+    since: Instant,
+}
+
+/// 目标主机到代理ID的映射，有效期在查询时给出，修改有效期后立即按新值判断
+#[derive(Debug, Default)]
+pub struct StickyTargets {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl StickyTargets {
+    /// 目标在有效期内固定的代理ID
+    pub fn get(&self, target: &str, ttl: Duration) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = target.to_ascii_lowercase();
+        match entries.get(&key) {
+            Some(entry) if entry.since.elapsed() < ttl => Some(entry.proxy_id.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// 把目标固定到代理，已经固定到同一代理时保留原来的开始时间
+    pub fn insert(&self, target: &str, proxy_id: &str, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap();
+        let key = target.to_ascii_lowercase();
+        if entries.get(&key).is_some_and(|entry| entry.proxy_id == proxy_id && entry.since.elapsed() < ttl) {
+            return;
+        }
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.since.elapsed() < ttl);
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+        }
+        entries.insert(key, Entry { proxy_id: proxy_id.to_string(), since: Instant::now() });
+    }
+
+    /// 有效期内的映射数
+    pub fn len(&self, ttl: Duration) -> usize {
+        self.entries.lock().unwrap().values().filter(|entry| entry.since.elapsed() < ttl).count()
+    }
+}
@@ -0,0 +1,89 @@
+//! 代理选择策略
+
+use std::fmt;
+use std::str::FromStr;
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::proxy::Proxy;
+
+/// 代理选择策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionStrategy {
+    /// 总是选择延迟最低的代理
+    #[default]
+    LowestLatency,
+    /// 按顺序轮流使用代理
+    RoundRobin,
+    /// 随机选择代理
+    Random,
+    /// 按代理权重随机选择
+    WeightedRandom,
+}
+
+impl SelectionStrategy {
+    /// 从候选代理中选择一个，`cursor` 为轮询计数
+    pub fn select<'a>(&self, candidates: &[&'a Proxy], cursor: usize) -> Option<&'a Proxy> {
+        if candidates.is_empty() {
+            return None;
+        }
+
+        match self {
+            SelectionStrategy::LowestLatency => candidates.iter().min_by_key(|p| p.latency).copied(),
+            SelectionStrategy::RoundRobin => {
+                // HashMap迭代顺序不稳定，按ID排序保证轮询顺序一致
+                let mut ordered = candidates.to_vec();
+                ordered.sort_by(|a, b| a.id.cmp(&b.id));
+                Some(ordered[cursor % ordered.len()])
+            }
+            SelectionStrategy::Random => {
+                let index = rand::rng().random_range(0..candidates.len());
+                Some(candidates[index])
+            }
+            SelectionStrategy::WeightedRandom => {
+                let total: u64 = candidates.iter().map(|p| p.info.weight as u64).sum();
+                if total == 0 {
+                    // 全部权重为0时退化为普通随机
+                    return SelectionStrategy::Random.select(candidates, cursor);
+                }
+
+                let mut point = rand::rng().random_range(0..total);
+                for proxy in candidates {
+                    let weight = proxy.info.weight as u64;
+                    if point < weight {
+                        return Some(proxy);
+                    }
+                    point -= weight;
+                }
+                candidates.last().copied()
+            }
+        }
+    }
+}
+
+impl fmt::Display for SelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectionStrategy::LowestLatency => write!(f, "lowest_latency"),
+            SelectionStrategy::RoundRobin => write!(f, "round_robin"),
+            SelectionStrategy::Random => write!(f, "random"),
+            SelectionStrategy::WeightedRandom => write!(f, "weighted_random"),
+        }
+    }
+}
+
+impl FromStr for SelectionStrategy {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lowest_latency" => Ok(SelectionStrategy::LowestLatency),
+            "round_robin" => Ok(SelectionStrategy::RoundRobin),
+            "random" => Ok(SelectionStrategy::Random),
+            "weighted_random" => Ok(SelectionStrategy::WeightedRandom),
+            other => Err(crate::error::Error::Configuration(format!("未知的选择策略: {}", other))),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! 后台任务的panic处理与监督
+//!
+//! `spawn_logged` 用于一次性任务（单个连接、后台检测），panic时记录任务名与原因；
+//! `supervise` 用于必须常驻的任务（健康检查等），panic后按指数退避重新启动。
+//! 两者都会累加全局的panic计数，可通过 `task_panics` 读取。
+
+use std::any::Any;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::task::{JoinError, JoinHandle};
+use tracing::{error, info};
+
+/// 进程内后台任务panic的总次数
+static TASK_PANICS: AtomicU64 = AtomicU64::new(0);
+
+/// 后台任务panic的总次数
+pub fn task_panics() -> u64 {
+    TASK_PANICS.load(Ordering::Relaxed)
+}
+
+/// 重启常驻任务时的退避策略
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    /// 首次重启前的等待时间
+    pub initial: Duration,
+    /// 等待时间上限，每次连续panic后翻倍
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+        }
+    }
+}
+
+/// 从panic负载中取出可读的原因
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("未知原因")
+}
+
+/// 记录任务异常结束，返回是否为panic
+fn report(name: &str, result: Result<(), JoinError>) -> bool {
+    match result {
+        Ok(()) => false,
+        Err(e) if e.is_panic() => {
+            TASK_PANICS.fetch_add(1, Ordering::Relaxed);
+            let payload = e.into_panic();
+            error!("后台任务 [{}] panic: {}", name, panic_message(payload.as_ref()));
+            true
+        }
+        // 任务被取消，属于正常的关闭流程
+        Err(_) => false,
+    }
+}
+
+/// 启动一次性任务，panic时记录任务名与原因而不是静默退出
+pub fn spawn_logged<F>(name: impl Into<String>, future: F) -> JoinHandle<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let name = name.into();
+    let task = tokio::spawn(future);
+    tokio::spawn(async move {
+        report(&name, task.await);
+    })
+}
+
+/// 启动常驻任务，panic后按 `backoff` 等待并用 `factory` 重新创建任务
+///
+/// 任务正常结束或被取消时监督随之结束。任务运行超过退避上限后才panic的，
+/// 视为已经稳定运行过，等待时间从 `initial` 重新开始。
+pub fn supervise<F, Fut>(name: impl Into<String>, backoff: Backoff, mut factory: F) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let name = name.into();
+    tokio::spawn(async move {
+        let mut delay = backoff.initial;
+        loop {
+            let started = Instant::now();
+            if !report(&name, tokio::spawn(factory()).await) {
+                return;
+            }
+            if started.elapsed() >= backoff.max {
+                delay = backoff.initial;
+            }
+            info!("后台任务 [{}] 将在 {:?} 后重启", name, delay);
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(backoff.max);
+        }
+    })
+}
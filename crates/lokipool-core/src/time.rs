@@ -0,0 +1,98 @@
+//! 时间处理
+//!
+//! 间隔、超时与新鲜度一律用单调时钟计算，容器中常见的系统时间跳变不会影响它们；
+//! 墙上时间只用于显示与持久化。`Stamp` 同时记录两者，序列化时只保留墙上时间。
+
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, TimeDelta, Utc};
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// 当前墙上时间，仅用于显示与持久化
+pub fn wall_now() -> DateTime<Utc> {
+    Utc::now()
+}
+
+/// 持久化的墙上时间距今的时长
+///
+/// 时间晚于当前时间（系统时钟被回拨，或数据来自时钟不同步的主机）时返回None，
+/// 调用方应把它当作无法判断新鲜度处理，而不是当作刚刚发生。
+pub fn wall_age(at: DateTime<Utc>) -> Option<Duration> {
+    (wall_now() - at).to_std().ok()
+}
+
+/// 同时记录单调时钟与墙上时间的时间点
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Stamp {
+    mono: Instant,
+    wall: DateTime<Utc>,
+}
+
+impl Stamp {
+    /// 当前时间点
+    pub fn now() -> Self {
+        Self { mono: Instant::now(), wall: wall_now() }
+    }
+
+    /// 从持久化的墙上时间恢复，按距今的时长推算单调时间，位于未来的时间视为现在
+    pub fn from_wall(wall: DateTime<Utc>) -> Self {
+        let now = Instant::now();
+        let mono = wall_age(wall).and_then(|age| now.checked_sub(age)).unwrap_or(now);
+        Self { mono, wall }
+    }
+
+    /// 距今的时长，不受系统时间跳变影响
+    pub fn elapsed(&self) -> Duration {
+        self.mono.elapsed()
+    }
+
+    /// 之后 `duration` 的时间点，用于表示截止时间
+    pub fn after(&self, duration: Duration) -> Self {
+        let wall = TimeDelta::from_std(duration).ok()
+            .and_then(|delta| self.wall.checked_add_signed(delta))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        // 超出可表示范围的时长按一百多年处理，相当于永不过期
+        let mono = self.mono.checked_add(duration)
+            .or_else(|| self.mono.checked_add(Duration::from_secs(u32::MAX as u64)))
+            .unwrap_or(self.mono);
+        Self { mono, wall }
+    }
+
+    /// 按单调时钟判断时间点是否已经过去
+    pub fn has_passed(&self) -> bool {
+        Instant::now() >= self.mono
+    }
+
+    /// 墙上时间，用于显示与持久化
+    pub fn wall(&self) -> DateTime<Utc> {
+        self.wall
+    }
+}
+
+impl Serialize for Stamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.wall.serialize(serializer)
+    }
+}
+
+/// 与序列化一致，Schema即墙上时间的Schema
+impl JsonSchema for Stamp {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        DateTime::<Utc>::schema_name()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        DateTime::<Utc>::json_schema(generator)
+    }
+}
+
+impl<'de> Deserialize<'de> for Stamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        DateTime::<Utc>::deserialize(deserializer).map(Self::from_wall)
+    }
+}
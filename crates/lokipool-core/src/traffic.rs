@@ -0,0 +1,104 @@
+//! 按客户端与目标统计流量
+//!
+//! 每个连接结束时按客户端IP与目标主机累加连接数和双向字节数，用于查看谁在消耗代理池。
+//! 两张表各自最多保存 `MAX_ENTRIES` 个键，超出后新出现的键合并到 `OTHER` 中，避免大量不同目标占满内存。
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// 每张表最多保存的键数
+const MAX_ENTRIES: usize = 10_000;
+
+/// 超出键数上限后合并统计使用的键
+pub const OTHER: &str = "(other)";
+
+/// 一个客户端或目标的累计流量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TrafficStats {
+    pub connections: u64,
+    /// 客户端发往目标的字节数
+    pub bytes_up: u64,
+    /// 目标发往客户端的字节数
+    pub bytes_down: u64,
+}
+
+impl TrafficStats {
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes_up + self.bytes_down
+    }
+
+    fn add(&mut self, bytes_up: u64, bytes_down: u64) {
+        self.connections += 1;
+        self.bytes_up += bytes_up;
+        self.bytes_down += bytes_down;
+    }
+}
+
+/// 流量报表中的一行
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TrafficEntry {
+    /// 客户端IP或目标主机
+    pub key: String,
+    #[serde(flatten)]
+    pub stats: TrafficStats,
+}
+
+/// 流量报表，两张表都按总字节数从多到少排列
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TrafficReport {
+    pub clients: Vec<TrafficEntry>,
+    pub destinations: Vec<TrafficEntry>,
+}
+
+impl TrafficReport {
+    /// 每张表只保留前 `limit` 行
+    pub fn truncate(&mut self, limit: usize) {
+        self.clients.truncate(limit);
+        self.destinations.truncate(limit);
+    }
+}
+
+/// 按客户端与目标累计的流量
+#[derive(Debug, Default)]
+pub struct TrafficAccounting {
+    clients: Mutex<HashMap<String, TrafficStats>>,
+    destinations: Mutex<HashMap<String, TrafficStats>>,
+}
+
+impl TrafficAccounting {
+    /// 记录一个结束的连接
+    pub fn record(&self, client: IpAddr, destination: &str, bytes_up: u64, bytes_down: u64) {
+        add(&mut self.clients.lock().unwrap(), client.to_string(), bytes_up, bytes_down);
+        add(&mut self.destinations.lock().unwrap(), destination.to_ascii_lowercase(), bytes_up, bytes_down);
+    }
+
+    /// 当前的累计流量
+    pub fn report(&self) -> TrafficReport {
+        TrafficReport {
+            clients: sorted(&self.clients.lock().unwrap()),
+            destinations: sorted(&self.destinations.lock().unwrap()),
+        }
+    }
+
+    /// 清空统计
+    pub fn clear(&self) {
+        self.clients.lock().unwrap().clear();
+        self.destinations.lock().unwrap().clear();
+    }
+}
+
+fn add(table: &mut HashMap<String, TrafficStats>, key: String, bytes_up: u64, bytes_down: u64) {
+    let key = if table.len() >= MAX_ENTRIES && !table.contains_key(&key) { OTHER.to_string() } else { key };
+    table.entry(key).or_default().add(bytes_up, bytes_down);
+}
+
+fn sorted(table: &HashMap<String, TrafficStats>) -> Vec<TrafficEntry> {
+    let mut entries: Vec<TrafficEntry> = table.iter()
+        .map(|(key, stats)| TrafficEntry { key: key.clone(), stats: *stats })
+        .collect();
+    entries.sort_by(|a, b| b.stats.total_bytes().cmp(&a.stats.total_bytes()).then_with(|| a.key.cmp(&b.key)));
+    entries
+}
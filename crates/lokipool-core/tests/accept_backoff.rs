@@ -0,0 +1,21 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use lokipool_core::accept_backoff;
+use lokipool_core::listener::{is_resource_error, RESOURCE_BACKOFF};
+
+#[tokio::test]
+async fn only_resource_errors_pause_the_accept_loop() {
+    // EMFILE与ENFILE
+    assert!(is_resource_error(&io::Error::from_raw_os_error(24)));
+    assert!(is_resource_error(&io::Error::from_raw_os_error(23)));
+    assert!(!is_resource_error(&io::Error::from(io::ErrorKind::ConnectionAborted)));
+
+    let start = Instant::now();
+    accept_backoff(&io::Error::from_raw_os_error(24)).await;
+    assert!(start.elapsed() >= RESOURCE_BACKOFF);
+
+    let start = Instant::now();
+    accept_backoff(&io::Error::from(io::ErrorKind::ConnectionAborted)).await;
+    assert!(start.elapsed() < Duration::from_millis(50));
+}
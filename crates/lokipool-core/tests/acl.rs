@@ -0,0 +1,46 @@
+use lokipool_core::{Acl, AclSettings, DestinationRule};
+
+fn rule(text: &str) -> DestinationRule {
+    text.parse().unwrap()
+}
+
+#[test]
+fn destination_rules_match_hosts_and_ports() {
+    assert!(rule("example.com").matches("EXAMPLE.com", 80));
+    assert!(!rule("example.com").matches("www.example.com", 80));
+    assert!(rule("*.example.com").matches("www.Example.com", 443));
+    assert!(!rule("*.example.com").matches("example.com", 443));
+    assert!(!rule("*.example.com").matches("badexample.com", 443));
+    assert!(rule("10.0.0.0/8:22").matches("10.1.2.3", 22));
+    assert!(!rule("10.0.0.0/8:22").matches("10.1.2.3", 23));
+    assert!(!rule("10.0.0.0/8").matches("ten.example", 22));
+    assert!(rule("*:25").matches("mail.example.com", 25));
+    assert!(rule("[::1]:443").matches("::1", 443));
+    assert!(rule("fe80::/10").matches("fe80::1", 8080));
+
+    for invalid in ["", "*.", "example.com:http", "[::1", "10.0.0.0/33"] {
+        assert!(invalid.parse::<DestinationRule>().is_err(), "{} 应当无效", invalid);
+    }
+}
+
+#[test]
+fn deny_rules_take_precedence_and_empty_allow_lists_permit_all() {
+    let acl = Acl::from_settings(&AclSettings {
+        allow_clients: vec!["192.168.0.0/16".to_string()],
+        deny_clients: vec!["192.168.1.13".to_string()],
+        allow_destinations: Vec::new(),
+        deny_destinations: vec!["*:25".to_string(), "*.internal".to_string()],
+    })
+    .unwrap();
+
+    assert!(acl.permits_client("192.168.1.12".parse().unwrap()));
+    assert!(!acl.permits_client("192.168.1.13".parse().unwrap()));
+    assert!(!acl.permits_client("10.0.0.1".parse().unwrap()));
+    assert!(acl.permits_destination("example.com", 443));
+    assert!(!acl.permits_destination("example.com", 25));
+    assert!(!acl.permits_destination("db.internal", 5432));
+    assert!(Acl::default().permits_client("10.0.0.1".parse().unwrap()));
+
+    let invalid = AclSettings { deny_clients: vec!["not-an-ip".to_string()], ..AclSettings::default() };
+    assert!(Acl::from_settings(&invalid).is_err());
+}
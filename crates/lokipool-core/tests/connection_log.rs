@@ -0,0 +1,52 @@
+use lokipool_core::{ConnectionLog, ConnectionRecord, ConnectionSummary, TrafficClass};
+
+fn summary(target: &str, success: bool) -> ConnectionSummary {
+    ConnectionSummary {
+        client: "127.0.0.1:50000".to_string(),
+        target: target.to_string(),
+        proxy: success.then(|| "10.0.0.1:1080".to_string()),
+        class: TrafficClass::Interactive,
+        success,
+        bytes_up: 100,
+        bytes_down: 2048,
+        duration_ms: 15,
+        error: (!success).then(|| "没有可用的代理".to_string()),
+    }
+}
+
+#[tokio::test]
+async fn connections_are_written_as_json_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("logs").join("connections.jsonl");
+    let log = ConnectionLog::spawn(&path);
+
+    log.record(&summary("example.com:443", true));
+    log.record(&summary("example.org:80", false));
+    log.flush().await.unwrap();
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    let records: Vec<ConnectionRecord> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].summary, summary("example.com:443", true));
+    assert_eq!(records[1].summary.error.as_deref(), Some("没有可用的代理"));
+    let first: serde_json::Value = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+    assert_eq!(first["proxy"], "10.0.0.1:1080");
+    assert_eq!(first["bytes_down"], 2048);
+}
+
+#[tokio::test]
+async fn file_moved_away_is_recreated() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("connections.jsonl");
+    let log = ConnectionLog::spawn(&path);
+
+    log.record(&summary("example.com:443", true));
+    log.flush().await.unwrap();
+    std::fs::rename(&path, dir.path().join("connections.jsonl.1")).unwrap();
+    log.record(&summary("example.org:443", true));
+    log.flush().await.unwrap();
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(content.lines().count(), 1);
+    assert!(content.contains("example.org:443"));
+}
@@ -0,0 +1,87 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use lokipool_core::event_log::{self, rotated_path};
+use lokipool_core::{ConnectionSummary, EventLog, EventLogOptions, LogEntry, Pool, PoolEvent, PoolOptions, ProxyConfig, TrafficClass};
+
+fn summary(target: &str) -> ConnectionSummary {
+    ConnectionSummary {
+        client: "127.0.0.1:50000".to_string(),
+        target: target.to_string(),
+        proxy: Some("10.0.0.1:1080".to_string()),
+        class: TrafficClass::Bulk,
+        success: true,
+        bytes_up: 100,
+        bytes_down: 2048,
+        duration_ms: 15,
+        error: None,
+    }
+}
+
+fn targets(path: &std::path::Path) -> Vec<String> {
+    event_log::read_all(path).unwrap()
+        .into_iter()
+        .filter_map(|record| match record.entry {
+            LogEntry::Connection(conn) => Some(conn.target),
+            LogEntry::Pool { .. } => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn pool_events_and_connections_are_logged() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.ndjson.zst");
+    let log = EventLog::spawn(EventLogOptions::new(&path));
+
+    let pool = Pool::new(PoolOptions::default());
+    log.follow(&pool);
+    pool.add_config(ProxyConfig::parse("10.0.0.1:1080").unwrap()).await.unwrap();
+    log.record_connection(summary("example.com:443"));
+    // 等待事件转发任务把事件交给写入任务
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    log.flush().await.unwrap();
+
+    let records = event_log::read_all(&path).unwrap();
+    assert!(records.iter().any(|record| matches!(
+        &record.entry,
+        LogEntry::Pool { event: PoolEvent::ProxyAdded { host, port: 1080, .. } } if host == "10.0.0.1"
+    )));
+    assert_eq!(targets(&path), vec!["example.com:443"]);
+}
+
+#[tokio::test]
+async fn log_is_rotated_and_read_oldest_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.ndjson.zst");
+    let log = EventLog::spawn(EventLogOptions { max_bytes: 1, keep: 2, ..EventLogOptions::new(&path) });
+
+    // 每次flush写出一帧，上限很小时每帧都会触发轮转
+    for index in 0..4 {
+        log.record_connection(summary(&format!("host{}:80", index)));
+        log.flush().await.unwrap();
+    }
+
+    assert!(rotated_path(&path, 2).exists());
+    assert!(!rotated_path(&path, 3).exists());
+    assert_eq!(event_log::log_files(&path).len(), 3);
+    assert_eq!(targets(&path), vec!["host1:80", "host2:80", "host3:80"]);
+}
+
+#[tokio::test]
+async fn truncated_frame_is_left_for_the_next_read() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.ndjson.zst");
+    let log = EventLog::spawn(EventLogOptions::new(&path));
+    log.record_connection(summary("first:80"));
+    log.flush().await.unwrap();
+    let complete = fs::metadata(&path).unwrap().len();
+
+    // 模拟写到一半的帧
+    let frame = zstd::encode_all(&b"{\"partial\":true}\n"[..], 3).unwrap();
+    OpenOptions::new().append(true).open(&path).unwrap().write_all(&frame[..frame.len() / 2]).unwrap();
+
+    let (records, offset) = event_log::read_from(&path, 0).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(offset, complete);
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use lokipool_core::{write_atomic, ProxyFileWriter};
+
+/// 目录中除目标文件外没有遗留的临时文件
+fn assert_no_leftovers(dir: &std::path::Path, name: &str) {
+    let entries: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(entries, vec![name.to_string()]);
+}
+
+#[test]
+fn write_atomic_replaces_contents() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("proxies.txt");
+
+    write_atomic(&path, b"127.0.0.1:1080").unwrap();
+    write_atomic(&path, b"127.0.0.1:1081\n127.0.0.1:1082").unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "127.0.0.1:1081\n127.0.0.1:1082");
+    assert_no_leftovers(dir.path(), "proxies.txt");
+}
+
+#[test]
+fn concurrent_writes_never_expose_partial_file() {
+    const SIZE: usize = 256 * 1024;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("proxies.txt");
+    write_atomic(&path, &vec![b'a'; SIZE]).unwrap();
+
+    let done = Arc::new(AtomicBool::new(false));
+    let reader = {
+        let path = path.clone();
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            let mut reads = 0;
+            while !done.load(Ordering::SeqCst) {
+                let contents = fs::read(&path).unwrap();
+                assert_eq!(contents.len(), SIZE, "读到了被截断的文件");
+                assert!(contents.iter().all(|&b| b == contents[0]), "读到了混合的内容");
+                reads += 1;
+            }
+            reads
+        })
+    };
+
+    let writers: Vec<_> = (b'b'..=b'i')
+        .map(|byte| {
+            let path = path.clone();
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    write_atomic(&path, &vec![byte; SIZE]).unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    done.store(true, Ordering::SeqCst);
+
+    assert!(reader.join().unwrap() > 0);
+    assert_no_leftovers(dir.path(), "proxies.txt");
+}
+
+#[tokio::test]
+async fn writer_keeps_latest_update() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("proxies.txt");
+    let writer = ProxyFileWriter::spawn(&path);
+
+    for port in 1000..1100 {
+        writer.write(vec![format!("127.0.0.1:{}", port)]);
+    }
+    writer.flush().await.unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "127.0.0.1:1099");
+    assert_no_leftovers(dir.path(), "proxies.txt");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn writer_serializes_concurrent_updates() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("proxies.txt");
+    let writer = ProxyFileWriter::spawn(&path);
+
+    let lists: Vec<Vec<String>> = (0..16)
+        .map(|task| (0..50).map(|i| format!("10.0.{}.{}:1080", task, i)).collect())
+        .collect();
+    let tasks: Vec<_> = lists
+        .iter()
+        .cloned()
+        .map(|list| {
+            let writer = writer.clone();
+            tokio::spawn(async move {
+                for _ in 0..10 {
+                    writer.write(list.clone());
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    writer.flush().await.unwrap();
+
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(lists.iter().any(|list| list.join("\n") == contents), "文件内容不是任何一次完整的写入");
+    assert_no_leftovers(dir.path(), "proxies.txt");
+}
+
+#[tokio::test]
+async fn flush_reports_write_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let writer = ProxyFileWriter::spawn(dir.path().join("missing").join("proxies.txt"));
+
+    writer.write(vec!["127.0.0.1:1080".to_string()]);
+
+    assert!(writer.flush().await.is_err());
+}
@@ -0,0 +1,61 @@
+use std::collections::HashSet;
+
+use lokipool_core::{IpNet, Pool, PoolOptions, Proxy, ProxyConfig, ProxyStatus, TrafficClass};
+
+/// 按给定延迟创建可用代理，返回延迟对应的代理ID
+async fn pool_with_latencies(options: PoolOptions, latencies: &[u64]) -> (Pool, Vec<String>) {
+    let pool = Pool::new(options);
+    let mut ids = Vec::new();
+    for (i, latency) in latencies.iter().enumerate() {
+        let mut proxy = Proxy::from_config(ProxyConfig::parse(&format!("10.0.0.{}:1080", i + 1)).unwrap());
+        proxy.status = ProxyStatus::Available;
+        proxy.latency = *latency;
+        ids.push(pool.add(proxy).await.unwrap());
+    }
+    (pool, ids)
+}
+
+#[tokio::test]
+async fn interactive_traffic_gets_reserved_low_latency_proxies() {
+    let options = PoolOptions { interactive_reserve: 2, ..PoolOptions::default() };
+    let (pool, ids) = pool_with_latencies(options, &[50, 10, 200, 20]).await;
+    let reserved: HashSet<&String> = [&ids[1], &ids[3]].into_iter().collect();
+
+    for _ in 0..20 {
+        let interactive = pool.get_available_for(TrafficClass::Interactive).await.unwrap();
+        assert!(reserved.contains(&interactive.id));
+        let (bulk, _guard) = pool.acquire_for(TrafficClass::Bulk, |_| true).await.unwrap();
+        assert!(!reserved.contains(&bulk.id));
+    }
+
+    // 批量流量的代理都不可用时退回到全部代理
+    pool.update_status(&ids[0], ProxyStatus::Failed).await;
+    pool.update_status(&ids[2], ProxyStatus::Failed).await;
+    let bulk = pool.get_available_for(TrafficClass::Bulk).await.unwrap();
+    assert!(reserved.contains(&bulk.id));
+}
+
+#[tokio::test]
+async fn classes_share_all_proxies_without_reserve() {
+    let (pool, ids) = pool_with_latencies(PoolOptions::default(), &[50, 10]).await;
+    assert_eq!(pool.get_available_for(TrafficClass::Bulk).await.unwrap().id, ids[1]);
+    assert_eq!(pool.get_available_for(TrafficClass::Interactive).await.unwrap().id, ids[1]);
+}
+
+#[test]
+fn ip_nets_match_addresses() {
+    let lan: IpNet = "192.168.0.0/16".parse().unwrap();
+    assert!(lan.contains("192.168.3.4".parse().unwrap()));
+    assert!(lan.contains("::ffff:192.168.3.4".parse().unwrap()));
+    assert!(!lan.contains("10.0.0.1".parse().unwrap()));
+
+    let host: IpNet = "10.0.0.1".parse().unwrap();
+    assert!(host.contains("10.0.0.1".parse().unwrap()));
+    assert!(!host.contains("10.0.0.2".parse().unwrap()));
+
+    let v6: IpNet = "fd00::/8".parse().unwrap();
+    assert!(v6.contains("fd12::1".parse().unwrap()));
+    assert!("0.0.0.0/0".parse::<IpNet>().unwrap().contains("8.8.8.8".parse().unwrap()));
+    assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+    assert!("example.com".parse::<IpNet>().is_err());
+}
@@ -0,0 +1,31 @@
+use lokipool_core::{LatencyPercentiles, Pool, PoolOptions, ProxyConfig, ProxyStatus, SelectionStrategy};
+
+#[test]
+fn percentiles_use_nearest_rank() {
+    let latency = LatencyPercentiles::from_samples((1..=100).rev().collect());
+    assert_eq!((latency.p50, latency.p90, latency.p99), (Some(50), Some(90), Some(99)));
+    assert_eq!((latency.samples, latency.mean), (100, Some(50.5)));
+    assert_eq!(LatencyPercentiles::from_samples(Vec::new()).p50, None);
+}
+
+#[tokio::test]
+async fn metrics_count_status_connections_and_selections() {
+    let configs = (1080..1083).map(|port| ProxyConfig::parse(&format!("10.0.0.1:{}", port)).unwrap()).collect();
+    let pool = Pool::new_with_proxies(configs, PoolOptions::default());
+    pool.test_all().await;
+    let failed = pool.get_all_proxies().await.into_iter().find(|p| p.info.port == 1082).unwrap();
+    pool.update_status(&failed.id, ProxyStatus::Failed).await;
+
+    let first = pool.acquire().await.unwrap();
+    pool.update_options(|options| options.strategy = SelectionStrategy::RoundRobin);
+    let second = pool.acquire().await.unwrap();
+
+    let metrics = pool.metrics().await;
+    assert_eq!((metrics.total, metrics.status.available, metrics.status.failed), (3, 2, 1));
+    assert_eq!(metrics.latency.samples, 2);
+    assert_eq!((metrics.connections.active_connections, metrics.connections.total_connections), (2, 2));
+    assert_eq!((metrics.selections.lowest_latency, metrics.selections.round_robin), (1, 1));
+
+    drop((first, second));
+    assert_eq!(pool.metrics().await.connections.active_connections, 0);
+}
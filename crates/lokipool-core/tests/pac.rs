@@ -0,0 +1,35 @@
+use lokipool_core::{pac, Config, ListenerSettings};
+
+fn config() -> Config {
+    let mut config = Config::default();
+    config.socks_server.bind_port = 1080;
+    config.socks_server.listeners.push(ListenerSettings { bind_port: 1081, ..ListenerSettings::default() });
+    config.socks_server.bypass.destinations = ["localhost", "*.lan", "192.168.0.0/16", "10.0.0.1:22", "fd00::/8"]
+        .map(String::from)
+        .to_vec();
+    config
+}
+
+#[test]
+fn bypass_rules_become_direct_conditions() {
+    let pac = pac::render(&config(), "192.168.1.10", None).unwrap();
+
+    assert!(pac.contains("function FindProxyForURL(url, host)"));
+    assert!(pac.contains(r#"if (h == "localhost") return "DIRECT";"#), "{}", pac);
+    assert!(pac.contains(r#"if (dnsDomainIs(h, ".lan")) return "DIRECT";"#));
+    assert!(pac.contains(r#"if (isIpv4(h) && isInNet(h, "192.168.0.0", "255.255.0.0")) return "DIRECT";"#));
+    assert!(pac.contains(r#"if ((h == "10.0.0.1") && port == 22) return "DIRECT";"#));
+    assert!(pac.contains("// 无法在PAC中表示的直连规则: fd00::/8"));
+    assert!(pac.contains(r#"return "SOCKS5 192.168.1.10:1080; SOCKS 192.168.1.10:1080";"#));
+}
+
+#[test]
+fn listeners_and_http_proxy_select_the_route() {
+    let mut config = config();
+    config.http_server.bind_port = Some(8080);
+
+    let pac = pac::render(&config, "::1", Some(1081)).unwrap();
+    assert!(pac.contains(r#"return "SOCKS5 [::1]:1081; SOCKS [::1]:1081; PROXY [::1]:8080";"#), "{}", pac);
+
+    assert!(pac::render(&config, "::1", Some(1082)).is_err());
+}
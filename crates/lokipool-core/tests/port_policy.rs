@@ -0,0 +1,42 @@
+use lokipool_core::{PolicyAction, PortPolicy, PortPolicySettings, PortRange, PortRuleSettings, Transport};
+
+fn rule(action: PolicyAction, ports: &[&str], protocol: Transport) -> PortRuleSettings {
+    PortRuleSettings { action, ports: ports.iter().map(|p| p.to_string()).collect(), protocol, log: false }
+}
+
+#[test]
+fn port_ranges_parse_single_ports_and_ranges() {
+    let range: PortRange = "8000-8999".parse().unwrap();
+    assert!(range.contains(8000) && range.contains(8999));
+    assert!(!range.contains(9000));
+    assert_eq!("25".parse::<PortRange>().unwrap().to_string(), "25");
+
+    for invalid in ["", "smtp", "9000-8000", "1-70000"] {
+        assert!(invalid.parse::<PortRange>().is_err(), "{} 应当无效", invalid);
+    }
+}
+
+#[test]
+fn first_matching_rule_decides() {
+    let policy = PortPolicy::from_settings(&PortPolicySettings {
+        default_action: PolicyAction::Deny,
+        rules: vec![
+            rule(PolicyAction::Deny, &["8443"], Transport::Any),
+            rule(PolicyAction::Allow, &["80", "443", "8000-9000"], Transport::Tcp),
+            rule(PolicyAction::Allow, &["53"], Transport::Udp),
+        ],
+    })
+    .unwrap();
+
+    assert_eq!(policy.evaluate(443, Transport::Tcp).0, PolicyAction::Allow);
+    assert_eq!(policy.evaluate(8443, Transport::Tcp).0, PolicyAction::Deny);
+    assert_eq!(policy.evaluate(8443, Transport::Tcp).1.map(|(index, _)| index), Some(1));
+    assert_eq!(policy.evaluate(53, Transport::Udp).0, PolicyAction::Allow);
+    assert_eq!(policy.evaluate(53, Transport::Tcp), (PolicyAction::Deny, None));
+    assert_eq!(policy.evaluate(443, Transport::Udp), (PolicyAction::Deny, None));
+    assert!(PortPolicy::default().is_empty());
+    assert_eq!(PortPolicy::default().evaluate(25, Transport::Tcp).0, PolicyAction::Allow);
+
+    let empty = PortPolicySettings { rules: vec![rule(PolicyAction::Deny, &[], Transport::Any)], ..PortPolicySettings::default() };
+    assert!(PortPolicy::from_settings(&empty).is_err());
+}
@@ -0,0 +1,55 @@
+use lokipool_core::{parse_list, ListFormat};
+
+#[test]
+fn formats_are_detected_from_content() {
+    assert_eq!(ListFormat::detect("# 注释\n1.2.3.4:1080\nsocks5://u:p@5.6.7.8:1080\n"), ListFormat::Lines);
+    assert_eq!(ListFormat::detect("host,port\n1.2.3.4,1080\n"), ListFormat::Csv);
+    assert_eq!(ListFormat::detect("port: 7890\nproxies:\n  - {name: a, type: socks5, server: 1.2.3.4, port: 1080}\n"), ListFormat::Clash);
+}
+
+#[test]
+fn csv_with_and_without_header() {
+    let entries = parse_list("Server,Port,User,Password,Country\n1.2.3.4,1080,alice,\"p,w\",JP\n5.6.7.8,,,,\n");
+    assert_eq!(entries.len(), 2);
+    let (line, proxy) = &entries[0];
+    let proxy = proxy.as_ref().unwrap();
+    assert_eq!((*line, proxy.host.as_str(), proxy.port), (2, "1.2.3.4", 1080));
+    assert_eq!((proxy.username.as_deref(), proxy.password.as_deref(), proxy.location.as_deref()), (Some("alice"), Some("p,w"), Some("JP")));
+    assert!(entries[1].1.is_err());
+
+    let entries = parse_list("9.9.9.9,1081,bob,secret\n");
+    let proxy = entries[0].1.as_ref().unwrap();
+    assert_eq!((proxy.port, proxy.username.as_deref()), (1081, Some("bob")));
+}
+
+#[test]
+fn clash_subscription_socks5_entries() {
+    let content = r#"
+mixed-port: 7890
+proxies:
+  - name: "东京 01"
+    type: socks5
+    server: 1.2.3.4
+    port: 1080
+    username: alice
+    password: 's3cret'
+  - {name: b, type: socks5, server: "2001:db8::1", port: 1081}
+  - name: ss
+    type: ss
+    server: 5.6.7.8
+    port: 8388
+    cipher: aes-256-gcm
+proxy-groups:
+  - name: auto
+    type: url-test
+"#;
+    let entries = parse_list(content);
+    assert_eq!(entries.len(), 3);
+    let first = entries[0].1.as_ref().unwrap();
+    assert_eq!((first.host.as_str(), first.port, first.location.as_deref()), ("1.2.3.4", 1080, Some("东京 01")));
+    assert_eq!((first.username.as_deref(), first.password.as_deref()), (Some("alice"), Some("s3cret")));
+    let second = entries[1].1.as_ref().unwrap();
+    assert_eq!((second.host.as_str(), second.port), ("2001:db8::1", 1081));
+    assert_eq!(entries[2].0, 11);
+    assert!(entries[2].1.as_ref().unwrap_err().contains("ss"));
+}
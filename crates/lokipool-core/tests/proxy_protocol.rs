@@ -0,0 +1,62 @@
+use std::net::SocketAddr;
+
+use lokipool_core::proxy_protocol::{parse, Parsed, ProxyHeader};
+
+fn addr(text: &str) -> Option<SocketAddr> {
+    Some(text.parse().unwrap())
+}
+
+#[test]
+fn v1_headers_carry_the_client_address() {
+    let header = b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 1080\r\n\x05\x01\x00";
+    assert_eq!(parse(header).unwrap(), Parsed::Complete(ProxyHeader {
+        source: addr("203.0.113.7:51234"),
+        destination: addr("192.0.2.1:1080"),
+        length: header.len() - 3,
+    }));
+
+    let header = b"PROXY TCP6 2001:db8::7 2001:db8::1 443 1080\r\n";
+    let Parsed::Complete(parsed) = parse(header).unwrap() else { panic!("v1 TCP6应当完整") };
+    assert_eq!(parsed.source, addr("[2001:db8::7]:443"));
+
+    let Parsed::Complete(parsed) = parse(b"PROXY UNKNOWN\r\n").unwrap() else { panic!("UNKNOWN应当完整") };
+    assert_eq!(parsed.source, None);
+
+    assert_eq!(parse(b"PROXY TCP4 203.0.113.7").unwrap(), Parsed::Incomplete(23));
+    assert_eq!(parse(b"PRO").unwrap(), Parsed::Incomplete(4));
+    for invalid in [&b"GET / HTTP/1.1\r\n"[..], b"\x05\x01\x00", b"PROXY TCP4 2001:db8::7 192.0.2.1 1 2\r\n", b"PROXY TCP4 1.2.3.4\r\n"] {
+        assert!(parse(invalid).is_err(), "{:?} 应当无效", String::from_utf8_lossy(invalid));
+    }
+    assert!(parse(&[b'P', b'R', b'O', b'X', b'Y', b' '].into_iter().chain([b'x'; 120]).collect::<Vec<_>>()).is_err());
+}
+
+#[test]
+fn v2_headers_carry_the_client_address() {
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend_from_slice(&[0x21, 0x11, 0x00, 0x0c]);
+    header.extend_from_slice(&[203, 0, 113, 7, 192, 0, 2, 1]);
+    header.extend_from_slice(&51234u16.to_be_bytes());
+    header.extend_from_slice(&1080u16.to_be_bytes());
+
+    assert_eq!(parse(&header[..10]).unwrap(), Parsed::Incomplete(16));
+    assert_eq!(parse(&header[..20]).unwrap(), Parsed::Incomplete(28));
+    assert_eq!(parse(&header).unwrap(), Parsed::Complete(ProxyHeader {
+        source: addr("203.0.113.7:51234"),
+        destination: addr("192.0.2.1:1080"),
+        length: 28,
+    }));
+
+    // LOCAL命令是负载均衡的健康检查，没有客户端地址
+    let mut local = header[..12].to_vec();
+    local.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+    let Parsed::Complete(parsed) = parse(&local).unwrap() else { panic!("LOCAL应当完整") };
+    assert_eq!((parsed.source, parsed.length), (None, 16));
+
+    let mut wrong_version = header.clone();
+    wrong_version[12] = 0x11;
+    assert!(parse(&wrong_version).is_err());
+    let mut short_addresses = header[..12].to_vec();
+    short_addresses.extend_from_slice(&[0x21, 0x21, 0x00, 0x0c]);
+    short_addresses.extend_from_slice(&[0; 12]);
+    assert!(parse(&short_addresses).is_err());
+}
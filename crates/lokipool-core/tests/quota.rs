@@ -0,0 +1,45 @@
+use std::net::IpAddr;
+
+use lokipool_core::{QuotaExceeded, QuotaLimits, QuotaSettings, QuotaTable};
+
+#[test]
+fn connections_are_limited_per_client_and_released_on_drop() {
+    let quotas = QuotaTable::default();
+    let limits = QuotaLimits::from_settings(&QuotaSettings { max_connections: 2, ..QuotaSettings::default() });
+    let alice: IpAddr = "192.168.1.10".parse().unwrap();
+    let bob: IpAddr = "192.168.1.11".parse().unwrap();
+
+    let first = quotas.admit(alice, &limits).unwrap();
+    let _second = quotas.admit(alice, &limits).unwrap();
+    assert_eq!(quotas.admit(alice, &limits).unwrap_err(), QuotaExceeded::Connections(2));
+    assert!(quotas.admit(bob, &limits).is_ok());
+
+    drop(first);
+    assert_eq!(quotas.usage(alice).connections, 1);
+    assert!(quotas.admit(alice, &limits).is_ok());
+}
+
+#[test]
+fn daily_bytes_survive_a_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("quota.json");
+    let limits = QuotaLimits::from_settings(&QuotaSettings { max_mb_per_day: 1, ..QuotaSettings::default() });
+    assert_eq!(limits.max_bytes_per_day, 1024 * 1024);
+    let client: IpAddr = "2001:db8::7".parse().unwrap();
+
+    let quotas = QuotaTable::default();
+    quotas.record(client, 600 * 1024);
+    assert!(quotas.admit(client, &limits).is_ok());
+    quotas.record(client, 600 * 1024);
+    assert_eq!(quotas.admit(client, &limits).unwrap_err(), QuotaExceeded::Bytes(1200 * 1024));
+    quotas.save(&path).unwrap();
+
+    let restored = QuotaTable::load(&path).unwrap();
+    assert_eq!(restored.usage(client).bytes, 1200 * 1024);
+    assert!(restored.admit(client, &limits).is_err());
+
+    // 其他日期的用量不再计入
+    let stale = std::fs::read_to_string(&path).unwrap().replace(&chrono::Utc::now().date_naive().to_string(), "2000-01-01");
+    std::fs::write(&path, stale).unwrap();
+    assert_eq!(QuotaTable::load(&path).unwrap().usage(client).bytes, 0);
+}
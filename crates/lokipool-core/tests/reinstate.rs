@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use lokipool_core::{Pool, PoolOptions, ProxyConfig, ProxyStatus};
+
+#[tokio::test]
+async fn reinstate_lifts_blacklist_and_quarantine() {
+    let configs = (1080..1082).map(|port| ProxyConfig::parse(&format!("10.0.0.1:{}", port)).unwrap()).collect();
+    let pool = Pool::new_with_proxies(configs, PoolOptions::default());
+    pool.test_all().await;
+    let proxies = pool.get_all_proxies().await;
+    let (blacklisted, quarantined) = (&proxies[0].id, &proxies[1].id);
+
+    assert!(pool.blacklist(blacklisted, Duration::from_secs(300)).await);
+    pool.update_status(quarantined, ProxyStatus::Quarantined).await;
+    let metrics = pool.metrics().await;
+    assert_eq!((metrics.status.blacklisted, metrics.status.quarantined), (1, 1));
+
+    assert!(pool.reinstate(blacklisted).await);
+    assert!(pool.reinstate(quarantined).await);
+    let metrics = pool.metrics().await;
+    assert_eq!((metrics.status.blacklisted, metrics.status.quarantined, metrics.status.available), (0, 0, 2));
+    assert!(!pool.reinstate("missing").await);
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use lokipool_core::{Pool, PoolHandle, PoolOptions, ProxyConfig, ProxyStatus};
+
+/// 一个指向本机未监听端口的代理，测试会很快结束
+fn unreachable_proxy() -> ProxyConfig {
+    ProxyConfig::parse("127.0.0.1:1").unwrap()
+}
+
+#[tokio::test]
+async fn shutdown_stops_background_tasks_and_saves_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    let snapshot = dir.path().join("snapshot.json");
+    let options = PoolOptions { test_interval: 3600, snapshot_file: Some(snapshot.clone()), ..PoolOptions::default() };
+    let pool = PoolHandle::from(Pool::new_with_proxies(vec![unreachable_proxy()], options));
+
+    let auto_test = pool.start_auto_test();
+    let report = pool.shutdown(Duration::from_secs(5)).await.unwrap();
+
+    assert_eq!(report.interrupted_tests, 0);
+    assert_eq!(report.snapshot_file.as_deref(), Some(snapshot.as_path()));
+    assert!(snapshot.exists());
+    // 定期测试要么已自行退出，要么已被取消
+    let _ = tokio::time::timeout(Duration::from_secs(1), auto_test).await.unwrap();
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_in_flight_tests_and_refuses_new_ones() {
+    let pool = PoolHandle::from(Pool::new_with_proxies(vec![unreachable_proxy()], PoolOptions::default()));
+
+    let testing = {
+        let pool = pool.clone();
+        tokio::spawn(async move { pool.test_all().await })
+    };
+    tokio::task::yield_now().await;
+    let report = pool.shutdown(Duration::from_secs(30)).await.unwrap();
+    assert_eq!(report.interrupted_tests, 0);
+    assert_eq!(report.snapshot_file, None);
+
+    let results = testing.await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_ne!(pool.get_all_proxies().await[0].status, ProxyStatus::Untested);
+
+    assert!(pool.test_all().await.is_empty());
+    assert_eq!(pool.retry_connections().await, None);
+    // 再次关闭是安全的
+    pool.shutdown(Duration::from_secs(1)).await.unwrap();
+}
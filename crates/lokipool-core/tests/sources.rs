@@ -0,0 +1,87 @@
+use std::fs;
+use std::time::Duration;
+
+use lokipool_core::{Pool, PoolHandle, PoolOptions, ProxyConfig, ProxySource, SyncReport};
+
+fn proxy(address: &str) -> ProxyConfig {
+    ProxyConfig::parse(address).unwrap()
+}
+
+/// 池中代理的地址，按地址排序
+async fn addresses(pool: &Pool) -> Vec<String> {
+    let mut addresses: Vec<String> = pool.get_all_proxies().await
+        .iter()
+        .map(|p| format!("{}:{}", p.info.host, p.info.port))
+        .collect();
+    addresses.sort();
+    addresses
+}
+
+#[tokio::test]
+async fn sources_only_remove_their_own_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("proxies.txt");
+    fs::write(&file, "10.0.0.1:1080\n10.0.0.2:1080\n").unwrap();
+
+    let pool = Pool::new_with_proxies(vec![proxy("10.0.0.1:1080")], PoolOptions::default());
+    let report = pool.sync_file(&file).await.unwrap();
+    assert_eq!(report, SyncReport { added: 1, existing: 1, ..SyncReport::default() });
+    pool.add_config(proxy("10.0.0.3:1080")).await.unwrap();
+    pool.add_config(proxy("10.0.0.2:1080")).await.unwrap();
+
+    // 文件清空后：配置文件与运行时添加的代理都保留
+    fs::write(&file, "").unwrap();
+    let report = pool.sync_file(&file).await.unwrap();
+    assert_eq!(report, SyncReport { released: 2, ..SyncReport::default() });
+    assert_eq!(addresses(&pool).await, vec!["10.0.0.1:1080", "10.0.0.2:1080", "10.0.0.3:1080"]);
+
+    // 配置文件不再列出的代理只属于该来源时被移除
+    let report = pool.sync_source(ProxySource::Config, Vec::new()).await;
+    assert_eq!(report, SyncReport { removed: 1, ..SyncReport::default() });
+    assert_eq!(addresses(&pool).await, vec!["10.0.0.2:1080", "10.0.0.3:1080"]);
+
+    let status = pool.source_status().await;
+    let count = |source| status.iter().find(|s| s.source == source).unwrap().proxies;
+    assert_eq!((count(ProxySource::Config), count(ProxySource::File), count(ProxySource::Api)), (0, 0, 2));
+}
+
+#[tokio::test]
+async fn unreadable_file_keeps_existing_proxies() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("proxies.txt");
+    fs::write(&file, "10.0.0.1:1080\n").unwrap();
+
+    let pool = Pool::new(PoolOptions::default());
+    pool.sync_file(&file).await.unwrap();
+    // 不是UTF-8文本，无法读取
+    fs::write(&file, [0xff, 0xfe, 0xfd]).unwrap();
+    assert!(pool.sync_file(&file).await.is_err());
+
+    assert_eq!(addresses(&pool).await, vec!["10.0.0.1:1080"]);
+    let status = pool.source_status().await;
+    let file_status = status.iter().find(|s| s.source == ProxySource::File).unwrap();
+    assert!(file_status.last_error.is_some());
+    assert_eq!(file_status.last_report.unwrap().added, 1);
+    assert_eq!(file_status.origin.as_deref(), Some(file.display().to_string().as_str()));
+}
+
+#[tokio::test]
+async fn watched_file_is_resynced_on_change() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("proxies.txt");
+    fs::write(&file, "10.0.0.1:1080\n").unwrap();
+
+    let pool = PoolHandle::from(Pool::new(PoolOptions::default()));
+    pool.sync_file(&file).await.unwrap();
+    pool.watch_file(file.clone(), Duration::from_millis(20));
+
+    fs::write(&file, "10.0.0.1:1080\n10.0.0.2:1080\n").unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while pool.get_all_proxies().await.len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    pool.shutdown(Duration::from_secs(1)).await.unwrap();
+}
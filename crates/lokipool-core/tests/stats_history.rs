@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use lokipool_core::{StatsHistory, StatsSample};
+
+fn at(minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 1, 1, 12, minute, 0).unwrap()
+}
+
+fn sample(minute: u32, available: usize, latency: Option<f64>, total_connections: u64) -> StatsSample {
+    StatsSample { at: at(minute), available, total: 10, average_latency_ms: latency, total_connections }
+}
+
+#[test]
+fn samples_are_bucketed_by_step() {
+    let history = StatsHistory::default();
+    for s in [
+        sample(0, 8, Some(100.0), 10),
+        sample(3, 6, Some(200.0), 25),
+        sample(6, 4, None, 40),
+        // 重启后累计值从头计数
+        sample(9, 5, Some(50.0), 7),
+    ] {
+        history.record(s).unwrap();
+    }
+
+    let buckets = history.series(at(10), Duration::from_secs(10 * 60), Duration::from_secs(5 * 60));
+    assert_eq!(buckets.iter().map(|bucket| bucket.start).collect::<Vec<_>>(), [at(0), at(5), at(10)]);
+
+    assert_eq!(buckets[0].samples, 2);
+    assert_eq!(buckets[0].available_proxies, Some(7.0));
+    assert_eq!(buckets[0].average_latency_ms, Some(150.0));
+    assert_eq!(buckets[0].requests, 15);
+
+    assert_eq!(buckets[1].samples, 2);
+    assert_eq!(buckets[1].available_proxies, Some(4.5));
+    assert_eq!(buckets[1].average_latency_ms, Some(50.0));
+    assert_eq!(buckets[1].requests, 15 + 7);
+
+    assert_eq!(buckets[2].samples, 0);
+    assert_eq!(buckets[2].available_proxies, None);
+    assert_eq!(buckets[2].requests, 0);
+
+    // 窗口之前的样本只用于计算第一个时间段的请求量
+    let recent = history.series(at(10), Duration::from_secs(5 * 60), Duration::from_secs(5 * 60));
+    assert_eq!(recent.len(), 2);
+    assert_eq!(recent[0].start, at(5));
+    assert_eq!(recent[0].requests, 22);
+}
+
+#[test]
+fn history_survives_a_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("stats.ndjson");
+
+    let history = StatsHistory::open(&path).unwrap();
+    assert!(history.is_empty());
+    let now = Utc::now();
+    history.record(StatsSample { at: now - chrono::Duration::days(30), available: 1, total: 1, average_latency_ms: None, total_connections: 0 }).unwrap();
+    history.record(StatsSample { at: now, available: 3, total: 4, average_latency_ms: Some(80.0), total_connections: 12 }).unwrap();
+
+    // 过期的样本在读回时丢弃
+    let restored = StatsHistory::open(&path).unwrap();
+    assert_eq!(restored.len(), 1);
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    let buckets = restored.series(now, Duration::from_secs(3600), Duration::from_secs(3600));
+    assert_eq!(buckets.last().unwrap().available_proxies, Some(3.0));
+}
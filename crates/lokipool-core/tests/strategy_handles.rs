@@ -0,0 +1,41 @@
+use std::collections::HashSet;
+
+use lokipool_core::{Pool, PoolOptions, ProxyConfig, ProxyStatus, SelectionStrategy};
+
+async fn pool() -> Pool {
+    let configs = (1080..1083).map(|port| ProxyConfig::parse(&format!("10.0.0.1:{}", port)).unwrap()).collect();
+    let pool = Pool::new_with_proxies(configs, PoolOptions::default());
+    pool.test_all().await;
+    pool
+}
+
+#[tokio::test]
+async fn strategy_handle_selects_with_its_own_strategy() {
+    let pool = pool().await;
+    let rotating = pool.with_strategy(SelectionStrategy::RoundRobin);
+    assert_eq!(pool.strategy(), SelectionStrategy::LowestLatency);
+    assert_eq!(rotating.strategy(), SelectionStrategy::RoundRobin);
+
+    let mut picked = HashSet::new();
+    for _ in 0..3 {
+        picked.insert(rotating.acquire().await.unwrap().0.id);
+    }
+    assert_eq!(picked.len(), 3);
+    drop(pool.acquire().await.unwrap());
+
+    let selections = pool.metrics().await.selections;
+    assert_eq!((selections.lowest_latency, selections.round_robin), (1, 3));
+}
+
+#[tokio::test]
+async fn strategy_handle_shares_proxy_state() {
+    let pool = pool().await;
+    let rotating = pool.with_strategy(SelectionStrategy::RoundRobin);
+    for proxy in pool.get_all_proxies().await.into_iter().filter(|p| p.info.port != 1081) {
+        pool.update_status(&proxy.id, ProxyStatus::Failed).await;
+    }
+
+    let (proxy, _guard) = rotating.acquire().await.unwrap();
+    assert_eq!(proxy.info.port, 1081);
+    assert_eq!(pool.get_all_proxies().await.iter().map(|p| p.active_connections()).sum::<usize>(), 1);
+}
@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use lokipool_core::{Pool, PoolHandle, PoolOptions, ProxyConfig, ProxyStatus, SyncReport};
+
+fn proxies(range: std::ops::Range<u16>) -> Vec<ProxyConfig> {
+    range.map(|port| ProxyConfig::parse(&format!("10.0.0.1:{}", port)).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn swap_keeps_state_of_retained_proxies() {
+    let pool = Pool::new_with_proxies(proxies(1000..1002), PoolOptions::default());
+    pool.test_all().await;
+    let kept = pool.get_all_proxies().await.into_iter().find(|p| p.info.port == 1000).unwrap();
+    assert_eq!(kept.status, ProxyStatus::Available);
+
+    let report = pool.swap(proxies(1000..1001).into_iter().chain(proxies(1005..1006)).collect()).await.unwrap();
+    assert_eq!(report, SyncReport { added: 1, existing: 1, removed: 1, ..SyncReport::default() });
+
+    let all = pool.get_all_proxies().await;
+    assert_eq!(all.len(), 2);
+    let retained = all.iter().find(|p| p.info.port == 1000).unwrap();
+    assert_eq!((retained.id.as_str(), retained.status), (kept.id.as_str(), ProxyStatus::Available));
+    assert_eq!(all.iter().find(|p| p.info.port == 1005).unwrap().status, ProxyStatus::Untested);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_readers_never_see_a_partial_pool() {
+    let pool = PoolHandle::from(Pool::new_with_proxies(proxies(1000..1050), PoolOptions::default()));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let reader = {
+        let pool = pool.clone();
+        let done = Arc::clone(&done);
+        tokio::spawn(async move {
+            let mut observations = 0;
+            while !done.load(Ordering::Relaxed) {
+                assert_eq!(pool.get_all_proxies().await.len(), 50);
+                observations += 1;
+                tokio::task::yield_now().await;
+            }
+            observations
+        })
+    };
+
+    // 两个各50个代理、部分重叠的列表交替换入
+    for round in 0..100 {
+        let next = if round % 2 == 0 { proxies(1025..1075) } else { proxies(1000..1050) };
+        pool.swap(next).await.unwrap();
+    }
+    done.store(true, Ordering::Relaxed);
+    assert!(reader.await.unwrap() > 0);
+}
@@ -0,0 +1,39 @@
+use lokipool_core::traffic::OTHER;
+use lokipool_core::TrafficAccounting;
+
+#[test]
+fn report_is_sorted_by_total_bytes() {
+    let traffic = TrafficAccounting::default();
+    traffic.record("10.0.0.1".parse().unwrap(), "Example.com", 100, 1000);
+    traffic.record("10.0.0.2".parse().unwrap(), "example.com", 10, 10);
+    traffic.record("10.0.0.2".parse().unwrap(), "mirror.example", 5000, 50_000);
+
+    let mut report = traffic.report();
+    assert_eq!(report.clients[0].key, "10.0.0.2");
+    assert_eq!(report.clients[0].stats.connections, 2);
+    assert_eq!(report.clients[0].stats.total_bytes(), 55_020);
+    assert_eq!(report.destinations[0].key, "mirror.example");
+    assert_eq!(report.destinations[1].key, "example.com");
+    assert_eq!(report.destinations[1].stats.connections, 2);
+
+    report.truncate(1);
+    assert_eq!(report.clients.len(), 1);
+    assert_eq!(report.destinations.len(), 1);
+}
+
+#[test]
+fn new_keys_beyond_capacity_are_merged() {
+    let traffic = TrafficAccounting::default();
+    let client = "10.0.0.1".parse().unwrap();
+    for i in 0..10_001 {
+        traffic.record(client, &format!("host{}.example", i), 1, 1);
+    }
+    traffic.record(client, "host0.example", 1, 1);
+
+    let report = traffic.report();
+    assert_eq!(report.destinations.len(), 10_001);
+    let other = report.destinations.iter().find(|entry| entry.key == OTHER).unwrap();
+    assert_eq!(other.stats.connections, 1);
+    let first = report.destinations.iter().find(|entry| entry.key == "host0.example").unwrap();
+    assert_eq!(first.stats.connections, 2);
+}
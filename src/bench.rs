@@ -0,0 +1,363 @@
+//! 压测
+//!
+//! `lokipool bench` 经运行中实例的本地SOCKS5监听端口向目标URL并发发起请求，输出吞吐量与延迟分位数，
+//! 用来验证代理池配置在真实流量下的表现。每个请求都新建连接，因此会按选择策略分配到不同的代理；
+//! 能访问实例的API时，压测前后各取一次各代理的连接计数，差值即本次压测在各代理上的分布
+//! （同一时间其他客户端的连接也会计入）。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+use lokipool_core::Config;
+use crate::commands::apply_tuned_overlay;
+use crate::tune::{format_rate, percentile};
+
+/// 压测配置
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// 配置文件，用于确定监听端口、API地址与默认目标
+    pub config: PathBuf,
+    /// 目标URL，默认使用配置中的第一个测试URL
+    pub url: Option<String>,
+    /// SOCKS5监听地址 `host:port`，默认取配置中的监听端口
+    pub socks: Option<String>,
+    /// 监听端口要求认证时使用的 `用户名:密码`
+    pub auth: Option<String>,
+    /// 请求总数
+    pub requests: usize,
+    /// 同时进行的请求数
+    pub concurrency: usize,
+    /// 单个请求的超时
+    pub timeout: Duration,
+    /// 实例的API地址，默认取配置中的API端口；用于统计各代理的请求分布
+    pub api: Option<String>,
+    /// 访问API的密钥，以 `x-api-key` 头发送
+    pub api_key: Option<String>,
+    /// 以JSON输出结果
+    pub json: bool,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            config: PathBuf::from("config.toml"),
+            url: None,
+            socks: None,
+            auth: None,
+            requests: 100,
+            concurrency: 10,
+            timeout: Duration::from_secs(30),
+            api: None,
+            api_key: None,
+            json: false,
+        }
+    }
+}
+
+impl BenchConfig {
+    /// 从命令行参数解析
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| anyhow!("参数 {} 缺少取值", name));
+            match arg.as_str() {
+                "--config" => config.config = PathBuf::from(value("--config")?),
+                "--url" => config.url = Some(value("--url")?),
+                "--socks" => config.socks = Some(value("--socks")?),
+                "--auth" => config.auth = Some(value("--auth")?),
+                "-n" | "--requests" => config.requests = value("--requests")?.parse()?,
+                "-c" | "--concurrency" => config.concurrency = value("--concurrency")?.parse()?,
+                "--timeout" => config.timeout = Duration::from_secs(value("--timeout")?.parse()?),
+                "--api" => config.api = Some(value("--api")?),
+                "--api-key" => config.api_key = Some(value("--api-key")?),
+                "--json" => config.json = true,
+                other => return Err(anyhow!("未知参数: {}", other)),
+            }
+        }
+
+        if config.requests == 0 || config.concurrency == 0 {
+            return Err(anyhow!("--requests 与 --concurrency 必须大于0"));
+        }
+        if config.auth.as_deref().is_some_and(|auth| !auth.contains(':')) {
+            return Err(anyhow!("--auth 的格式为 用户名:密码"));
+        }
+
+        Ok(config)
+    }
+}
+
+/// 延迟分位数（毫秒）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Latency {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+/// 一个代理承接的请求
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProxyShare {
+    pub address: String,
+    /// 压测期间新增的连接数
+    pub connections: u64,
+    /// 压测期间连接上游失败的次数
+    pub failures: u64,
+}
+
+/// 压测结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub url: String,
+    pub socks: String,
+    pub requests: usize,
+    pub concurrency: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub elapsed_ms: u64,
+    /// 每秒完成的成功请求数
+    pub requests_per_sec: f64,
+    /// 成功请求的响应体字节数
+    pub bytes: u64,
+    /// 成功请求的延迟，没有成功请求时为None
+    pub latency: Option<Latency>,
+    /// 失败原因及次数，按次数从多到少排列
+    pub errors: Vec<(String, usize)>,
+    /// 各代理承接的连接，按连接数从多到少排列；无法访问API时为None
+    pub distribution: Option<Vec<ProxyShare>>,
+}
+
+/// 一个请求的结果
+enum Attempt {
+    Success { latency: Duration, bytes: u64 },
+    Failure(String),
+}
+
+/// 发起一个请求并读完响应体，非2xx/3xx的状态码视为失败
+async fn fetch(client: &reqwest::Client, url: &str) -> Attempt {
+    let started = Instant::now();
+    let response = match client.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => return Attempt::Failure(describe_error(&e)),
+    };
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        return Attempt::Failure(format!("HTTP {}", status.as_u16()));
+    }
+    match response.bytes().await {
+        Ok(body) => Attempt::Success { latency: started.elapsed(), bytes: body.len() as u64 },
+        Err(e) => Attempt::Failure(describe_error(&e)),
+    }
+}
+
+/// 归并同类错误，避免每个请求的错误文本各不相同
+fn describe_error(error: &reqwest::Error) -> String {
+    if error.is_timeout() {
+        "超时".to_string()
+    } else if error.is_connect() {
+        "连接失败".to_string()
+    } else if error.is_body() || error.is_decode() {
+        "读取响应失败".to_string()
+    } else {
+        "请求失败".to_string()
+    }
+}
+
+/// 通过 `GET /api/v1/stats?detail=true` 读取各代理的累计连接数与失败数，键为代理ID
+async fn proxy_counters(api: &str, api_key: Option<&str>) -> Result<HashMap<String, (String, u64, u64)>> {
+    let url = format!("{}/api/v1/stats?detail=true", api.trim_end_matches('/'));
+    let mut request = reqwest::Client::new().get(&url).timeout(Duration::from_secs(10));
+    if let Some(key) = api_key {
+        request = request.header("x-api-key", key);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("请求 {} 失败 ({})", url, status));
+    }
+    let stats: serde_json::Value = response.json().await?;
+    let proxies = stats["proxies"].as_array().ok_or_else(|| anyhow!("{} 没有返回各代理的统计", url))?;
+    Ok(proxies.iter()
+        .filter_map(|proxy| Some((
+            proxy["id"].as_str()?.to_string(),
+            (
+                proxy["address"].as_str().unwrap_or_default().to_string(),
+                proxy["total_connections"].as_u64().unwrap_or(0),
+                proxy["connect_failures"].as_u64().unwrap_or(0),
+            ),
+        )))
+        .collect())
+}
+
+/// 两次计数之差，只保留压测期间有连接的代理
+fn distribution(before: &HashMap<String, (String, u64, u64)>, after: HashMap<String, (String, u64, u64)>) -> Vec<ProxyShare> {
+    let mut shares: Vec<ProxyShare> = after.into_iter()
+        .map(|(id, (address, connections, failures))| {
+            let (_, base_connections, base_failures) = before.get(&id).cloned().unwrap_or_default();
+            ProxyShare {
+                address,
+                connections: connections.saturating_sub(base_connections),
+                failures: failures.saturating_sub(base_failures),
+            }
+        })
+        .filter(|share| share.connections > 0 || share.failures > 0)
+        .collect();
+    shares.sort_by(|a, b| b.connections.cmp(&a.connections).then_with(|| a.address.cmp(&b.address)));
+    shares
+}
+
+/// 监听地址为通配地址时改为连接本机
+fn local_address(host: &str, port: u16) -> String {
+    match host {
+        "0.0.0.0" | "" => format!("127.0.0.1:{}", port),
+        "::" | "[::]" => format!("[::1]:{}", port),
+        host if host.contains(':') && !host.starts_with('[') => format!("[{}]:{}", host, port),
+        host => format!("{}:{}", host, port),
+    }
+}
+
+/// 运行压测并输出结果
+pub async fn run(bench: BenchConfig) -> Result<BenchReport> {
+    let config = if bench.config.exists() {
+        apply_tuned_overlay(Config::from_file(&bench.config)?, &bench.config).0
+    } else {
+        Config::default()
+    };
+    let url = bench.url.clone().or_else(|| config.test_urls.first().cloned())
+        .ok_or_else(|| anyhow!("没有压测目标，请用 --url 指定"))?;
+    reqwest::Url::parse(&url).map_err(|e| anyhow!("无效的URL {}: {}", url, e))?;
+    let socks = bench.socks.clone()
+        .unwrap_or_else(|| local_address(&config.socks_server.bind_address, config.socks_server.bind_port));
+    let api = bench.api.clone()
+        .or_else(|| config.api_server.bind_port.map(|port| format!("http://{}", local_address(&config.api_server.bind_address, port))));
+
+    // 由监听端口解析域名，与普通客户端的用法一致；不复用连接，每个请求都重新选择代理
+    let proxy_url = match &bench.auth {
+        Some(auth) => format!("socks5h://{}@{}", auth, socks),
+        None => format!("socks5h://{}", socks),
+    };
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(&proxy_url)?)
+        .pool_max_idle_per_host(0)
+        .timeout(bench.timeout)
+        .user_agent("lokipool-bench")
+        .build()?;
+
+    let before = match &api {
+        Some(api) => match proxy_counters(api, bench.api_key.as_deref()).await {
+            Ok(counters) => Some(counters),
+            Err(e) => {
+                if !bench.json {
+                    println!("无法读取各代理的连接计数，不统计请求分布: {}", e);
+                }
+                None
+            }
+        },
+        None => None,
+    };
+
+    if !bench.json {
+        println!("经 {} 向 {} 发起 {} 个请求，并发 {}", socks, url, bench.requests, bench.concurrency);
+    }
+    let started = Instant::now();
+    let next = Arc::new(AtomicUsize::new(0));
+    let url = Arc::new(url);
+    let mut workers = JoinSet::new();
+    for _ in 0..bench.concurrency.min(bench.requests) {
+        let client = client.clone();
+        let next = Arc::clone(&next);
+        let url = Arc::clone(&url);
+        let total = bench.requests;
+        workers.spawn(async move {
+            let mut outcomes = Vec::new();
+            while next.fetch_add(1, Ordering::Relaxed) < total {
+                outcomes.push(fetch(&client, &url).await);
+            }
+            outcomes
+        });
+    }
+    let mut latencies = Vec::new();
+    let mut bytes = 0;
+    let mut errors: HashMap<String, usize> = HashMap::new();
+    while let Some(outcomes) = workers.join_next().await {
+        for outcome in outcomes? {
+            match outcome {
+                Attempt::Success { latency, bytes: body } => {
+                    latencies.push(latency);
+                    bytes += body;
+                }
+                Attempt::Failure(reason) => *errors.entry(reason).or_default() += 1,
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+
+    let distribution = match (&api, before) {
+        (Some(api), Some(before)) => proxy_counters(api, bench.api_key.as_deref()).await.ok()
+            .map(|after| distribution(&before, after)),
+        _ => None,
+    };
+    let ms = |duration: Option<Duration>| duration.unwrap_or_default().as_millis() as u64;
+    let latency = (!latencies.is_empty()).then(|| Latency {
+        p50: ms(percentile(&latencies, 50)),
+        p90: ms(percentile(&latencies, 90)),
+        p99: ms(percentile(&latencies, 99)),
+        max: ms(latencies.iter().max().copied()),
+    });
+    let mut errors: Vec<(String, usize)> = errors.into_iter().collect();
+    errors.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let report = BenchReport {
+        url: url.to_string(),
+        socks,
+        requests: bench.requests,
+        concurrency: bench.concurrency,
+        succeeded: latencies.len(),
+        failed: bench.requests - latencies.len(),
+        elapsed_ms: elapsed.as_millis() as u64,
+        requests_per_sec: latencies.len() as f64 / elapsed.as_secs_f64().max(0.001),
+        bytes,
+        latency,
+        errors,
+        distribution,
+    };
+
+    if bench.json {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        print_report(&report);
+    }
+    Ok(report)
+}
+
+fn print_report(report: &BenchReport) {
+    let seconds = report.elapsed_ms as f64 / 1000.0;
+    println!("\n完成 {}/{} 个请求，失败 {} 个，用时 {:.2}s", report.succeeded, report.requests, report.failed, seconds);
+    println!("吞吐量: {:.1} 请求/s, {}/s", report.requests_per_sec, format_rate(report.bytes as f64 / seconds.max(0.001)));
+    if let Some(latency) = &report.latency {
+        println!("延迟: p50 {}ms, p90 {}ms, p99 {}ms, 最大 {}ms", latency.p50, latency.p90, latency.p99, latency.max);
+    }
+    if !report.errors.is_empty() {
+        println!("\n失败原因:");
+        for (reason, count) in &report.errors {
+            println!("  {:<16} {:>6}", reason, count);
+        }
+    }
+    if let Some(distribution) = &report.distribution {
+        let total: u64 = distribution.iter().map(|share| share.connections).sum();
+        println!("\n{:<40} {:>8} {:>7} {:>8}", "代理", "连接", "占比", "失败");
+        if distribution.is_empty() {
+            println!("  API没有记录到新的连接");
+        }
+        for share in distribution {
+            println!("{:<40} {:>8} {:>6.1}% {:>8}", share.address, share.connections,
+                share.connections as f64 * 100.0 / total.max(1) as f64, share.failures);
+        }
+    }
+}
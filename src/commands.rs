@@ -0,0 +1,442 @@
+//! 一次性执行的维护命令：`test`、`import`、`export`、`doctor`
+//!
+//! 这些命令不启动SOCKS5服务器，只读取配置与代理文件，执行完毕即退出，结果按 `status` 模块的约定报告。
+
+use std::collections::HashSet;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use tracing::warn;
+use lokipool_core::{write_atomic, Acl, Bypass, Config, Error, ListFormat, PolicyAction, Pool, PoolOptions, PoolSnapshot, PortPolicy, ProxyConfig, ProxyStatus};
+use crate::status::{usage, Counts, Report};
+
+/// 叠加 `lokipool tune` 生成的覆盖文件，返回配置及其来源
+pub fn apply_tuned_overlay(config: Config, config_path: &Path) -> (Config, String) {
+    let overlay = Config::overlay_path(config_path);
+    if !overlay.exists() {
+        return (config, config_path.display().to_string());
+    }
+    match config.with_overlay(&overlay) {
+        Ok(tuned) => (tuned, format!("{} + {}", config_path.display(), overlay.display())),
+        Err(e) => {
+            warn!("忽略无效的调优覆盖文件 {}: {}", overlay.display(), e);
+            (config, config_path.display().to_string())
+        }
+    }
+}
+
+/// 读取配置文件并叠加调优覆盖文件，配置文件不存在时使用默认配置
+fn load_config(path: &Path) -> Result<Config> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let config = Config::from_file(path)?;
+    Ok(apply_tuned_overlay(config, path).0)
+}
+
+/// 由配置文件与代理文件中的代理组成代理池，重复的代理只保留一份
+async fn load_pool(config: &Config) -> Result<Pool> {
+    let pool = Pool::new_with_proxies(config.proxies.clone(), PoolOptions::from_config(config));
+    let proxy_file = Path::new(&config.proxy.proxy_file);
+    pool.sync_file(proxy_file).await
+        .map_err(|e| anyhow!("读取代理文件 {} 失败: {}", proxy_file.display(), e))?;
+    Ok(pool)
+}
+
+/// 命令参数：`--config`、各命令自己的选项与按顺序出现的位置参数
+struct Args {
+    config: PathBuf,
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    /// `flags` 为不带取值的开关，`valued` 为需要取值的选项
+    fn parse<I: IntoIterator<Item = String>>(args: I, flags: &[&str], valued: &[&str]) -> Result<Self> {
+        let mut parsed = Self { config: PathBuf::from("config.toml"), positional: Vec::new(), options: Vec::new() };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                parsed.config = PathBuf::from(args.next().ok_or_else(|| usage("参数 --config 缺少取值"))?);
+            } else if flags.contains(&arg.as_str()) {
+                parsed.options.push((arg, None));
+            } else if valued.contains(&arg.as_str()) {
+                let value = args.next().ok_or_else(|| usage(format!("参数 {} 缺少取值", arg)))?;
+                parsed.options.push((arg, Some(value)));
+            } else if arg.starts_with("--") {
+                return Err(usage(format!("未知参数: {}", arg)));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(option, _)| option == name).and_then(|(_, value)| value.as_deref())
+    }
+}
+
+/// `lokipool test [--config <配置文件>]`：测试所有代理，每个代理为一项
+pub async fn test<I: IntoIterator<Item = String>>(args: I) -> Result<Report> {
+    let args = Args::parse(args, &[], &[])?;
+    let config = load_config(&args.config)?;
+    let pool = load_pool(&config).await?;
+
+    let results = pool.test_all().await;
+    if results.is_empty() {
+        return Ok(Report::failure("没有可测试的代理"));
+    }
+    let mut succeeded = 0;
+    for (proxy, result) in &results {
+        if result.success {
+            succeeded += 1;
+            println!("✓ {} - {}ms", proxy.to_address(), result.latency.unwrap_or(0));
+        } else {
+            println!("✗ {} - {}", proxy.to_address(), result.error.as_deref().unwrap_or("未知错误"));
+        }
+    }
+    let failed = results.len() - succeeded;
+    println!("共 {} 个代理: {} 个可用, {} 个不可用", results.len(), succeeded, failed);
+    Ok(Report::from_counts(Counts::new(succeeded, failed, 0)))
+}
+
+/// `lokipool import <文件或URL> [--format list|csv|clash] [--test] [--api <API地址>] [--config <配置文件>]`
+///
+/// 自动识别每行一个地址、CSV与clash订阅YAML，输入中重复的代理只保留一份。默认把新代理追加到代理文件，
+/// `--test` 时只追加测试通过的；给出 `--api` 时改为导入运行中的实例。
+/// 每个条目为一项：新加入的计为成功，重复或已有的计为跳过，无法解析、不支持或测试失败的计为失败。
+pub async fn import<I: IntoIterator<Item = String>>(args: I) -> Result<Report> {
+    let args = Args::parse(args, &["--test"], &["--format", "--api", "--api-key"])?;
+    let [source] = args.positional.as_slice() else {
+        return Err(usage("用法: lokipool import <文件或URL> [--format list|csv|clash] [--test] [--api <API地址> [--api-key <密钥>]] [--config <配置文件>]"));
+    };
+    let format = args.value("--format").map(str::parse::<ListFormat>).transpose().map_err(usage)?;
+    let content = read_source(source).await?;
+    let format = format.unwrap_or_else(|| ListFormat::detect(&content));
+
+    let mut seen = HashSet::new();
+    let mut proxies = Vec::new();
+    let (mut skipped, mut failed) = (0, 0);
+    for (line_no, parsed) in format.parse(&content) {
+        match parsed {
+            Ok(proxy) if seen.insert(proxy.canonical_key()) => proxies.push(proxy),
+            Ok(_) => skipped += 1,
+            Err(e) => {
+                eprintln!("{}:{} {}", source, line_no, e);
+                failed += 1;
+            }
+        }
+    }
+
+    if let Some(api) = args.value("--api") {
+        return import_via_api(api, args.value("--api-key"), &proxies, args.flag("--test"), Counts::new(0, failed, skipped)).await;
+    }
+
+    let config = load_config(&args.config)?;
+    let proxy_file = Path::new(&config.proxy.proxy_file);
+    let existing = if proxy_file.exists() { std::fs::read_to_string(proxy_file)? } else { String::new() };
+    let mut known: HashSet<String> = config.proxies.iter().map(ProxyConfig::canonical_key).collect();
+    if proxy_file.exists() {
+        known.extend(ProxyConfig::load_list(proxy_file)?.iter().map(ProxyConfig::canonical_key));
+    }
+    let (mut added, existed): (Vec<ProxyConfig>, Vec<ProxyConfig>) = proxies.into_iter().partition(|proxy| !known.contains(&proxy.canonical_key()));
+    skipped += existed.len();
+
+    if args.flag("--test") && !added.is_empty() {
+        let pool = Pool::new_with_proxies(added.clone(), PoolOptions::from_config(&config));
+        let mut passed = HashSet::new();
+        for (proxy, result) in pool.test_all().await {
+            match result.success {
+                true => { passed.insert(proxy.canonical_key()); }
+                false => {
+                    eprintln!("✗ {} - {}", proxy.to_address(), result.error.as_deref().unwrap_or("未知错误"));
+                    failed += 1;
+                }
+            }
+        }
+        // 保持输入中的顺序
+        added.retain(|proxy| passed.contains(&proxy.canonical_key()));
+    }
+
+    if !added.is_empty() {
+        let mut lines = existing;
+        if !lines.is_empty() && !lines.ends_with('\n') {
+            lines.push('\n');
+        }
+        for proxy in &added {
+            lines.push_str(&proxy.to_address());
+            lines.push('\n');
+        }
+        write_atomic(proxy_file, lines.as_bytes())
+            .map_err(|e| anyhow!("写入代理文件 {} 失败: {}", proxy_file.display(), e))?;
+    }
+    println!("从 {}（{}格式）导入 {} 个新代理到 {}, 跳过 {} 个重复或已有的代理, {} 项失败",
+        source, format, added.len(), proxy_file.display(), skipped, failed);
+    Ok(Report::from_counts(Counts::new(added.len(), failed, skipped)))
+}
+
+/// 读取本地文件或以HTTP(S)下载订阅
+async fn read_source(source: &str) -> Result<String> {
+    if !(source.starts_with("http://") || source.starts_with("https://")) {
+        return std::fs::read_to_string(source).map_err(|e| anyhow!("读取 {} 失败: {}", source, e));
+    }
+    let response = reqwest::get(source).await.map_err(|e| anyhow!("下载 {} 失败: {}", source, e))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("下载 {} 失败 ({})", source, status));
+    }
+    Ok(response.text().await?)
+}
+
+/// 向运行中实例的API发送请求，返回应答内容，应答不是2xx时返回带应答内容的错误
+///
+/// `path` 为 `/api/v1/...` 形式的路径。`api_key` 以 `x-api-key` 头发送，未给出时使用环境变量 `LOKIPOOL_API_KEY`。
+pub async fn call_api(api: &str, api_key: Option<&str>, method: reqwest::Method, path: &str, body: Option<serde_json::Value>) -> Result<String> {
+    let url = format!("{}{}", api.trim_end_matches('/'), path);
+    let mut request = reqwest::Client::new().request(method, &url);
+    let env_key = std::env::var("LOKIPOOL_API_KEY").ok().filter(|key| !key.is_empty());
+    if let Some(key) = api_key.or(env_key.as_deref()) {
+        request = request.header("x-api-key", key);
+    }
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let text = response.text().await?;
+    if !status.is_success() {
+        return Err(anyhow!("请求 {} 失败 ({}): {}", url, status, text));
+    }
+    Ok(text)
+}
+
+/// 通过 `POST /api/v1/proxies/import` 把代理导入运行中的实例，`test` 时由实例在后台测试新代理
+async fn import_via_api(api: &str, api_key: Option<&str>, proxies: &[ProxyConfig], test: bool, local: Counts) -> Result<Report> {
+    let path = format!("/api/v1/proxies/import?test={}", test);
+    let body = call_api(api, api_key, reqwest::Method::POST, &path, Some(serde_json::to_value(proxies)?)).await?;
+    let summary: serde_json::Value = serde_json::from_str(&body)?;
+    let count = |key: &str| summary[key].as_u64().unwrap_or_default() as usize;
+    // 提交的是数组，`entry` 为其中的下标（从1开始）
+    for error in summary["errors"].as_array().into_iter().flatten() {
+        let entry = error["entry"].as_u64().and_then(|entry| proxies.get((entry as usize).checked_sub(1)?));
+        let address = entry.map(ProxyConfig::to_address).unwrap_or_default();
+        eprintln!("✗ {} - {}", address, error["reason"].as_str().unwrap_or_default());
+    }
+    println!("导入 {} 个新代理到 {}, 跳过 {} 个重复或已有的代理, {} 项失败",
+        count("added"), api, local.skipped + count("duplicate"), local.failed + count("invalid") + count("rejected"));
+    if let Some(job) = summary["job"]["id"].as_str() {
+        println!("新代理正在后台测试，可通过 GET /api/v1/jobs/{} 查看进度", job);
+    }
+    Ok(Report::from_counts(Counts::new(count("added"), local.failed + count("invalid") + count("rejected"), local.skipped + count("duplicate"))))
+}
+
+/// `lokipool export [--output <文件>] [--available] [--config <配置文件>]`：导出代理列表
+///
+/// 未指定 `--output` 时写到标准输出，汇总信息写到标准错误。加上 `--available` 时先测试，
+/// 只导出可用的代理，不可用的计为跳过。
+pub async fn export<I: IntoIterator<Item = String>>(args: I) -> Result<Report> {
+    let args = Args::parse(args, &["--available"], &["--output"])?;
+    if let Some(extra) = args.positional.first() {
+        return Err(usage(format!("未知参数: {}", extra)));
+    }
+    let config = load_config(&args.config)?;
+    let pool = load_pool(&config).await?;
+    let available_only = args.flag("--available");
+    if available_only {
+        pool.test_all().await;
+    }
+
+    let mut proxies = pool.get_all_proxies().await;
+    proxies.sort_by(|a, b| (&a.info.host, a.info.port).cmp(&(&b.info.host, b.info.port)));
+    let total = proxies.len();
+    let lines: Vec<String> = proxies.iter()
+        .filter(|proxy| !available_only || proxy.status == ProxyStatus::Available)
+        .map(|proxy| proxy.to_config().to_address())
+        .collect();
+    let counts = Counts::new(lines.len(), 0, total - lines.len());
+
+    let mut contents = lines.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    match args.value("--output") {
+        Some(output) => {
+            write_atomic(output, contents.as_bytes()).map_err(|e| anyhow!("写入 {} 失败: {}", output, e))?;
+            eprintln!("已导出 {} 个代理到 {}", lines.len(), output);
+        }
+        None => {
+            print!("{}", contents);
+            eprintln!("已导出 {} 个代理", lines.len());
+        }
+    }
+
+    if available_only && lines.is_empty() && total > 0 {
+        return Ok(Report { counts, ..Report::failure("没有可用的代理") });
+    }
+    Ok(Report::from_counts(counts))
+}
+
+/// 单项检查的结果，None表示不适用
+type Check = Option<std::result::Result<String, String>>;
+
+/// `lokipool doctor [--config <配置文件>]`：检查配置与运行环境，每项检查为一项
+pub async fn doctor<I: IntoIterator<Item = String>>(args: I) -> Result<Report> {
+    let args = Args::parse(args, &[], &[])?;
+    let path = args.config.as_path();
+    let mut checks: Vec<(&str, Check)> = Vec::new();
+
+    let parsed = if path.exists() {
+        Config::from_file(path).map_err(|e| e.to_string())
+    } else {
+        Err(format!("{} 不存在，启动时将生成示例配置", path.display()))
+    };
+    checks.push(("配置文件", Some(parsed.as_ref().map(|_| path.display().to_string()).map_err(Clone::clone))));
+    let config = parsed.unwrap_or_default();
+
+    checks.push(("废弃配置项", match Config::from_file_checked(path, true) {
+        Ok(_) => Some(Ok("无".to_string())),
+        Err(Error::Deprecated(keys)) => Some(Err(format!("{}（启动时会自动迁移，参见 --fail-on-deprecated）", keys))),
+        Err(_) => None,
+    }));
+
+    let overlay = Config::overlay_path(path);
+    checks.push(("调优覆盖文件", overlay.exists().then(|| config.with_overlay(&overlay)
+        .map(|_| overlay.display().to_string())
+        .map_err(|e| format!("{}: {}", overlay.display(), e)))));
+    let config = apply_tuned_overlay(config, path).0;
+
+    let proxy_file = Path::new(&config.proxy.proxy_file);
+    let listed = if proxy_file.exists() { ProxyConfig::load_list(proxy_file).map(|list| list.len()) } else { Ok(0) };
+    checks.push(("代理来源", Some(match listed {
+        Ok(0) if config.proxies.is_empty() => Err(format!("配置文件与代理文件 {} 中都没有代理", proxy_file.display())),
+        Ok(listed) => Ok(format!("配置文件 {} 个, 代理文件 {} 个", config.proxies.len(), listed)),
+        Err(e) => Err(format!("读取代理文件 {} 失败: {}", proxy_file.display(), e)),
+    })));
+
+    let port = config.socks_server.bind_port;
+    let bound = config.socks_server.listen_addresses().into_iter().try_fold(Vec::new(), |mut bound, address| {
+        match TcpListener::bind((address.as_str(), port)) {
+            Ok(_) => {
+                bound.push(address);
+                Ok(bound)
+            }
+            Err(e) => Err(format!("无法绑定 {} 端口 {}: {}（可能已有实例在运行）", address, port, e)),
+        }
+    });
+    checks.push(("SOCKS5监听", Some(bound.map(|bound| format!("{} 端口 {}", bound.join(", "), port)))));
+
+    let acl = &config.socks_server.acl;
+    let rules = acl.allow_clients.len() + acl.deny_clients.len() + acl.allow_destinations.len() + acl.deny_destinations.len();
+    checks.push(("访问控制", (rules > 0).then(|| Acl::from_settings(acl)
+        .map(|_| format!("{} 条规则", rules))
+        .map_err(|e| e.to_string()))));
+
+    let port_policy = &config.socks_server.port_policy;
+    checks.push(("端口策略", (!port_policy.rules.is_empty() || port_policy.default_action == PolicyAction::Deny)
+        .then(|| PortPolicy::from_settings(port_policy)
+            .map(|policy| format!("{} 条规则, 默认 {}", policy.rules.len(), policy.default_action))
+            .map_err(|e| e.to_string()))));
+
+    let listeners = &config.socks_server.listeners;
+    checks.push(("额外监听", (!listeners.is_empty()).then(|| {
+        let mut ports = HashSet::from([config.socks_server.bind_port]);
+        listeners.iter().try_for_each(|listener| {
+            let address = listener.bind_address.as_deref().unwrap_or(&config.socks_server.bind_address);
+            if !ports.insert(listener.bind_port) {
+                return Err(format!("端口 {} 重复", listener.bind_port));
+            }
+            TcpListener::bind((address, listener.bind_port)).map(drop)
+                .map_err(|e| format!("无法绑定 {} 端口 {}: {}", address, listener.bind_port, e))
+        })
+        .map(|_| listeners.iter().map(|listener| listener.bind_port.to_string()).collect::<Vec<_>>().join(", "))
+    })));
+
+    let http = &config.http_server;
+    checks.push(("HTTP代理", http.bind_port.map(|port| {
+        if port == config.socks_server.bind_port || listeners.iter().any(|listener| listener.bind_port == port) {
+            return Err(format!("端口 {} 已被SOCKS5监听使用", port));
+        }
+        TcpListener::bind((http.bind_address.as_str(), port))
+            .map(|_| format!("{}:{}", http.bind_address, port))
+            .map_err(|e| format!("无法绑定 {} 端口 {}: {}", http.bind_address, port, e))
+    })));
+
+    let health = &config.health_server;
+    checks.push(("健康检查", health.bind_port.map(|port| {
+        if port == config.socks_server.bind_port || http.bind_port == Some(port)
+            || listeners.iter().any(|listener| listener.bind_port == port) {
+            return Err(format!("端口 {} 已被代理端口使用", port));
+        }
+        TcpListener::bind((health.bind_address.as_str(), port))
+            .map(|_| format!("{}:{}（可用代理不少于 {}）", health.bind_address, port, health.min_available))
+            .map_err(|e| format!("无法绑定 {} 端口 {}: {}", health.bind_address, port, e))
+    })));
+
+    let dns = &config.dns_server;
+    checks.push(("DNS转发", dns.bind_port.map(|port| {
+        if dns.resolver.parse::<SocketAddr>().is_err() {
+            return Err(format!("上游解析器地址无效，应为 IP:端口: {}", dns.resolver));
+        }
+        UdpSocket::bind((dns.bind_address.as_str(), port))
+            .map(|_| format!("{}:{}（经代理{}）", dns.bind_address, port, dns.transport))
+            .map_err(|e| format!("无法绑定 {} 端口 {}: {}", dns.bind_address, port, e))
+    })));
+
+    let api = &config.api_server;
+    checks.push(("内嵌API", api.bind_port.map(|port| {
+        if port == config.socks_server.bind_port || http.bind_port == Some(port) || health.bind_port == Some(port)
+            || listeners.iter().any(|listener| listener.bind_port == port) {
+            return Err(format!("端口 {} 已被其他端口使用", port));
+        }
+        TcpListener::bind((api.bind_address.as_str(), port))
+            .map(|_| format!("{}:{}", api.bind_address, port))
+            .map_err(|e| format!("无法绑定 {} 端口 {}: {}", api.bind_address, port, e))
+    })));
+
+    let bypass = &config.socks_server.bypass;
+    checks.push(("直连列表", (!bypass.destinations.is_empty()).then(|| Bypass::from_settings(bypass)
+        .map(|bypass| format!("{} 条规则", bypass.destinations.len()))
+        .map_err(|e| e.to_string()))));
+
+    let writable = |log: &String| {
+        let dir = Path::new(log).parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        match std::fs::metadata(dir) {
+            Ok(meta) if meta.permissions().readonly() => Err(format!("目录 {} 不可写", dir.display())),
+            Ok(_) => Ok(log.clone()),
+            Err(_) => Ok(format!("{}（目录 {} 将在首次写入时创建）", log, dir.display())),
+        }
+    };
+    checks.push(("事件日志", config.event_log.path.as_ref().map(writable)));
+    checks.push(("连接日志", config.log.connection_file.as_ref().map(writable)));
+
+    checks.push(("状态快照", config.proxy.snapshot_file.as_ref()
+        .filter(|snapshot| Path::new(snapshot).exists())
+        .map(|snapshot| match PoolSnapshot::load(snapshot) {
+            Ok(loaded) => Ok(format!("{}（{} 个代理）", snapshot, loaded.proxies.len())),
+            Err(e) => Err(format!("{} 无法读取: {}", snapshot, e)),
+        })));
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for (name, check) in checks {
+        match check {
+            Some(Ok(detail)) => {
+                passed += 1;
+                println!("✓ {}: {}", name, detail);
+            }
+            Some(Err(problem)) => {
+                failed += 1;
+                println!("✗ {}: {}", name, problem);
+            }
+            None => {
+                skipped += 1;
+                println!("- {}: 不适用，跳过", name);
+            }
+        }
+    }
+    Ok(Report::from_counts(Counts::new(passed, failed, skipped)))
+}
@@ -0,0 +1,119 @@
+//! 进行中的连接登记表
+//!
+//! 作为连接观察者登记各监听端口接受的连接，记录目标、代理与实时流量，
+//! API通过它列出连接并强制关闭单个连接。
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use chrono::{DateTime, Utc};
+use lokipool_core::{ConnectionControl, ConnectionEntry, ConnectionSummary, Proxy};
+use tokio::sync::Notify;
+
+use crate::socks_server::{ConnectionInfo, ConnectionObserver};
+
+struct Live {
+    info: ConnectionInfo,
+    target: Option<String>,
+    proxy: Option<String>,
+    opened_at: DateTime<Utc>,
+    started: Instant,
+    bytes_up: u64,
+    bytes_down: u64,
+    /// 强制关闭的信号，连接开始等待前发出的信号也不会丢失
+    close: Arc<Notify>,
+}
+
+impl Live {
+    fn entry(&self) -> ConnectionEntry {
+        ConnectionEntry {
+            id: self.info.id,
+            port: self.info.port,
+            client: self.info.client.to_string(),
+            class: self.info.class,
+            target: self.target.clone(),
+            proxy: self.proxy.clone(),
+            opened_at: self.opened_at,
+            age_ms: self.started.elapsed().as_millis() as u64,
+            bytes_up: self.bytes_up,
+            bytes_down: self.bytes_down,
+        }
+    }
+}
+
+/// 全部监听端口进行中的连接，克隆后共享同一份数据
+#[derive(Clone, Default)]
+pub struct ConnectionRegistry {
+    live: Arc<Mutex<BTreeMap<u64, Live>>>,
+}
+
+impl ConnectionRegistry {
+    /// 单个连接的信息，连接不存在时为None
+    pub fn get(&self, id: u64) -> Option<ConnectionEntry> {
+        self.live.lock().unwrap().get(&id).map(Live::entry)
+    }
+
+    /// 等到连接被强制关闭；连接未登记时永不返回
+    pub(crate) async fn closed(&self, id: u64) {
+        let close = self.live.lock().unwrap().get(&id).map(|live| Arc::clone(&live.close));
+        match close {
+            Some(close) => close.notified().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn update(&self, id: u64, change: impl FnOnce(&mut Live)) {
+        if let Some(live) = self.live.lock().unwrap().get_mut(&id) {
+            change(live);
+        }
+    }
+}
+
+impl ConnectionObserver for ConnectionRegistry {
+    fn on_open(&self, connection: &ConnectionInfo) {
+        self.live.lock().unwrap().insert(connection.id, Live {
+            info: connection.clone(),
+            target: None,
+            proxy: None,
+            opened_at: Utc::now(),
+            started: Instant::now(),
+            bytes_up: 0,
+            bytes_down: 0,
+            close: Arc::new(Notify::new()),
+        });
+    }
+
+    fn on_select_proxy(&self, connection: &ConnectionInfo, target: &str, proxy: Option<&Proxy>) {
+        self.update(connection.id, |live| {
+            live.target = (!target.is_empty()).then(|| target.to_string());
+            live.proxy = proxy.map(|proxy| format!("{}:{}", proxy.info.host, proxy.info.port));
+        });
+    }
+
+    fn on_bytes(&self, connection: &ConnectionInfo, up: u64, down: u64) {
+        self.update(connection.id, |live| {
+            live.bytes_up += up;
+            live.bytes_down += down;
+        });
+    }
+
+    fn on_close(&self, connection: &ConnectionInfo, _summary: &ConnectionSummary) {
+        self.live.lock().unwrap().remove(&connection.id);
+    }
+}
+
+impl ConnectionControl for ConnectionRegistry {
+    fn connections(&self) -> Vec<ConnectionEntry> {
+        self.live.lock().unwrap().values().map(Live::entry).collect()
+    }
+
+    fn close(&self, id: u64) -> bool {
+        match self.live.lock().unwrap().get(&id) {
+            Some(live) => {
+                live.close.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! DNS转发端口
+//!
+//! 在本机监听UDP与TCP的DNS查询，经代理池中的代理发往上游解析器后把应答原样转回。
+//! `doh` 方式经代理的CONNECT发送DNS-over-HTTPS请求，`udp` 方式经代理的UDP ASSOCIATE发送普通DNS查询；
+//! 两种方式下解析器都只看到代理的出口地址。转发失败时换一个代理重试，仍失败则应答SERVFAIL。
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use lokipool_core::dns::{self, DnsTransport};
+use lokipool_core::{accept_backoff, spawn_logged, PoolHandle, Proxy, TrafficClass};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+use crate::socks_server::{encode_address, parse_udp_header, SocksServer, UpstreamReply};
+
+/// 每个查询最多尝试的代理数
+const FORWARD_ATTEMPTS: usize = 2;
+
+/// TCP连接上两次查询之间的最长空闲时间
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// DNS-over-HTTPS的媒体类型
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// DNS转发端口配置
+#[derive(Debug, Clone)]
+pub struct DnsServerConfig {
+    /// 监听地址
+    pub bind_address: String,
+    /// 监听端口，UDP与TCP共用；为0时由系统分配，TCP随后绑定到同一端口
+    pub bind_port: u16,
+    /// 转发方式
+    pub transport: DnsTransport,
+    /// DNS-over-HTTPS地址
+    pub doh_url: String,
+    /// `udp` 方式下的上游解析器
+    pub resolver: SocketAddr,
+    /// 单次转发的超时
+    pub timeout: Duration,
+}
+
+impl Default for DnsServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1".to_string(),
+            bind_port: 53,
+            transport: DnsTransport::default(),
+            doh_url: "https://1.1.1.1/dns-query".to_string(),
+            resolver: SocketAddr::from(([1, 1, 1, 1], 53)),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// DNS转发服务器，克隆后共享同一个代理池与HTTP客户端
+#[derive(Clone)]
+pub struct DnsServer {
+    config: Arc<DnsServerConfig>,
+    pool: PoolHandle,
+    /// 按代理缓存的DoH客户端，复用与解析器之间的连接
+    clients: Arc<Mutex<HashMap<String, reqwest::Client>>>,
+    /// 已绑定在同一端口上的UDP套接字与TCP监听器，设置后不再绑定配置中的监听地址
+    sockets: Arc<Mutex<Option<(UdpSocket, TcpListener)>>>,
+}
+
+impl DnsServer {
+    pub fn new(config: DnsServerConfig, pool: impl Into<PoolHandle>) -> Self {
+        Self { config: Arc::new(config), pool: pool.into(), clients: Arc::default(), sockets: Arc::default() }
+    }
+
+    /// 在已绑定的UDP套接字与TCP监听器上接受查询，忽略配置中的监听地址
+    pub fn with_sockets(self, socket: UdpSocket, listener: TcpListener) -> Self {
+        *self.sockets.lock().unwrap() = Some((socket, listener));
+        self
+    }
+
+    /// 启动DNS转发端口，收到shutdown信号后停止
+    pub async fn run_with_shutdown(&self, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
+        let prebound = self.sockets.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let (socket, listener) = match prebound {
+            Some(sockets) => sockets,
+            None => {
+                let socket = UdpSocket::bind((self.config.bind_address.as_str(), self.config.bind_port)).await?;
+                let listener = TcpListener::bind(socket.local_addr()?).await?;
+                (socket, listener)
+            }
+        };
+        let socket = Arc::new(socket);
+        let local_addr = socket.local_addr()?;
+        info!("DNS转发开始监听: {} (UDP/TCP，经代理{})", local_addr, self.config.transport);
+
+        let mut buf = vec![0u8; 65535];
+        loop {
+            tokio::select! {
+                received = socket.recv_from(&mut buf) => {
+                    let (n, client_addr) = match received {
+                        Ok(received) => received,
+                        Err(e) => {
+                            // Windows上对端不可达的ICMP也会以错误的形式返回，不影响其他客户端
+                            debug!("接收DNS查询失败: {}", e);
+                            continue;
+                        }
+                    };
+                    let query = buf[..n].to_vec();
+                    let server = self.clone();
+                    let socket = Arc::clone(&socket);
+                    spawn_logged(format!("DNS查询 {}", client_addr), async move {
+                        if let Some(response) = server.resolve(client_addr.ip(), &query).await {
+                            if let Err(e) = socket.send_to(&response, client_addr).await {
+                                debug!("向 {} 发送DNS应答失败: {}", client_addr, e);
+                            }
+                        }
+                    });
+                },
+                accepted = listener.accept() => {
+                    match accepted {
+                        Ok((stream, client_addr)) => {
+                            let server = self.clone();
+                            spawn_logged(format!("DNS连接 {}", client_addr), async move {
+                                if let Err(e) = server.serve_tcp(stream, client_addr).await {
+                                    debug!("DNS连接 {} 结束: {}", client_addr, e);
+                                }
+                            });
+                        }
+                        Err(e) => {
+                            warn!("接受连接失败: {}", e);
+                            accept_backoff(&e).await;
+                        }
+                    }
+                },
+                _ = shutdown.recv() => {
+                    info!("DNS转发收到关闭信号，停止监听");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// TCP上的查询与应答都带两字节长度前缀，一个连接上可以依次发送多个查询
+    async fn serve_tcp(&self, mut stream: TcpStream, client_addr: SocketAddr) -> Result<()> {
+        loop {
+            let len = match tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_u16()).await {
+                Ok(Ok(len)) => len as usize,
+                // 客户端关闭连接或空闲超时
+                Ok(Err(_)) | Err(_) => return Ok(()),
+            };
+            let mut query = vec![0u8; len];
+            stream.read_exact(&mut query).await?;
+            let Some(response) = self.resolve(client_addr.ip(), &query).await else {
+                return Ok(());
+            };
+            let len = u16::try_from(response.len()).map_err(|_| anyhow!("DNS应答过长: {} 字节", response.len()))?;
+            stream.write_u16(len).await?;
+            stream.write_all(&response).await?;
+        }
+    }
+
+    /// 经代理转发一个查询，返回应答；全部尝试失败时返回SERVFAIL，不是查询报文时返回None
+    pub async fn resolve(&self, client: IpAddr, query: &[u8]) -> Option<Vec<u8>> {
+        let failure = dns::servfail(query)?;
+        let transport = self.config.transport;
+        let mut tried: Vec<String> = Vec::new();
+        for _ in 0..FORWARD_ATTEMPTS {
+            let acquired = self.pool.acquire_for(TrafficClass::Interactive, |p| {
+                !tried.contains(&p.id) && (transport != DnsTransport::Udp || p.udp != Some(false))
+            }).await;
+            let Some((proxy, _guard)) = acquired else { break };
+            tried.push(proxy.id.clone());
+
+            let forwarded = match transport {
+                DnsTransport::Doh => self.forward_doh(&proxy, query).await,
+                DnsTransport::Udp => self.forward_udp(&proxy, query).await,
+            };
+            match forwarded {
+                Ok(response) => {
+                    self.pool.report_connection(&proxy.id, true).await;
+                    proxy.usage.record_up(query.len() as u64);
+                    proxy.usage.record_down(response.len() as u64);
+                    self.pool.traffic().record(client, "dns", query.len() as u64, response.len() as u64);
+                    debug!("DNS查询经代理 {}:{} 转发成功", proxy.info.host, proxy.info.port);
+                    return Some(response);
+                }
+                Err(e) if matches!(e.downcast_ref::<UpstreamReply>(), Some(UpstreamReply(0x07))) => {
+                    info!("代理 {}:{} 不支持UDP ASSOCIATE，之后不再用于UDP", proxy.info.host, proxy.info.port);
+                    self.pool.set_udp_support(&proxy.id, false).await;
+                }
+                Err(e) => {
+                    warn!("DNS查询经代理 {}:{} 转发失败: {}", proxy.info.host, proxy.info.port, e);
+                    SocksServer::report_failure(&self.pool, &proxy, &e).await;
+                }
+            }
+        }
+        if tried.is_empty() {
+            warn!("没有可用于DNS转发的代理，应答SERVFAIL");
+        }
+        Some(failure)
+    }
+
+    /// 经代理向DoH地址POST查询报文
+    async fn forward_doh(&self, proxy: &Proxy, query: &[u8]) -> Result<Vec<u8>> {
+        let client = self.client_for(proxy)?;
+        let response = client.post(&self.config.doh_url)
+            .header(reqwest::header::CONTENT_TYPE, DNS_MESSAGE)
+            .header(reqwest::header::ACCEPT, DNS_MESSAGE)
+            .body(query.to_vec())
+            .send().await?
+            .error_for_status()?;
+        let response = response.bytes().await?.to_vec();
+        check_response(query, &response)?;
+        Ok(response)
+    }
+
+    /// 经代理的UDP中继向上游解析器发送查询，控制连接在收到应答前保持打开
+    async fn forward_udp(&self, proxy: &Proxy, query: &[u8]) -> Result<Vec<u8>> {
+        let exchange = async {
+            let (_control, relay_addr) = SocksServer::associate_upstream(proxy).await?;
+            let unspecified = match relay_addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            };
+            let socket = UdpSocket::bind((unspecified, 0)).await?;
+            let mut datagram = vec![0x00, 0x00, 0x00];
+            datagram.extend_from_slice(&encode_address(self.config.resolver));
+            datagram.extend_from_slice(query);
+            socket.send_to(&datagram, relay_addr).await?;
+
+            let mut buf = vec![0u8; 65535];
+            loop {
+                let (n, from) = socket.recv_from(&mut buf).await?;
+                if from.ip() != relay_addr.ip() {
+                    continue;
+                }
+                let Some((_, _, offset)) = parse_udp_header(&buf[..n]) else { continue };
+                let response = &buf[offset..n];
+                // 迟到的其他应答不会出现在新建的端口上，ID不符的报文只可能是伪造的
+                if check_response(query, response).is_ok() {
+                    return Ok(response.to_vec());
+                }
+            }
+        };
+        tokio::time::timeout(self.config.timeout, exchange).await
+            .unwrap_or_else(|_| Err(anyhow!("等待DNS应答超过 {}ms", self.config.timeout.as_millis())))
+    }
+
+    fn client_for(&self, proxy: &Proxy) -> Result<reqwest::Client> {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&proxy.id) {
+            return Ok(client.clone());
+        }
+        // socks5h让代理解析DoH地址中的域名，本机不发出任何DNS查询
+        let url = proxy.url().replacen("socks5://", "socks5h://", 1);
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all(url)?)
+            .timeout(self.config.timeout)
+            .build()?;
+        clients.insert(proxy.id.clone(), client.clone());
+        Ok(client)
+    }
+}
+
+/// 应答必须是对这个查询的应答
+fn check_response(query: &[u8], response: &[u8]) -> Result<()> {
+    if response.len() < dns::HEADER_LENGTH || response[2] & 0x80 == 0 || dns::message_id(response) != dns::message_id(query) {
+        return Err(anyhow!("上游返回的不是该查询的DNS应答"));
+    }
+    Ok(())
+}
@@ -0,0 +1,385 @@
+//! 出口节点（Bridge模式）
+//!
+//! 在远程主机上运行 `lokipool exit --token <令牌> --central <API地址>`，
+//! 即可启动一个直连目标的SOCKS5服务，并将其注册到中心LokiPool实例作为上游代理。
+//!
+//! 出口节点的SOCKS5端口通常暴露在公网上，因此只接受来自中心实例的连接（默认取 `--central` 解析出的地址，
+//! 中心实例经NAT出网时用 `--allow` 指定其出口地址段），并要求中心实例在注册时签发的用户名和密码（RFC 1929）。
+//! 注册成功之前拒绝所有连接。
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn};
+use lokipool_core::{accept_backoff, spawn_logged, IpNet, SocksAccount};
+use crate::socks_server::SocksServer;
+
+/// 出口节点配置
+#[derive(Debug, Clone)]
+pub struct ExitAgentConfig {
+    /// 中心实例API地址，例如 http://10.0.0.1:3000
+    pub central: String,
+    /// 中心实例签发的加入令牌
+    pub token: String,
+    /// 本地监听地址
+    pub bind_address: String,
+    /// 本地监听端口
+    pub bind_port: u16,
+    /// 对外公布的地址，缺省时由中心实例根据来源地址推断
+    pub public_host: Option<String>,
+    /// 节点名称
+    pub name: Option<String>,
+    /// 节点所在区域
+    pub region: Option<String>,
+    /// 节点带宽（Mbps）
+    pub bandwidth_mbps: Option<u32>,
+    /// 允许连接SOCKS5端口的来源地址段，为空时只允许 `central` 解析出的地址
+    pub allow: Vec<IpNet>,
+}
+
+impl Default for ExitAgentConfig {
+    fn default() -> Self {
+        Self {
+            central: "http://127.0.0.1:3000".to_string(),
+            token: String::new(),
+            bind_address: "0.0.0.0".to_string(),
+            bind_port: 1080,
+            public_host: None,
+            name: None,
+            region: None,
+            bandwidth_mbps: None,
+            allow: Vec::new(),
+        }
+    }
+}
+
+impl ExitAgentConfig {
+    /// 从命令行参数解析配置（不包含子命令本身）
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| anyhow!("参数 {} 缺少取值", name));
+            match arg.as_str() {
+                "--token" => config.token = value("--token")?,
+                "--central" => config.central = value("--central")?,
+                "--bind" => config.bind_address = value("--bind")?,
+                "--port" => config.bind_port = value("--port")?.parse()?,
+                "--public-host" => config.public_host = Some(value("--public-host")?),
+                "--name" => config.name = Some(value("--name")?),
+                "--region" => config.region = Some(value("--region")?),
+                "--bandwidth" => config.bandwidth_mbps = Some(value("--bandwidth")?.parse()?),
+                "--allow" => config.allow.push(value("--allow")?.parse()?),
+                other => return Err(anyhow!("未知参数: {}", other)),
+            }
+        }
+
+        if config.token.is_empty() {
+            return Err(anyhow!("必须通过 --token 指定加入令牌"));
+        }
+
+        Ok(config)
+    }
+}
+
+#[derive(Serialize)]
+struct RegisterRequest<'a> {
+    token: &'a str,
+    host: Option<&'a str>,
+    port: u16,
+    name: Option<&'a str>,
+    region: Option<&'a str>,
+    bandwidth_mbps: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RegisterResponse {
+    id: String,
+    secret: String,
+    heartbeat_interval: u64,
+    /// 中心实例连接本节点SOCKS5端口时使用的账户
+    socks_username: String,
+    socks_password: String,
+}
+
+#[derive(Serialize)]
+struct AgentAuth<'a> {
+    secret: &'a str,
+}
+
+/// 当前注册会话
+struct Registration {
+    id: String,
+    secret: String,
+}
+
+/// 出口节点
+pub struct ExitAgent {
+    config: ExitAgentConfig,
+    client: reqwest::Client,
+    registration: Mutex<Option<Registration>>,
+    /// 当前注册签发的SOCKS5账户，未注册时为None，此时拒绝所有连接
+    account: Arc<RwLock<Option<SocksAccount>>>,
+    /// 已绑定的监听器，设置后不再绑定配置中的监听地址
+    listener: Mutex<Option<TcpListener>>,
+}
+
+impl ExitAgent {
+    /// 创建新的出口节点
+    pub fn new(config: ExitAgentConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            registration: Mutex::new(None),
+            account: Arc::new(RwLock::new(None)),
+            listener: Mutex::new(None),
+        }
+    }
+
+    /// 在已绑定的监听器上接受连接，忽略配置中的监听地址，注册的端口以监听器为准
+    pub fn with_listener(mut self, listener: TcpListener) -> Self {
+        if let Ok(addr) = listener.local_addr() {
+            self.config.bind_port = addr.port();
+        }
+        self.listener = Mutex::new(Some(listener));
+        self
+    }
+
+    /// 运行出口节点，直到收到Ctrl-C后注销并退出
+    pub async fn run(&self) -> Result<()> {
+        let allow = match self.config.allow.is_empty() {
+            true => self.central_addresses().await?,
+            false => self.config.allow.clone(),
+        };
+        let prebound = self.listener.lock().await.take();
+        let listener = match prebound {
+            Some(listener) => listener,
+            None => TcpListener::bind((self.config.bind_address.as_str(), self.config.bind_port)).await?,
+        };
+        info!("出口节点SOCKS5服务开始监听: {}，只接受来自 {} 的连接",
+            listener.local_addr()?, allow.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "));
+
+        let accept_loop = async {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, client_addr)) => {
+                        if !allow.iter().any(|net| net.contains(client_addr.ip())) {
+                            debug!("拒绝来自 {} 的出口连接: 不是中心实例的地址", client_addr);
+                            continue;
+                        }
+                        let account = self.account.read().unwrap_or_else(|e| e.into_inner()).clone();
+                        spawn_logged(format!("出口连接 {}", client_addr), async move {
+                            if let Err(e) = handle_direct(stream, account.as_slice()).await {
+                                debug!("出口连接 {} 处理失败: {}", client_addr, e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        warn!("接受连接失败: {}", e);
+                        accept_backoff(&e).await;
+                    }
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = accept_loop => {},
+            res = self.registration_loop() => {
+                if let Err(e) = res {
+                    error!("出口节点注册任务退出: {}", e);
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("收到退出信号，正在注销出口节点...");
+            }
+        }
+
+        if let Some(registration) = self.registration.lock().await.take() {
+            self.deregister(&registration).await;
+        }
+
+        Ok(())
+    }
+
+    /// 注册并保持心跳，中心实例丢失注册信息时自动重新注册
+    async fn registration_loop(&self) -> Result<()> {
+        loop {
+            let response = match self.register().await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    warn!("注册到中心实例失败: {}，10秒后重试", e);
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    continue;
+                }
+            };
+            info!("已注册到中心实例 {} (ID: {})", self.config.central, response.id);
+
+            let url = format!("{}/api/v1/exits/{}/heartbeat", self.central(), response.id);
+            let auth = AgentAuth { secret: &response.secret };
+            *self.registration.lock().await = Some(Registration {
+                id: response.id.clone(),
+                secret: response.secret.clone(),
+            });
+            *self.account.write().unwrap_or_else(|e| e.into_inner()) = Some(SocksAccount {
+                username: response.socks_username.clone(),
+                password: response.socks_password.clone(),
+            });
+            let interval = Duration::from_secs(response.heartbeat_interval.max(1));
+            loop {
+                tokio::time::sleep(interval).await;
+                match self.client.post(&url).json(&auth).send().await {
+                    Ok(resp) if resp.status() == reqwest::StatusCode::NOT_FOUND => {
+                        warn!("中心实例已不再识别本节点（可能已被吊销），重新注册");
+                        *self.registration.lock().await = None;
+                        *self.account.write().unwrap_or_else(|e| e.into_inner()) = None;
+                        break;
+                    }
+                    Ok(resp) if !resp.status().is_success() => {
+                        warn!("心跳被拒绝: {}", resp.status());
+                    }
+                    Ok(_) => debug!("心跳成功"),
+                    Err(e) => warn!("发送心跳失败: {}", e),
+                }
+            }
+        }
+    }
+
+    async fn register(&self) -> Result<RegisterResponse> {
+        let request = RegisterRequest {
+            token: &self.config.token,
+            host: self.config.public_host.as_deref(),
+            port: self.config.bind_port,
+            name: self.config.name.as_deref(),
+            region: self.config.region.as_deref(),
+            bandwidth_mbps: self.config.bandwidth_mbps,
+        };
+        let resp = self.client
+            .post(format!("{}/api/v1/exits", self.central()))
+            .json(&request)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            return Err(anyhow!("中心实例返回 {}", resp.status()));
+        }
+        Ok(resp.json().await?)
+    }
+
+    async fn deregister(&self, registration: &Registration) {
+        let url = format!("{}/api/v1/exits/{}", self.central(), registration.id);
+        let request = self.client.delete(&url).json(&AgentAuth { secret: &registration.secret }).send();
+        match tokio::time::timeout(Duration::from_secs(3), request).await {
+            Ok(Ok(resp)) if resp.status().is_success() => info!("出口节点已注销 (ID: {})", registration.id),
+            Ok(Ok(resp)) => warn!("注销出口节点失败: {}", resp.status()),
+            Ok(Err(e)) => warn!("注销出口节点失败: {}", e),
+            Err(_) => warn!("注销出口节点超时"),
+        }
+    }
+
+    fn central(&self) -> &str {
+        self.config.central.trim_end_matches('/')
+    }
+
+    /// 解析 `central` 得到中心实例的地址，作为默认允许的来源
+    async fn central_addresses(&self) -> Result<Vec<IpNet>> {
+        let url = reqwest::Url::parse(self.central()).map_err(|e| anyhow!("无效的中心实例地址 {}: {}", self.config.central, e))?;
+        let host = url.host_str().ok_or_else(|| anyhow!("中心实例地址缺少主机: {}", self.config.central))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addresses: Vec<IpAddr> = tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port)).await
+            .map_err(|e| anyhow!("无法解析中心实例地址 {}: {}，可用 --allow 指定允许的来源", host, e))?
+            .map(|addr| addr.ip())
+            .collect();
+        Ok(addresses.into_iter().map(|ip| ip.to_string().parse()).collect::<Result<_, _>>()?)
+    }
+}
+
+/// 处理直连SOCKS5请求，要求 `accounts` 中的用户名和密码，仅支持CONNECT；`accounts` 为空时拒绝连接
+async fn handle_direct(mut stream: TcpStream, accounts: &[SocksAccount]) -> Result<()> {
+    if accounts.is_empty() {
+        return Err(anyhow!("尚未注册到中心实例，拒绝连接"));
+    }
+    let (host, port) = read_connect_request(&mut stream, accounts).await?;
+
+    let mut target = match TcpStream::connect((host.as_str(), port)).await {
+        Ok(target) => target,
+        Err(e) => {
+            stream.write_all(&[0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+            return Err(anyhow!("连接目标 {}:{} 失败: {}", host, port, e));
+        }
+    };
+    stream.write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+
+    tokio::io::copy_bidirectional(&mut stream, &mut target).await?;
+    Ok(())
+}
+
+/// 完成SOCKS5握手（`accounts` 非空时要求用户名和密码）并读取CONNECT请求的目标地址，尚未发送应答
+pub(crate) async fn read_connect_request(stream: &mut TcpStream, accounts: &[SocksAccount]) -> Result<(String, u16)> {
+    let (command, host, port) = read_request_with(stream, accounts).await?;
+    if command != 0x01 {
+        stream.write_all(&[0x05, 0x07, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+        return Err(anyhow!("不支持的SOCKS5命令: {}", command));
+    }
+    Ok((host, port))
+}
+
+/// 完成无认证的SOCKS5握手并读取请求，返回命令与目标地址，尚未发送应答
+pub(crate) async fn read_request(stream: &mut TcpStream) -> Result<(u8, String, u16)> {
+    read_request_with(stream, &[]).await
+}
+
+/// 完成SOCKS5握手并读取请求；`accounts` 非空时要求用户名/密码认证（RFC 1929）
+async fn read_request_with(stream: &mut TcpStream, accounts: &[SocksAccount]) -> Result<(u8, String, u16)> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await?;
+    if header[0] != 0x05 {
+        return Err(anyhow!("收到非SOCKS5请求: 版本={}", header[0]));
+    }
+    let mut methods = vec![0u8; header[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if accounts.is_empty() {
+        stream.write_all(&[0x05, 0x00]).await?;
+    } else {
+        if !methods.contains(&0x02) {
+            stream.write_all(&[0x05, 0xFF]).await?;
+            return Err(anyhow!("客户端不支持用户名/密码认证"));
+        }
+        stream.write_all(&[0x05, 0x02]).await?;
+        if let Err(e) = SocksServer::authenticate(stream, accounts).await {
+            stream.write_all(&[0x01, 0x01]).await?;
+            return Err(e);
+        }
+        stream.write_all(&[0x01, 0x00]).await?;
+    }
+
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf).await?;
+
+    let host = match buf[3] {
+        0x01 => {
+            let mut addr = [0u8; 4];
+            stream.read_exact(&mut addr).await?;
+            Ipv4Addr::from(addr).to_string()
+        }
+        0x03 => {
+            let len = stream.read_u8().await? as usize;
+            let mut domain = vec![0u8; len];
+            stream.read_exact(&mut domain).await?;
+            String::from_utf8(domain)?
+        }
+        0x04 => {
+            let mut addr = [0u8; 16];
+            stream.read_exact(&mut addr).await?;
+            Ipv6Addr::from(addr).to_string()
+        }
+        _ => return Err(anyhow!("不支持的地址类型")),
+    };
+    let port = stream.read_u16().await?;
+    Ok((buf[1], host, port))
+}
@@ -0,0 +1,141 @@
+//! 健康检查端口
+//!
+//! 供负载均衡（haproxy、Kubernetes探针等）判断实例能否接收流量：SOCKS5主端口正在接受连接、
+//! 且可用代理不少于 `min_available` 时应答 `200 OK`，否则应答 `503 Service Unavailable`。
+//! 不解析请求内容，任何路径的HTTP请求与只建立TCP连接的检查都得到同样的应答，应答后关闭连接。
+
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use anyhow::Result;
+use lokipool_core::{accept_backoff, spawn_logged, PoolHandle};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+use crate::socks_server::Accepting;
+
+/// 等待请求头的时间，只建立TCP连接的检查等这么久后得到应答
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 健康检查端口配置
+#[derive(Debug, Clone)]
+pub struct HealthServerConfig {
+    /// 监听地址
+    pub bind_address: String,
+    /// 监听端口
+    pub bind_port: u16,
+    /// 可用代理少于这个数量时报告不健康
+    pub min_available: usize,
+}
+
+impl Default for HealthServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1".to_string(),
+            bind_port: 8081,
+            min_available: 1,
+        }
+    }
+}
+
+/// 健康检查服务器
+pub struct HealthServer {
+    config: HealthServerConfig,
+    pool: PoolHandle,
+    accepting: Accepting,
+    /// 已绑定的监听器，设置后不再绑定配置中的监听地址
+    listener: Mutex<Option<TcpListener>>,
+}
+
+impl HealthServer {
+    /// 创建健康检查服务器，`accepting` 取自要检查的SOCKS5服务器
+    pub fn new(config: HealthServerConfig, pool: impl Into<PoolHandle>, accepting: Accepting) -> Self {
+        Self { config, pool: pool.into(), accepting, listener: Mutex::new(None) }
+    }
+
+    /// 在已绑定的监听器上接受连接，忽略配置中的监听地址
+    pub fn with_listener(mut self, listener: TcpListener) -> Self {
+        self.listener = Mutex::new(Some(listener));
+        self
+    }
+
+    /// 当前是否健康，附带说明原因的一行文字
+    pub async fn check(&self) -> (bool, String) {
+        check(&self.pool, &self.accepting, self.config.min_available).await
+    }
+
+    /// 启动健康检查端口，收到shutdown信号后停止
+    pub async fn run_with_shutdown(&self, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
+        let prebound = self.listener.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let listener = match prebound {
+            Some(listener) => listener,
+            None => TcpListener::bind((self.config.bind_address.as_str(), self.config.bind_port)).await?,
+        };
+        info!("健康检查端口开始监听: {}", listener.local_addr()?);
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    match accepted {
+                        Ok((stream, client_addr)) => {
+                            // 读取代理池指标可能要等锁，放到连接自己的任务里，不拖慢接受连接
+                            let pool = self.pool.clone();
+                            let accepting = self.accepting.clone();
+                            let min_available = self.config.min_available;
+                            spawn_logged(format!("健康检查 {}", client_addr), async move {
+                                let status = check(&pool, &accepting, min_available).await;
+                                respond(stream, client_addr, status).await;
+                            });
+                        }
+                        Err(e) => {
+                            warn!("接受连接失败: {}", e);
+                            accept_backoff(&e).await;
+                        }
+                    }
+                },
+                _ = shutdown.recv() => {
+                    info!("健康检查端口收到关闭信号，停止监听");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// 主端口正在接受连接且可用代理足够时为健康
+async fn check(pool: &PoolHandle, accepting: &Accepting, min_available: usize) -> (bool, String) {
+    let available = pool.metrics().await.status.available;
+    let accepting = accepting.get();
+    let healthy = accepting && available >= min_available;
+    let detail = format!("accepting={} available={} min_available={}", accepting, available, min_available);
+    (healthy, detail)
+}
+
+/// 读完请求头（或等待超时）后写出应答并关闭连接
+async fn respond(mut stream: TcpStream, client_addr: SocketAddr, (healthy, detail): (bool, String)) {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 512];
+    let _ = tokio::time::timeout(REQUEST_TIMEOUT, async {
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < 8192 {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => head.extend_from_slice(&buf[..n]),
+            }
+        }
+    }).await;
+
+    let status = match healthy {
+        true => "200 OK",
+        false => "503 Service Unavailable",
+    };
+    let body = format!("{}\n", detail);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body,
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        debug!("健康检查应答 {} 失败: {}", client_addr, e);
+    }
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,310 @@
+//! HTTP代理前端
+//!
+//! 供只支持HTTP代理的浏览器与工具使用：CONNECT请求建立到目标的隧道（用于HTTPS），
+//! 绝对URI形式的普通HTTP请求改写为源站形式后转发。两种请求都与SOCKS5服务器一样从代理池按流量类别选择代理，
+//! 经所选代理的SOCKS5 CONNECT连接目标，连接失败时换用其他代理重试。
+//! 普通HTTP请求每个客户端连接只转发一个，转发时加上 `Connection: close`，下一个请求由客户端重新连接发出。
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use lokipool_core::{accept_backoff, spawn_logged, ConnectionGuard, PolicyAction, PoolHandle, Proxy, Transport};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+use crate::relay::relay_duplex;
+use crate::socks_server::{address_type, within, Activity, ChainError, CountingReader, Direction, SocksServer, SocksServerConfig, UpstreamReply, REP_CONNECTION_REFUSED};
+
+/// 请求头的最大长度
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// 读取请求头的超时
+const HEAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 转发普通HTTP请求时去掉的逐跳请求头
+const HOP_HEADERS: [&str; 4] = ["connection", "keep-alive", "proxy-connection", "proxy-authorization"];
+
+/// HTTP代理配置
+#[derive(Debug, Clone)]
+pub struct HttpServerConfig {
+    /// 监听地址
+    pub bind_address: String,
+    /// 监听端口
+    pub bind_port: u16,
+    /// 与SOCKS5服务器共用的转发设置：缓冲区、流量类别、超时、重试、访问控制、端口策略与代理链，
+    /// 其中的监听地址、认证账户与直连列表不使用
+    pub forwarding: SocksServerConfig,
+}
+
+impl Default for HttpServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1".to_string(),
+            bind_port: 8080,
+            forwarding: SocksServerConfig::default(),
+        }
+    }
+}
+
+/// HTTP代理服务器
+pub struct HttpServer {
+    config: Arc<HttpServerConfig>,
+    pool: PoolHandle,
+    /// 已绑定的监听器，设置后不再绑定配置中的监听地址
+    listener: Mutex<Option<TcpListener>>,
+}
+
+impl HttpServer {
+    /// 创建新的HTTP代理服务器
+    pub fn new(config: HttpServerConfig, pool: impl Into<PoolHandle>) -> Self {
+        Self { config: Arc::new(config), pool: pool.into(), listener: Mutex::new(None) }
+    }
+
+    /// 在已绑定的监听器上接受连接，忽略配置中的监听地址
+    pub fn with_listener(mut self, listener: TcpListener) -> Self {
+        self.listener = Mutex::new(Some(listener));
+        self
+    }
+
+    /// 启动HTTP代理，收到shutdown信号后停止接受新连接，已有连接继续转发
+    pub async fn run_with_shutdown(&self, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
+        let prebound = self.listener.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let listener = match prebound {
+            Some(listener) => listener,
+            None => TcpListener::bind((self.config.bind_address.as_str(), self.config.bind_port)).await?,
+        };
+        info!("HTTP代理开始监听: {}", listener.local_addr()?);
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    match accepted {
+                        Ok((stream, client_addr)) => {
+                            let config = Arc::clone(&self.config);
+                            let pool = self.pool.clone();
+                            spawn_logged(format!("HTTP代理连接 {}", client_addr), async move {
+                                if let Err(e) = Self::serve_connection(stream, client_addr, &config, &pool).await {
+                                    debug!("HTTP代理连接 {} 结束: {}", client_addr, e);
+                                }
+                            });
+                        }
+                        Err(e) => {
+                            warn!("接受连接失败: {}", e);
+                            accept_backoff(&e).await;
+                        }
+                    }
+                },
+                _ = shutdown.recv() => {
+                    info!("HTTP代理收到关闭信号，停止接受新连接");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// 读取请求，经代理连接目标后双向转发，结束时累计流量
+    async fn serve_connection(
+        mut stream: TcpStream,
+        client_addr: SocketAddr,
+        config: &HttpServerConfig,
+        pool: &PoolHandle,
+    ) -> Result<()> {
+        let forwarding = &config.forwarding;
+        if !forwarding.acl.permits_client(client_addr.ip()) {
+            respond(&mut stream, 403, "Forbidden").await;
+            return Err(anyhow!("访问控制不允许客户端 {}", client_addr));
+        }
+
+        let head = tokio::time::timeout(HEAD_TIMEOUT, read_head(&mut stream)).await
+            .map_err(|_| anyhow!("读取请求头超时"))??;
+        let Some(request) = Request::parse(&head.head) else {
+            respond(&mut stream, 400, "Bad Request").await;
+            return Err(anyhow!("无法解析请求: {}", String::from_utf8_lossy(&head.head).lines().next().unwrap_or_default()));
+        };
+        debug!("HTTP代理请求: {} {}:{} (来自: {})", request.method, request.host, request.port, client_addr);
+
+        let port_allowed = forwarding.port_policy.evaluate(request.port, Transport::Tcp).0 == PolicyAction::Allow;
+        if !forwarding.acl.permits_destination(&request.host, request.port) || !port_allowed {
+            respond(&mut stream, 403, "Forbidden").await;
+            return Err(anyhow!("不允许访问 {}:{}", request.host, request.port));
+        }
+
+        let (proxy, _guard, mut upstream) = match Self::connect(forwarding, pool, client_addr, &request.host, request.port).await {
+            Ok(connected) => connected,
+            Err((status, reason, e)) => {
+                respond(&mut stream, status, reason).await;
+                return Err(e);
+            }
+        };
+        info!("HTTP代理使用代理 {}:{} 连接到 {}:{}", proxy.info.host, proxy.info.port, request.host, request.port);
+
+        // CONNECT隧道先应答客户端，普通请求把改写后的请求头交给目标；请求头之后已读到的数据原样转发
+        match &request.forward {
+            None => stream.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").await?,
+            Some(forward) => upstream.write_all(forward).await?,
+        }
+        upstream.write_all(&head.rest).await?;
+
+        let (client_reader, mut client_writer) = stream.into_split();
+        let (upstream_reader, mut upstream_writer) = upstream.into_split();
+        let activity = Arc::new(Activity::new());
+        let mut client_reader = CountingReader::new(client_reader, &proxy.usage, Direction::Up, &activity, None);
+        let mut upstream_reader = CountingReader::new(upstream_reader, &proxy.usage, Direction::Down, &activity, None);
+        tokio::select! {
+            res = relay_duplex(&mut client_reader, &mut client_writer, &mut upstream_reader, &mut upstream_writer, forwarding.relay) => {
+                if let Err(e) = res {
+                    debug!("HTTP代理转发数据错误: {}", e);
+                }
+            },
+            reason = activity.expired(forwarding.idle_timeout, forwarding.max_lifetime) => {
+                info!("来自 {} 的HTTP代理连接{}，关闭转发", client_addr, reason);
+            }
+        }
+        let bytes_up = client_reader.total + (head.rest.len() + request.forward.as_ref().map_or(0, Vec::len)) as u64;
+        pool.traffic().record(client_addr.ip(), &request.host, bytes_up, upstream_reader.total);
+        Ok(())
+    }
+
+    /// 从代理池选择代理连接目标，失败时换用其他代理重试；都失败时返回应答给客户端的状态码
+    async fn connect(
+        forwarding: &SocksServerConfig,
+        pool: &PoolHandle,
+        client_addr: SocketAddr,
+        host: &str,
+        port: u16,
+    ) -> std::result::Result<(Proxy, ConnectionGuard, TcpStream), (u16, &'static str, anyhow::Error)> {
+        let class = forwarding.classify(client_addr);
+        let mut tried: Vec<String> = Vec::new();
+        loop {
+            let Some((proxy, guard)) = pool.acquire_for(class, |p| !tried.contains(&p.id)).await else {
+                return Err((503, "Service Unavailable", anyhow!("没有可用的代理")));
+            };
+            let connecting = SocksServer::connect_upstream(&forwarding.chain, &proxy, address_type(host), host, port);
+            match within(forwarding.handshake_timeout, connecting).await {
+                Ok((upstream, _)) => {
+                    pool.report_connection(&proxy.id, true).await;
+                    return Ok((proxy, guard, upstream));
+                }
+                Err(e) if e.is::<ChainError>() => {
+                    error!("HTTP代理连接 {}:{} 失败: {}", host, port, e);
+                    return Err((502, "Bad Gateway", e));
+                }
+                Err(e) => {
+                    SocksServer::report_failure(pool, &proxy, &e).await;
+                    tried.push(proxy.id.clone());
+                    let refused = e.downcast_ref::<UpstreamReply>().is_some_and(|reply| reply.0 == REP_CONNECTION_REFUSED);
+                    if refused || tried.len() > forwarding.connect_retries {
+                        error!("HTTP代理经 {}:{} 连接 {}:{} 失败: {}", proxy.info.host, proxy.info.port, host, port, e);
+                        let timed_out = e.downcast_ref::<tokio::time::error::Elapsed>().is_some();
+                        return Err(match timed_out {
+                            true => (504, "Gateway Timeout", e),
+                            false => (502, "Bad Gateway", e),
+                        });
+                    }
+                    warn!("经代理 {}:{} 连接 {}:{} 失败: {}，换用其他代理重试",
+                        proxy.info.host, proxy.info.port, host, port, e);
+                }
+            }
+        }
+    }
+}
+
+/// 请求头与随请求头一起读到的后续数据
+struct Head {
+    head: Vec<u8>,
+    rest: Vec<u8>,
+}
+
+/// 读取到请求头结束的空行为止
+async fn read_head(stream: &mut TcpStream) -> Result<Head> {
+    let mut buf = Vec::with_capacity(4096);
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!("客户端在请求头结束前关闭了连接"));
+        }
+        let searched = buf.len().saturating_sub(3);
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(end) = buf[searched..].windows(4).position(|w| w == b"\r\n\r\n") {
+            let rest = buf.split_off(searched + end + 4);
+            return Ok(Head { head: buf, rest });
+        }
+        if buf.len() > MAX_HEAD_SIZE {
+            return Err(anyhow!("请求头超过 {} 字节", MAX_HEAD_SIZE));
+        }
+    }
+}
+
+/// 解析后的代理请求
+struct Request {
+    method: String,
+    host: String,
+    port: u16,
+    /// 改写为源站形式、发给目标的请求头，CONNECT请求为None
+    forward: Option<Vec<u8>>,
+}
+
+impl Request {
+    fn parse(head: &[u8]) -> Option<Self> {
+        let head = std::str::from_utf8(head).ok()?;
+        let mut lines = head.split("\r\n").filter(|line| !line.is_empty());
+        let mut request_line = lines.next()?.split_whitespace();
+        let (method, target, version) = (request_line.next()?, request_line.next()?, request_line.next()?);
+
+        if method.eq_ignore_ascii_case("CONNECT") {
+            let (host, port) = split_authority(target, None)?;
+            return Some(Self { method: method.to_string(), host, port, forward: None });
+        }
+
+        // 只转发绝对URI形式的http请求，https应使用CONNECT
+        let scheme = target.get(..7).filter(|scheme| scheme.eq_ignore_ascii_case("http://"))?;
+        let rest = &target[scheme.len()..];
+        let path_start = rest.find(['/', '?']).unwrap_or(rest.len());
+        let authority = &rest[..path_start];
+        let path = match &rest[path_start..] {
+            "" => "/".to_string(),
+            query if query.starts_with('?') => format!("/{}", query),
+            path => path.to_string(),
+        };
+        let (host, port) = split_authority(authority, Some(80))?;
+
+        let mut forward = format!("{} {} {}\r\n", method, path, version);
+        let mut has_host = false;
+        for line in lines {
+            let name = line.split(':').next().unwrap_or_default().trim();
+            if HOP_HEADERS.iter().any(|hop| name.eq_ignore_ascii_case(hop)) {
+                continue;
+            }
+            has_host |= name.eq_ignore_ascii_case("host");
+            forward.push_str(line);
+            forward.push_str("\r\n");
+        }
+        if !has_host {
+            forward.push_str(&format!("Host: {}\r\n", authority));
+        }
+        forward.push_str("Connection: close\r\n\r\n");
+        Some(Self { method: method.to_string(), host, port, forward: Some(forward.into_bytes()) })
+    }
+}
+
+/// 拆分 `host:port`，IPv6地址写在方括号中；没有端口时使用 `default_port`
+fn split_authority(authority: &str, default_port: Option<u16>) -> Option<(String, u16)> {
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()?),
+        _ => (authority, default_port?),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    (!host.is_empty()).then(|| (host.to_string(), port))
+}
+
+/// 应答错误状态并结束连接
+async fn respond(stream: &mut TcpStream, status: u16, reason: &str) {
+    let response = format!("HTTP/1.1 {} {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status, reason);
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        debug!("发送HTTP应答失败: {}", e);
+    }
+}
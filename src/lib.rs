@@ -6,7 +6,7 @@
 pub use lokipool_core::{
     Config, ProxyConfig,
     Error, Result,
-    Pool, PoolManager, PoolOptions,
+    Pool, PoolHandle, PoolManager, PoolOptions,
     Proxy, ProxyInfo, ProxyStatus,
     Tester, TestOptions, TestResult,
     ProxyPool, ProxyEntry,
@@ -15,6 +15,20 @@ pub use lokipool_core::{
 
 // 本地模块
 pub mod socks_server;
+pub mod listeners;
+pub mod connections;
+pub mod http_server;
+pub mod health_server;
+pub mod dns_server;
+pub mod relay;
+pub mod warm_pool;
+pub mod exit_agent;
+pub mod synth;
+pub mod tune;
+pub mod bench;
+pub mod status;
+pub mod commands;
+pub mod shell;
 // 移除这行，因为我们不再需要自己的proxy_pool实现
 // mod proxy_pool;
 
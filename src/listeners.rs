@@ -0,0 +1,236 @@
+//! SOCKS5监听端口的运行时管理
+//!
+//! 每个监听端口单独运行、单独停止，接受状态与连接数在端口重启后沿用，
+//! 健康检查与API看到的始终是同一份状态。修改选择策略时用新策略的代理池重新创建服务器。
+
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use lokipool_core::{supervise, Backoff, ListenerControl, ListenerError, ListenerInfo, ListenerState, PoolHandle, SelectionStrategy};
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::socks_server::{Accepting, ConnectionCounts, SocksServer, SocksServerConfig};
+
+/// 按设置与代理池创建服务器，附加镜像、日志等各端口共用的部分
+type Factory = dyn Fn(SocksServerConfig, PoolHandle) -> SocksServer + Send + Sync;
+
+/// 运行中的端口
+struct Running {
+    stop: broadcast::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+struct Listener {
+    /// 当前设置，重新加载时经这里发给运行中的服务器
+    settings: watch::Sender<SocksServerConfig>,
+    strategy: Option<SelectionStrategy>,
+    accepting: Accepting,
+    connections: ConnectionCounts,
+    running: Option<Running>,
+}
+
+impl Listener {
+    fn port(&self) -> u16 {
+        self.settings.borrow().bind_port
+    }
+
+    fn info(&self) -> ListenerInfo {
+        let settings = self.settings.borrow();
+        ListenerInfo {
+            port: settings.bind_port,
+            bind_addresses: settings.listen_addresses().into_iter().map(str::to_string).collect(),
+            state: if self.is_running() { ListenerState::Running } else { ListenerState::Stopped },
+            strategy: self.strategy,
+            active_connections: self.connections.active(),
+            total_connections: self.connections.total(),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.as_ref().is_some_and(|running| !running.task.is_finished())
+    }
+}
+
+/// 全部SOCKS5监听端口
+pub struct ListenerManager {
+    pool: PoolHandle,
+    factory: Box<Factory>,
+    listeners: Mutex<Vec<Listener>>,
+    /// 启停操作依次进行，停止时等待连接结束期间不妨碍查询
+    control: tokio::sync::Mutex<()>,
+}
+
+impl ListenerManager {
+    pub fn new(pool: PoolHandle, factory: impl Fn(SocksServerConfig, PoolHandle) -> SocksServer + Send + Sync + 'static) -> Self {
+        Self {
+            pool,
+            factory: Box::new(factory),
+            listeners: Mutex::new(Vec::new()),
+            control: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// 登记监听端口但不启动，返回端口的接受状态
+    pub fn add(&self, settings: SocksServerConfig, strategy: Option<SelectionStrategy>) -> Accepting {
+        let accepting = Accepting::default();
+        self.listeners.lock().unwrap().push(Listener {
+            settings: watch::channel(settings).0,
+            strategy,
+            accepting: accepting.clone(),
+            connections: ConnectionCounts::default(),
+            running: None,
+        });
+        accepting
+    }
+
+    /// 启动全部已登记的端口，无法绑定的端口记录错误后跳过
+    pub async fn start_all(&self) {
+        let _control = self.control.lock().await;
+        let ports: Vec<u16> = self.listeners.lock().unwrap().iter().map(Listener::port).collect();
+        for port in ports {
+            if let Err(e) = self.start_locked(port).await {
+                error!("SOCKS5服务器 :{} 未启动: {}", port, e);
+            }
+        }
+    }
+
+    /// 同时停止全部端口，等待各端口的连接结束
+    pub async fn stop_all(&self) {
+        let _control = self.control.lock().await;
+        let tasks: Vec<_> = self.listeners.lock().unwrap().iter_mut()
+            .filter_map(|listener| listener.running.take())
+            .map(|running| {
+                let _ = running.stop.send(());
+                running.task
+            })
+            .collect();
+        for task in tasks {
+            let _ = task.await;
+        }
+    }
+
+    /// 把重新加载的设置按顺序发给各端口，返回已更新的端口数
+    pub fn reload(&self, configs: Vec<SocksServerConfig>) -> usize {
+        let listeners = self.listeners.lock().unwrap();
+        if configs.len() != listeners.len() {
+            warn!("监听端口数量的变化需要重启后生效");
+        }
+        let updated = configs.len().min(listeners.len());
+        for (listener, next) in listeners.iter().zip(configs) {
+            listener.settings.send_replace(next);
+        }
+        updated
+    }
+
+    fn info(&self, port: u16) -> Result<ListenerInfo, ListenerError> {
+        let listeners = self.listeners.lock().unwrap();
+        listeners.iter().find(|listener| listener.port() == port).map(Listener::info).ok_or(ListenerError::NotFound(port))
+    }
+
+    async fn start_locked(&self, port: u16) -> Result<ListenerInfo, ListenerError> {
+        let (settings, server) = {
+            let listeners = self.listeners.lock().unwrap();
+            let listener = listeners.iter().find(|listener| listener.port() == port).ok_or(ListenerError::NotFound(port))?;
+            if listener.is_running() {
+                return Err(ListenerError::AlreadyRunning(port));
+            }
+            let settings = listener.settings.borrow().clone();
+            let pool = listener.strategy.map_or_else(|| self.pool.clone(), |strategy| self.pool.with_strategy(strategy));
+            let server = (self.factory)(settings.clone(), pool)
+                .with_reload(listener.settings.subscribe())
+                .with_accepting(listener.accepting.clone())
+                .with_connection_counts(listener.connections.clone());
+            (settings, server)
+        };
+        // 先绑定端口，端口被占用等错误直接返回给调用方，而不是只出现在监督任务的日志里；
+        // 绑定好的监听器交给服务器，返回时端口已经在接受连接
+        let bound = settings.bind().await.map_err(|e| ListenerError::Bind(port, e.to_string()))?;
+        let server = server.with_listeners(bound);
+
+        // 监听循环panic后重新绑定端口继续服务
+        let server = Arc::new(server);
+        let (stop, shutdown_rx) = broadcast::channel(1);
+        let mut shutdown_rx = Some(shutdown_rx);
+        let restart_tx = stop.clone();
+        let task = supervise(format!("SOCKS5服务器 :{}", port), Backoff::default(), move || {
+            let server = Arc::clone(&server);
+            let shutdown_rx = shutdown_rx.take().unwrap_or_else(|| restart_tx.subscribe());
+            async move {
+                if let Err(e) = server.run_with_shutdown(shutdown_rx).await {
+                    error!("SOCKS5服务器 :{} 运行出错: {}", port, e);
+                }
+            }
+        });
+
+        let mut listeners = self.listeners.lock().unwrap();
+        let listener = listeners.iter_mut().find(|listener| listener.port() == port).ok_or(ListenerError::NotFound(port))?;
+        listener.running = Some(Running { stop, task });
+        Ok(listener.info())
+    }
+
+    async fn stop_locked(&self, port: u16) -> Result<ListenerInfo, ListenerError> {
+        let running = {
+            let mut listeners = self.listeners.lock().unwrap();
+            let listener = listeners.iter_mut().find(|listener| listener.port() == port).ok_or(ListenerError::NotFound(port))?;
+            match listener.running.take() {
+                Some(running) if !running.task.is_finished() => running,
+                _ => return Err(ListenerError::NotRunning(port)),
+            }
+        };
+        let _ = running.stop.send(());
+        let _ = running.task.await;
+        self.info(port)
+    }
+}
+
+#[async_trait]
+impl ListenerControl for ListenerManager {
+    async fn listeners(&self) -> Vec<ListenerInfo> {
+        self.listeners.lock().unwrap().iter().map(Listener::info).collect()
+    }
+
+    async fn start(&self, port: u16) -> Result<ListenerInfo, ListenerError> {
+        let _control = self.control.lock().await;
+        let info = self.start_locked(port).await?;
+        info!("已启动SOCKS5监听端口 :{}", port);
+        Ok(info)
+    }
+
+    async fn stop(&self, port: u16) -> Result<ListenerInfo, ListenerError> {
+        let _control = self.control.lock().await;
+        let info = self.stop_locked(port).await?;
+        info!("已停止SOCKS5监听端口 :{}", port);
+        Ok(info)
+    }
+
+    async fn restart(&self, port: u16) -> Result<ListenerInfo, ListenerError> {
+        let _control = self.control.lock().await;
+        match self.stop_locked(port).await {
+            Ok(_) | Err(ListenerError::NotRunning(_)) => {}
+            Err(e) => return Err(e),
+        }
+        let info = self.start_locked(port).await?;
+        info!("已重启SOCKS5监听端口 :{}", port);
+        Ok(info)
+    }
+
+    async fn set_strategy(&self, port: u16, strategy: Option<SelectionStrategy>) -> Result<ListenerInfo, ListenerError> {
+        let _control = self.control.lock().await;
+        let was_running = {
+            let mut listeners = self.listeners.lock().unwrap();
+            let listener = listeners.iter_mut().find(|listener| listener.port() == port).ok_or(ListenerError::NotFound(port))?;
+            listener.strategy = strategy;
+            listener.is_running()
+        };
+        let strategy = strategy.map_or_else(|| "代理池的策略".to_string(), |strategy| format!("策略 {}", strategy));
+        if !was_running {
+            info!("SOCKS5监听端口 :{} 改用{}，下次启动时生效", port, strategy);
+            return self.info(port);
+        }
+        self.stop_locked(port).await?;
+        let info = self.start_locked(port).await?;
+        info!("SOCKS5监听端口 :{} 已改用{}并重启", port, strategy);
+        Ok(info)
+    }
+}
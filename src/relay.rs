@@ -0,0 +1,111 @@
+//! 带背压的双向转发
+//!
+//! 每个方向维护一个待写缓冲区：读取端持续读入，写入端持续写出；
+//! 缓冲的数据达到高水位时暂停读取，写出到低水位以下再恢复。
+//! 客户端上传很快而上游免费代理很慢时，内存占用被限制在高水位附近。
+//!
+//! 一个方向读到EOF后只关闭对应的写端（半关闭），另一个方向继续转发，
+//! 先发完请求再等待应答的协议（如HTTP上传后 `shutdown(SHUT_WR)`）不会被提前断开。
+
+use std::collections::VecDeque;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::debug;
+
+/// 转发缓冲区配置
+#[derive(Debug, Clone, Copy)]
+pub struct RelayOptions {
+    /// 单次读取的缓冲区大小（字节）
+    pub buffer_size: usize,
+    /// 待写数据达到该值时暂停读取（字节）
+    pub high_watermark: usize,
+    /// 待写数据降到该值以下时恢复读取（字节）
+    pub low_watermark: usize,
+}
+
+impl Default for RelayOptions {
+    fn default() -> Self {
+        Self {
+            buffer_size: 16 * 1024,
+            high_watermark: 256 * 1024,
+            low_watermark: 64 * 1024,
+        }
+    }
+}
+
+impl RelayOptions {
+    /// 修正不合理的配置：缓冲区至少1字节，低水位不超过高水位
+    pub fn normalized(self) -> Self {
+        let buffer_size = self.buffer_size.max(1);
+        let high_watermark = self.high_watermark.max(buffer_size);
+        Self {
+            buffer_size,
+            high_watermark,
+            low_watermark: self.low_watermark.min(high_watermark),
+        }
+    }
+}
+
+/// 从 `reader` 转发到 `writer` 直到读取端关闭，随后关闭 `writer` 的写方向，返回写出的字节数
+pub async fn relay<R, W>(reader: &mut R, writer: &mut W, options: RelayOptions) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let options = options.normalized();
+    let mut chunk = vec![0u8; options.buffer_size];
+    let mut pending: VecDeque<u8> = VecDeque::with_capacity(options.buffer_size);
+    let mut paused = false;
+    let mut eof = false;
+    let mut written = 0u64;
+
+    loop {
+        if !paused && pending.len() >= options.high_watermark {
+            debug!("转发缓冲达到高水位 ({} 字节)，暂停读取", pending.len());
+            paused = true;
+        } else if paused && pending.len() <= options.low_watermark {
+            debug!("转发缓冲降至低水位 ({} 字节)，恢复读取", pending.len());
+            paused = false;
+        }
+        if eof && pending.is_empty() {
+            writer.shutdown().await?;
+            return Ok(written);
+        }
+
+        tokio::select! {
+            read = reader.read(&mut chunk), if !eof && !paused => {
+                match read? {
+                    0 => eof = true,
+                    n => pending.extend(&chunk[..n]),
+                }
+            }
+            write = writer.write(pending.as_slices().0), if !pending.is_empty() => {
+                let n = write?;
+                if n == 0 {
+                    return Err(io::ErrorKind::WriteZero.into());
+                }
+                pending.drain(..n);
+                written += n as u64;
+            }
+        }
+    }
+}
+
+/// 双向转发直到两个方向都读到EOF，任一方向出错时两个方向都停止
+///
+/// 返回 (`a_reader` 到 `b_writer` 写出的字节数, `b_reader` 到 `a_writer` 写出的字节数)。
+pub async fn relay_duplex<AR, AW, BR, BW>(
+    a_reader: &mut AR,
+    a_writer: &mut AW,
+    b_reader: &mut BR,
+    b_writer: &mut BW,
+    options: RelayOptions,
+) -> io::Result<(u64, u64)>
+where
+    AR: AsyncRead + Unpin + ?Sized,
+    AW: AsyncWrite + Unpin + ?Sized,
+    BR: AsyncRead + Unpin + ?Sized,
+    BW: AsyncWrite + Unpin + ?Sized,
+{
+    tokio::try_join!(relay(a_reader, b_writer, options), relay(b_reader, a_writer, options))
+}
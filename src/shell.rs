@@ -0,0 +1,176 @@
+//! 交互式命令行
+//!
+//! 基于rustyline提供行编辑、命令历史与Tab补全：命令名、`remove` 的代理地址与ID、
+//! `unblock` 的黑名单哈希、`loglevel` 的级别都可以补全。补全候选通过回调按需获取，
+//! 输入循环运行在独立线程上，阻塞读取不会占用tokio的工作线程。
+
+use std::path::PathBuf;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+
+/// 历史记录保留的最大条数
+const MAX_HISTORY: usize = 1000;
+/// 历史记录文件名，位于用户主目录下
+const HISTORY_FILE: &str = ".lokipool_history";
+
+/// 可用命令，`help` 中列出的顺序
+pub const COMMANDS: &[&str] = &[
+    "show", "list", "next", "test", "retry", "sources", "traffic", "add", "remove", "reload",
+    "diag", "diagnose", "mirror", "screen", "blocklist", "unblock", "loglevel", "help", "quit", "exit",
+];
+
+/// `loglevel` 可补全的级别
+const LOG_LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+
+/// 命令参数的种类，决定补全候选从哪里来
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Argument {
+    /// 代理的 `host:port` 或ID
+    Proxy,
+    /// 永久黑名单条目的哈希
+    Blocked,
+    /// 日志级别
+    LogLevel,
+    /// `blocklist` 的子命令
+    Blocklist,
+}
+
+impl Argument {
+    /// 命令接受的参数种类，不接受可补全参数时返回None
+    pub fn of(command: &str) -> Option<Self> {
+        match command {
+            "remove" => Some(Self::Proxy),
+            "unblock" => Some(Self::Blocked),
+            "loglevel" => Some(Self::LogLevel),
+            "blocklist" => Some(Self::Blocklist),
+            _ => None,
+        }
+    }
+}
+
+/// 补全 `line` 中光标 `pos` 之前的单词，返回被替换部分的起点与按字典序排列的候选
+///
+/// 第一个单词补全为命令名，之后的单词由 `lookup` 按参数种类给出候选；
+/// 只有需要补全参数时才调用 `lookup`。
+pub fn complete(line: &str, pos: usize, lookup: impl FnOnce(Argument) -> Vec<String>) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+    let start = before.rfind(char::is_whitespace).map_or(0, |index| index + 1);
+    let word = &before[start..];
+    let mut candidates: Vec<String> = match before[..start].split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => COMMANDS.iter().map(|command| command.to_string()).collect(),
+        [command] => match Argument::of(command) {
+            Some(Argument::LogLevel) => LOG_LEVELS.iter().map(|level| level.to_string()).collect(),
+            Some(Argument::Blocklist) => vec!["clear".to_string()],
+            Some(argument) => lookup(argument),
+            None => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+    candidates.retain(|candidate| candidate.starts_with(word));
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+/// rustyline的补全器，参数候选由回调提供
+pub struct ShellHelper<F> {
+    lookup: F,
+}
+
+impl<F: Fn(Argument) -> Vec<String>> ShellHelper<F> {
+    /// `lookup` 在按Tab时于输入线程上调用
+    pub fn new(lookup: F) -> Self {
+        Self { lookup }
+    }
+}
+
+impl<F: Fn(Argument) -> Vec<String>> Completer for ShellHelper<F> {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = complete(line, pos, &self.lookup);
+        let pairs = candidates.into_iter()
+            .map(|candidate| Pair { display: candidate.clone(), replacement: candidate })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl<F> Hinter for ShellHelper<F> {
+    type Hint = String;
+}
+
+impl<F> Highlighter for ShellHelper<F> {}
+
+impl<F> Validator for ShellHelper<F> {}
+
+impl<F: Fn(Argument) -> Vec<String>> Helper for ShellHelper<F> {}
+
+/// 一次读取的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// 一行命令，已去掉首尾空白
+    Line(String),
+    /// 在提示符处按下Ctrl-C
+    Interrupted,
+    /// 输入结束（Ctrl-D或标准输入被关闭）
+    Eof,
+}
+
+/// 带历史记录与补全的行编辑器
+pub struct Shell<F: Fn(Argument) -> Vec<String>> {
+    editor: Editor<ShellHelper<F>, FileHistory>,
+    history: Option<PathBuf>,
+}
+
+impl<F: Fn(Argument) -> Vec<String>> Shell<F> {
+    /// 创建编辑器并载入主目录下的历史记录，找不到主目录时只在本次运行中保留历史
+    pub fn new(lookup: F) -> rustyline::Result<Self> {
+        Self::with_history(lookup, home::home_dir().map(|dir| dir.join(HISTORY_FILE)))
+    }
+
+    /// 使用指定的历史记录文件，None表示不持久化
+    pub fn with_history(lookup: F, history: Option<PathBuf>) -> rustyline::Result<Self> {
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .max_history_size(MAX_HISTORY)?
+            .history_ignore_dups(true)?
+            .build();
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(ShellHelper::new(lookup)));
+        if let Some(path) = &history {
+            // 首次运行时历史文件还不存在
+            let _ = editor.load_history(path);
+        }
+        Ok(Self { editor, history })
+    }
+
+    /// 显示提示符并读取一行，非空的行记入历史
+    pub fn read(&mut self, prompt: &str) -> rustyline::Result<Input> {
+        match self.editor.readline(prompt) {
+            Ok(line) => {
+                let line = line.trim().to_string();
+                if !line.is_empty() {
+                    self.editor.add_history_entry(line.as_str())?;
+                }
+                Ok(Input::Line(line))
+            }
+            Err(ReadlineError::Interrupted) => Ok(Input::Interrupted),
+            Err(ReadlineError::Eof) => Ok(Input::Eof),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 把历史记录写回文件
+    pub fn save_history(&mut self) -> rustyline::Result<()> {
+        match &self.history {
+            Some(path) => self.editor.save_history(path),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,122 +1,815 @@
-use std::net::SocketAddr;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadBuf};
 use anyhow::{Result, anyhow};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 // 修改导入路径，使用lokipool_core而不是lokipool
-use lokipool_core::Pool;
+use lokipool_core::{accept_backoff, constant_time_eq, proxy_protocol, spawn_logged, Acl, BlockReason, Bypass, ConnectionGuard, ConnectionLog, ConnectionSummary, EventLog, IpNet, PolicyAction, PoolHandle, PortPolicy, Proxy, ProxyAffinity, ProxyConfig, StickyTargets, ProxyUsage, QuotaLimits, QuotaPermit, QuotaTable, SocksAccount, Threat, TrafficClass, TrafficMirror, Transport};
 use tracing::{info, error, warn, debug}; // 引入debug日志级别
-use tokio::sync::broadcast;
+use crate::connections::ConnectionRegistry;
+use crate::relay::{relay_duplex, RelayOptions};
+use crate::warm_pool::{WarmPool, WarmPoolOptions};
+use tokio::sync::{broadcast, watch, OwnedSemaphorePermit, Semaphore};
 // use std::error::Error as StdError; // 导入StdError
 use std::net::{Ipv4Addr, Ipv6Addr}; // 导入Ipv6Addr
+use std::fmt;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 /// SOCKS5服务器配置
 #[derive(Debug, Clone)]
 pub struct SocksServerConfig {
     /// 监听地址
     pub bind_address: String,
+    /// 额外的监听地址，与 `bind_address` 使用同一端口
+    pub bind_addresses: Vec<String>,
     /// 监听端口
     pub bind_port: u16,
+    /// 转发缓冲区与背压水位
+    pub relay: RelayOptions,
+    /// 该监听端口上连接的流量类别
+    pub traffic_class: TrafficClass,
+    /// 代理亲和模式：每个连接按策略选择、每个连接换一个代理或同一客户端固定一个代理
+    pub affinity: ProxyAffinity,
+    /// 只使用位置标签为其中之一的代理，为空时不限制
+    pub locations: Vec<String>,
+    /// 来自这些地址段的连接按批量流量处理
+    pub bulk_clients: Vec<IpNet>,
+    /// 与上游代理完成握手并连接到目标的超时，为0时不限制
+    pub handshake_timeout: Duration,
+    /// 两个方向都没有数据超过该时长后关闭转发，为0时不限制
+    pub idle_timeout: Duration,
+    /// 单个连接的最长存活时间，为0时不限制
+    pub max_lifetime: Duration,
+    /// 连接上游失败后最多换用其他代理重试的次数，为0时不重试
+    pub connect_retries: usize,
+    /// 同一目标主机固定使用同一代理的时长，为0时不固定
+    pub sticky_target_ttl: Duration,
+    /// 关闭时等待进行中连接结束的最长时间，为0时立即关闭
+    pub drain_timeout: Duration,
+    /// 连接开头带PROXY协议头，真实的客户端地址取自其中
+    pub proxy_protocol: bool,
+    /// 客户端认证账户，为空时不要求认证
+    pub accounts: Vec<SocksAccount>,
+    /// 客户端与目标的访问控制列表
+    pub acl: Acl,
+    /// 目标端口策略
+    pub port_policy: PortPolicy,
+    /// 代理链：依次经过这些代理再连接池中选出的代理，为空时直接连接
+    pub chain: Vec<ProxyConfig>,
+    /// 不经上游代理、直接连接的目标
+    pub bypass: Bypass,
+    /// 同时处理的最大客户端连接数，达到后暂停接受新连接，为0时不限制
+    pub max_clients: usize,
+    /// 每秒最多接受的新连接数，为0时不限制
+    pub accepts_per_second: u32,
+    /// 每个客户端IP的并发连接数与每日流量配额
+    pub quota: QuotaLimits,
+    /// 预热到延迟最低的代理的连接
+    pub warm_pool: WarmPoolOptions,
 }
 
 impl Default for SocksServerConfig {
     fn default() -> Self {
         Self {
             bind_address: "127.0.0.1".to_string(),
+            bind_addresses: Vec::new(),
             bind_port: 1080,
+            relay: RelayOptions::default(),
+            traffic_class: TrafficClass::default(),
+            affinity: ProxyAffinity::default(),
+            locations: Vec::new(),
+            bulk_clients: Vec::new(),
+            handshake_timeout: Duration::from_secs(10),
+            idle_timeout: Duration::from_secs(600),
+            max_lifetime: Duration::ZERO,
+            connect_retries: 2,
+            sticky_target_ttl: Duration::ZERO,
+            drain_timeout: Duration::from_secs(10),
+            proxy_protocol: false,
+            accounts: Vec::new(),
+            acl: Acl::default(),
+            port_policy: PortPolicy::default(),
+            chain: Vec::new(),
+            bypass: Bypass::default(),
+            max_clients: 0,
+            accepts_per_second: 0,
+            quota: QuotaLimits::default(),
+            warm_pool: WarmPoolOptions::default(),
         }
     }
 }
 
+impl SocksServerConfig {
+    /// 全部监听地址，`bind_address` 在前，重复的地址只保留一个
+    pub fn listen_addresses(&self) -> Vec<&str> {
+        let mut addresses = vec![self.bind_address.as_str()];
+        for address in &self.bind_addresses {
+            if !addresses.contains(&address.as_str()) {
+                addresses.push(address);
+            }
+        }
+        addresses
+    }
+
+    /// 绑定全部监听地址，任一地址绑定失败时返回错误
+    ///
+    /// 监听 `::` 时默认同时接受IPv4连接（双栈）；同时配置了IPv4地址时只接受IPv6，以免与其端口冲突。
+    pub async fn bind(&self) -> Result<Vec<TcpListener>> {
+        let mut resolved = Vec::new();
+        for address in self.listen_addresses() {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((address, self.bind_port)).await
+                .map_err(|e| anyhow!("无法解析监听地址 {}: {}", address, e))?
+                .collect();
+            resolved.push((address, addrs));
+        }
+        let has_ipv4 = resolved.iter().any(|(_, addrs)| addrs.first().is_some_and(SocketAddr::is_ipv4));
+
+        let mut listeners = Vec::with_capacity(resolved.len());
+        for (address, addrs) in resolved {
+            // 与 `TcpListener::bind` 一样，依次尝试解析出的地址直到有一个绑定成功
+            let mut last_error = None;
+            for addr in addrs {
+                match bind_listener(addr, has_ipv4) {
+                    Ok(listener) => {
+                        listeners.push(listener);
+                        last_error = None;
+                        break;
+                    }
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            if let Some(e) = last_error {
+                return Err(anyhow!("无法绑定 {}: {}", address, e));
+            }
+        }
+        Ok(listeners)
+    }
+
+    /// 连接的流量类别：客户端规则优先，其次是监听端口的类别
+    pub fn classify(&self, client_addr: SocketAddr) -> TrafficClass {
+        if self.bulk_clients.iter().any(|net| net.contains(client_addr.ip())) {
+            return TrafficClass::Bulk;
+        }
+        self.traffic_class
+    }
+}
+
+/// 关闭时等待进行中连接的结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrainReport {
+    /// 在关闭等待期内自行结束的连接数
+    pub drained: usize,
+    /// 等待期结束后被强制关闭的连接数
+    pub cut: usize,
+}
+
+/// 监听端口是否正在接受连接，健康检查据此判断实例能否接收流量
+#[derive(Debug, Clone, Default)]
+pub struct Accepting(Arc<AtomicBool>);
+
+impl Accepting {
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// 监听期间持有，监听循环退出或panic时自动清除
+    fn guard(&self) -> AcceptingGuard {
+        self.0.store(true, Ordering::Relaxed);
+        AcceptingGuard(self.clone())
+    }
+}
+
+struct AcceptingGuard(Accepting);
+
+impl Drop for AcceptingGuard {
+    fn drop(&mut self) {
+        self.0 .0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// 监听端口上的连接数，克隆后共享同一份计数
+#[derive(Debug, Clone)]
+pub struct ConnectionCounts {
+    active: Arc<watch::Sender<usize>>,
+    total: Arc<AtomicU64>,
+}
+
+impl Default for ConnectionCounts {
+    fn default() -> Self {
+        Self { active: Arc::new(watch::channel(0).0), total: Arc::new(AtomicU64::new(0)) }
+    }
+}
+
+impl ConnectionCounts {
+    /// 进行中的连接数
+    pub fn active(&self) -> usize {
+        *self.active.borrow()
+    }
+
+    /// 累计接受的连接数
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    fn track(&self) -> Tracked {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        Tracked::new(&self.active)
+    }
+}
+
+/// 进行中的连接计数，连接任务结束时减一
+struct Tracked(Arc<watch::Sender<usize>>);
+
+impl Tracked {
+    fn new(active: &Arc<watch::Sender<usize>>) -> Self {
+        active.send_modify(|count| *count += 1);
+        Self(Arc::clone(active))
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.0.send_modify(|count| *count -= 1);
+    }
+}
+
+/// 连接的编号，进程内所有监听端口共用
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 观察者回调中用于区分连接的信息
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// 进程内唯一的连接编号
+    pub id: u64,
+    /// 接受连接的监听端口
+    pub port: u16,
+    /// 客户端地址
+    pub client: SocketAddr,
+    pub class: TrafficClass,
+}
+
+/// 连接事件的观察者，嵌入LokiPool的程序可以借此实现自己的计费、配额或审计
+///
+/// 回调在转发连接的任务中同步调用，应尽快返回，耗时的处理请转交给其他任务。
+/// 所有方法都有空的默认实现，只需实现关心的事件。
+pub trait ConnectionObserver: Send + Sync {
+    /// 接受了客户端连接，尚未握手
+    fn on_open(&self, _connection: &ConnectionInfo) {}
+
+    /// 为到 `target`（`host:port`）的请求选定了上游代理，直接连接时 `proxy` 为None；
+    /// 连接失败换用其他代理重试时每次选择都会调用
+    fn on_select_proxy(&self, _connection: &ConnectionInfo, _target: &str, _proxy: Option<&Proxy>) {}
+
+    /// 转发了数据，`up` 为客户端发往目标的字节数，`down` 为目标发往客户端的字节数
+    fn on_bytes(&self, _connection: &ConnectionInfo, _up: u64, _down: u64) {}
+
+    /// 连接结束，与 `on_open` 一一对应；握手阶段就断开的连接目标为空
+    fn on_close(&self, _connection: &ConnectionInfo, _summary: &ConnectionSummary) {}
+}
+
+/// 一个连接与注册的全部观察者
+#[derive(Clone)]
+pub(crate) struct Observers {
+    list: Arc<[Arc<dyn ConnectionObserver>]>,
+    connection: Arc<ConnectionInfo>,
+}
+
+impl Observers {
+    fn notify(&self, event: impl Fn(&dyn ConnectionObserver, &ConnectionInfo)) {
+        for observer in self.list.iter() {
+            event(observer.as_ref(), &self.connection);
+        }
+    }
+}
+
+/// 处理单个连接所需的共享状态
+#[derive(Clone)]
+struct ConnectionContext {
+    pool: PoolHandle,
+    mirror: Option<TrafficMirror>,
+    event_log: Option<EventLog>,
+    connection_log: Option<ConnectionLog>,
+    relay: RelayOptions,
+    handshake_timeout: Duration,
+    idle_timeout: Duration,
+    max_lifetime: Duration,
+    connect_retries: usize,
+    accounts: Arc<[SocksAccount]>,
+    acl: Arc<Acl>,
+    port_policy: Arc<PortPolicy>,
+    chain: Arc<[ProxyConfig]>,
+    bypass: Arc<Bypass>,
+    locations: Arc<[String]>,
+    affinity: Arc<Affinity>,
+    sticky_target_ttl: Duration,
+    sticky_targets: Arc<StickyTargets>,
+    observers: Observers,
+    /// 进行中的连接登记表，经它强制关闭连接
+    registry: Option<ConnectionRegistry>,
+    proxy_protocol: bool,
+    traffic_class: TrafficClass,
+    bulk_clients: Arc<[IpNet]>,
+    quota: QuotaLimits,
+    quotas: QuotaTable,
+    warm: WarmPool,
+}
+
+impl ConnectionContext {
+    /// 为到 `target` 的连接选择代理：目标固定的代理可用时继续使用，否则按亲和模式选择
+    async fn acquire<F>(&self, class: TrafficClass, client: IpAddr, target: &str, filter: F) -> Option<(Proxy, ConnectionGuard)>
+    where
+        F: Fn(&Proxy) -> bool,
+    {
+        if !self.sticky_target_ttl.is_zero() {
+            if let Some(id) = self.sticky_targets.get(target, self.sticky_target_ttl) {
+                let stuck = self.pool.acquire_for(class, |p| p.id == id && self.serves(p) && filter(p)).await;
+                if stuck.is_some() {
+                    return stuck;
+                }
+                debug!("{} 固定的代理当前不可用，重新选择", target);
+            }
+        }
+        self.affinity.acquire(&self.pool, class, client, |p| self.serves(p) && filter(p)).await
+    }
+
+    /// 按PROXY协议头给出的客户端地址重新判定流量类别，与 `SocksServerConfig::classify` 一致
+    fn classify(&self, client_addr: SocketAddr) -> TrafficClass {
+        if self.bulk_clients.iter().any(|net| net.contains(client_addr.ip())) {
+            return TrafficClass::Bulk;
+        }
+        self.traffic_class
+    }
+
+    /// 代理的位置标签是否符合该监听端口的限制
+    fn serves(&self, proxy: &Proxy) -> bool {
+        self.locations.is_empty() || proxy.info.location.as_ref()
+            .is_some_and(|location| self.locations.iter().any(|allowed| allowed.eq_ignore_ascii_case(location)))
+    }
+
+    /// 按端口策略判定目标端口是否放行，命中开启了日志的规则时记录
+    fn permits_port(&self, client_addr: SocketAddr, host: &str, port: u16, protocol: Transport) -> bool {
+        let (action, rule) = self.port_policy.evaluate(port, protocol);
+        if let Some((index, rule)) = rule.filter(|(_, rule)| rule.log) {
+            info!("端口策略第 {} 条规则 [{}] 命中: {} -> {}:{} ({})", index, rule, client_addr, host, port, protocol);
+        }
+        action == PolicyAction::Allow
+    }
+
+    /// 检查客户端配额并占用一个连接名额，超出时以“规则不允许”应答客户端
+    async fn admit_quota(&self, writer: &mut OwnedWriteHalf, version: Version, client: IpAddr) -> Result<QuotaPermit> {
+        match self.quotas.admit(client, &self.quota) {
+            Ok(permit) => Ok(permit),
+            Err(exceeded) => {
+                version.reject(writer, REP_NOT_ALLOWED).await;
+                Err(anyhow!("配额: 客户端 {} {}", client, exceeded))
+            }
+        }
+    }
+}
+
+/// 同一客户端固定的代理最多记录的条数，超过后清空重新固定
+const MAX_PINS: usize = 10_000;
+
+/// 代理亲和模式的状态：轮换模式记录上一个连接的代理，固定模式记录各客户端的代理
+#[derive(Debug, Default)]
+struct Affinity {
+    mode: ProxyAffinity,
+    last: Mutex<Option<String>>,
+    pins: Mutex<HashMap<IpAddr, String>>,
+}
+
+impl Affinity {
+    fn new(mode: ProxyAffinity) -> Self {
+        Self { mode, ..Self::default() }
+    }
+
+    /// 按亲和模式选择满足条件的代理，偏好的代理不可用时退回选择策略
+    async fn acquire<F>(&self, pool: &PoolHandle, class: TrafficClass, client: IpAddr, filter: F) -> Option<(Proxy, ConnectionGuard)>
+    where
+        F: Fn(&Proxy) -> bool,
+    {
+        let preferred = match self.mode {
+            ProxyAffinity::Strategy => None,
+            ProxyAffinity::Rotate => {
+                let last = self.last.lock().unwrap().clone();
+                pool.acquire_for(class, |p| filter(p) && last.as_ref() != Some(&p.id)).await
+            }
+            ProxyAffinity::Pin => {
+                let pinned = self.pins.lock().unwrap().get(&client).cloned();
+                match pinned {
+                    Some(pinned) => pool.acquire_for(class, |p| filter(p) && p.id == pinned).await,
+                    None => None,
+                }
+            }
+        };
+        let selected = match preferred {
+            Some(selected) => selected,
+            None => pool.acquire_for(class, &filter).await?,
+        };
+        match self.mode {
+            ProxyAffinity::Strategy => {}
+            ProxyAffinity::Rotate => *self.last.lock().unwrap() = Some(selected.0.id.clone()),
+            ProxyAffinity::Pin => {
+                let mut pins = self.pins.lock().unwrap();
+                if pins.len() >= MAX_PINS && !pins.contains_key(&client) {
+                    pins.clear();
+                }
+                if pins.insert(client, selected.0.id.clone()).as_ref() != Some(&selected.0.id) {
+                    debug!("客户端 {} 固定使用代理 {}:{}", client, selected.0.info.host, selected.0.info.port);
+                }
+            }
+        }
+        Some(selected)
+    }
+}
+
 /// SOCKS5 代理服务器
 pub struct SocksServer {
     config: SocksServerConfig,
-    pool: Arc<Pool>,
+    pool: PoolHandle,
+    mirror: Option<TrafficMirror>,
+    event_log: Option<EventLog>,
+    connection_log: Option<ConnectionLog>,
+    /// 代理亲和模式的状态，所有连接共享
+    affinity: Arc<Affinity>,
+    /// 各目标主机固定使用的代理
+    sticky_targets: Arc<StickyTargets>,
+    /// 重新加载的设置
+    reload: Option<watch::Receiver<SocksServerConfig>>,
+    /// 连接事件的观察者
+    observers: Arc<[Arc<dyn ConnectionObserver>]>,
+    /// 监听端口是否正在接受连接
+    accepting: Accepting,
+    /// 进行中与累计的连接数
+    connections: ConnectionCounts,
+    /// 各客户端的配额用量
+    quotas: QuotaTable,
+    /// 进行中的连接登记表
+    registry: Option<ConnectionRegistry>,
+    /// 预热到延迟最低的代理的连接
+    warm: WarmPool,
+    /// 已绑定的监听器，设置后不再绑定配置中的监听地址
+    listeners: Mutex<Vec<TcpListener>>,
 }
 
 impl SocksServer {
     /// 创建新的SOCKS5服务器
-    pub fn new(socks_config: SocksServerConfig, pool: Pool) -> Self {
+    pub fn new(socks_config: SocksServerConfig, pool: impl Into<PoolHandle>) -> Self {
+        // 预热的连接直接连到代理，经代理链时无法使用
+        let warm_options = match socks_config.chain.is_empty() {
+            true => socks_config.warm_pool,
+            false => WarmPoolOptions { size: 0, ..socks_config.warm_pool },
+        };
         Self {
+            warm: WarmPool::new(warm_options, socks_config.handshake_timeout, socks_config.locations.clone()),
+            affinity: Arc::new(Affinity::new(socks_config.affinity)),
             config: socks_config,
-            pool: Arc::new(pool),
+            pool: pool.into(),
+            mirror: None,
+            event_log: None,
+            connection_log: None,
+            sticky_targets: Arc::new(StickyTargets::default()),
+            reload: None,
+            observers: Arc::new([]),
+            accepting: Accepting::default(),
+            connections: ConnectionCounts::default(),
+            quotas: QuotaTable::default(),
+            registry: None,
+            listeners: Mutex::new(Vec::new()),
         }
     }
 
-    #[allow(dead_code)]
-    /// 启动SOCKS5服务器
-    pub async fn run(&self) -> Result<()> {
-        let addr = format!("{}:{}", self.config.bind_address, self.config.bind_port);
-        let listener = TcpListener::bind(&addr).await?;
-        
-        info!("SOCKS5服务器开始监听: {}", addr);
-        
-        loop {
-            match listener.accept().await {
-                Ok((stream, client_addr)) => {
-                    let pool = Arc::clone(&self.pool);
-                    tokio::spawn(async move {
-                        if let Err(e) = Self::handle_connection(stream, client_addr, pool).await {
-                            error!("处理连接出错: {}", e);
-                        }
-                    });
-                }
-                Err(e) => {
-                    warn!("接受连接失败: {}", e);
-                }
-            }
+    /// 启用流量镜像，抽样的连接会同时用于评估候选代理
+    pub fn with_mirror(mut self, mirror: TrafficMirror) -> Self {
+        self.mirror = Some(mirror);
+        self
+    }
+
+    /// 每个连接结束时把连接摘要写入事件日志
+    pub fn with_event_log(mut self, event_log: EventLog) -> Self {
+        self.event_log = Some(event_log);
+        self
+    }
+
+    /// 每个连接结束时向连接日志写一行JSON
+    pub fn with_connection_log(mut self, connection_log: ConnectionLog) -> Self {
+        self.connection_log = Some(connection_log);
+        self
+    }
+
+    /// 从通道接收新的设置，`run_with_shutdown` 在不中断监听与已有连接的情况下应用
+    pub fn with_reload(mut self, reload: watch::Receiver<SocksServerConfig>) -> Self {
+        self.reload = Some(reload);
+        self
+    }
+
+    /// 注册连接事件的观察者，可以注册多个，按注册顺序调用
+    pub fn with_observer(mut self, observer: Arc<dyn ConnectionObserver>) -> Self {
+        self.observers = self.observers.iter().cloned().chain(std::iter::once(observer)).collect();
+        self
+    }
+
+    /// 与其他监听端口共用配额用量表，同一客户端在各端口上的连接与流量合并计算
+    pub fn with_quota_table(mut self, quotas: QuotaTable) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// 监听端口是否正在接受连接：绑定成功后为真，收到关闭信号或监听循环退出后为假
+    pub fn accepting(&self) -> Accepting {
+        self.accepting.clone()
+    }
+
+    /// 使用外部的接受状态，重新创建的服务器沿用同一个状态，健康检查不需要重新获取
+    pub fn with_accepting(mut self, accepting: Accepting) -> Self {
+        self.accepting = accepting;
+        self
+    }
+
+    /// 在各监听端口共用的登记表中登记进行中的连接，允许经登记表强制关闭单个连接
+    pub fn with_connection_registry(mut self, registry: ConnectionRegistry) -> Self {
+        self.registry = Some(registry.clone());
+        self.with_observer(Arc::new(registry))
+    }
+
+    /// 使用外部的连接计数，重新创建的服务器接着原来的计数
+    pub fn with_connection_counts(mut self, connections: ConnectionCounts) -> Self {
+        self.connections = connections;
+        self
+    }
+
+    /// 在已绑定的监听器上接受连接，忽略配置中的监听地址，端口以监听器为准
+    pub fn with_listener(mut self, listener: TcpListener) -> Self {
+        if let Ok(addr) = listener.local_addr() {
+            self.config.bind_port = addr.port();
         }
+        self.with_listeners(vec![listener])
     }
 
-    /// 启动SOCKS5服务器，可以通过接收shutdown信号优雅关闭
-    pub async fn run_with_shutdown(&self, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
-        let addr = format!("{}:{}", self.config.bind_address, self.config.bind_port);
-        let listener = TcpListener::bind(&addr).await?;
-        
-        info!("SOCKS5服务器开始监听: {}", addr);
+    /// 在已绑定的监听器上接受连接，只用于首次监听，重新监听时按配置绑定
+    pub fn with_listeners(mut self, listeners: Vec<TcpListener>) -> Self {
+        self.listeners = Mutex::new(listeners);
+        self
+    }
+
+    /// 绑定全部监听地址
+    async fn listen(&self) -> Result<Vec<TcpListener>> {
+        let prebound = std::mem::take(&mut *self.listeners.lock().unwrap_or_else(|e| e.into_inner()));
+        let listeners = match prebound.is_empty() {
+            true => self.config.bind().await?,
+            false => prebound,
+        };
+        for listener in &listeners {
+            info!("SOCKS5服务器开始监听: {}", listener.local_addr()?);
+        }
+        Ok(listeners)
+    }
+
+    fn context(&self, config: &SocksServerConfig, client_addr: SocketAddr, class: TrafficClass) -> ConnectionContext {
+        ConnectionContext {
+            pool: self.pool.clone(),
+            mirror: self.mirror.clone(),
+            event_log: self.event_log.clone(),
+            connection_log: self.connection_log.clone(),
+            relay: config.relay,
+            handshake_timeout: config.handshake_timeout,
+            idle_timeout: config.idle_timeout,
+            max_lifetime: config.max_lifetime,
+            connect_retries: config.connect_retries,
+            accounts: config.accounts.clone().into(),
+            acl: Arc::new(config.acl.clone()),
+            port_policy: Arc::new(config.port_policy.clone()),
+            chain: config.chain.clone().into(),
+            bypass: Arc::new(config.bypass.clone()),
+            locations: config.locations.clone().into(),
+            affinity: Arc::clone(&self.affinity),
+            sticky_target_ttl: config.sticky_target_ttl,
+            sticky_targets: Arc::clone(&self.sticky_targets),
+            observers: Observers {
+                list: Arc::clone(&self.observers),
+                connection: Arc::new(ConnectionInfo {
+                    id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+                    port: config.bind_port,
+                    client: client_addr,
+                    class,
+                }),
+            },
+            registry: self.registry.clone(),
+            proxy_protocol: config.proxy_protocol,
+            traffic_class: config.traffic_class,
+            bulk_clients: config.bulk_clients.clone().into(),
+            quota: config.quota,
+            quotas: self.quotas.clone(),
+            warm: self.warm.clone(),
+        }
+    }
+
+    /// 预热池，可用于查看预热的连接数与命中次数
+    pub fn warm_pool(&self) -> WarmPool {
+        self.warm.clone()
+    }
+
+    /// 启动SOCKS5服务器，不等待关闭信号，只在绑定监听地址失败时返回
+    pub async fn run(&self) -> Result<()> {
+        self.serve(std::future::pending()).await.map(|_| ())
+    }
+
+    /// 启动SOCKS5服务器，收到shutdown信号后停止接受新连接，等待已有连接结束
+    ///
+    /// 已有连接最多等待 `drain_timeout`，之后仍未结束的连接被强制关闭，返回两类连接的数量。
+    pub async fn run_with_shutdown(&self, mut shutdown: broadcast::Receiver<()>) -> Result<DrainReport> {
+        self.serve(async move {
+            let _ = shutdown.recv().await;
+        }).await
+    }
+
+    /// 接受连接直到 `shutdown` 完成，随后等待已有连接结束
+    async fn serve(&self, shutdown: impl std::future::Future<Output = ()>) -> Result<DrainReport> {
+        tokio::pin!(shutdown);
+        let listeners = self.listen().await?;
+        let accepting = self.accepting.guard();
+        self.warm.start(self.pool.clone());
+        let mut config = self.config.clone();
+        let mut reload = self.reload.clone();
+        let mut admission = Admission::new(&config);
+        let active = &self.connections.active;
+        let (cut_tx, cut_rx) = watch::channel(false);
         
         loop {
             tokio::select! {
-                accept_result = listener.accept() => {
+                accept_result = next_client(&listeners, &mut admission) => {
                     match accept_result {
-                        Ok((stream, client_addr)) => {
-                            let pool = Arc::clone(&self.pool);
-                            let mut shutdown_clone = shutdown.resubscribe();
-                            tokio::spawn(async move {
+                        Ok((stream, client_addr, permit)) => {
+                            let class = config.classify(client_addr);
+                            let context = self.context(&config, client_addr, class);
+                            let tracked = self.connections.track();
+                            let mut cut = cut_rx.clone();
+                            spawn_logged(format!("SOCKS5连接 {}", client_addr), async move {
+                                let _permit = permit;
+                                let _tracked = tracked;
                                 tokio::select! {
-                                    conn_result = Self::handle_connection(stream, client_addr, pool) => {
-                                        if let Err(e) = conn_result {
-                                            error!("处理连接出错: {}", e);
-                                        }
-                                    },
-                                    _ = shutdown_clone.recv() => {
-                                        info!("连接处理器收到关闭信号");
+                                    _ = Self::serve_connection(stream, client_addr, class, context) => {},
+                                    _ = cut.wait_for(|cut| *cut) => {
+                                        info!("来自 {} 的连接在关闭等待期后仍未结束，强制关闭", client_addr);
                                     }
                                 }
                             });
                         }
                         Err(e) => {
                             warn!("接受连接失败: {}", e);
+                            accept_backoff(&e).await;
                         }
                     }
                 },
-                _ = shutdown.recv() => {
-                    info!("SOCKS5服务器收到关闭信号，正在停止...");
+                next = reloaded(&mut reload) => {
+                    self.apply(&mut config, &mut admission, next);
+                },
+                _ = &mut shutdown => {
+                    info!("SOCKS5服务器收到关闭信号，停止接受新连接");
                     break;
                 }
             }
         }
-        
-        Ok(())
+        drop(accepting);
+        drop(listeners);
+        self.warm.close();
+
+        let open = *active.borrow();
+        if open > 0 {
+            info!("等待 {} 个进行中的连接结束（最长 {:?}）", open, self.config.drain_timeout);
+        }
+        let mut remaining = active.subscribe();
+        let _ = tokio::time::timeout(self.config.drain_timeout, remaining.wait_for(|count| *count == 0)).await;
+        let cut = *active.borrow();
+        let _ = cut_tx.send(true);
+        let report = DrainReport { drained: open.saturating_sub(cut), cut };
+        info!("SOCKS5服务器已停止: {} 个连接正常结束, {} 个连接被强制关闭", report.drained, report.cut);
+        Ok(report)
     }
 
-    /// 处理SOCKS5连接
+    /// 应用重新加载的设置，之后接受的连接使用新设置，已有连接不受影响
+    ///
+    /// 监听地址、代理亲和模式与预热池的变化需要重启才能生效，其余设置立即生效。
+    fn apply(&self, config: &mut SocksServerConfig, admission: &mut Admission, next: SocksServerConfig) {
+        if next.listen_addresses() != config.listen_addresses() || next.bind_port != config.bind_port {
+            warn!("监听地址或端口的变化需要重启后生效，继续监听 {}", config.bind_port);
+        }
+        if next.affinity != config.affinity {
+            warn!("代理亲和模式的变化需要重启后生效，继续使用 {}", config.affinity);
+        }
+        if next.warm_pool != config.warm_pool {
+            warn!("预热池设置的变化需要重启后生效");
+        }
+        admission.resize(&next);
+        *config = SocksServerConfig {
+            bind_address: config.bind_address.clone(),
+            bind_addresses: config.bind_addresses.clone(),
+            bind_port: config.bind_port,
+            affinity: config.affinity,
+            warm_pool: config.warm_pool,
+            ..next
+        };
+        info!("SOCKS5服务器 :{} 已应用新的设置", config.bind_port);
+    }
+
+    /// 处理一个连接，结束后累计流量并把连接摘要写入事件日志与连接日志
+    async fn serve_connection(
+        mut stream: TcpStream,
+        mut client_addr: SocketAddr,
+        mut class: TrafficClass,
+        mut context: ConnectionContext,
+    ) {
+        let started = Instant::now();
+        // 负载均衡转发的连接先读取PROXY协议头，之后的访问控制、亲和与日志都使用真实的客户端地址
+        if context.proxy_protocol {
+            match read_proxy_header(&mut stream).await {
+                Ok(Some(source)) => {
+                    debug!("PROXY协议头: {} 转发自 {}", source, client_addr);
+                    client_addr = source;
+                    class = context.classify(source);
+                    context.observers.connection = Arc::new(ConnectionInfo { client: source, class, ..(*context.observers.connection).clone() });
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("来自 {} 的连接: {}", client_addr, e);
+                    return;
+                }
+            }
+        }
+        context.observers.notify(|observer, connection| observer.on_open(connection));
+        let mut summary = ConnectionSummary {
+            client: client_addr.to_string(),
+            target: String::new(),
+            proxy: None,
+            class,
+            success: false,
+            bytes_up: 0,
+            bytes_down: 0,
+            duration_ms: 0,
+            error: None,
+        };
+        let id = context.observers.connection.id;
+        let mut closed = false;
+        let handled = Self::handle_connection(stream, client_addr, class, &context, &mut summary);
+        let result = match &context.registry {
+            Some(registry) => tokio::select! {
+                result = handled => result,
+                _ = registry.closed(id) => {
+                    closed = true;
+                    Ok(())
+                }
+            },
+            None => handled.await,
+        };
+        // 强制关闭时转发被中断，流量取登记表中累计的部分
+        if let Some(entry) = context.registry.as_ref().and_then(|registry| registry.get(id)).filter(|_| closed) {
+            info!("来自 {} 的连接已被强制关闭", client_addr);
+            summary.bytes_up = entry.bytes_up;
+            summary.bytes_down = entry.bytes_down;
+            summary.error = Some("强制关闭".to_string());
+        }
+        if let Err(e) = &result {
+            error!("处理连接出错: {}", e);
+            summary.error = Some(e.to_string());
+        }
+        // 只统计分配到上游代理的连接；目标为 host:port，UDP关联带 udp:// 前缀
+        if summary.proxy.is_some() {
+            if let Some((host, _)) = summary.target.trim_start_matches("udp://").rsplit_once(':') {
+                context.pool.traffic().record(client_addr.ip(), host, summary.bytes_up, summary.bytes_down);
+            }
+        }
+        // 配额按客户端收发的全部字节计算，直连的目标也计入
+        if !context.quota.is_unlimited() {
+            context.quotas.record(client_addr.ip(), summary.bytes_up + summary.bytes_down);
+        }
+        summary.duration_ms = started.elapsed().as_millis() as u64;
+        context.observers.notify(|observer, connection| observer.on_close(connection, &summary));
+        // 握手阶段就断开、没有请求目标的连接不记录
+        if summary.target.is_empty() {
+            return;
+        }
+        if let Some(connection_log) = &context.connection_log {
+            connection_log.record(&summary);
+        }
+        if let Some(event_log) = context.event_log {
+            event_log.record_connection(summary);
+        }
+    }
+
+    /// 处理客户端连接，把目标、代理与流量填入连接摘要；首字节为4时按SOCKS4/4a处理
     async fn handle_connection(
         stream: TcpStream, 
         client_addr: SocketAddr,
-        pool: Arc<Pool>
+        class: TrafficClass,
+        context: &ConnectionContext,
+        summary: &mut ConnectionSummary,
     ) -> Result<()> {
-        info!("接受来自 {} 的新连接", client_addr);
+        info!("接受来自 {} 的新连接 ({})", client_addr, class);
         
         // 改进错误处理，添加更多诊断信息
         let handle_err = |step: &str, e: anyhow::Error| -> Result<()> {
@@ -124,7 +817,14 @@ impl SocksServer {
             Err(anyhow!("{}: {}", step, e))
         };
         
+        // 不在访问控制允许范围内的客户端不做任何应答直接断开
+        if !context.acl.permits_client(client_addr.ip()) {
+            warn!("拒绝来自 {} 的连接（访问控制）", client_addr);
+            return Ok(());
+        }
+
         // 1. 认证方法协商
+        let local_ip = stream.local_addr()?.ip();
         let (mut inbound_reader, mut inbound_writer) = stream.into_split();
         
         // 读取客户端支持的认证方法
@@ -132,6 +832,9 @@ impl SocksServer {
         match inbound_reader.read_exact(&mut method_selection).await {
             Ok(_) => {
                 debug!("收到认证方法协商请求: {:x?}", method_selection);
+                if method_selection[0] == 0x04 { // SOCKS4/4a，第二个字节是命令
+                    return Self::handle_socks4(inbound_reader, inbound_writer, method_selection[1], client_addr, class, context, summary).await;
+                }
                 if method_selection[0] != 0x05 { // SOCKS5
                     let e = anyhow!("收到非SOCKS5请求: 版本={}", method_selection[0]);
                     return handle_err("协议版本检查", e);
@@ -148,9 +851,26 @@ impl SocksServer {
         inbound_reader.read_exact(&mut methods).await?;
         debug!("客户端支持的认证方法: {:x?}", methods);
 
-        // 回复使用无认证方法
-        debug!("回复客户端使用无认证方法");
-        inbound_writer.write_all(&[0x05, 0x00]).await?;
+        // 配置了账户时只接受用户名/密码认证，客户端不支持时回复没有可用的方法
+        if context.accounts.is_empty() {
+            debug!("回复客户端使用无认证方法");
+            inbound_writer.write_all(&[0x05, 0x00]).await?;
+        } else if methods.contains(&0x02) {
+            inbound_writer.write_all(&[0x05, 0x02]).await?;
+            match Self::authenticate(&mut inbound_reader, &context.accounts).await {
+                Ok(username) => {
+                    inbound_writer.write_all(&[0x01, 0x00]).await?;
+                    debug!("客户端 {} 以用户 {} 通过认证", client_addr, username);
+                }
+                Err(e) => {
+                    inbound_writer.write_all(&[0x01, 0x01]).await?;
+                    return handle_err("认证", e);
+                }
+            }
+        } else {
+            inbound_writer.write_all(&[0x05, 0xFF]).await?;
+            return handle_err("认证", anyhow!("客户端不支持用户名/密码认证"));
+        }
         inbound_writer.flush().await?;
         
         // 2. 读取请求
@@ -158,7 +878,8 @@ impl SocksServer {
         match inbound_reader.read_exact(&mut buf).await {
             Ok(_) => {
                 debug!("收到连接请求: {:x?}", buf);
-                if buf[0] != 0x05 || buf[1] != 0x01 {
+                if buf[0] != 0x05 || !matches!(buf[1], 0x01 | 0x03) {
+                    Version::Socks5.reject(&mut inbound_writer, REP_COMMAND_NOT_SUPPORTED).await;
                     let e = anyhow!("不支持的SOCKS5命令: VER={}, CMD={}", buf[0], buf[1]);
                     return handle_err("命令检查", e);
                 }
@@ -205,63 +926,625 @@ impl SocksServer {
                 debug!("目标地址类型: IPv6, 地址: {}", addr_str);
                 addr_str
             },
-            _ => return Err(anyhow::anyhow!("不支持的地址类型")),
+            _ => {
+                Version::Socks5.reject(&mut inbound_writer, REP_ADDRESS_NOT_SUPPORTED).await;
+                return Err(anyhow::anyhow!("不支持的地址类型"));
+            }
         };
         
         // 4. 读取端口
         let port = inbound_reader.read_u16().await?;
         debug!("目标端口: {}", port);
+        summary.target = format!("{}:{}", target_addr, port);
+        let _quota = context.admit_quota(&mut inbound_writer, Version::Socks5, client_addr.ip()).await?;
+        if buf[1] == 0x03 {
+            summary.target = format!("udp://{}", summary.target);
+            return Self::associate(inbound_reader, inbound_writer, local_ip, client_addr, class, context, summary).await;
+        }
         
-        // 5. 获取代理
-        let proxy = match pool.get_available() {
-            Some(p) => {
-                info!("找到可用代理: {}:{}", p.info.host, p.info.port);
-                p
-            },
+        Self::connect_and_relay(inbound_reader, inbound_writer, atyp, target_addr, port, Version::Socks5, client_addr, class, context, summary).await
+    }
+
+    /// 处理SOCKS4/4a连接：只支持CONNECT，目标转为SOCKS5请求发往上游代理
+    ///
+    /// SOCKS4没有密码认证，配置了客户端账户时一律拒绝。
+    async fn handle_socks4(
+        mut inbound_reader: OwnedReadHalf,
+        mut inbound_writer: OwnedWriteHalf,
+        command: u8,
+        client_addr: SocketAddr,
+        class: TrafficClass,
+        context: &ConnectionContext,
+        summary: &mut ConnectionSummary,
+    ) -> Result<()> {
+        let port = inbound_reader.read_u16().await?;
+        let mut ip = [0u8; 4];
+        inbound_reader.read_exact(&mut ip).await?;
+        let user_id = read_null_terminated(&mut inbound_reader).await?;
+        // SOCKS4a：地址为0.0.0.x（x不为0）时，用户ID之后跟着目标域名
+        let (atyp, target_addr) = match ip {
+            [0, 0, 0, last] if last != 0 => {
+                let domain = read_null_terminated(&mut inbound_reader).await?;
+                (0x03, String::from_utf8(domain)?)
+            }
+            _ => (0x01, Ipv4Addr::from(ip).to_string()),
+        };
+        debug!("收到SOCKS4请求: CMD={}, 目标={}:{}, 用户ID={}", command, target_addr, port, String::from_utf8_lossy(&user_id));
+        summary.target = format!("{}:{}", target_addr, port);
+
+        if command != 0x01 {
+            Version::Socks4.reject(&mut inbound_writer, REP_GENERAL_FAILURE).await;
+            return Err(anyhow!("不支持的SOCKS4命令: CMD={}", command));
+        }
+        if !context.accounts.is_empty() {
+            Version::Socks4.reject(&mut inbound_writer, REP_GENERAL_FAILURE).await;
+            return Err(anyhow!("认证: SOCKS4不支持用户名/密码认证"));
+        }
+        let _quota = context.admit_quota(&mut inbound_writer, Version::Socks4, client_addr.ip()).await?;
+        Self::connect_and_relay(inbound_reader, inbound_writer, atyp, target_addr, port, Version::Socks4, client_addr, class, context, summary).await
+    }
+
+    /// 选择代理连接目标，向客户端发送成功应答后双向转发数据
+    #[allow(clippy::too_many_arguments)]
+    async fn connect_and_relay(
+        inbound_reader: OwnedReadHalf,
+        mut inbound_writer: OwnedWriteHalf,
+        atyp: u8,
+        target_addr: String,
+        port: u16,
+        version: Version,
+        client_addr: SocketAddr,
+        class: TrafficClass,
+        context: &ConnectionContext,
+        summary: &mut ConnectionSummary,
+    ) -> Result<()> {
+        let pool = &context.pool;
+        let handle_err = |step: &str, e: anyhow::Error| -> Result<()> {
+            error!("{} {}失败: {} (来自: {})", version, step, e, client_addr);
+            Err(anyhow!("{}: {}", step, e))
+        };
+
+        // 5. 按流量类别获取代理，达到并发上限的代理会被跳过；访问控制或端口策略不允许的目标在选择代理之前拒绝
+        if !context.acl.permits_destination(&target_addr, port) {
+            version.reject(&mut inbound_writer, REP_NOT_ALLOWED).await;
+            return handle_err("访问控制", anyhow!("不允许访问 {}:{}", target_addr, port));
+        }
+        if !context.permits_port(client_addr, &target_addr, port, Transport::Tcp) {
+            version.reject(&mut inbound_writer, REP_NOT_ALLOWED).await;
+            return handle_err("端口策略", anyhow!("不允许访问端口 {}", port));
+        }
+        // 6. 通过上游代理连接目标地址，超时按连接失败处理；失败的代理被记录后换用其他代理重试，
+        //    目标拒绝连接时换代理也无济于事，直接应答客户端。命中直连列表的目标不选择代理
+        let mut tried: Vec<String> = Vec::new();
+        let mut last_error = None;
+        let direct = context.bypass.matches(&target_addr, port);
+        let route = loop {
+            if direct {
+                break None;
+            }
+            let Some((proxy, guard)) = context.acquire(class, client_addr.ip(), &target_addr, |p| !tried.contains(&p.id)).await else {
+                if last_error.is_none() && context.bypass.when_pool_empty {
+                    info!("没有可用的代理，直接连接 {}:{}", target_addr, port);
+                    break None;
+                }
+                let Some(e) = last_error else {
+                    // 添加更多日志以便调试
+                    let proxies = pool.get_all_proxies().await;
+                    error!("没有可用的代理，当前有 {} 个代理", proxies.len());
+
+                    for proxy in proxies {
+                        error!("代理 {}:{} 状态: {:?}, 延迟: {}ms, 活跃连接: {}",
+                                proxy.info.host, proxy.info.port,
+                                proxy.status, proxy.latency, proxy.active_connections());
+                    }
+
+                    version.reject(&mut inbound_writer, REP_GENERAL_FAILURE).await;
+                    return Err(anyhow::anyhow!("没有可用的代理"));
+                };
+                // 已经试过所有可用的代理，按最后一次失败应答
+                version.reject(&mut inbound_writer, reply_code(&e)).await;
+                return handle_err("上游代理连接", e);
+            };
+            // 开启候选竞速时再取一个候选代理，两者同时连接
+            let mut candidates = vec![(proxy, guard)];
+            if pool.options().race_candidates {
+                let first = candidates[0].0.id.clone();
+                if let Some(rival) = context.acquire(class, client_addr.ip(), &target_addr, |p| p.id != first && !tried.contains(&p.id)).await {
+                    candidates.push(rival);
+                }
+            }
+            for (proxy, _) in &candidates {
+                info!("使用代理 {}:{} 连接到 {}:{} (活跃连接: {})",
+                    proxy.info.host, proxy.info.port, target_addr, port, proxy.active_connections());
+                summary.proxy = Some(format!("{}:{}", proxy.info.host, proxy.info.port));
+                context.observers.notify(|observer, connection| observer.on_select_proxy(connection, &summary.target, Some(proxy)));
+                if let Some(mirror) = &context.mirror {
+                    mirror.observe(proxy);
+                }
+            }
+
+            let (connected, failures) = Self::race_upstream(context, candidates, atyp, &target_addr, port).await;
+            let mut refused = false;
+            for (proxy, e) in failures {
+                if e.is::<ChainError>() {
+                    // 代理链本身不通，换用池中其他代理也无济于事
+                    version.reject(&mut inbound_writer, REP_GENERAL_FAILURE).await;
+                    return handle_err("代理链", e);
+                }
+                Self::report_failure(pool, &proxy, &e).await;
+                tried.push(proxy.id.clone());
+                refused |= e.downcast_ref::<UpstreamReply>().is_some_and(|reply| reply.0 == REP_CONNECTION_REFUSED);
+                if connected.is_none() {
+                    warn!("经代理 {}:{} 连接 {}:{} 失败: {}", proxy.info.host, proxy.info.port, target_addr, port, e);
+                }
+                last_error = Some(e);
+            }
+            if let Some((proxy, guard, upstream, bound)) = connected {
+                pool.report_connection(&proxy.id, true).await;
+                if !context.sticky_target_ttl.is_zero() {
+                    context.sticky_targets.insert(&target_addr, &proxy.id, context.sticky_target_ttl);
+                }
+                summary.proxy = Some(format!("{}:{}", proxy.info.host, proxy.info.port));
+                break Some((proxy, guard, upstream, bound));
+            }
+            // 目标拒绝连接时换代理也无济于事，直接应答客户端
+            if refused || tried.len() > context.connect_retries {
+                let e = last_error.take().unwrap_or_else(|| anyhow!("连接被取消"));
+                version.reject(&mut inbound_writer, reply_code(&e)).await;
+                return handle_err("上游代理连接", e);
+            }
+            info!("换用其他代理重试连接 {}:{}", target_addr, port);
+        };
+        // 直连的流量不计入任何代理
+        let (usage, _conn_guard, upstream, bound) = match route {
+            Some((proxy, guard, upstream, bound)) => (Arc::clone(&proxy.usage), Some(guard), upstream, bound),
             None => {
-                // 添加更多日志以便调试
-                let proxies = pool.get_all_proxies();
-                error!("没有可用的代理，当前有 {} 个代理", proxies.len());
-                
-                for proxy in proxies {
-                    error!("代理 {}:{} 状态: {:?}, 延迟: {}ms", 
-                            proxy.info.host, proxy.info.port, 
-                            proxy.status, proxy.latency);
-                }
-                
-                return Err(anyhow::anyhow!("没有可用的代理"));
+                if direct {
+                    info!("{}:{} 命中直连列表，直接连接", target_addr, port);
+                }
+                context.observers.notify(|observer, connection| observer.on_select_proxy(connection, &summary.target, None));
+                match within(context.handshake_timeout, Self::connect_direct(&target_addr, port)).await {
+                    Ok((upstream, bound)) => (Arc::new(ProxyUsage::default()), None, upstream, bound),
+                    Err(e) => {
+                        version.reject(&mut inbound_writer, reply_code(&e)).await;
+                        return handle_err("直接连接", e);
+                    }
+                }
             }
         };
         
-        info!("使用代理 {}:{} 连接到 {}:{}", proxy.info.host, proxy.info.port, target_addr, port);
+        // 7. 发送成功响应给客户端，带上上游代理的绑定地址
+        let response = version.reply(REP_SUCCEEDED, bound);
+        debug!("向客户端发送连接成功响应: {:x?}", response);
+        inbound_writer.write_all(&response).await?;
+        summary.success = true;
         
-        // 6. 连接到目标地址（通过代理）
-        let proxy_addr = proxy.info.socket_addr()?;
-        debug!("连接到上游代理: {}", proxy_addr);
-        let mut upstream = TcpStream::connect(proxy_addr).await?;
+        // 8. 双向转发数据
+        let (upstream_reader, mut upstream_writer) = upstream.into_split();
+        let activity = Arc::new(Activity::new());
+        let mut inbound_reader = CountingReader::new(inbound_reader, &usage, Direction::Up, &activity, Some(&context.observers));
+        let mut upstream_reader = CountingReader::new(upstream_reader, &usage, Direction::Down, &activity, Some(&context.observers));
+        let relayed = relay_duplex(&mut inbound_reader, &mut inbound_writer, &mut upstream_reader, &mut upstream_writer, context.relay);
         
-        // 7. 与上游SOCKS5服务器进行握手
-        info!("向上游代理 {}:{} 发送握手请求", proxy.info.host, proxy.info.port);
-        upstream.write_all(&[0x05, 0x01, 0x00]).await?;
+        info!("开始双向转发数据");
+        tokio::select! {
+            res = relayed => {
+                match res {
+                    Ok((up, down)) => debug!("双向传输完成, 客户端 -> 代理 {} bytes, 代理 -> 客户端 {} bytes", up, down),
+                    Err(e) => error!("转发数据错误: {}", e),
+                }
+            },
+            reason = activity.expired(context.idle_timeout, context.max_lifetime) => {
+                info!("来自 {} 的连接{}，关闭转发", client_addr, reason);
+                summary.error = Some(reason.to_string());
+            }
+        }
+        summary.bytes_up = inbound_reader.total;
+        summary.bytes_down = upstream_reader.total;
+        
+        Ok(())
+    }
+
+    /// 处理UDP ASSOCIATE：经支持UDP的上游代理建立UDP中继，直到客户端或上游关闭控制连接
+    ///
+    /// 客户端的数据报原样转发给上游代理的中继端口，上游的应答原样转回客户端，
+    /// 两个方向都使用SOCKS5 UDP数据报头，这里只校验报头并丢弃分片。
+    /// 代理是否支持UDP在首次关联时得知，拒绝该命令的代理之后不再用于UDP。
+    async fn associate(
+        mut inbound_reader: OwnedReadHalf,
+        mut inbound_writer: OwnedWriteHalf,
+        local_ip: IpAddr,
+        client_addr: SocketAddr,
+        class: TrafficClass,
+        context: &ConnectionContext,
+        summary: &mut ConnectionSummary,
+    ) -> Result<()> {
+        let pool = &context.pool;
+        if !context.chain.is_empty() {
+            // UDP数据报无法经多跳TCP隧道转发
+            Version::Socks5.reject(&mut inbound_writer, REP_COMMAND_NOT_SUPPORTED).await;
+            return Err(anyhow!("配置了代理链时不支持UDP ASSOCIATE"));
+        }
+        let mut attempts = 0;
+        let (proxy, _conn_guard, mut upstream, relay_addr) = loop {
+            let Some((proxy, guard)) = context.affinity.acquire(pool, class, client_addr.ip(), |p| context.serves(p) && p.udp != Some(false)).await else {
+                // 试过的代理都不支持UDP时按命令不支持应答
+                let code = if attempts > 0 { REP_COMMAND_NOT_SUPPORTED } else { REP_GENERAL_FAILURE };
+                Version::Socks5.reject(&mut inbound_writer, code).await;
+                return Err(anyhow!("没有支持UDP的可用代理"));
+            };
+            attempts += 1;
+            match within(context.handshake_timeout, Self::associate_upstream(&proxy)).await {
+                Ok((upstream, relay_addr)) => {
+                    pool.set_udp_support(&proxy.id, true).await;
+                    pool.report_connection(&proxy.id, true).await;
+                    break (proxy, guard, upstream, relay_addr);
+                }
+                Err(e) if matches!(e.downcast_ref::<UpstreamReply>(), Some(UpstreamReply(0x07))) => {
+                    info!("代理 {}:{} 不支持UDP ASSOCIATE，之后不再用于UDP", proxy.info.host, proxy.info.port);
+                    pool.set_udp_support(&proxy.id, false).await;
+                    if attempts >= UDP_ASSOCIATE_ATTEMPTS {
+                        Version::Socks5.reject(&mut inbound_writer, REP_COMMAND_NOT_SUPPORTED).await;
+                        return Err(anyhow!("连续 {} 个代理不支持UDP", attempts));
+                    }
+                }
+                Err(e) => {
+                    Self::report_failure(pool, &proxy, &e).await;
+                    Version::Socks5.reject(&mut inbound_writer, reply_code(&e)).await;
+                    return Err(anyhow!("上游代理UDP关联失败: {}", e));
+                }
+            }
+        };
+        summary.proxy = Some(format!("{}:{}", proxy.info.host, proxy.info.port));
+        context.observers.notify(|observer, connection| observer.on_select_proxy(connection, &summary.target, Some(&proxy)));
+
+        // 面向客户端的端口绑定在客户端连入的地址上，面向上游的端口按中继地址的协议族绑定
+        let client_socket = UdpSocket::bind((local_ip, 0)).await?;
+        let unspecified = match relay_addr {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let upstream_socket = UdpSocket::bind((unspecified, 0)).await?;
+
+        let mut response = vec![0x05, 0x00, 0x00];
+        response.extend_from_slice(&encode_address(client_socket.local_addr()?));
+        inbound_writer.write_all(&response).await?;
+        summary.success = true;
+        info!("使用代理 {}:{} 转发UDP，上游中继: {}", proxy.info.host, proxy.info.port, relay_addr);
+
+        // 只接受来自控制连接同一地址的数据报，第一个数据报的来源端口即客户端端口
+        let mut client_peer: Option<SocketAddr> = None;
+        let mut client_buf = vec![0u8; UDP_BUFFER_SIZE];
+        let mut upstream_buf = vec![0u8; UDP_BUFFER_SIZE];
+        let mut inbound_probe = [0u8; 1];
+        let mut upstream_probe = [0u8; 1];
+        let activity = Activity::new();
+        loop {
+            tokio::select! {
+                received = client_socket.recv_from(&mut client_buf) => {
+                    let (n, from) = received?;
+                    if from.ip() != client_addr.ip() || client_peer.is_some_and(|peer| peer != from) {
+                        debug!("丢弃来自 {} 的UDP数据报", from);
+                        continue;
+                    }
+                    let Some((host, port, _)) = parse_udp_header(&client_buf[..n]) else {
+                        debug!("丢弃无效或分片的UDP数据报 (来自: {})", from);
+                        continue;
+                    };
+                    if !context.acl.permits_destination(&host, port) {
+                        debug!("丢弃发往 {}:{} 的UDP数据报（访问控制）", host, port);
+                        continue;
+                    }
+                    if !context.permits_port(client_addr, &host, port, Transport::Udp) {
+                        debug!("丢弃发往 {}:{} 的UDP数据报（端口策略）", host, port);
+                        continue;
+                    }
+                    debug!("UDP 客户端 -> {}:{}, {} bytes", host, port, n);
+                    client_peer = Some(from);
+                    if let Err(e) = upstream_socket.send_to(&client_buf[..n], relay_addr).await {
+                        debug!("向上游中继发送UDP数据报失败: {}", e);
+                        continue;
+                    }
+                    proxy.usage.record_up(n as u64);
+                    summary.bytes_up += n as u64;
+                    context.observers.notify(|observer, connection| observer.on_bytes(connection, n as u64, 0));
+                    activity.touch();
+                },
+                received = upstream_socket.recv_from(&mut upstream_buf) => {
+                    let (n, from) = received?;
+                    let Some(peer) = client_peer.filter(|_| from == relay_addr) else {
+                        debug!("丢弃来自 {} 的UDP数据报", from);
+                        continue;
+                    };
+                    if let Err(e) = client_socket.send_to(&upstream_buf[..n], peer).await {
+                        debug!("向客户端发送UDP数据报失败: {}", e);
+                        continue;
+                    }
+                    proxy.usage.record_down(n as u64);
+                    summary.bytes_down += n as u64;
+                    context.observers.notify(|observer, connection| observer.on_bytes(connection, 0, n as u64));
+                    activity.touch();
+                },
+                _ = inbound_reader.read(&mut inbound_probe) => {
+                    debug!("客户端关闭了UDP控制连接");
+                    break;
+                },
+                _ = upstream.read(&mut upstream_probe) => {
+                    debug!("上游代理关闭了UDP关联");
+                    break;
+                },
+                reason = activity.expired(context.idle_timeout, context.max_lifetime) => {
+                    info!("来自 {} 的UDP关联{}，关闭转发", client_addr, reason);
+                    summary.error = Some(reason.to_string());
+                    break;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// 不经代理直接连接目标，返回连接与本机使用的地址
+    async fn connect_direct(host: &str, port: u16) -> Result<(TcpStream, SocketAddr)> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let stream = TcpStream::connect((host, port)).await?;
+        let bound = stream.local_addr()?;
+        Ok((stream, bound))
+    }
+
+    /// 向上游代理发起UDP ASSOCIATE，返回控制连接与上游的UDP中继地址
+    pub(crate) async fn associate_upstream(proxy: &Proxy) -> Result<(TcpStream, SocketAddr)> {
+        let (upstream, host, port) = Self::upstream_request(&[], proxy, 0x03, 0x01, "0.0.0.0", 0).await?;
+        // 上游返回未指定地址时，中继与代理本身在同一主机上
+        let relay_addr = match host.parse::<IpAddr>() {
+            Ok(ip) if ip.is_unspecified() => SocketAddr::new(proxy.info.socket_addr()?.ip(), port),
+            Ok(ip) => SocketAddr::new(ip, port),
+            Err(_) => tokio::net::lookup_host((host.as_str(), port)).await?
+                .next()
+                .ok_or_else(|| anyhow!("无法解析上游中继地址: {}", host))?,
+        };
+        Ok((upstream, relay_addr))
+    }
+
+    /// 按上游连接失败的原因处理代理：永久性错误直接加入永久黑名单，不再参与重试
+    pub(crate) async fn report_failure(pool: &PoolHandle, proxy: &Proxy, e: &anyhow::Error) {
+        if let Some(threat) = e.downcast_ref::<Threat>() {
+            pool.report_threat(&proxy.id, threat.clone()).await;
+        } else if let Some(reason) = e.downcast_ref::<BlockReason>() {
+            pool.block(&proxy.id, *reason).await;
+        } else {
+            pool.report_connection(&proxy.id, false).await;
+        }
+    }
+
+    /// 读取用户名/密码子协商请求（RFC 1929）并校验，成功时返回用户名
+    pub(crate) async fn authenticate<R: AsyncRead + Unpin>(reader: &mut R, accounts: &[SocksAccount]) -> Result<String> {
+        let version = reader.read_u8().await?;
+        if version != 0x01 {
+            return Err(anyhow!("不支持的认证子协商版本: {}", version));
+        }
+        let len = reader.read_u8().await? as usize;
+        let mut username = vec![0u8; len];
+        reader.read_exact(&mut username).await?;
+        let len = reader.read_u8().await? as usize;
+        let mut password = vec![0u8; len];
+        reader.read_exact(&mut password).await?;
+
+        // 逐个比较全部账户，耗时不随匹配位置变化
+        let matched = accounts.iter().fold(false, |matched, account| {
+            matched | (constant_time_eq(account.username.as_bytes(), &username)
+                & constant_time_eq(account.password.as_bytes(), &password))
+        });
+        let username = String::from_utf8_lossy(&username).into_owned();
+        if !matched {
+            return Err(anyhow!("用户名或密码错误: {}", username));
+        }
+        Ok(username)
+    }
+
+    /// 同时经各候选代理连接目标，返回最先完成握手的代理与连接，其余尝试随即取消
+    ///
+    /// 先完成的尝试失败时继续等待其他候选；返回值中的失败按完成顺序排列，被取消的候选既不算成功也不算失败。
+    async fn race_upstream(
+        context: &ConnectionContext,
+        candidates: Vec<(Proxy, ConnectionGuard)>,
+        atyp: u8,
+        target_addr: &str,
+        port: u16,
+    ) -> (Option<(Proxy, ConnectionGuard, TcpStream, SocketAddr)>, Vec<(Proxy, anyhow::Error)>) {
+        let mut attempts = tokio::task::JoinSet::new();
+        for (index, (proxy, _)) in candidates.iter().enumerate() {
+            let chain = Arc::clone(&context.chain);
+            let warm = context.warm.clone();
+            let proxy = proxy.clone();
+            let target_addr = target_addr.to_string();
+            let handshake_timeout = context.handshake_timeout;
+            attempts.spawn(async move {
+                (index, within(handshake_timeout, Self::connect_warm_or_new(&warm, &chain, &proxy, atyp, &target_addr, port)).await)
+            });
+        }
+        let mut candidates: Vec<_> = candidates.into_iter().map(Some).collect();
+        let mut failures = Vec::new();
+        while let Some(joined) = attempts.join_next().await {
+            let Ok((index, result)) = joined else { continue };
+            let Some((proxy, guard)) = candidates[index].take() else { continue };
+            match result {
+                Ok((upstream, bound)) => {
+                    if candidates.iter().any(Option::is_some) {
+                        debug!("代理 {}:{} 先完成握手，取消其他候选", proxy.info.host, proxy.info.port);
+                    }
+                    return (Some((proxy, guard, upstream, bound)), failures);
+                }
+                Err(e) => failures.push((proxy, e)),
+            }
+        }
+        (None, failures)
+    }
+
+    /// 连接上游代理并完成到目标地址的SOCKS5 CONNECT握手，返回连接与绑定地址
+    ///
+    /// 上游返回域名或未指定地址作为绑定地址时，退回到本机连接上游所用的地址。
+    pub(crate) async fn connect_upstream(
+        chain: &[ProxyConfig],
+        proxy: &Proxy,
+        atyp: u8,
+        target_addr: &str,
+        port: u16,
+    ) -> Result<(TcpStream, SocketAddr)> {
+        let (upstream, host, bound_port) = Self::upstream_request(chain, proxy, 0x01, atyp, target_addr, port).await?;
+        let bound = bound_address(&upstream, &host, bound_port)?;
+        Ok((upstream, bound))
+    }
+
+    /// 优先在预热的连接上发送CONNECT请求，没有预热连接或它在请求中断开时新建连接
+    async fn connect_warm_or_new(
+        warm: &WarmPool,
+        chain: &[ProxyConfig],
+        proxy: &Proxy,
+        atyp: u8,
+        target_addr: &str,
+        port: u16,
+    ) -> Result<(TcpStream, SocketAddr)> {
+        // 重新加载后才配置的代理链同样不能使用预热的连接
+        let warmed = match chain.is_empty() {
+            true => warm.take(&proxy.id),
+            false => None,
+        };
+        if let Some(mut upstream) = warmed {
+            debug!("使用到代理 {}:{} 的预热连接", proxy.info.host, proxy.info.port);
+            match Self::socks5_command(&mut upstream, 0x01, atyp, target_addr, port).await {
+                Ok((host, bound_port)) => {
+                    let bound = bound_address(&upstream, &host, bound_port)?;
+                    return Ok((upstream, bound));
+                }
+                // 代理给出了应答，新建连接也会得到同样的结果
+                Err(e) if e.is::<UpstreamReply>() || e.is::<BlockReason>() => return Err(e),
+                Err(e) => debug!("预热的连接已失效，新建连接: {}", e),
+            }
+        }
+        Self::connect_upstream(chain, proxy, atyp, target_addr, port).await
+    }
+
+    /// 连接上游代理（经过代理链时逐跳建立隧道）并发送一个SOCKS5请求，返回连接与应答中的绑定地址和端口
+    async fn upstream_request(
+        chain: &[ProxyConfig],
+        proxy: &Proxy,
+        command: u8,
+        atyp: u8,
+        target_addr: &str,
+        port: u16,
+    ) -> Result<(TcpStream, String, u16)> {
+        let mut upstream = match chain.is_empty() {
+            true => {
+                let proxy_addr = proxy.info.socket_addr()?;
+                debug!("连接到上游代理: {}", proxy_addr);
+                TcpStream::connect(proxy_addr).await?
+            }
+            false => Self::open_chain(chain, &proxy.info.host, proxy.info.port).await?,
+        };
+        let label = format!("{}:{}", proxy.info.host, proxy.info.port);
+        let (bound, bound_port) = Self::socks5_request(&mut upstream, &label, credentials(&proxy.info.username, &proxy.info.password), command, atyp, target_addr, port).await?;
+        Ok((upstream, bound, bound_port))
+    }
+
+    /// 依次经过代理链的每一跳建立到 `host:port` 的隧道
+    ///
+    /// 链上某一跳本身出错时返回 `ChainError`，与所选代理无关；最后一跳无法连到所选代理时按所选代理连接失败处理。
+    async fn open_chain(chain: &[ProxyConfig], host: &str, port: u16) -> Result<TcpStream> {
+        let hop_error = |index: usize, e: anyhow::Error| ChainError { hop: index + 1, proxy: format!("{}:{}", chain[index].host, chain[index].port), reason: e.to_string() };
+        let first = &chain[0];
+        debug!("经代理链连接，第一跳: {}:{}", first.host, first.port);
+        let mut stream = TcpStream::connect((first.host.as_str(), first.port)).await
+            .map_err(|e| hop_error(0, e.into()))?;
+        for (index, hop) in chain.iter().enumerate() {
+            let (next_host, next_port) = chain.get(index + 1).map_or((host, port), |next| (next.host.as_str(), next.port));
+            let label = format!("{}:{}", hop.host, hop.port);
+            let next_host = next_host.trim_start_matches('[').trim_end_matches(']');
+            let requested = Self::socks5_request(&mut stream, &label, credentials(&hop.username, &hop.password), 0x01, address_type(next_host), next_host, next_port).await;
+            match requested {
+                Err(e) if index + 1 == chain.len() && e.downcast_ref::<UpstreamReply>().is_some() => {
+                    return Err(anyhow!("代理链最后一跳 {} 无法连接所选代理: {}", label, e));
+                }
+                Err(e) => return Err(hop_error(index, e).into()),
+                Ok(_) => {}
+            }
+        }
+        Ok(stream)
+    }
+
+    /// 在已建立的连接上与SOCKS5代理握手并发送一个请求，返回应答中的绑定地址和端口
+    async fn socks5_request(
+        upstream: &mut TcpStream,
+        label: &str,
+        credentials: Option<(&str, &str)>,
+        command: u8,
+        atyp: u8,
+        target_addr: &str,
+        port: u16,
+    ) -> Result<(String, u16)> {
+        Self::socks5_greet(upstream, label, credentials).await?;
+        Self::socks5_command(upstream, command, atyp, target_addr, port).await
+    }
+
+    /// 与SOCKS5代理协商认证方法（有凭据时同时提供用户名/密码认证），完成后连接等待请求
+    pub(crate) async fn socks5_greet(upstream: &mut TcpStream, label: &str, credentials: Option<(&str, &str)>) -> Result<()> {
+        // 与上游SOCKS5服务器进行握手
+        info!("向上游代理 {} 发送握手请求", label);
+        match credentials {
+            Some(_) => upstream.write_all(&[0x05, 0x02, 0x00, 0x02]).await?,
+            None => upstream.write_all(&[0x05, 0x01, 0x00]).await?,
+        }
         let mut response = [0u8; 2];
         match upstream.read_exact(&mut response).await {
             Ok(_) => {
                 debug!("收到上游代理握手响应: {:x?}", response);
-                if response[0] != 0x05 || response[1] != 0x00 {
-                    let e = anyhow!("上游代理握手失败: VER={}, METHOD={}", response[0], response[1]);
-                    return handle_err("上游代理握手", e);
+                if response[0] != 0x05 {
+                    return Err(BlockReason::Tampering.into());
+                }
+                if matches!(response[1], 0x02 | 0xFF) && credentials.is_none() {
+                    return Err(BlockReason::AuthRequired.into());
+                }
+                if let (0x02, Some((username, password))) = (response[1], credentials) {
+                    Self::socks5_login(upstream, username, password).await?;
+                } else if response[1] != 0x00 {
+                    return Err(anyhow!("上游代理握手失败: VER={}, METHOD={}", response[0], response[1]));
+                }
+                // 正常的代理在收到连接请求之前不会再发送任何数据
+                if upstream.try_read(&mut [0u8; 1]).is_ok_and(|n| n > 0) {
+                    return Err(Threat::UnsolicitedData.into());
                 }
                 info!("上游代理握手成功");
             }
             Err(e) => {
-                let e = anyhow!("读取上游代理握手响应失败: {}", e);
-                return handle_err("读取上游代理握手响应", e);
+                return Err(anyhow!("读取上游代理握手响应失败: {}", e));
             }
         }
-        
-        // 8. 发送连接请求到上游代理
+        Ok(())
+    }
+
+    /// 向上游代理发送用户名/密码子协商（RFC 1929）
+    async fn socks5_login(upstream: &mut TcpStream, username: &str, password: &str) -> Result<()> {
+        if username.len() > 255 || password.len() > 255 {
+            return Err(anyhow!("上游代理的用户名或密码过长"));
+        }
+        let mut request = vec![0x01, username.len() as u8];
+        request.extend_from_slice(username.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        upstream.write_all(&request).await?;
+        let mut response = [0u8; 2];
+        upstream.read_exact(&mut response).await
+            .map_err(|e| anyhow!("读取上游代理认证响应失败: {}", e))?;
+        if response[1] != 0x00 {
+            return Err(anyhow!("上游代理拒绝了用户名/密码认证: STATUS={}", response[1]));
+        }
+        Ok(())
+    }
+
+    /// 在已完成握手的连接上发送一个请求，返回应答中的绑定地址和端口
+    async fn socks5_command(upstream: &mut TcpStream, command: u8, atyp: u8, target_addr: &str, port: u16) -> Result<(String, u16)> {
+        // 发送请求
         let mut request = Vec::new();
-        request.extend_from_slice(&[0x05, 0x01, 0x00]); // VER, CMD, RSV
+        request.extend_from_slice(&[0x05, command, 0x00]); // VER, CMD, RSV
         
         match atyp {
             0x01 => { // IPv4
@@ -292,88 +1575,511 @@ impl SocksServer {
         info!("向上游代理发送连接请求: 目标={}:{}", target_addr, port);
         upstream.write_all(&request).await?;
         
-        // 9. 读取上游代理响应
+        // 读取上游代理响应
         let mut response = [0u8; 4];
         match upstream.read_exact(&mut response).await {
             Ok(_) => {
                 debug!("收到上游代理连接目标响应: {:x?}", response);
+                if response[0] != 0x05 {
+                    return Err(BlockReason::Tampering.into());
+                }
                 if response[1] != 0x00 {
-                    let e = anyhow!("上游代理连接目标失败: {}", response[1]);
-                    return handle_err("上游代理连接目标", e);
+                    return Err(UpstreamReply(response[1]).into());
                 }
                 info!("上游代理连接目标成功");
             }
             Err(e) => {
-                let e = anyhow!("读取上游代理连接目标响应失败: {}", e);
-                return handle_err("读取上游代理连接目标响应", e);
+                return Err(anyhow!("读取上游代理连接目标响应失败: {}", e));
             }
         }
         
-        // 10. 跳过绑定地址和端口
-        match response[3] {
+        // 读取绑定地址和端口
+        let bound = match response[3] {
             0x01 => { // IPv4
                 let mut addr = [0u8; 4];
                 upstream.read_exact(&mut addr).await?;
-                let ipv4 = Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]);
-                debug!("上游代理返回的绑定地址: IPv4={:?}", ipv4);
+                Ipv4Addr::from(addr).to_string()
             },
             0x03 => { // Domain
                 let len = upstream.read_u8().await?;
                 let mut domain = vec![0u8; len as usize];
                 upstream.read_exact(&mut domain).await?;
-                debug!("上游代理返回的绑定地址: Domain={:?}", String::from_utf8(domain.clone()));
+                String::from_utf8(domain)?
             },
             0x04 => { // IPv6
                 let mut addr = [0u8; 16];
                 upstream.read_exact(&mut addr).await?;
-                let ipv6 = Ipv6Addr::new(
-                    ((addr[0] as u16) << 8) | (addr[1] as u16),
-                    ((addr[2] as u16) << 8) | (addr[3] as u16),
-                    ((addr[4] as u16) << 8) | (addr[5] as u16),
-                    ((addr[6] as u16) << 8) | (addr[7] as u16),
-                    ((addr[8] as u16) << 8) | (addr[9] as u16),
-                    ((addr[10] as u16) << 8) | (addr[11] as u16),
-                    ((addr[12] as u16) << 8) | (addr[13] as u16),
-                    ((addr[14] as u16) << 8) | (addr[15] as u16),
-                );
-                debug!("上游代理返回的绑定地址: IPv6={:?}", ipv6);
+                Ipv6Addr::from(addr).to_string()
             },
             _ => return Err(anyhow::anyhow!("上游代理返回了不支持的地址类型")),
-        }
-        let mut port = [0u8; 2];
-        upstream.read_exact(&mut port).await?;
-        debug!("上游代理返回的绑定端口: {:?}", port);
-        
-        // 11. 发送成功响应给客户端
-        let response = [
-            0x05, 0x00, 0x00, 0x01,
-            0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00,
-        ];
-        debug!("向客户端发送连接成功响应: {:x?}", response);
-        inbound_writer.write_all(&response).await?;
-        
-        // 12. 双向转发数据
-        let (mut upstream_reader, mut upstream_writer) = upstream.into_split();
-        let client_to_proxy = tokio::io::copy(&mut inbound_reader, &mut upstream_writer);
-        let proxy_to_client = tokio::io::copy(&mut upstream_reader, &mut inbound_writer);
+        };
+        let bound_port = upstream.read_u16().await?;
+        debug!("上游代理返回的绑定地址: {}:{}", bound, bound_port);
         
-        info!("开始双向转发数据");
-        tokio::select! {
-            res = client_to_proxy => {
-                match res {
-                    Ok(bytes) => debug!("客户端 -> 代理 传输完成, {} bytes", bytes),
-                    Err(e) => error!("客户端到代理传输错误: {}", e),
+        Ok((bound, bound_port))
+    }
+}
+
+/// 创建监听套接字，`only_v6` 为false时IPv6未指定地址同时接受IPv4连接
+fn bind_listener(addr: SocketAddr, only_v6: bool) -> std::io::Result<TcpListener> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(only_v6)?;
+    }
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// 接受新连接前的准入控制：并发客户端上限与接受速率
+struct Admission {
+    clients: Option<Arc<Semaphore>>,
+    limit: usize,
+    rate: Option<AcceptRate>,
+}
+
+impl Admission {
+    fn new(config: &SocksServerConfig) -> Self {
+        Self {
+            clients: (config.max_clients > 0).then(|| Arc::new(Semaphore::new(config.max_clients))),
+            limit: config.max_clients,
+            rate: (config.accepts_per_second > 0).then(|| AcceptRate::new(config.accepts_per_second)),
+        }
+    }
+
+    /// 等到有空闲名额与速率配额，返回的名额在连接结束时释放
+    async fn admit(&mut self) -> Option<OwnedSemaphorePermit> {
+        let permit = match &self.clients {
+            Some(clients) => {
+                if clients.available_permits() == 0 {
+                    debug!("已达到最大客户端数 {}，暂停接受新连接", self.limit);
                 }
+                Some(Arc::clone(clients).acquire_owned().await.expect("客户端名额信号量不会被关闭"))
+            }
+            None => None,
+        };
+        if let Some(rate) = &mut self.rate {
+            rate.wait().await;
+        }
+        permit
+    }
+
+    /// 按新的设置调整上限，已有连接占用的名额仍然计入
+    ///
+    /// 调低上限时先收回空闲名额，不足的部分等已有连接结束后收回；从不限制改为限制时已有连接不计入。
+    fn resize(&mut self, config: &SocksServerConfig) {
+        if self.rate.as_ref().map_or(0, |rate| rate.rate as u32) != config.accepts_per_second {
+            self.rate = (config.accepts_per_second > 0).then(|| AcceptRate::new(config.accepts_per_second));
+        }
+        let limit = config.max_clients;
+        match &self.clients {
+            Some(clients) if limit > 0 => {
+                if limit > self.limit {
+                    clients.add_permits(limit - self.limit);
+                } else if limit < self.limit {
+                    let excess = self.limit - limit;
+                    let owed = excess - clients.forget_permits(excess);
+                    if owed > 0 {
+                        let clients = Arc::clone(clients);
+                        tokio::spawn(async move {
+                            if let Ok(permits) = clients.acquire_many_owned(owed as u32).await {
+                                permits.forget();
+                            }
+                        });
+                    }
+                }
+            }
+            _ => self.clients = (limit > 0).then(|| Arc::new(Semaphore::new(limit))),
+        }
+        self.limit = limit;
+    }
+}
+
+/// 等待下一次重新加载的设置，没有设置通道或发送端已关闭时一直等待
+async fn reloaded(reload: &mut Option<watch::Receiver<SocksServerConfig>>) -> SocksServerConfig {
+    loop {
+        match reload {
+            Some(rx) => match rx.changed().await {
+                Ok(()) => return rx.borrow_and_update().clone(),
+                Err(_) => *reload = None,
             },
-            res = proxy_to_client => {
-                match res {
-                    Ok(bytes) => debug!("代理 -> 客户端 传输完成, {} bytes", bytes),
-                    Err(e) => error!("代理到客户端传输错误: {}", e),
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// 接受新连接的令牌桶，最多积攒一秒的配额
+struct AcceptRate {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl AcceptRate {
+    fn new(per_second: u32) -> Self {
+        Self { rate: per_second as f64, tokens: per_second as f64, last: Instant::now() }
+    }
+
+    async fn wait(&mut self) {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * self.rate).min(self.rate);
+        self.last = now;
+        if self.tokens < 1.0 {
+            tokio::time::sleep(Duration::from_secs_f64((1.0 - self.tokens) / self.rate)).await;
+            self.tokens = 1.0;
+            self.last = Instant::now();
+        }
+        self.tokens -= 1.0;
+    }
+}
+
+/// 等待PROXY协议头的最长时间
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 读取并消耗连接开头的PROXY协议头，返回其中的客户端地址；负载均衡的健康检查等没有地址时为None
+///
+/// 先窥探数据，确认协议头完整后只读走协议头本身，之后的数据留给SOCKS握手。
+async fn read_proxy_header(stream: &mut TcpStream) -> Result<Option<SocketAddr>> {
+    let deadline = tokio::time::Instant::now() + PROXY_HEADER_TIMEOUT;
+    let mut buf = vec![0u8; proxy_protocol::MAX_V1_LENGTH];
+    loop {
+        let peeked = tokio::time::timeout_at(deadline, stream.peek(&mut buf)).await
+            .map_err(|_| anyhow!("等待PROXY协议头超时"))??;
+        if peeked == 0 {
+            return Err(anyhow!("连接在PROXY协议头之前关闭"));
+        }
+        match proxy_protocol::parse(&buf[..peeked])? {
+            proxy_protocol::Parsed::Complete(header) => {
+                stream.read_exact(&mut buf[..header.length]).await?;
+                return Ok(header.source);
+            }
+            proxy_protocol::Parsed::Incomplete(needed) if needed > buf.len() => buf.resize(needed, 0),
+            proxy_protocol::Parsed::Incomplete(_) => {
+                if tokio::time::Instant::now() >= deadline {
+                    return Err(anyhow!("等待PROXY协议头超时"));
                 }
+                // 窥探不会消耗数据，其余部分尚未到达时稍等再看
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    }
+}
+
+/// 通过准入控制后从任一监听端口接受连接
+async fn next_client(
+    listeners: &[TcpListener],
+    admission: &mut Admission,
+) -> std::io::Result<(TcpStream, SocketAddr, Option<OwnedSemaphorePermit>)> {
+    let permit = admission.admit().await;
+    let (stream, client_addr) = accept_any(listeners).await?;
+    Ok((stream, client_addr, permit))
+}
+
+/// 从任一监听端口接受连接
+async fn accept_any(listeners: &[TcpListener]) -> std::io::Result<(TcpStream, SocketAddr)> {
+    std::future::poll_fn(|cx| {
+        for listener in listeners {
+            if let Poll::Ready(result) = listener.poll_accept(cx) {
+                return Poll::Ready(result);
+            }
+        }
+        Poll::Pending
+    }).await
+}
+
+/// 客户端使用的协议版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Version {
+    Socks4,
+    Socks5,
+}
+
+impl Version {
+    /// 按SOCKS5应答码与绑定地址生成应答；SOCKS4只区分成功与拒绝，且只能携带IPv4地址
+    fn reply(self, code: u8, bound: SocketAddr) -> Vec<u8> {
+        match self {
+            Version::Socks4 => {
+                let mut reply = vec![0x00, if code == REP_SUCCEEDED { 0x5A } else { 0x5B }];
+                reply.extend_from_slice(&bound.port().to_be_bytes());
+                match bound.ip() {
+                    IpAddr::V4(ip) => reply.extend_from_slice(&ip.octets()),
+                    IpAddr::V6(_) => reply.extend_from_slice(&[0; 4]),
+                }
+                reply
+            }
+            Version::Socks5 => {
+                let mut reply = vec![0x05, code, 0x00];
+                reply.extend_from_slice(&encode_address(bound));
+                reply
             }
         }
-        
-        Ok(())
     }
-}
\ No newline at end of file
+
+    /// 在关闭连接前把失败原因告知客户端，客户端已断开时忽略写入错误
+    async fn reject(self, writer: &mut OwnedWriteHalf, code: u8) {
+        let unbound = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+        let _ = writer.write_all(&self.reply(code, unbound)).await;
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Version::Socks4 => "SOCKS4",
+            Version::Socks5 => "SOCKS5",
+        })
+    }
+}
+
+/// 读取以NUL结尾的字段（SOCKS4的用户ID与4a的域名），不含结尾的NUL
+async fn read_null_terminated<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut field = Vec::new();
+    loop {
+        match reader.read_u8().await? {
+            0 => return Ok(field),
+            _ if field.len() >= 255 => return Err(anyhow!("SOCKS4字段超过255字节")),
+            byte => field.push(byte),
+        }
+    }
+}
+
+// SOCKS5应答码（RFC 1928 第6节）
+const REP_SUCCEEDED: u8 = 0x00;
+const REP_GENERAL_FAILURE: u8 = 0x01;
+const REP_NOT_ALLOWED: u8 = 0x02;
+const REP_NETWORK_UNREACHABLE: u8 = 0x03;
+const REP_HOST_UNREACHABLE: u8 = 0x04;
+pub(crate) const REP_CONNECTION_REFUSED: u8 = 0x05;
+const REP_TTL_EXPIRED: u8 = 0x06;
+const REP_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const REP_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+/// 上游失败对应的应答码：上游代理的应答码原样转给客户端，网络错误按错误类型映射，握手超时视为TTL过期
+fn reply_code(e: &anyhow::Error) -> u8 {
+    if let Some(UpstreamReply(code)) = e.downcast_ref::<UpstreamReply>() {
+        if (REP_GENERAL_FAILURE..=REP_ADDRESS_NOT_SUPPORTED).contains(code) {
+            return *code;
+        }
+    }
+    if e.is::<tokio::time::error::Elapsed>() {
+        return REP_TTL_EXPIRED;
+    }
+    match e.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+        Some(std::io::ErrorKind::ConnectionRefused) => REP_CONNECTION_REFUSED,
+        Some(std::io::ErrorKind::NetworkUnreachable) => REP_NETWORK_UNREACHABLE,
+        Some(std::io::ErrorKind::HostUnreachable) => REP_HOST_UNREACHABLE,
+        Some(std::io::ErrorKind::TimedOut) => REP_TTL_EXPIRED,
+        _ => REP_GENERAL_FAILURE,
+    }
+}
+
+/// 上游代理拒绝请求时的应答码（REP）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UpstreamReply(pub u8);
+
+impl fmt::Display for UpstreamReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "上游代理拒绝请求: REP={}", self.0)
+    }
+}
+
+impl std::error::Error for UpstreamReply {}
+
+/// 代理链中某一跳失败，与所选的代理无关
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ChainError {
+    /// 失败的跳，从1开始
+    pub hop: usize,
+    pub proxy: String,
+    pub reason: String,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "代理链第 {} 跳 {} 失败: {}", self.hop, self.proxy, self.reason)
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+/// 目标地址对应的SOCKS5地址类型
+pub(crate) fn address_type(host: &str) -> u8 {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => 0x01,
+        Ok(IpAddr::V6(_)) => 0x04,
+        Err(_) => 0x03,
+    }
+}
+
+/// 选择代理时最多尝试的不支持UDP的代理数
+const UDP_ASSOCIATE_ATTEMPTS: usize = 3;
+
+/// UDP数据报的最大长度
+const UDP_BUFFER_SIZE: usize = 65536;
+
+/// 上游应答中的绑定地址，为域名或未指定地址时退回到本机连接上游所用的地址
+fn bound_address(upstream: &TcpStream, host: &str, port: u16) -> Result<SocketAddr> {
+    match host.parse::<IpAddr>() {
+        Ok(ip) if !ip.is_unspecified() => Ok(SocketAddr::new(ip, port)),
+        _ => Ok(upstream.local_addr()?),
+    }
+}
+
+/// 在限定时间内完成握手，为0时不限制
+pub(crate) async fn within<T>(limit: Duration, future: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+    if limit.is_zero() {
+        return future.await;
+    }
+    tokio::time::timeout(limit, future).await
+        .unwrap_or_else(|elapsed| Err(anyhow::Error::new(elapsed).context(format!("握手超过 {}ms", limit.as_millis()))))
+}
+
+/// 编码SOCKS5地址字段：ATYP、地址与端口
+pub(crate) fn encode_address(addr: SocketAddr) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(19);
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            encoded.push(0x01);
+            encoded.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            encoded.push(0x04);
+            encoded.extend_from_slice(&ip.octets());
+        }
+    }
+    encoded.extend_from_slice(&addr.port().to_be_bytes());
+    encoded
+}
+
+/// 解析SOCKS5 UDP数据报头，返回目标地址、端口与载荷的起始位置
+///
+/// 不支持分片，FRAG不为0或报头不完整时返回None。
+pub(crate) fn parse_udp_header(datagram: &[u8]) -> Option<(String, u16, usize)> {
+    let (&[0, 0, 0, atyp], rest) = datagram.split_first_chunk::<4>()? else {
+        return None;
+    };
+    let (host, len) = match atyp {
+        0x01 => (Ipv4Addr::from(<[u8; 4]>::try_from(rest.get(..4)?).ok()?).to_string(), 4),
+        0x03 => {
+            let len = *rest.first()? as usize;
+            (String::from_utf8(rest.get(1..1 + len)?.to_vec()).ok()?, 1 + len)
+        }
+        0x04 => (Ipv6Addr::from(<[u8; 16]>::try_from(rest.get(..16)?).ok()?).to_string(), 16),
+        _ => return None,
+    };
+    let port = u16::from_be_bytes(rest.get(len..len + 2)?.try_into().ok()?);
+    Some((host, port, 4 + len + 2))
+}
+
+/// 代理配置中的用户名和密码，没有用户名时为None
+pub(crate) fn credentials<'a>(username: &'a Option<String>, password: &'a Option<String>) -> Option<(&'a str, &'a str)> {
+    username.as_deref().map(|username| (username, password.as_deref().unwrap_or_default()))
+}
+
+/// 连接最近一次收发数据的时间，转发的两个方向共用
+pub(crate) struct Activity {
+    started: Instant,
+    /// 最近一次活动距 `started` 的毫秒数
+    last: AtomicU64,
+}
+
+impl Activity {
+    pub(crate) fn new() -> Self {
+        Self { started: Instant::now(), last: AtomicU64::new(0) }
+    }
+
+    fn touch(&self) {
+        self.last.store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// 等到连接空闲超过 `idle_timeout` 或存活超过 `max_lifetime`，返回关闭原因；两者都为0时永不返回
+    pub(crate) async fn expired(&self, idle_timeout: Duration, max_lifetime: Duration) -> &'static str {
+        loop {
+            let now = Instant::now();
+            let lifetime_deadline = (!max_lifetime.is_zero()).then(|| self.started + max_lifetime);
+            let idle_deadline = (!idle_timeout.is_zero())
+                .then(|| self.started + Duration::from_millis(self.last.load(Ordering::Relaxed)) + idle_timeout);
+            if lifetime_deadline.is_some_and(|deadline| now >= deadline) {
+                return "超过最长存活时间";
+            }
+            if idle_deadline.is_some_and(|deadline| now >= deadline) {
+                return "空闲超时";
+            }
+            match lifetime_deadline.into_iter().chain(idle_deadline).min() {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        }
+    }
+}
+
+/// 转发方向
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Direction {
+    /// 客户端发往目标
+    Up,
+    /// 目标发往客户端
+    Down,
+}
+
+/// 统计读取字节数的包装，转发过程中实时累加到代理的流量计数
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    usage: Arc<ProxyUsage>,
+    direction: Direction,
+    activity: Arc<Activity>,
+    observers: Option<Observers>,
+    /// 本连接在该方向上读取的字节数
+    pub(crate) total: u64,
+}
+
+impl<R> CountingReader<R> {
+    pub(crate) fn new(inner: R, usage: &Arc<ProxyUsage>, direction: Direction, activity: &Arc<Activity>, observers: Option<&Observers>) -> Self {
+        Self {
+            inner,
+            usage: Arc::clone(usage),
+            direction,
+            activity: Arc::clone(activity),
+            observers: observers.cloned(),
+            total: 0,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            let read = (buf.filled().len() - before) as u64;
+            let (up, down) = match this.direction {
+                Direction::Up => {
+                    this.usage.record_up(read);
+                    (read, 0)
+                }
+                Direction::Down => {
+                    this.usage.record_down(read);
+                    (0, read)
+                }
+            };
+            this.total += read;
+            if read > 0 {
+                this.activity.touch();
+                if let Some(observers) = &this.observers {
+                    observers.notify(|observer, connection| observer.on_bytes(connection, up, down));
+                }
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,188 @@
+//! 子命令的退出状态
+//!
+//! 所有子命令共用一套退出码，编排脚本只看退出码就能区分成功、部分成功与失败：
+//!
+//! | 退出码 | 状态      | 含义                                   |
+//! |--------|-----------|----------------------------------------|
+//! | 0      | `success` | 全部成功                               |
+//! | 1      | `failure` | 执行失败，或没有任何一项成功           |
+//! | 2      | `usage`   | 参数错误，命令没有执行                 |
+//! | 3      | `partial` | 部分成功，`counts.failed` 为失败的项数 |
+//!
+//! 加上 `--status-json` 时，命令结束前在标准输出的最后一行打印一行JSON（字段见 `CommandStatus`），
+//! 不需要解析给人看的彩色输出。字段只增不改，`schema` 在出现不兼容的变化时递增。
+
+use std::fmt;
+use std::io::Write;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+
+/// 状态行的格式版本
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// 命令的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Partial,
+    Failure,
+    Usage,
+}
+
+impl Outcome {
+    /// 对应的进程退出码
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::Failure => 1,
+            Outcome::Usage => 2,
+            Outcome::Partial => 3,
+        }
+    }
+
+    /// 按成功与失败的项数判断结果，没有失败项时为成功
+    pub fn from_counts(succeeded: usize, failed: usize) -> Self {
+        match (succeeded, failed) {
+            (_, 0) => Outcome::Success,
+            (0, _) => Outcome::Failure,
+            _ => Outcome::Partial,
+        }
+    }
+}
+
+/// 命令处理的项数，每个命令的“项”见README中的说明
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counts {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// 无需处理而跳过的项，例如导入时已存在的代理
+    pub skipped: usize,
+}
+
+impl Counts {
+    pub fn new(succeeded: usize, failed: usize, skipped: usize) -> Self {
+        Self { total: succeeded + failed + skipped, succeeded, failed, skipped }
+    }
+}
+
+/// 子命令执行完毕后的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub outcome: Outcome,
+    pub counts: Counts,
+    pub message: Option<String>,
+}
+
+impl Report {
+    /// 没有可计数项的命令成功结束
+    pub fn success() -> Self {
+        Self { outcome: Outcome::Success, counts: Counts::default(), message: None }
+    }
+
+    /// 没有任何一项成功
+    pub fn failure<S: Into<String>>(message: S) -> Self {
+        Self { outcome: Outcome::Failure, counts: Counts::default(), message: Some(message.into()) }
+    }
+
+    /// 按计数判断结果，跳过的项视为成功
+    pub fn from_counts(counts: Counts) -> Self {
+        Self { outcome: Outcome::from_counts(counts.succeeded + counts.skipped, counts.failed), counts, message: None }
+    }
+}
+
+/// 参数错误，以退出码2结束
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+/// 把参数解析错误标记为用法错误
+pub fn usage<E: fmt::Display>(error: E) -> anyhow::Error {
+    UsageError(error.to_string()).into()
+}
+
+/// `--status-json` 输出的状态行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandStatus {
+    pub schema: u32,
+    pub command: String,
+    pub status: Outcome,
+    pub exit_code: i32,
+    pub counts: Counts,
+    pub duration_ms: u64,
+    pub message: Option<String>,
+}
+
+/// 记录子命令的开始时间，结束时输出状态行并以对应的退出码退出
+#[derive(Debug)]
+pub struct StatusReporter {
+    command: String,
+    json: bool,
+    started: Instant,
+}
+
+impl StatusReporter {
+    /// 从命令行参数中取出子命令名与 `--status-json`，返回其余参数
+    ///
+    /// 第一个参数不是子命令（为空或以 `-` 开头）时视为 `run`。
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> (Self, Vec<String>) {
+        let mut args: Vec<String> = args.into_iter().collect();
+        let json = args.iter().any(|arg| arg == "--status-json");
+        args.retain(|arg| arg != "--status-json");
+        let command = match args.first() {
+            Some(first) if !first.starts_with('-') => args.remove(0),
+            _ => "run".to_string(),
+        };
+        (Self { command, json, started: Instant::now() }, args)
+    }
+
+    /// 子命令名
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// 命令结果对应的状态行
+    pub fn status(&self, result: &anyhow::Result<Report>) -> CommandStatus {
+        let report = match result {
+            Ok(report) => report.clone(),
+            Err(e) => Report {
+                outcome: if e.is::<UsageError>() { Outcome::Usage } else { Outcome::Failure },
+                counts: Counts::default(),
+                message: Some(format!("{:#}", e)),
+            },
+        };
+        CommandStatus {
+            schema: SCHEMA_VERSION,
+            command: self.command.clone(),
+            status: report.outcome,
+            exit_code: report.outcome.exit_code(),
+            counts: report.counts,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            message: report.message,
+        }
+    }
+
+    /// 输出错误与状态行，然后以结果对应的退出码退出进程
+    pub fn finish(self, result: anyhow::Result<Report>) -> ! {
+        if let Err(e) = &result {
+            eprintln!("错误: {:#}", e);
+        }
+        let status = self.status(&result);
+        let mut stdout = std::io::stdout().lock();
+        if self.json {
+            if let Ok(line) = serde_json::to_string(&status) {
+                let _ = writeln!(stdout, "{}", line);
+            }
+        }
+        let _ = stdout.flush();
+        std::process::exit(status.exit_code)
+    }
+}
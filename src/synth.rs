@@ -0,0 +1,306 @@
+//! 合成代理（测试夹具）
+//!
+//! `lokipool synth` 在本机启动一批进程内SOCKS5服务器，每个服务器有各自的模拟延迟与失败率，
+//! 并把它们的地址写入代理文件。将 `proxy.proxy_file` 指向该文件，
+//! 无需真实代理即可演示和压测代理池的选择、熔断、隔离等逻辑。
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+use tracing::debug;
+use lokipool_core::{accept_backoff, spawn_logged, write_atomic};
+use crate::exit_agent::read_request;
+use crate::socks_server::{encode_address, parse_udp_header};
+
+/// 参数的取值范围，各服务器的取值在范围内均匀分布
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spread {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Spread {
+    /// 固定取值
+    pub fn fixed(value: f64) -> Self {
+        Self { min: value, max: value }
+    }
+
+    /// 解析 `a` 或 `a-b` 形式的取值
+    pub fn parse(text: &str) -> Result<Self> {
+        let parse = |s: &str| s.trim().parse::<f64>().map_err(|_| anyhow!("无效的取值: {}", text));
+        let spread = match text.split_once('-') {
+            Some((min, max)) => Self { min: parse(min)?, max: parse(max)? },
+            None => Self::fixed(parse(text)?),
+        };
+        if spread.min < 0.0 || spread.max < spread.min {
+            return Err(anyhow!("无效的取值范围: {}", text));
+        }
+        Ok(spread)
+    }
+
+    /// 第 `i` 个（共 `n` 个）服务器的取值
+    fn at(&self, i: usize, n: usize) -> f64 {
+        if n <= 1 {
+            return self.min;
+        }
+        self.min + (self.max - self.min) * i as f64 / (n - 1) as f64
+    }
+}
+
+/// 合成代理配置
+#[derive(Debug, Clone)]
+pub struct SynthConfig {
+    /// 服务器数量
+    pub count: usize,
+    /// 监听地址
+    pub bind_address: String,
+    /// 第一个服务器的端口，之后依次递增；0表示由系统分配
+    pub base_port: u16,
+    /// 每次握手的模拟延迟（毫秒）
+    pub latency_ms: Spread,
+    /// 请求失败的概率（0.0-1.0）
+    pub failure_rate: Spread,
+    /// 是否支持UDP ASSOCIATE，关闭时回复命令不支持
+    pub udp: bool,
+    /// 写入的代理文件
+    pub output: PathBuf,
+}
+
+impl Default for SynthConfig {
+    fn default() -> Self {
+        Self {
+            count: 10,
+            bind_address: "127.0.0.1".to_string(),
+            base_port: 0,
+            latency_ms: Spread { min: 50.0, max: 500.0 },
+            failure_rate: Spread { min: 0.0, max: 0.2 },
+            udp: true,
+            output: PathBuf::from("synth_proxies.txt"),
+        }
+    }
+}
+
+impl SynthConfig {
+    /// 从命令行参数解析配置（不包含子命令本身）
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| anyhow!("参数 {} 缺少取值", name));
+            match arg.as_str() {
+                "--count" => config.count = value("--count")?.parse()?,
+                "--bind" => config.bind_address = value("--bind")?,
+                "--base-port" => config.base_port = value("--base-port")?.parse()?,
+                "--latency" => config.latency_ms = Spread::parse(&value("--latency")?)?,
+                "--failure-rate" => config.failure_rate = Spread::parse(&value("--failure-rate")?)?,
+                "--no-udp" => config.udp = false,
+                "--output" => config.output = PathBuf::from(value("--output")?),
+                other => return Err(anyhow!("未知参数: {}", other)),
+            }
+        }
+
+        if config.count == 0 {
+            return Err(anyhow!("--count 必须大于0"));
+        }
+        if config.failure_rate.max > 1.0 {
+            return Err(anyhow!("--failure-rate 不能超过1.0"));
+        }
+
+        Ok(config)
+    }
+}
+
+/// 单个合成代理的统计
+#[derive(Debug, Default)]
+pub struct SynthStats {
+    /// 收到的连接数
+    pub connections: AtomicU64,
+    /// 模拟失败的请求数
+    pub failures: AtomicU64,
+}
+
+/// 单个合成代理
+#[derive(Debug, Clone)]
+pub struct SynthProxy {
+    pub addr: SocketAddr,
+    pub latency: Duration,
+    pub failure_rate: f64,
+    pub udp: bool,
+    pub stats: Arc<SynthStats>,
+}
+
+/// 一组运行中的合成代理，释放时停止所有服务器
+pub struct SynthFleet {
+    proxies: Vec<SynthProxy>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl SynthFleet {
+    /// 按配置启动所有服务器
+    pub async fn start(config: &SynthConfig) -> Result<Self> {
+        let mut fleet = Self { proxies: Vec::with_capacity(config.count), tasks: Vec::with_capacity(config.count) };
+        for i in 0..config.count {
+            let port = match config.base_port {
+                0 => 0,
+                base => base.checked_add(i as u16).ok_or_else(|| anyhow!("端口超出范围"))?,
+            };
+            let listener = TcpListener::bind((config.bind_address.as_str(), port)).await?;
+            let proxy = SynthProxy {
+                addr: listener.local_addr()?,
+                latency: Duration::from_millis(config.latency_ms.at(i, config.count).round() as u64),
+                failure_rate: config.failure_rate.at(i, config.count),
+                udp: config.udp,
+                stats: Arc::new(SynthStats::default()),
+            };
+            fleet.tasks.push(tokio::spawn(serve(listener, proxy.clone())));
+            fleet.proxies.push(proxy);
+        }
+        Ok(fleet)
+    }
+
+    pub fn proxies(&self) -> &[SynthProxy] {
+        &self.proxies
+    }
+
+    /// 代理文件内容，每行一个 `socks5://地址`
+    pub fn proxy_list(&self) -> String {
+        let mut content = String::from("# 由 lokipool synth 生成的合成代理\n");
+        for proxy in &self.proxies {
+            content.push_str(&format!("socks5://{}\n", proxy.addr));
+        }
+        content
+    }
+
+    /// 原子地写入代理文件
+    pub fn write_proxy_file(&self, path: &Path) -> Result<()> {
+        write_atomic(path, self.proxy_list().as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Drop for SynthFleet {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// 接受连接，每个连接在独立任务中处理
+async fn serve(listener: TcpListener, proxy: SynthProxy) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, client_addr)) => {
+                let proxy = proxy.clone();
+                spawn_logged(format!("合成代理连接 {}", client_addr), async move {
+                    if let Err(e) = handle(stream, &proxy).await {
+                        debug!("合成代理 {} 处理连接失败: {}", proxy.addr, e);
+                    }
+                });
+            }
+            Err(e) => {
+                debug!("合成代理 {} 接受连接失败: {}", proxy.addr, e);
+                accept_backoff(&e).await;
+            }
+        }
+    }
+}
+
+/// 读取请求后等待模拟延迟，按失败率返回一般性失败，否则直连目标并转发
+async fn handle(mut stream: TcpStream, proxy: &SynthProxy) -> Result<()> {
+    proxy.stats.connections.fetch_add(1, Ordering::Relaxed);
+    let (command, host, port) = read_request(&mut stream).await?;
+    if command != 0x01 && !(command == 0x03 && proxy.udp) {
+        stream.write_all(&[0x05, 0x07, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+        return Err(anyhow!("不支持的SOCKS5命令: {}", command));
+    }
+    tokio::time::sleep(proxy.latency).await;
+
+    if rand::random::<f64>() < proxy.failure_rate {
+        proxy.stats.failures.fetch_add(1, Ordering::Relaxed);
+        stream.write_all(&[0x05, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+        return Ok(());
+    }
+
+    if command == 0x03 {
+        return associate(stream, proxy).await;
+    }
+
+    let mut target = match TcpStream::connect((host.as_str(), port)).await {
+        Ok(target) => target,
+        Err(e) => {
+            stream.write_all(&[0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+            return Err(anyhow!("连接目标 {}:{} 失败: {}", host, port, e));
+        }
+    };
+    let mut response = vec![0x05, 0x00, 0x00];
+    response.extend_from_slice(&encode_address(target.local_addr()?));
+    stream.write_all(&response).await?;
+
+    tokio::io::copy_bidirectional(&mut stream, &mut target).await?;
+    Ok(())
+}
+
+/// UDP中继：解开客户端数据报的报头直接发往目标，目标的应答加上来源地址后发回客户端，控制连接关闭时结束
+async fn associate(mut stream: TcpStream, proxy: &SynthProxy) -> Result<()> {
+    let client_ip = stream.peer_addr()?.ip();
+    let socket = UdpSocket::bind((proxy.addr.ip(), 0)).await?;
+    let mut response = vec![0x05, 0x00, 0x00];
+    response.extend_from_slice(&encode_address(socket.local_addr()?));
+    stream.write_all(&response).await?;
+
+    let mut client = None;
+    let mut buf = vec![0u8; 65536];
+    let mut probe = [0u8; 1];
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buf) => {
+                let (n, from) = received?;
+                if from.ip() == client_ip && client.is_none_or(|client| client == from) {
+                    let Some((host, port, offset)) = parse_udp_header(&buf[..n]) else { continue };
+                    client = Some(from);
+                    let target = tokio::net::lookup_host((host.as_str(), port)).await?.next();
+                    if let Some(target) = target {
+                        socket.send_to(&buf[offset..n], target).await?;
+                    }
+                } else if let Some(client) = client {
+                    let mut datagram = vec![0x00, 0x00, 0x00];
+                    datagram.extend_from_slice(&encode_address(from));
+                    datagram.extend_from_slice(&buf[..n]);
+                    socket.send_to(&datagram, client).await?;
+                }
+            },
+            _ = stream.read(&mut probe) => return Ok(()),
+        }
+    }
+}
+
+/// 运行合成代理，写入代理文件后直到收到Ctrl-C才退出
+pub async fn run(config: SynthConfig) -> Result<()> {
+    let fleet = SynthFleet::start(&config).await?;
+    fleet.write_proxy_file(&config.output)?;
+
+    println!("已启动 {} 个合成SOCKS5代理，地址写入 {}", fleet.proxies().len(), config.output.display());
+    println!("{:<24} {:>10} {:>10}", "地址", "延迟", "失败率");
+    for proxy in fleet.proxies() {
+        println!("{:<24} {:>8}ms {:>9.1}%", proxy.addr, proxy.latency.as_millis(), proxy.failure_rate * 100.0);
+    }
+    println!("将 config.toml 中的 proxy_file 指向该文件即可使用，按 Ctrl-C 退出");
+
+    tokio::signal::ctrl_c().await?;
+
+    println!("{:<24} {:>10} {:>10}", "地址", "连接数", "模拟失败");
+    for proxy in fleet.proxies() {
+        println!("{:<24} {:>10} {:>10}", proxy.addr,
+            proxy.stats.connections.load(Ordering::Relaxed),
+            proxy.stats.failures.load(Ordering::Relaxed));
+    }
+    Ok(())
+}
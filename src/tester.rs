@@ -46,7 +46,6 @@ pub struct TestResult {
 
 /// 代理测试器
 pub struct Tester {
-    #[allow(dead_code)]
     options: TestOptions,
 }
 
@@ -56,6 +55,11 @@ impl Tester {
         Self { options }
     }
 
+    /// 测试选项
+    pub fn options(&self) -> &TestOptions {
+        &self.options
+    }
+
     /// 测试单个代理
     pub fn test_proxy(&self, proxy: &mut Proxy) -> Result<TestResult> {
         // 实际实现中，您需要使用reqwest或其他HTTP客户端通过代理请求目标URL
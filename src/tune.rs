@@ -0,0 +1,421 @@
+//! 参数调优
+//!
+//! 默认的转发缓冲区、握手超时与测试并发数是按本机回环环境定的，面对真实的高延迟代理往往不合适。
+//! `lokipool tune` 用配置中的代理做对照实验，把推荐值写入配置覆盖文件（`config.toml` 对应
+//! `config.tuned.toml`）。启动时覆盖文件中的值优先于 `config.toml`，删除该文件即恢复原配置。
+//!
+//! - 握手：经每个抽样代理多次连接目标，按握手耗时与首字节耗时的p95推荐 `handshake_timeout_ms` 与 `test_timeout`
+//! - 缓冲区：经响应最快的几个代理用不同的读取缓冲区下载目标URL，取吞吐量达到最高值90%的最小缓冲区
+//! - 并发：逐级增加同时握手的数量，取成功率与耗时都没有明显变差的最高一级作为 `retry_concurrency`
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use rand::seq::SliceRandom;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use lokipool_core::{write_atomic, Config, Pool, PoolOptions, Proxy};
+use crate::socks_server::{address_type, SocksServer};
+
+/// 候选的转发缓冲区大小
+const BUFFER_SIZES: [usize; 4] = [4 * 1024, 16 * 1024, 64 * 1024, 256 * 1024];
+
+/// 逐级尝试的并发数
+const CONCURRENCY_LEVELS: [usize; 5] = [4, 8, 16, 32, 64];
+
+/// 单次连接或读取的超时上限，超过即视为失败
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 下载量低于该值时吞吐量主要由握手决定，不据此推荐缓冲区
+const MIN_DOWNLOAD_BYTES: u64 = 256 * 1024;
+
+/// 参与缓冲区实验的代理数
+const BUFFER_PROXIES: usize = 3;
+
+/// 调优配置
+#[derive(Debug, Clone)]
+pub struct TuneConfig {
+    /// 配置文件
+    pub config: PathBuf,
+    /// 写入的覆盖文件，默认为配置文件对应的 `.tuned.toml`
+    pub output: Option<PathBuf>,
+    /// 实验目标URL，默认使用配置中的第一个测试URL；下载较大的 `http://` 文件时缓冲区实验更准确
+    pub url: Option<String>,
+    /// 抽样的代理数
+    pub samples: usize,
+    /// 每个代理、每种缓冲区重复的次数
+    pub rounds: usize,
+    /// 只输出推荐值，不写入覆盖文件
+    pub dry_run: bool,
+}
+
+impl Default for TuneConfig {
+    fn default() -> Self {
+        Self {
+            config: PathBuf::from("config.toml"),
+            output: None,
+            url: None,
+            samples: 10,
+            rounds: 3,
+            dry_run: false,
+        }
+    }
+}
+
+impl TuneConfig {
+    /// 从命令行参数解析
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| anyhow!("参数 {} 缺少取值", name));
+            match arg.as_str() {
+                "--config" => config.config = PathBuf::from(value("--config")?),
+                "--output" => config.output = Some(PathBuf::from(value("--output")?)),
+                "--url" => config.url = Some(value("--url")?),
+                "--samples" => config.samples = value("--samples")?.parse()?,
+                "--rounds" => config.rounds = value("--rounds")?.parse()?,
+                "--dry-run" => config.dry_run = true,
+                other => return Err(anyhow!("未知参数: {}", other)),
+            }
+        }
+
+        if config.samples == 0 || config.rounds == 0 {
+            return Err(anyhow!("--samples 与 --rounds 必须大于0"));
+        }
+
+        Ok(config)
+    }
+}
+
+/// 实验目标
+#[derive(Debug, Clone)]
+struct Target {
+    host: String,
+    port: u16,
+    /// HTTP目标的请求路径，HTTPS等其他目标为None，只测量握手
+    path: Option<String>,
+}
+
+impl Target {
+    fn parse(url: &str) -> Result<Self> {
+        let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("无效的URL {}: {}", url, e))?;
+        let host = parsed.host_str().ok_or_else(|| anyhow!("URL缺少主机: {}", url))?;
+        let port = parsed.port_or_known_default().ok_or_else(|| anyhow!("无法确定URL的端口: {}", url))?;
+        let path = (parsed.scheme() == "http").then(|| match parsed.query() {
+            Some(query) => format!("{}?{}", parsed.path(), query),
+            None => parsed.path().to_string(),
+        });
+        Ok(Self {
+            host: host.trim_start_matches('[').trim_end_matches(']').to_string(),
+            port,
+            path,
+        })
+    }
+
+    fn request(&self, path: &str) -> String {
+        format!("GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: lokipool-tune\r\nConnection: close\r\n\r\n", path, self.host)
+    }
+}
+
+/// 一次探测的耗时
+#[derive(Debug, Clone, Copy)]
+struct Probe {
+    /// 握手并连接到目标的耗时
+    handshake: Duration,
+    /// 到收到响应首字节的耗时，非HTTP目标与握手耗时相同
+    total: Duration,
+}
+
+/// 经代理连接目标，返回连接与握手耗时
+async fn connect(proxy: &Proxy, target: &Target) -> Result<(TcpStream, Duration)> {
+    let started = Instant::now();
+    let atyp = address_type(&target.host);
+    let (stream, _) = tokio::time::timeout(PROBE_TIMEOUT, SocksServer::connect_upstream(&[], proxy, atyp, &target.host, target.port))
+        .await
+        .map_err(|_| anyhow!("握手超时"))??;
+    Ok((stream, started.elapsed()))
+}
+
+/// 连接目标，HTTP目标再等待响应的首字节
+async fn probe(proxy: &Proxy, target: &Target) -> Result<Probe> {
+    let started = Instant::now();
+    let (mut stream, handshake) = connect(proxy, target).await?;
+    if let Some(path) = &target.path {
+        stream.write_all(target.request(path).as_bytes()).await?;
+        let read = tokio::time::timeout(PROBE_TIMEOUT, stream.read(&mut [0u8; 1])).await
+            .map_err(|_| anyhow!("等待响应超时"))??;
+        if read == 0 {
+            return Err(anyhow!("目标关闭了连接"));
+        }
+    }
+    Ok(Probe { handshake, total: started.elapsed() })
+}
+
+/// 用指定大小的读取缓冲区下载目标，返回下载的字节数与吞吐量（字节/秒）
+async fn download(proxy: &Proxy, target: &Target, path: &str, buffer_size: usize) -> Result<(u64, f64)> {
+    let (mut stream, _) = connect(proxy, target).await?;
+    stream.write_all(target.request(path).as_bytes()).await?;
+    let started = Instant::now();
+    let mut buffer = vec![0u8; buffer_size];
+    let mut total = 0u64;
+    loop {
+        let read = tokio::time::timeout(PROBE_TIMEOUT, stream.read(&mut buffer)).await
+            .map_err(|_| anyhow!("下载超时"))??;
+        if read == 0 {
+            break;
+        }
+        total += read as u64;
+    }
+    Ok((total, total as f64 / started.elapsed().as_secs_f64().max(0.001)))
+}
+
+/// 第 `pct` 百分位（0-100），样本为空时返回None
+pub(crate) fn percentile(samples: &[Duration], pct: usize) -> Option<Duration> {
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    let index = (sorted.len() * pct).div_ceil(100).max(1) - 1;
+    sorted.get(index).copied()
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    values.get(values.len() / 2).copied().unwrap_or(0.0)
+}
+
+/// 一个推荐值
+#[derive(Debug, Clone)]
+pub struct Recommendation {
+    /// 所在的表
+    pub table: &'static str,
+    pub key: &'static str,
+    /// 当前生效的取值
+    pub current: u64,
+    pub value: u64,
+    /// 推荐依据
+    pub basis: String,
+}
+
+/// 各代理的探测结果
+struct ProxyProbes {
+    proxy: Proxy,
+    probes: Vec<Probe>,
+}
+
+/// 握手实验：各代理并行、每个代理依次探测 `rounds` 次
+async fn measure_handshakes(proxies: &[Proxy], target: &Arc<Target>, rounds: usize) -> Vec<ProxyProbes> {
+    let mut tasks = JoinSet::new();
+    for proxy in proxies.iter().cloned() {
+        let target = Arc::clone(target);
+        tasks.spawn(async move {
+            let mut result = ProxyProbes { proxy, probes: Vec::new() };
+            for _ in 0..rounds {
+                if let Ok(probe) = probe(&result.proxy, &target).await {
+                    result.probes.push(probe);
+                }
+            }
+            result
+        });
+    }
+    let mut results = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        if let Ok(result) = result {
+            results.push(result);
+        }
+    }
+    results
+}
+
+/// 并发实验：同时发起 `level` 个握手，轮流分配给各代理，返回成功率与握手耗时p95
+async fn measure_concurrency(proxies: &[Proxy], target: &Arc<Target>, level: usize) -> (f64, Option<Duration>) {
+    let mut tasks = JoinSet::new();
+    for proxy in proxies.iter().cycle().take(level) {
+        let proxy = proxy.clone();
+        let target = Arc::clone(target);
+        tasks.spawn(async move { connect(&proxy, &target).await.map(|(_, handshake)| handshake) });
+    }
+    let mut handshakes = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        if let Ok(Ok(handshake)) = result {
+            handshakes.push(handshake);
+        }
+    }
+    (handshakes.len() as f64 / level as f64, percentile(&handshakes, 95))
+}
+
+/// 运行全部实验并写入覆盖文件，返回推荐值
+pub async fn run(tune: TuneConfig) -> Result<Vec<Recommendation>> {
+    let base = if tune.config.exists() { Config::from_file(&tune.config)? } else { Config::default() };
+    let overlay = tune.output.clone().unwrap_or_else(|| Config::overlay_path(&tune.config));
+    // 当前生效的取值包含之前调优的结果
+    let current = if overlay.exists() { base.with_overlay(&overlay).unwrap_or_else(|_| base.clone()) } else { base.clone() };
+
+    let pool = Pool::new_with_proxies(base.proxies.clone(), PoolOptions::from_config(&base));
+    pool.sync_file(Path::new(&base.proxy.proxy_file)).await?;
+    let mut proxies = pool.get_all_proxies().await;
+    if proxies.is_empty() {
+        return Err(anyhow!("配置与代理文件中没有可用于调优的代理"));
+    }
+    proxies.shuffle(&mut rand::rng());
+    proxies.truncate(tune.samples);
+
+    let url = tune.url.clone().or_else(|| base.test_urls.first().cloned())
+        .ok_or_else(|| anyhow!("没有实验目标，请用 --url 指定"))?;
+    let target = Arc::new(Target::parse(&url)?);
+    println!("使用 {} 个代理对 {} 进行调优实验", proxies.len(), url);
+
+    let mut recommendations = Vec::new();
+
+    // 1. 握手与首字节耗时
+    println!("\n[1/3] 握手耗时（每个代理 {} 次）", tune.rounds);
+    let mut results = measure_handshakes(&proxies, &target, tune.rounds).await;
+    results.sort_by_key(|result| percentile(&result.probes.iter().map(|p| p.total).collect::<Vec<_>>(), 50).unwrap_or(Duration::MAX));
+    for result in &results {
+        let totals: Vec<Duration> = result.probes.iter().map(|p| p.total).collect();
+        match percentile(&totals, 50) {
+            Some(median) => println!("  {:<24} 成功 {}/{}, 中位耗时 {}ms", address(&result.proxy),
+                result.probes.len(), tune.rounds, median.as_millis()),
+            None => println!("  {:<24} 全部失败", address(&result.proxy)),
+        }
+    }
+    let responsive: Vec<Proxy> = results.iter().filter(|r| !r.probes.is_empty()).map(|r| r.proxy.clone()).collect();
+    if responsive.is_empty() {
+        return Err(anyhow!("所有抽样代理都无法连接到 {}", url));
+    }
+    let handshakes: Vec<Duration> = results.iter().flat_map(|r| r.probes.iter().map(|p| p.handshake)).collect();
+    let totals: Vec<Duration> = results.iter().flat_map(|r| r.probes.iter().map(|p| p.total)).collect();
+    let handshake_p95 = percentile(&handshakes, 95).unwrap_or_default();
+    let total_p95 = percentile(&totals, 95).unwrap_or_default();
+    // 留出3倍余量，偶尔变慢的代理不会被误判为失败
+    let handshake_timeout = ((handshake_p95.as_millis() as u64 * 3).div_ceil(100) * 100).clamp(1000, 30000);
+    recommendations.push(Recommendation {
+        table: "socks_server",
+        key: "handshake_timeout_ms",
+        current: current.socks_server.handshake_timeout_ms,
+        value: handshake_timeout,
+        basis: format!("握手耗时p95 {}ms", handshake_p95.as_millis()),
+    });
+    recommendations.push(Recommendation {
+        table: "proxy",
+        key: "test_timeout",
+        current: current.proxy.test_timeout,
+        value: (total_p95.as_millis() as u64 * 3).div_ceil(1000).clamp(2, 60),
+        basis: format!("{}p95 {}ms", if target.path.is_some() { "首字节耗时" } else { "握手耗时" }, total_p95.as_millis()),
+    });
+
+    // 2. 转发缓冲区
+    println!("\n[2/3] 转发缓冲区");
+    match &target.path {
+        Some(path) => {
+            let mut throughput = Vec::new();
+            let mut downloaded = Vec::new();
+            for &size in &BUFFER_SIZES {
+                let mut rates = Vec::new();
+                for proxy in responsive.iter().take(BUFFER_PROXIES) {
+                    for _ in 0..tune.rounds {
+                        if let Ok((bytes, rate)) = download(proxy, &target, path, size).await {
+                            downloaded.push(bytes as f64);
+                            rates.push(rate);
+                        }
+                    }
+                }
+                let rate = median(&mut rates);
+                println!("  {:>8} 字节: {}/s", size, format_rate(rate));
+                throughput.push((size, rate));
+            }
+            let best = throughput.iter().map(|(_, rate)| *rate).fold(0.0, f64::max);
+            let chosen = throughput.iter().find(|(_, rate)| *rate >= best * 0.9).map(|(size, _)| *size);
+            match chosen {
+                Some(size) if median(&mut downloaded) >= MIN_DOWNLOAD_BYTES as f64 => {
+                    let basis = format!("吞吐量 {}/s", format_rate(best));
+                    // 背压水位与缓冲区保持默认配置中的比例
+                    for (key, value, current) in [
+                        ("relay_buffer_size", size, current.socks_server.relay_buffer_size),
+                        ("relay_high_watermark", size * 16, current.socks_server.relay_high_watermark),
+                        ("relay_low_watermark", size * 4, current.socks_server.relay_low_watermark),
+                    ] {
+                        recommendations.push(Recommendation {
+                            table: "socks_server",
+                            key,
+                            current: current as u64,
+                            value: value as u64,
+                            basis: basis.clone(),
+                        });
+                    }
+                }
+                _ => println!("  目标响应过小或下载失败，跳过缓冲区推荐；可用 --url 指定一个较大的 http:// 文件"),
+            }
+        }
+        None => println!("  目标不是 http:// 地址，跳过缓冲区实验；可用 --url 指定一个较大的 http:// 文件"),
+    }
+
+    // 3. 测试并发数
+    println!("\n[3/3] 并发握手");
+    let mut baseline: Option<(f64, Duration)> = None;
+    let mut chosen = CONCURRENCY_LEVELS[0];
+    for &level in &CONCURRENCY_LEVELS {
+        let (success_rate, p95) = measure_concurrency(&responsive, &target, level).await;
+        let p95 = p95.unwrap_or(PROBE_TIMEOUT);
+        println!("  并发 {:>3}: 成功率 {:>5.1}%, 握手耗时p95 {}ms", level, success_rate * 100.0, p95.as_millis());
+        let (base_rate, base_p95) = *baseline.get_or_insert((success_rate, p95));
+        // 成功率下降超过5个百分点或耗时翻倍时，说明本机或上游已经饱和
+        if success_rate < base_rate - 0.05 || p95 > base_p95 * 2 + Duration::from_millis(50) {
+            break;
+        }
+        chosen = level;
+    }
+    recommendations.push(Recommendation {
+        table: "proxy",
+        key: "retry_concurrency",
+        current: current.proxy.retry_concurrency as u64,
+        value: chosen as u64,
+        basis: "成功率与耗时没有明显变差的最高并发".to_string(),
+    });
+
+    println!("\n{:<34} {:>12} {:>12}  依据", "配置项", "当前", "推荐");
+    for rec in &recommendations {
+        println!("{:<34} {:>12} {:>12}  {}", format!("{}.{}", rec.table, rec.key), rec.current, rec.value, rec.basis);
+    }
+    if tune.dry_run {
+        println!("\n--dry-run: 未写入覆盖文件");
+    } else {
+        write_atomic(&overlay, render_overlay(&recommendations, responsive.len()).as_bytes())?;
+        println!("\n推荐值已写入 {}，启动时覆盖 {} 中的同名配置；删除该文件即恢复", overlay.display(), tune.config.display());
+    }
+    Ok(recommendations)
+}
+
+/// 覆盖文件内容，按表分组，每个值注明依据
+pub fn render_overlay(recommendations: &[Recommendation], proxies: usize) -> String {
+    let mut content = format!(
+        "# 由 lokipool tune 于 {} 根据 {} 个代理的测量结果生成\n# 启动时这些值覆盖 config.toml 中的同名配置，删除本文件即恢复\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), proxies);
+    let mut tables: Vec<&str> = Vec::new();
+    for rec in recommendations {
+        if !tables.contains(&rec.table) {
+            tables.push(rec.table);
+        }
+    }
+    for table in tables {
+        content.push_str(&format!("\n[{}]\n", table));
+        for rec in recommendations.iter().filter(|rec| rec.table == table) {
+            content.push_str(&format!("{} = {}  # {}\n", rec.key, rec.value, rec.basis));
+        }
+    }
+    content
+}
+
+fn address(proxy: &Proxy) -> String {
+    format!("{}:{}", proxy.info.host, proxy.info.port)
+}
+
+pub(crate) fn format_rate(bytes_per_sec: f64) -> String {
+    if bytes_per_sec >= 1024.0 * 1024.0 {
+        format!("{:.1}MB", bytes_per_sec / 1024.0 / 1024.0)
+    } else {
+        format!("{:.1}KB", bytes_per_sec / 1024.0)
+    }
+}
@@ -0,0 +1,237 @@
+//! 上游握手预热池
+//!
+//! 为延迟最低的若干个代理预先建立TCP连接并完成SOCKS5认证方法协商，客户端发起CONNECT时
+//! 直接在预热的连接上发送请求，省去连接与协商的往返。预热的连接超过 `idle_timeout` 未被使用、
+//! 被代理关闭或代理跌出前列时丢弃；取用前检查连接仍然打开，已失效的连接不会交给客户端。
+//! 预热的连接直接连到代理，配置了代理链时不使用。
+
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use lokipool_core::{spawn_logged, PoolHandle, Proxy, ProxyStatus, WarmPoolSettings};
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tracing::{debug, info};
+use crate::socks_server::{credentials, within, SocksServer};
+
+/// 检查与补充预热连接的间隔，连接被取用后立即补充
+const MAINTAIN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 预热池配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarmPoolOptions {
+    /// 每个代理保持的预热连接数，为0时不预热
+    pub size: usize,
+    /// 预热延迟最低的这么多个可用代理
+    pub proxies: usize,
+    /// 预热的连接超过该时长未被使用后丢弃
+    pub idle_timeout: Duration,
+}
+
+impl Default for WarmPoolOptions {
+    fn default() -> Self {
+        Self {
+            size: 0,
+            proxies: 3,
+            idle_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl WarmPoolOptions {
+    pub fn from_settings(settings: &WarmPoolSettings) -> Self {
+        Self {
+            size: settings.size,
+            proxies: settings.proxies,
+            idle_timeout: Duration::from_secs(settings.idle_timeout_secs),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.size > 0 && self.proxies > 0 && !self.idle_timeout.is_zero()
+    }
+}
+
+/// 预热池，克隆的实例共用同一组连接
+#[derive(Clone)]
+pub struct WarmPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    options: WarmPoolOptions,
+    handshake_timeout: Duration,
+    /// 只预热位置标签为其中之一的代理，为空时不限制
+    locations: Vec<String>,
+    /// 各代理（按代理ID）的预热连接，较新的在后
+    idle: Mutex<HashMap<String, VecDeque<(TcpStream, Instant)>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// 连接被取用或预热池关闭时唤醒维护任务
+    wake: Notify,
+    closed: AtomicBool,
+}
+
+impl WarmPool {
+    /// `handshake_timeout` 限制预热单个连接的时长，为0时不限制
+    pub fn new(options: WarmPoolOptions, handshake_timeout: Duration, locations: Vec<String>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                options,
+                handshake_timeout,
+                locations,
+                idle: Mutex::new(HashMap::new()),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                wake: Notify::new(),
+                closed: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.options.is_enabled()
+    }
+
+    /// 启动维护任务，预热池关闭或所有实例都被释放后结束；未启用时什么也不做
+    pub fn start(&self, pool: PoolHandle) {
+        if !self.is_enabled() {
+            return;
+        }
+        let options = self.inner.options;
+        info!("预热池已启用: 延迟最低的 {} 个代理各保持 {} 个连接，闲置 {:?} 后丢弃", options.proxies, options.size, options.idle_timeout);
+        spawn_logged("预热池维护", maintain(Arc::downgrade(&self.inner), pool));
+    }
+
+    /// 取出一个到该代理、仍然打开的预热连接
+    pub fn take(&self, proxy_id: &str) -> Option<TcpStream> {
+        if !self.is_enabled() || self.inner.closed.load(Ordering::Relaxed) {
+            return None;
+        }
+        let taken = {
+            let mut idle = self.inner.idle.lock().unwrap();
+            let connections = idle.get_mut(proxy_id);
+            connections.and_then(|connections| {
+                while let Some((stream, since)) = connections.pop_back() {
+                    if since.elapsed() < self.inner.options.idle_timeout && is_open(&stream) {
+                        return Some(stream);
+                    }
+                }
+                None
+            })
+        };
+        match taken {
+            Some(_) => self.inner.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.inner.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        self.inner.wake.notify_one();
+        taken
+    }
+
+    /// 当前保持的预热连接数
+    pub fn idle(&self) -> usize {
+        self.inner.idle.lock().unwrap().values().map(VecDeque::len).sum()
+    }
+
+    /// 使用了预热连接的CONNECT次数
+    pub fn hits(&self) -> u64 {
+        self.inner.hits.load(Ordering::Relaxed)
+    }
+
+    /// 没有可用预热连接、新建连接的CONNECT次数
+    pub fn misses(&self) -> u64 {
+        self.inner.misses.load(Ordering::Relaxed)
+    }
+
+    /// 关闭全部预热连接并停止维护任务
+    pub fn close(&self) {
+        self.inner.closed.store(true, Ordering::Relaxed);
+        self.inner.idle.lock().unwrap().clear();
+        self.inner.wake.notify_one();
+    }
+}
+
+/// 连接是否仍然打开：代理在收到请求之前不应发送任何数据，读到数据或EOF的连接都不能再用
+fn is_open(stream: &TcpStream) -> bool {
+    matches!(stream.try_read(&mut [0u8; 1]), Err(e) if e.kind() == ErrorKind::WouldBlock)
+}
+
+async fn maintain(inner: Weak<Inner>, pool: PoolHandle) {
+    loop {
+        let Some(inner) = inner.upgrade().filter(|inner| !inner.closed.load(Ordering::Relaxed)) else {
+            return;
+        };
+        inner.refill(&pool).await;
+        tokio::select! {
+            _ = tokio::time::sleep(MAINTAIN_INTERVAL) => {}
+            _ = inner.wake.notified() => {}
+        }
+    }
+}
+
+impl Inner {
+    fn serves(&self, proxy: &Proxy) -> bool {
+        self.locations.is_empty() || proxy.info.location.as_ref()
+            .is_some_and(|location| self.locations.iter().any(|allowed| allowed.eq_ignore_ascii_case(location)))
+    }
+
+    /// 丢弃失效与跌出前列的代理的连接，把前列的代理补足到 `size` 个连接
+    async fn refill(&self, pool: &PoolHandle) {
+        let mut warmest: Vec<Proxy> = pool.get_all_proxies().await.into_iter()
+            .filter(|proxy| proxy.status == ProxyStatus::Available && self.serves(proxy))
+            .collect();
+        warmest.sort_by_key(|proxy| proxy.latency);
+        warmest.truncate(self.options.proxies);
+
+        let wanted: Vec<(Proxy, usize)> = {
+            let mut idle = self.idle.lock().unwrap();
+            idle.retain(|id, _| warmest.iter().any(|proxy| &proxy.id == id));
+            for connections in idle.values_mut() {
+                connections.retain(|(stream, since)| since.elapsed() < self.options.idle_timeout && is_open(stream));
+            }
+            warmest.into_iter()
+                .filter_map(|proxy| {
+                    let have = idle.get(&proxy.id).map_or(0, VecDeque::len);
+                    (have < self.options.size).then(|| (proxy, self.options.size - have))
+                })
+                .collect()
+        };
+
+        let mut opening = JoinSet::new();
+        for (proxy, count) in wanted {
+            for _ in 0..count {
+                let proxy = proxy.clone();
+                let handshake_timeout = self.handshake_timeout;
+                opening.spawn(async move {
+                    let opened = within(handshake_timeout, open(&proxy)).await;
+                    (proxy, opened)
+                });
+            }
+        }
+        while let Some(joined) = opening.join_next().await {
+            let Ok((proxy, opened)) = joined else { continue };
+            match opened {
+                Ok(stream) if !self.closed.load(Ordering::Relaxed) => {
+                    let mut idle = self.idle.lock().unwrap();
+                    let connections = idle.entry(proxy.id.clone()).or_default();
+                    if connections.len() < self.options.size {
+                        connections.push_back((stream, Instant::now()));
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => debug!("预热到代理 {}:{} 的连接失败: {}", proxy.info.host, proxy.info.port, e),
+            }
+        }
+    }
+}
+
+/// 连接代理并完成认证方法协商
+async fn open(proxy: &Proxy) -> anyhow::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy.info.socket_addr()?).await?;
+    let label = format!("{}:{}", proxy.info.host, proxy.info.port);
+    SocksServer::socks5_greet(&mut stream, &label, credentials(&proxy.info.username, &proxy.info.password)).await?;
+    Ok(stream)
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::listener;
+use lokipool::commands::call_api;
+use lokipool::{Config, Pool, PoolOptions};
+use lokipool_api::auth::{ApiAuth, ApiKey};
+use lokipool_api::{ApiConfig, ApiServer};
+use reqwest::Method;
+
+/// 启动读取也需要密钥的API服务器，返回其地址
+async fn start_api() -> String {
+    let (listener, addr) = listener().await;
+    let pool = Pool::new_with_proxies(Vec::new(), PoolOptions::default()).handle();
+    let api = ApiServer::new(pool, Config::default(), ApiConfig {
+        auth: ApiAuth { keys: vec![ApiKey::parse("admin:s3cret").unwrap()], jwt_secret: None, protect_reads: true },
+        ..ApiConfig::default()
+    });
+    tokio::spawn(async move { api.run_with_listener(listener).await });
+    format!("http://{}", addr)
+}
+
+#[tokio::test]
+async fn api_key_comes_from_argument_or_environment() {
+    let api = start_api().await;
+
+    let error = call_api(&api, None, Method::GET, "/api/v1/sources", None).await.unwrap_err().to_string();
+    assert!(error.contains("401"), "{}", error);
+    let sources = call_api(&api, Some("s3cret"), Method::GET, "/api/v1/sources", None).await.unwrap();
+    assert!(sources.starts_with('['), "{}", sources);
+
+    // 未给出 --api-key 时使用环境变量，命令行参数优先
+    std::env::set_var("LOKIPOOL_API_KEY", "s3cret");
+    let stats = call_api(&api, None, Method::GET, "/api/v1/stats", None).await;
+    let wrong = call_api(&api, Some("wrong"), Method::GET, "/api/v1/traffic?limit=1", None).await;
+    std::env::remove_var("LOKIPOOL_API_KEY");
+    assert!(stats.unwrap().contains("metrics"));
+    assert!(wrong.unwrap_err().to_string().contains("401"));
+}
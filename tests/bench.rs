@@ -0,0 +1,118 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::{listener, start_socks};
+use lokipool::bench::{self, BenchConfig};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Config, Pool, PoolHandle, PoolOptions, ProxyConfig};
+use lokipool_api::{ApiConfig, ApiServer};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// 启动一个对任何请求都返回 `size` 字节响应体的HTTP服务器，返回其端口
+async fn http_server(size: usize) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", size);
+                let _ = stream.write_all(header.as_bytes()).await;
+                let _ = stream.write_all(&vec![b'x'; size]).await;
+            });
+        }
+    });
+    port
+}
+
+/// 以合成代理为上游启动SOCKS5服务器
+async fn start_server(fleet: &SynthFleet) -> (SocketAddr, PoolHandle) {
+    let pool = Pool::new_with_proxies(
+        fleet.proxies().iter().map(|proxy| ProxyConfig::parse(&proxy.addr.to_string()).unwrap()).collect(),
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    let pool = pool.handle();
+    let addr = start_socks(SocksServer::new(SocksServerConfig::default(), pool.clone())).await;
+    (addr, pool)
+}
+
+async fn fleet(count: usize) -> SynthFleet {
+    SynthFleet::start(&SynthConfig {
+        count,
+        latency_ms: Spread::fixed(5.0),
+        failure_rate: Spread::fixed(0.0),
+        ..SynthConfig::default()
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn bench_reports_throughput_and_latency() {
+    let target = http_server(4096).await;
+    let fleet = fleet(2).await;
+    let (socks, _pool) = start_server(&fleet).await;
+
+    let report = bench::run(BenchConfig {
+        config: "does-not-exist.toml".into(),
+        url: Some(format!("http://127.0.0.1:{}/", target)),
+        socks: Some(socks.to_string()),
+        requests: 20,
+        concurrency: 4,
+        json: true,
+        ..BenchConfig::default()
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(report.succeeded, 20);
+    assert_eq!(report.failed, 0);
+    assert_eq!(report.bytes, 20 * 4096);
+    let latency = report.latency.unwrap();
+    assert!(latency.p50 <= latency.p99 && latency.p99 <= latency.max);
+    // 没有API时不统计分布
+    assert!(report.distribution.is_none());
+}
+
+#[tokio::test]
+async fn bench_reports_per_proxy_distribution_from_api() {
+    let target = http_server(128).await;
+    let fleet = fleet(3).await;
+    let (socks, pool) = start_server(&fleet).await;
+    let (api_listener, api_addr) = listener().await;
+    let api = ApiServer::new(pool, Config::default(), ApiConfig::default());
+    tokio::spawn(async move { api.run_with_listener(api_listener).await });
+
+    let report = bench::run(BenchConfig {
+        config: "does-not-exist.toml".into(),
+        url: Some(format!("http://127.0.0.1:{}/", target)),
+        socks: Some(socks.to_string()),
+        api: Some(format!("http://{}", api_addr)),
+        requests: 30,
+        concurrency: 5,
+        json: true,
+        ..BenchConfig::default()
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(report.succeeded, 30);
+    let distribution = report.distribution.unwrap();
+    assert_eq!(distribution.iter().map(|share| share.connections).sum::<u64>(), 30);
+    assert!(distribution.windows(2).all(|pair| pair[0].connections >= pair[1].connections));
+}
+
+#[test]
+fn bench_config_rejects_invalid_arguments() {
+    let parse = |args: &[&str]| BenchConfig::from_args(args.iter().map(|arg| arg.to_string()));
+    let config = parse(&["-n", "500", "-c", "25", "--url", "http://example.com"]).unwrap();
+    assert_eq!((config.requests, config.concurrency), (500, 25));
+    assert!(parse(&["-c", "0"]).is_err());
+    assert!(parse(&["--auth", "nopassword"]).is_err());
+    assert!(parse(&["--bogus"]).is_err());
+}
@@ -0,0 +1,59 @@
+//! 集成测试共用的辅助函数
+//!
+//! 服务器在绑定好的监听器上启动，返回时已经可以连接，不需要先取空闲端口再轮询等待。
+
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+
+use lokipool::http_server::HttpServer;
+use lokipool::socks_server::SocksServer;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+/// 绑定本机的一个空闲端口
+pub async fn listener() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener, addr)
+}
+
+/// 启动回显服务器，返回其端口
+pub async fn echo_server() -> u16 {
+    let (listener, addr) = listener().await;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    addr.port()
+}
+
+/// 在空闲端口上启动SOCKS5服务器，返回其地址
+pub async fn start_socks(server: SocksServer) -> SocketAddr {
+    let (listener, addr) = listener().await;
+    let server = server.with_listener(listener);
+    tokio::spawn(async move { server.run().await });
+    addr
+}
+
+/// 在空闲端口上启动可关闭的SOCKS5服务器，返回其地址与关闭信号
+pub async fn start_socks_with_shutdown(server: SocksServer) -> (SocketAddr, broadcast::Sender<()>) {
+    let (listener, addr) = listener().await;
+    let server = server.with_listener(listener);
+    let (shutdown, shutdown_rx) = broadcast::channel(1);
+    tokio::spawn(async move { server.run_with_shutdown(shutdown_rx).await });
+    (addr, shutdown)
+}
+
+/// 在空闲端口上启动HTTP代理，返回其地址与关闭信号
+pub async fn start_http(server: HttpServer) -> (SocketAddr, broadcast::Sender<()>) {
+    let (listener, addr) = listener().await;
+    let server = server.with_listener(listener);
+    let (shutdown, shutdown_rx) = broadcast::channel(1);
+    tokio::spawn(async move { server.run_with_shutdown(shutdown_rx).await });
+    (addr, shutdown)
+}
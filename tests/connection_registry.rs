@@ -0,0 +1,71 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{echo_server, start_socks};
+use lokipool::connections::ConnectionRegistry;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use lokipool_core::ConnectionControl;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn synth_pool() -> (SynthFleet, PoolHandle) {
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let configs = fleet.proxies().iter().map(|proxy| ProxyConfig::parse(&proxy.addr.to_string()).unwrap()).collect();
+    let pool = Pool::new_with_proxies(configs, PoolOptions::default());
+    pool.test_all().await;
+    (fleet, pool.handle())
+}
+
+/// 启动登记连接的服务器，返回其地址
+async fn server(pool: PoolHandle, registry: ConnectionRegistry) -> SocketAddr {
+    start_socks(SocksServer::new(SocksServerConfig::default(), pool).with_connection_registry(registry)).await
+}
+
+/// 完成握手与CONNECT，返回仍然打开的连接
+async fn connect(addr: SocketAddr, target: u16) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+    stream
+}
+
+#[tokio::test]
+async fn relays_are_listed_and_can_be_closed() {
+    let target = echo_server().await;
+    let (fleet, pool) = synth_pool().await;
+    let registry = ConnectionRegistry::default();
+    let addr = server(pool, registry.clone()).await;
+
+    let mut stream = connect(addr, target).await;
+    stream.write_all(b"ping").await.unwrap();
+    stream.read_exact(&mut [0u8; 4]).await.unwrap();
+
+    // 等待期间的探测连接在结束后移出登记表
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let connections = registry.connections();
+    assert_eq!(connections.len(), 1);
+    let entry = &connections[0];
+    assert_eq!(entry.port, addr.port());
+    assert_eq!(entry.target.as_deref(), Some(format!("127.0.0.1:{}", target).as_str()));
+    assert_eq!(entry.proxy.as_deref(), Some(fleet.proxies()[0].addr.to_string().as_str()));
+    assert_eq!((entry.bytes_up, entry.bytes_down), (4, 4));
+
+    assert!(registry.close(entry.id));
+    let read = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut [0u8; 1])).await.unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(registry.connections().is_empty());
+    assert!(!registry.close(entry.id));
+}
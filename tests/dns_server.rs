@@ -0,0 +1,166 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use lokipool::dns_server::{DnsServer, DnsServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use lokipool_core::DnsTransport;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::broadcast;
+
+/// `example.com` 的A记录查询
+fn query(id: u16) -> Vec<u8> {
+    let mut query = id.to_be_bytes().to_vec();
+    query.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    query.extend_from_slice(b"\x07example\x03com\x00");
+    query.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+    query
+}
+
+/// 把查询改成应答：置QR与RA位，末尾附上来源标记，便于确认应答经过了哪个上游
+fn answer(query: &[u8], marker: &[u8]) -> Vec<u8> {
+    let mut response = query.to_vec();
+    response[2] |= 0x80;
+    response[3] = 0x80;
+    response.extend_from_slice(marker);
+    response
+}
+
+/// 启动应答所有查询的UDP解析器，返回其地址
+async fn udp_resolver() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 512];
+        while let Ok((n, from)) = socket.recv_from(&mut buf).await {
+            let _ = socket.send_to(&answer(&buf[..n], b"udp"), from).await;
+        }
+    });
+    addr
+}
+
+/// 启动只接受POST的明文DoH服务器，返回其地址
+async fn doh_resolver() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                let head_end = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "请求头不完整");
+                    request.extend_from_slice(&buf[..n]);
+                    if let Some(at) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break at + 4;
+                    }
+                };
+                let head = String::from_utf8_lossy(&request[..head_end]).to_ascii_lowercase();
+                assert!(head.starts_with("post "), "{}", head);
+                assert!(head.contains("content-type: application/dns-message"), "{}", head);
+                let length: usize = head.lines()
+                    .find_map(|line| line.strip_prefix("content-length: "))
+                    .unwrap()
+                    .trim()
+                    .parse()
+                    .unwrap();
+                while request.len() < head_end + length {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let body = answer(&request[head_end..head_end + length], b"doh");
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: {}\r\n\r\n",
+                    body.len(),
+                ).into_bytes();
+                response.extend_from_slice(&body);
+                stream.write_all(&response).await.unwrap();
+            });
+        }
+    });
+    addr
+}
+
+/// 启动经给定代理转发的DNS服务器，返回监听地址与关闭信号的发送端
+async fn start_server(proxies: Vec<String>, config: DnsServerConfig) -> (SocketAddr, broadcast::Sender<()>) {
+    let pool = pool_of(proxies).await;
+    let (socket, listener) = sockets().await;
+    let addr = socket.local_addr().unwrap();
+    let server = DnsServer::new(config, pool).with_sockets(socket, listener);
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    tokio::spawn(async move { server.run_with_shutdown(shutdown_rx).await });
+    (addr, shutdown_tx)
+}
+
+/// 绑定同一空闲端口上的UDP套接字与TCP监听器，TCP端口已被占用时换一个端口重试
+async fn sockets() -> (UdpSocket, TcpListener) {
+    loop {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        if let Ok(listener) = TcpListener::bind(socket.local_addr().unwrap()).await {
+            return (socket, listener);
+        }
+    }
+}
+
+async fn pool_of(proxies: Vec<String>) -> PoolHandle {
+    let pool = Pool::new_with_proxies(
+        proxies.iter().map(|addr| ProxyConfig::parse(addr).unwrap()).collect(),
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    pool.handle()
+}
+
+async fn fleet() -> SynthFleet {
+    SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn udp_queries_are_forwarded_through_udp_associate() {
+    let resolver = udp_resolver().await;
+    let fleet = fleet().await;
+    let config = DnsServerConfig { transport: DnsTransport::Udp, resolver, ..DnsServerConfig::default() };
+    let (addr, _shutdown) = start_server(fleet.proxies().iter().map(|proxy| proxy.addr.to_string()).collect(), config).await;
+
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client.send_to(&query(0x1234), addr).await.unwrap();
+    let mut buf = [0u8; 512];
+    let n = tokio::time::timeout(Duration::from_secs(5), client.recv(&mut buf)).await.unwrap().unwrap();
+    assert_eq!(&buf[..n], answer(&query(0x1234), b"udp").as_slice());
+}
+
+#[tokio::test]
+async fn tcp_queries_are_forwarded_over_doh() {
+    let resolver = doh_resolver().await;
+    let fleet = fleet().await;
+    let config = DnsServerConfig { doh_url: format!("http://{}/dns-query", resolver), ..DnsServerConfig::default() };
+    let (addr, _shutdown) = start_server(fleet.proxies().iter().map(|proxy| proxy.addr.to_string()).collect(), config).await;
+
+    // 同一个TCP连接上依次发送两个查询
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    for id in [1u16, 2] {
+        let query = query(id);
+        stream.write_u16(query.len() as u16).await.unwrap();
+        stream.write_all(&query).await.unwrap();
+        let len = stream.read_u16().await.unwrap() as usize;
+        let mut response = vec![0u8; len];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(response, answer(&query, b"doh"));
+    }
+}
+
+#[tokio::test]
+async fn empty_pool_answers_servfail() {
+    let server = DnsServer::new(DnsServerConfig::default(), pool_of(Vec::new()).await);
+    let response = server.resolve(IpAddr::V4(Ipv4Addr::LOCALHOST), &query(0x4242)).await.unwrap();
+    assert_eq!(&response[..2], &[0x42, 0x42]);
+    assert_eq!(response[3] & 0x0f, 2, "应答SERVFAIL");
+    assert_eq!(&response[12..], &query(0x4242)[12..], "保留问题部分");
+
+    // 应答报文不是查询，不作应答
+    assert!(server.resolve(IpAddr::V4(Ipv4Addr::LOCALHOST), &answer(&query(1), b"")).await.is_none());
+}
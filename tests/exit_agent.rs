@@ -0,0 +1,178 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{echo_server, listener, start_socks};
+use lokipool::exit_agent::{ExitAgent, ExitAgentConfig};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::{Config, Pool, PoolHandle, PoolOptions};
+use lokipool_api::{ApiConfig, ApiServer};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const TOKEN: &str = "exit-master-token";
+
+/// 启动开启了出口节点注册的中心实例，返回API地址与代理池
+async fn start_central() -> (String, PoolHandle) {
+    let pool = Pool::new_with_proxies(Vec::new(), PoolOptions::default()).handle();
+    let (listener, addr) = listener().await;
+    let api = ApiServer::new(pool.clone(), Config::default(), ApiConfig {
+        exit_token: Some(TOKEN.to_string()),
+        ..ApiConfig::default()
+    });
+    tokio::spawn(async move { api.run_with_listener(listener).await });
+    (format!("http://{}", addr), pool)
+}
+
+/// 启动出口节点并等待其注册到中心实例，返回节点的SOCKS5地址
+async fn start_agent(central: &str, pool: &PoolHandle, allow: &[&str]) -> SocketAddr {
+    let (listener, addr) = listener().await;
+    let agent = ExitAgent::new(ExitAgentConfig {
+        central: central.to_string(),
+        token: TOKEN.to_string(),
+        public_host: Some("127.0.0.1".to_string()),
+        allow: allow.iter().map(|net| net.parse().unwrap()).collect(),
+        ..ExitAgentConfig::default()
+    }).with_listener(listener);
+    tokio::spawn(async move { agent.run().await });
+    for _ in 0..100 {
+        if pool.get_all_proxies().await.iter().any(|proxy| proxy.info.port == addr.port()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    addr
+}
+
+/// 向SOCKS5服务器发起方法协商，返回服务器选择的方法
+async fn negotiate(stream: &mut TcpStream, methods: &[u8]) -> u8 {
+    let mut hello = vec![0x05, methods.len() as u8];
+    hello.extend_from_slice(methods);
+    stream.write_all(&hello).await.unwrap();
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await.unwrap();
+    reply[1]
+}
+
+/// 用户名/密码子协商，返回服务器的应答状态
+async fn login(stream: &mut TcpStream, username: &str, password: &str) -> u8 {
+    let mut request = vec![0x01, username.len() as u8];
+    request.extend_from_slice(username.as_bytes());
+    request.push(password.len() as u8);
+    request.extend_from_slice(password.as_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await.unwrap();
+    reply[1]
+}
+
+#[tokio::test]
+async fn agent_is_registered_with_socks_credentials() {
+    let (central, pool) = start_central().await;
+    let agent = start_agent(&central, &pool, &[]).await;
+
+    let proxies = pool.get_all_proxies().await;
+    assert_eq!(proxies.len(), 1);
+    let proxy = &proxies[0];
+    assert_eq!(proxy.info.port, agent.port());
+    assert!(proxy.info.username.as_deref().is_some_and(|username| username.starts_with("exit-")));
+    assert!(proxy.info.password.is_some());
+
+    let listed: Value = reqwest::Client::new().get(format!("{}/api/v1/exits", central))
+        .bearer_auth(TOKEN)
+        .send().await.unwrap().json().await.unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    // 密钥与SOCKS5账户不出现在列表中
+    assert!(listed[0].get("secret").is_none());
+}
+
+#[tokio::test]
+async fn agent_requires_issued_credentials() {
+    let (central, pool) = start_central().await;
+    let agent = start_agent(&central, &pool, &[]).await;
+    let proxy = pool.get_all_proxies().await.remove(0);
+
+    // 不提供用户名/密码认证的客户端被拒绝
+    let mut stream = TcpStream::connect(agent).await.unwrap();
+    assert_eq!(negotiate(&mut stream, &[0x00]).await, 0xFF);
+
+    let mut stream = TcpStream::connect(agent).await.unwrap();
+    assert_eq!(negotiate(&mut stream, &[0x00, 0x02]).await, 0x02);
+    assert_eq!(login(&mut stream, proxy.info.username.as_deref().unwrap(), "wrong").await, 0x01);
+
+    let mut stream = TcpStream::connect(agent).await.unwrap();
+    assert_eq!(negotiate(&mut stream, &[0x02]).await, 0x02);
+    assert_eq!(login(&mut stream, proxy.info.username.as_deref().unwrap(), proxy.info.password.as_deref().unwrap()).await, 0x00);
+}
+
+#[tokio::test]
+async fn central_relays_through_agent() {
+    let echo = echo_server().await;
+    let (central, pool) = start_central().await;
+    start_agent(&central, &pool, &[]).await;
+
+    let addr = start_socks(SocksServer::new(SocksServerConfig::default(), pool.clone())).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(negotiate(&mut stream, &[0x00]).await, 0x00);
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&echo.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+
+    stream.write_all(b"through the exit").await.unwrap();
+    let mut echoed = [0u8; 16];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"through the exit");
+}
+
+#[tokio::test]
+async fn agent_rejects_sources_outside_allow_list() {
+    let (central, pool) = start_central().await;
+    let agent = start_agent(&central, &pool, &["10.0.0.0/8"]).await;
+
+    let mut stream = TcpStream::connect(agent).await.unwrap();
+    let _ = stream.write_all(&[0x05, 0x01, 0x02]).await;
+    let mut reply = [0u8; 2];
+    let read = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut reply)).await.unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+}
+
+#[tokio::test]
+async fn registry_rejects_invalid_join_token_and_secret() {
+    let (central, _pool) = start_central().await;
+    let client = reqwest::Client::new();
+    let (_listener, agent) = listener().await;
+    let port = agent.port();
+
+    let response = client.post(format!("{}/api/v1/exits", central))
+        .json(&json!({ "token": "wrong", "host": "127.0.0.1", "port": port }))
+        .send().await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    let registered: Value = client.post(format!("{}/api/v1/exits", central))
+        .json(&json!({ "token": TOKEN, "host": "127.0.0.1", "port": port }))
+        .send().await.unwrap().json().await.unwrap();
+    let id = registered["id"].as_str().unwrap();
+    assert!(registered["socks_username"].as_str().is_some_and(|username| !username.is_empty()));
+    assert!(registered["socks_password"].as_str().is_some_and(|password| !password.is_empty()));
+
+    let heartbeat = |secret: &str| client.post(format!("{}/api/v1/exits/{}/heartbeat", central, id))
+        .json(&json!({ "secret": secret }))
+        .send();
+    assert_eq!(heartbeat("wrong").await.unwrap().status(), 401);
+    assert_eq!(heartbeat(registered["secret"].as_str().unwrap()).await.unwrap().status(), 204);
+
+    let deregister = client.delete(format!("{}/api/v1/exits/{}", central, id))
+        .json(&json!({ "secret": "wrong" }))
+        .send().await.unwrap();
+    assert_eq!(deregister.status(), 401);
+    let deregister = client.delete(format!("{}/api/v1/exits/{}", central, id))
+        .json(&json!({ "secret": registered["secret"] }))
+        .send().await.unwrap();
+    assert_eq!(deregister.status(), 204);
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{listener, start_socks_with_shutdown};
+use lokipool::health_server::{HealthServer, HealthServerConfig};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+
+/// 启动SOCKS5服务器与检查它的健康检查端口，返回健康检查地址与两者的关闭信号
+async fn start_servers(pool: PoolHandle, min_available: usize) -> (SocketAddr, broadcast::Sender<()>, broadcast::Sender<()>) {
+    let socks = SocksServer::new(SocksServerConfig::default(), pool.clone());
+    let accepting = socks.accepting();
+    let (_socks_addr, socks_shutdown) = start_socks_with_shutdown(socks).await;
+
+    let (health_listener, addr) = listener().await;
+    let config = HealthServerConfig { min_available, ..HealthServerConfig::default() };
+    let health = HealthServer::new(config, pool, accepting).with_listener(health_listener);
+    let (health_shutdown, health_rx) = broadcast::channel(1);
+    tokio::spawn(async move { health.run_with_shutdown(health_rx).await });
+    (addr, socks_shutdown, health_shutdown)
+}
+
+async fn probe(addr: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /healthz HTTP/1.1\r\nHost: lb\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    response
+}
+
+/// 等待SOCKS5服务器开始接受连接
+async fn probe_until_ok(addr: SocketAddr) -> String {
+    for _ in 0..50 {
+        let response = probe(addr).await;
+        if response.starts_with("HTTP/1.1 200 ") {
+            return response;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    probe(addr).await
+}
+
+#[tokio::test]
+async fn healthy_only_with_enough_proxies_and_an_accepting_listener() {
+    let fleet = SynthFleet::start(&SynthConfig { count: 2, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let proxies = fleet.proxies().iter().map(|proxy| ProxyConfig::parse(&proxy.addr.to_string()).unwrap()).collect();
+    let pool = Pool::new_with_proxies(proxies, PoolOptions::default());
+    pool.test_all().await;
+
+    let (addr, socks_shutdown, _health_shutdown) = start_servers(pool.handle(), 2).await;
+    let response = probe_until_ok(addr).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("accepting=true available=2 min_available=2\n"), "{}", response);
+
+    // SOCKS5主端口停止接受连接后立即报告不健康
+    socks_shutdown.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let response = probe(addr).await;
+    assert!(response.starts_with("HTTP/1.1 503 "), "{}", response);
+    assert!(response.contains("accepting=false"));
+}
+
+#[tokio::test]
+async fn unhealthy_below_min_available_and_answers_bare_tcp_checks() {
+    let pool = Pool::new_with_proxies(Vec::new(), PoolOptions::default());
+    let (addr, _socks_shutdown, _health_shutdown) = start_servers(pool.handle(), 1).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // 只建立连接不发请求的检查在等待超时后也得到应答
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", response);
+    assert!(response.ends_with("accepting=true available=0 min_available=1\n"), "{}", response);
+}
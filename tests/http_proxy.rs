@@ -0,0 +1,114 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{echo_server, start_http};
+use lokipool::http_server::{HttpServer, HttpServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+/// 启动把收到的请求头作为响应体返回的HTTP服务器，返回其端口
+async fn origin_server() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut head = Vec::new();
+                let mut byte = [0u8; 1];
+                while !head.ends_with(b"\r\n\r\n") && stream.read_exact(&mut byte).await.is_ok() {
+                    head.push(byte[0]);
+                }
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", head.len());
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.write_all(&head).await;
+            });
+        }
+    });
+    port
+}
+
+/// 启动使用给定代理的HTTP代理
+async fn start_server(proxies: Vec<ProxyConfig>) -> (SocketAddr, broadcast::Sender<()>) {
+    let pool = Pool::new_with_proxies(proxies, PoolOptions::default());
+    pool.test_all().await;
+    start_http(HttpServer::new(HttpServerConfig::default(), pool.handle())).await
+}
+
+async fn fleet() -> SynthFleet {
+    SynthFleet::start(&SynthConfig { count: 2, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap()
+}
+
+fn proxies(fleet: &SynthFleet) -> Vec<ProxyConfig> {
+    fleet.proxies().iter().map(|proxy| ProxyConfig::parse(&proxy.addr.to_string()).unwrap()).collect()
+}
+
+/// 读到连接关闭为止
+async fn read_all(stream: &mut TcpStream) -> String {
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await.unwrap().unwrap();
+    String::from_utf8(response).unwrap()
+}
+
+#[tokio::test]
+async fn connect_opens_a_tunnel_through_the_pool() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    let (addr, _shutdown) = start_server(proxies(&fleet)).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    // 请求头之后紧跟的数据也要转发给目标
+    let request = format!("CONNECT localhost:{} HTTP/1.1\r\nHost: localhost:{}\r\n\r\nearly", target, target);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let established = b"HTTP/1.1 200 Connection Established\r\n\r\n";
+    let mut reply = vec![0u8; established.len() + 5];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(&reply[..established.len()], established);
+    assert_eq!(&reply[established.len()..], b"early");
+
+    stream.write_all(b"ping").await.unwrap();
+    let mut echoed = [0u8; 4];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"ping");
+}
+
+#[tokio::test]
+async fn absolute_uri_requests_are_rewritten_for_the_origin() {
+    let origin = origin_server().await;
+    let fleet = fleet().await;
+    let (addr, _shutdown) = start_server(proxies(&fleet)).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET http://127.0.0.1:{}/path?q=1 HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nProxy-Connection: keep-alive\r\nProxy-Authorization: Basic eDp5\r\nAccept: */*\r\n\r\n",
+        origin, origin,
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let response = read_all(&mut stream).await;
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    let (_, forwarded) = response.split_once("\r\n\r\n").unwrap();
+    assert!(forwarded.starts_with("GET /path?q=1 HTTP/1.1\r\n"), "{}", forwarded);
+    assert!(forwarded.contains("Accept: */*\r\n"));
+    assert!(forwarded.contains("Connection: close\r\n"));
+    assert!(!forwarded.to_ascii_lowercase().contains("proxy-"), "{}", forwarded);
+}
+
+#[tokio::test]
+async fn errors_are_answered_with_http_status() {
+    let (addr, _shutdown) = start_server(Vec::new()).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"CONNECT example.com:443 HTTP/1.1\r\n\r\n").await.unwrap();
+    assert!(read_all(&mut stream).await.starts_with("HTTP/1.1 503 "));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /relative HTTP/1.1\r\nHost: example.com\r\n\r\n").await.unwrap();
+    assert!(read_all(&mut stream).await.starts_with("HTTP/1.1 400 "));
+}
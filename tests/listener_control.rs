@@ -0,0 +1,117 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::echo_server;
+use lokipool::listeners::ListenerManager;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use lokipool_core::{ListenerControl, ListenerError, ListenerState, SelectionStrategy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn synth_pool() -> (SynthFleet, PoolHandle) {
+    let fleet = SynthFleet::start(&SynthConfig { count: 2, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let configs = fleet.proxies().iter().map(|proxy| ProxyConfig::parse(&proxy.addr.to_string()).unwrap()).collect();
+    let pool = Pool::new_with_proxies(configs, PoolOptions::default());
+    pool.test_all().await;
+    (fleet, pool.handle())
+}
+
+/// 登记并启动一个监听端口，返回时端口已经在接受连接；取到的空闲端口被占用时换一个重试
+async fn manager(pool: PoolHandle) -> (ListenerManager, u16) {
+    loop {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let manager = ListenerManager::new(pool.clone(), SocksServer::new);
+        let config = SocksServerConfig { bind_port: port, drain_timeout: Duration::from_millis(200), ..SocksServerConfig::default() };
+        manager.add(config, None);
+        if manager.start(port).await.is_ok() {
+            return (manager, port);
+        }
+    }
+}
+
+/// 完成握手与CONNECT，返回仍然打开的连接
+async fn connect(addr: SocketAddr, target: u16) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+    stream
+}
+
+#[tokio::test]
+async fn listeners_report_live_connection_counts() {
+    let target = echo_server().await;
+    let (_fleet, pool) = synth_pool().await;
+    let (manager, port) = manager(pool).await;
+    let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+    let open = connect(addr, target).await;
+    let listeners = manager.listeners().await;
+    assert_eq!(listeners.len(), 1);
+    assert_eq!(listeners[0].port, port);
+    assert_eq!(listeners[0].state, ListenerState::Running);
+    assert_eq!(listeners[0].active_connections, 1);
+
+    drop(open);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let listeners = manager.listeners().await;
+    assert_eq!(listeners[0].active_connections, 0);
+    assert_eq!(listeners[0].total_connections, 1);
+}
+
+#[tokio::test]
+async fn stopped_listener_can_be_started_again() {
+    let (_fleet, pool) = synth_pool().await;
+    let (manager, port) = manager(pool).await;
+    let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+    let info = manager.stop(port).await.unwrap();
+    assert_eq!(info.state, ListenerState::Stopped);
+    assert!(TcpStream::connect(addr).await.is_err());
+    assert!(matches!(manager.stop(port).await, Err(ListenerError::NotRunning(_))));
+
+    let info = manager.start(port).await.unwrap();
+    assert_eq!(info.state, ListenerState::Running);
+    assert!(matches!(manager.start(port).await, Err(ListenerError::AlreadyRunning(_))));
+    assert!(matches!(manager.restart(port + 1).await, Err(ListenerError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn occupied_port_is_reported_on_start() {
+    let (_fleet, pool) = synth_pool().await;
+    let (manager, port) = manager(pool).await;
+    manager.stop(port).await.unwrap();
+
+    let _occupied = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    assert!(matches!(manager.start(port).await, Err(ListenerError::Bind(..))));
+    assert_eq!(manager.listeners().await[0].state, ListenerState::Stopped);
+}
+
+#[tokio::test]
+async fn strategy_change_restarts_running_listener() {
+    let target = echo_server().await;
+    let (_fleet, pool) = synth_pool().await;
+    let (manager, port) = manager(pool).await;
+    let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+    let info = manager.set_strategy(port, Some(SelectionStrategy::RoundRobin)).await.unwrap();
+    assert_eq!(info.strategy, Some(SelectionStrategy::RoundRobin));
+    assert_eq!(info.state, ListenerState::Running);
+    drop(connect(addr, target).await);
+
+    manager.stop(port).await.unwrap();
+    let info = manager.set_strategy(port, None).await.unwrap();
+    assert_eq!(info.strategy, None);
+    assert_eq!(info.state, ListenerState::Stopped);
+}
@@ -0,0 +1,49 @@
+use lokipool::shell::{complete, Argument};
+
+fn no_lookup(argument: Argument) -> Vec<String> {
+    panic!("不应查询 {:?} 的候选", argument)
+}
+
+#[test]
+fn completes_command_names() {
+    let (start, candidates) = complete("re", 2, no_lookup);
+    assert_eq!(start, 0);
+    assert_eq!(candidates, ["reload", "remove", "retry"]);
+
+    let (_, candidates) = complete("", 0, no_lookup);
+    assert!(candidates.iter().any(|command| command == "quit"));
+}
+
+#[test]
+fn completes_proxy_arguments_from_lookup() {
+    let lookup = |argument| {
+        assert_eq!(argument, Argument::Proxy);
+        vec!["10.0.0.2:1080".to_string(), "10.0.0.1:1080".to_string(), "192.168.1.1:1080".to_string()]
+    };
+    let (start, candidates) = complete("remove 10.", 10, lookup);
+    assert_eq!(start, "remove ".len());
+    assert_eq!(candidates, ["10.0.0.1:1080", "10.0.0.2:1080"]);
+}
+
+#[test]
+fn completes_fixed_arguments_without_lookup() {
+    let (start, candidates) = complete("loglevel d", 10, no_lookup);
+    assert_eq!(start, "loglevel ".len());
+    assert_eq!(candidates, ["debug"]);
+
+    let (_, candidates) = complete("blocklist ", 10, no_lookup);
+    assert_eq!(candidates, ["clear"]);
+}
+
+#[test]
+fn only_completes_before_cursor_and_first_argument() {
+    // 光标在命令名中间时只看光标之前的部分
+    let (start, candidates) = complete("sho 10.0.0.1", 2, no_lookup);
+    assert_eq!(start, 0);
+    assert_eq!(candidates, ["show"]);
+
+    let (_, candidates) = complete("remove a b", 10, no_lookup);
+    assert!(candidates.is_empty());
+    let (_, candidates) = complete("show x", 6, no_lookup);
+    assert!(candidates.is_empty());
+}
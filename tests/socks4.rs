@@ -0,0 +1,72 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolOptions, ProxyConfig};
+use lokipool_core::SocksAccount;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动SOCKS服务器，上游为一个合成代理
+async fn start_server(fleet: &SynthFleet, accounts: Vec<SocksAccount>) -> SocketAddr {
+    let pool = Pool::new_with_proxies(
+        vec![ProxyConfig::parse(&fleet.proxies()[0].addr.to_string()).unwrap()],
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    start_socks(SocksServer::new(SocksServerConfig { accounts, ..SocksServerConfig::default() }, pool)).await
+}
+
+async fn fleet() -> SynthFleet {
+    SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap()
+}
+
+/// 发送SOCKS4 CONNECT请求，`domain` 不为空时使用4a扩展，返回应答码
+async fn connect(stream: &mut TcpStream, ip: [u8; 4], port: u16, domain: &str) -> u8 {
+    let mut request = vec![0x04, 0x01];
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&ip);
+    request.extend_from_slice(b"tester\0");
+    if !domain.is_empty() {
+        request.extend_from_slice(domain.as_bytes());
+        request.push(0);
+    }
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 8];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[0], 0x00);
+    reply[1]
+}
+
+#[tokio::test]
+async fn socks4_and_socks4a_clients_are_relayed() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    let addr = start_server(&fleet, Vec::new()).await;
+
+    for (ip, domain) in [([127, 0, 0, 1], ""), ([0, 0, 0, 1], "localhost")] {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(connect(&mut stream, ip, target, domain).await, 0x5A);
+        stream.write_all(b"ping").await.unwrap();
+        let mut echoed = [0u8; 4];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping");
+    }
+}
+
+#[tokio::test]
+async fn socks4_is_rejected_when_accounts_are_configured() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    let accounts = vec![SocksAccount { username: "alice".to_string(), password: "wonderland".to_string() }];
+    let addr = start_server(&fleet, accounts).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(connect(&mut stream, [127, 0, 0, 1], target, "").await, 0x5B);
+    assert_eq!(stream.read(&mut [0u8; 1]).await.unwrap(), 0);
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::start_socks;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::{Pool, PoolOptions};
+use lokipool_core::{Acl, AclSettings};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动带访问控制的SOCKS5服务器，代理池为空
+async fn start_server(acl: AclSettings) -> SocketAddr {
+    let config = SocksServerConfig { acl: Acl::from_settings(&acl).unwrap(), ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, Pool::new_with_proxies(Vec::new(), PoolOptions::default()));
+    let addr = start_socks(server).await;
+    addr
+}
+
+#[tokio::test]
+async fn denied_client_is_disconnected_without_reply() {
+    let addr = start_server(AclSettings { deny_clients: vec!["127.0.0.0/8".to_string()], ..AclSettings::default() }).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let _ = stream.write_all(&[0x05, 0x01, 0x00]).await;
+    assert_eq!(stream.read(&mut [0u8; 2]).await.unwrap_or(0), 0);
+}
+
+#[tokio::test]
+async fn denied_destination_is_refused_before_proxy_selection() {
+    let addr = start_server(AclSettings { deny_destinations: vec!["*:25".to_string()], ..AclSettings::default() }).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00, 0x03, 8]).await.unwrap();
+    stream.write_all(b"mail.com").await.unwrap();
+    stream.write_all(&25u16.to_be_bytes()).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    // 空代理池时其他目标得到一般性失败，被拒绝的目标得到规则不允许
+    assert_eq!(reply[1], 0x02);
+}
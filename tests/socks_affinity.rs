@@ -0,0 +1,83 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use lokipool_core::{ProxyAffinity, SelectionStrategy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动SOCKS5服务器，上游为三个合成代理
+async fn start_server(fleet: &SynthFleet, strategy: SelectionStrategy, affinity: ProxyAffinity) -> (SocketAddr, PoolHandle) {
+    let pool = Pool::new_with_proxies(
+        fleet.proxies().iter().map(|proxy| ProxyConfig::parse(&proxy.addr.to_string()).unwrap()).collect(),
+        PoolOptions { strategy, ..PoolOptions::default() },
+    );
+    pool.test_all().await;
+    let pool = pool.handle();
+    let config = SocksServerConfig { affinity, ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, pool.clone());
+    let addr = start_socks(server).await;
+    (addr, pool)
+}
+
+/// 经服务器连接一次回显服务器，返回本次使用的代理ID
+async fn used_proxy(addr: SocketAddr, pool: &PoolHandle, target: u16) -> String {
+    // 使用计数在代理的克隆之间共享，先取出数值
+    let before: Vec<(String, u64)> = pool.get_all_proxies().await
+        .iter()
+        .map(|proxy| (proxy.id.clone(), proxy.usage_stats().total_connections))
+        .collect();
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+
+    let after = pool.get_all_proxies().await;
+    before.into_iter()
+        .find(|(id, total)| after.iter().any(|proxy| proxy.id == *id && proxy.usage_stats().total_connections > *total))
+        .map(|(id, _)| id)
+        .unwrap()
+}
+
+async fn fleet() -> SynthFleet {
+    SynthFleet::start(&SynthConfig { count: 3, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn rotate_never_reuses_the_previous_proxy() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    // 延迟最低策略本会一直选中同一个代理
+    let (addr, pool) = start_server(&fleet, SelectionStrategy::LowestLatency, ProxyAffinity::Rotate).await;
+
+    let mut previous = used_proxy(addr, &pool, target).await;
+    for _ in 0..5 {
+        let current = used_proxy(addr, &pool, target).await;
+        assert_ne!(current, previous);
+        previous = current;
+    }
+}
+
+#[tokio::test]
+async fn pin_keeps_a_client_on_one_proxy() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    // 轮询策略本会每次换一个代理
+    let (addr, pool) = start_server(&fleet, SelectionStrategy::RoundRobin, ProxyAffinity::Pin).await;
+
+    let pinned = used_proxy(addr, &pool, target).await;
+    for _ in 0..5 {
+        assert_eq!(used_proxy(addr, &pool, target).await, pinned);
+    }
+}
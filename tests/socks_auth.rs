@@ -0,0 +1,87 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolOptions, ProxyConfig};
+use lokipool_core::SocksAccount;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动要求认证的SOCKS5服务器，上游为一个合成代理
+async fn start_server(fleet: &SynthFleet) -> SocketAddr {
+    let pool = Pool::new_with_proxies(
+        vec![ProxyConfig::parse(&fleet.proxies()[0].addr.to_string()).unwrap()],
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    let server = SocksServer::new(SocksServerConfig {
+        accounts: vec![
+            SocksAccount { username: "alice".to_string(), password: "wonderland".to_string() },
+            SocksAccount { username: "bob".to_string(), password: "builder".to_string() },
+        ],
+        ..SocksServerConfig::default()
+    }, pool);
+    start_socks(server).await
+}
+
+/// 用户名/密码子协商，返回服务器的应答状态
+async fn login(stream: &mut TcpStream, username: &str, password: &str) -> u8 {
+    stream.write_all(&[0x05, 0x02, 0x00, 0x02]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x02]);
+
+    let mut request = vec![0x01, username.len() as u8];
+    request.extend_from_slice(username.as_bytes());
+    request.push(password.len() as u8);
+    request.extend_from_slice(password.as_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await.unwrap();
+    reply[1]
+}
+
+#[tokio::test]
+async fn authenticated_client_is_relayed() {
+    let target = echo_server().await;
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let addr = start_server(&fleet).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(login(&mut stream, "bob", "builder").await, 0x00);
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+
+    stream.write_all(b"ping").await.unwrap();
+    let mut echoed = [0u8; 4];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"ping");
+}
+
+#[tokio::test]
+async fn wrong_password_and_no_auth_clients_are_rejected() {
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let addr = start_server(&fleet).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(login(&mut stream, "alice", "builder").await, 0x01);
+    assert_eq!(stream.read(&mut [0u8; 1]).await.unwrap(), 0);
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0xFF]);
+    assert_eq!(stream.read(&mut [0u8; 1]).await.unwrap(), 0);
+}
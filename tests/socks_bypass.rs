@@ -0,0 +1,76 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolOptions, ProxyConfig};
+use lokipool_core::{Bypass, BypassSettings};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动带直连列表的SOCKS5服务器，上游为给定的代理
+async fn start_server(proxies: Vec<String>, bypass: BypassSettings) -> SocketAddr {
+    let pool = Pool::new_with_proxies(
+        proxies.iter().map(|addr| ProxyConfig::parse(addr).unwrap()).collect(),
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    let config = SocksServerConfig { bypass: Bypass::from_settings(&bypass).unwrap(), ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, pool.handle());
+    let addr = start_socks(server).await;
+    addr
+}
+
+/// 以域名方式发送CONNECT请求，返回应答码与连接
+async fn connect(addr: SocketAddr, host: &str, port: u16) -> (u8, TcpStream) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    (reply[1], stream)
+}
+
+/// 确认连接能够往返数据
+async fn assert_echo(stream: &mut TcpStream) {
+    stream.write_all(b"direct").await.unwrap();
+    let mut echoed = [0u8; 6];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"direct");
+}
+
+#[tokio::test]
+async fn listed_destination_skips_upstream_proxy() {
+    let target = echo_server().await;
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let bypass = BypassSettings { destinations: vec!["localhost".to_string()], ..BypassSettings::default() };
+    let addr = start_server(vec![fleet.proxies()[0].addr.to_string()], bypass).await;
+    let before = fleet.proxies()[0].stats.connections.load(Ordering::Relaxed);
+
+    let (code, mut stream) = connect(addr, "localhost", target).await;
+    assert_eq!(code, 0x00);
+    assert_echo(&mut stream).await;
+    assert_eq!(fleet.proxies()[0].stats.connections.load(Ordering::Relaxed), before);
+}
+
+#[tokio::test]
+async fn empty_pool_falls_back_to_direct_only_when_enabled() {
+    let target = echo_server().await;
+    let addr = start_server(Vec::new(), BypassSettings::default()).await;
+    let (code, _stream) = connect(addr, "localhost", target).await;
+    assert_eq!(code, 0x01);
+
+    let addr = start_server(Vec::new(), BypassSettings { when_pool_empty: true, ..BypassSettings::default() }).await;
+    let (code, mut stream) = connect(addr, "localhost", target).await;
+    assert_eq!(code, 0x00);
+    assert_echo(&mut stream).await;
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动不会失败的合成代理
+async fn fleet(count: usize) -> SynthFleet {
+    SynthFleet::start(&SynthConfig { count, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap()
+}
+
+/// 启动SOCKS5服务器，池中只有 `proxy`，经 `chain` 连接它
+async fn start_server(proxy: String, chain: Vec<String>) -> (SocketAddr, PoolHandle) {
+    let pool = Pool::new_with_proxies(vec![ProxyConfig::parse(&proxy).unwrap()], PoolOptions::default());
+    pool.test_all().await;
+    let pool = pool.handle();
+    let chain = chain.iter().map(|hop| ProxyConfig::parse(hop).unwrap()).collect();
+    let config = SocksServerConfig { chain, ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, pool.clone());
+    let addr = start_socks(server).await;
+    (addr, pool)
+}
+
+/// 发送CONNECT请求，返回应答码与连接
+async fn connect(addr: SocketAddr, target: u16) -> (u8, TcpStream) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    (reply[1], stream)
+}
+
+#[tokio::test]
+async fn traffic_passes_through_every_hop() {
+    let target = echo_server().await;
+    let fleet = fleet(3).await;
+    let [first, second, exit] = fleet.proxies() else { unreachable!() };
+    let (addr, _pool) = start_server(exit.addr.to_string(), vec![first.addr.to_string(), format!("socks5://{}", second.addr)]).await;
+    let before: Vec<u64> = fleet.proxies().iter().map(|proxy| proxy.stats.connections.load(Ordering::Relaxed)).collect();
+
+    let (code, mut stream) = connect(addr, target).await;
+    assert_eq!(code, 0x00);
+    stream.write_all(b"chained").await.unwrap();
+    let mut echoed = [0u8; 7];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"chained");
+
+    for (proxy, before) in fleet.proxies().iter().zip(before) {
+        assert_eq!(proxy.stats.connections.load(Ordering::Relaxed), before + 1, "代理 {} 应转发一次连接", proxy.addr);
+    }
+}
+
+#[tokio::test]
+async fn broken_hop_is_not_blamed_on_pool_proxy() {
+    let target = echo_server().await;
+    let fleet = fleet(1).await;
+    let dead_hop = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let (addr, pool) = start_server(fleet.proxies()[0].addr.to_string(), vec![dead_hop]).await;
+
+    let (code, _stream) = connect(addr, target).await;
+    assert_eq!(code, 0x01);
+    let failures: u64 = pool.get_all_proxies().await.iter().map(|proxy| proxy.usage_stats().connect_failures).sum();
+    assert_eq!(failures, 0);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{echo_server, listener};
+use lokipool::socks_server::{DrainReport, SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// 启动可关闭的SOCKS5服务器，上游为一个合成代理
+async fn start_server(fleet: &SynthFleet, drain_timeout: Duration) -> (SocketAddr, broadcast::Sender<()>, JoinHandle<DrainReport>) {
+    let pool = Pool::new_with_proxies(vec![ProxyConfig::parse(&fleet.proxies()[0].addr.to_string()).unwrap()], PoolOptions::default());
+    pool.test_all().await;
+    let config = SocksServerConfig { drain_timeout, ..SocksServerConfig::default() };
+    let (listener, addr) = listener().await;
+    let server = SocksServer::new(config, pool.handle()).with_listener(listener);
+    let (shutdown, rx) = broadcast::channel(1);
+    let handle = tokio::spawn(async move { server.run_with_shutdown(rx).await.unwrap() });
+    (addr, shutdown, handle)
+}
+
+/// 经服务器建立到回显服务器的转发
+async fn open_relay(addr: SocketAddr, target: u16) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+    stream
+}
+
+async fn fleet() -> SynthFleet {
+    SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn live_relay_finishes_during_grace_period() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    let (addr, shutdown, handle) = start_server(&fleet, Duration::from_secs(5)).await;
+    let mut stream = open_relay(addr, target).await;
+
+    shutdown.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(TcpStream::connect(addr).await.is_err(), "关闭后不应再接受新连接");
+
+    // 等待期内已有连接照常转发
+    stream.write_all(b"still here").await.unwrap();
+    let mut echoed = [0u8; 10];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"still here");
+    drop(stream);
+
+    let report = tokio::time::timeout(Duration::from_secs(3), handle).await.unwrap().unwrap();
+    assert_eq!(report, DrainReport { drained: 1, cut: 0 });
+}
+
+#[tokio::test]
+async fn relays_are_cut_after_grace_period() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    let (addr, shutdown, handle) = start_server(&fleet, Duration::from_millis(200)).await;
+    let mut stream = open_relay(addr, target).await;
+
+    shutdown.send(()).unwrap();
+    let report = tokio::time::timeout(Duration::from_secs(3), handle).await.unwrap().unwrap();
+    assert_eq!(report, DrainReport { drained: 0, cut: 1 });
+    let closed = tokio::time::timeout(Duration::from_secs(3), stream.read(&mut [0u8; 1])).await.unwrap();
+    assert!(matches!(closed, Ok(0) | Err(_)));
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 没有进程监听的本地地址，连接会被拒绝
+fn dead_proxy() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// 启动SOCKS5服务器，上游为给定的代理
+async fn start_server(proxies: Vec<String>, connect_retries: usize) -> (SocketAddr, PoolHandle) {
+    let pool = Pool::new_with_proxies(
+        proxies.iter().map(|addr| ProxyConfig::parse(addr).unwrap()).collect(),
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    let pool = pool.handle();
+    let config = SocksServerConfig { connect_retries, ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, pool.clone());
+    let addr = start_socks(server).await;
+    (addr, pool)
+}
+
+/// 发送CONNECT请求，返回应答码与连接
+async fn connect(addr: SocketAddr, target: u16) -> (u8, TcpStream) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    (reply[1], stream)
+}
+
+/// 各代理累计的连接失败次数
+async fn connect_failures(pool: &PoolHandle) -> u64 {
+    pool.get_all_proxies().await.iter().map(|proxy| proxy.usage_stats().connect_failures).sum()
+}
+
+#[tokio::test]
+async fn failed_upstream_is_replaced_by_another_proxy() {
+    let target = echo_server().await;
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let proxies = vec![dead_proxy(), dead_proxy(), fleet.proxies()[0].addr.to_string()];
+    let (addr, _pool) = start_server(proxies, 2).await;
+
+    // 无论先选中哪个代理，最多两次重试都能轮到可用的那个
+    for _ in 0..3 {
+        let (code, mut stream) = connect(addr, target).await;
+        assert_eq!(code, 0x00);
+        stream.write_all(b"ping").await.unwrap();
+        let mut echoed = [0u8; 4];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping");
+    }
+}
+
+#[tokio::test]
+async fn client_gets_failure_after_retries_are_exhausted() {
+    let (addr, pool) = start_server(vec![dead_proxy(), dead_proxy(), dead_proxy()], 1).await;
+
+    let (code, _stream) = connect(addr, 80).await;
+    assert_eq!(code, 0x05);
+    assert_eq!(connect_failures(&pool).await, 2);
+
+    let (addr, pool) = start_server(vec![dead_proxy(), dead_proxy()], 5).await;
+    let (code, _stream) = connect(addr, 80).await;
+    assert_eq!(code, 0x05);
+    assert_eq!(connect_failures(&pool).await, 2);
+}
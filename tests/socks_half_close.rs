@@ -0,0 +1,58 @@
+mod common;
+
+use std::time::Duration;
+
+use common::start_socks;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 启动目标服务器：读完客户端发来的全部数据后才应答收到的字节数，然后关闭连接
+async fn upload_server() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut body = Vec::new();
+                stream.read_to_end(&mut body).await.unwrap();
+                stream.write_all(format!("received {}", body.len()).as_bytes()).await.unwrap();
+            });
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn response_after_client_half_close_is_delivered() {
+    let target = upload_server().await;
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let pool = Pool::new_with_proxies(
+        vec![ProxyConfig::parse(&fleet.proxies()[0].addr.to_string()).unwrap()],
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    let server = SocksServer::new(SocksServerConfig::default(), pool.handle());
+    let addr = start_socks(server).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+
+    // 上传完毕后关闭写方向，应答仍应完整送达
+    stream.write_all(&vec![b'x'; 100_000]).await.unwrap();
+    stream.shutdown().await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    assert_eq!(response, "received 100000");
+}
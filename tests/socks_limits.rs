@@ -0,0 +1,63 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use common::start_socks;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动没有任何代理的SOCKS5服务器，只检查握手阶段
+async fn start_server(max_clients: usize, accepts_per_second: u32) -> SocketAddr {
+    let pool = lokipool::Pool::new_with_proxies(Vec::new(), lokipool::PoolOptions::default()).handle();
+    let config = SocksServerConfig { max_clients, accepts_per_second, ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, pool);
+    let addr = start_socks(server).await;
+    addr
+}
+
+/// 发送方法协商并等待服务器应答
+async fn greet(stream: &mut TcpStream) -> std::io::Result<[u8; 2]> {
+    stream.write_all(&[0x05, 0x01, 0x00]).await?;
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await?;
+    Ok(method)
+}
+
+#[tokio::test]
+async fn clients_beyond_limit_wait_for_a_free_slot() {
+    let addr = start_server(1, 0).await;
+    // 等探测连接的名额释放
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut first = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(greet(&mut first).await.unwrap(), [0x05, 0x00]);
+
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    let waiting = tokio::time::timeout(Duration::from_millis(300), greet(&mut second)).await;
+    assert!(waiting.is_err(), "第二个客户端不应在名额释放前被处理");
+
+    drop(first);
+    let mut method = [0u8; 2];
+    tokio::time::timeout(Duration::from_secs(5), second.read_exact(&mut method)).await.unwrap().unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+}
+
+#[tokio::test]
+async fn accepts_are_spaced_by_rate_limit() {
+    let addr = start_server(0, 5).await;
+    // 用完探测连接之后剩余的配额
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    for _ in 0..5 {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let _ = tokio::time::timeout(Duration::from_secs(5), greet(&mut stream)).await;
+    }
+
+    let started = Instant::now();
+    for _ in 0..3 {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(tokio::time::timeout(Duration::from_secs(5), greet(&mut stream)).await.unwrap().unwrap(), [0x05, 0x00]);
+    }
+    assert!(started.elapsed() >= Duration::from_millis(400), "三次接受应至少间隔两个配额周期: {:?}", started.elapsed());
+}
@@ -0,0 +1,73 @@
+use std::net::SocketAddr;
+
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::{Pool, PoolOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+fn ipv6_available() -> bool {
+    std::net::TcpListener::bind("[::1]:0").is_ok()
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// 完成方法协商，确认连接由SOCKS5服务器处理
+async fn negotiate(addr: SocketAddr) {
+    let mut stream = TcpStream::connect(addr).await.unwrap_or_else(|e| panic!("无法连接 {}: {}", addr, e));
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+}
+
+#[tokio::test]
+async fn listens_on_every_configured_address() {
+    if !ipv6_available() {
+        return;
+    }
+    // 先绑定再交给服务器，不必等待服务器开始监听；取到的端口在 ::1 上被占用时换一个重试
+    let (config, listeners) = loop {
+        let config = SocksServerConfig {
+            bind_port: free_port(),
+            bind_addresses: vec!["::1".to_string(), "127.0.0.1".to_string()],
+            ..SocksServerConfig::default()
+        };
+        if let Ok(listeners) = config.bind().await {
+            break (config, listeners);
+        }
+    };
+    assert_eq!(config.listen_addresses(), ["127.0.0.1", "::1"]);
+    assert_eq!(listeners.len(), 2);
+    let port = config.bind_port;
+    let server = SocksServer::new(config, Pool::new_with_proxies(Vec::new(), PoolOptions::default())).with_listeners(listeners);
+    tokio::spawn(async move { server.run().await });
+
+    negotiate(SocketAddr::from(([127, 0, 0, 1], port))).await;
+    negotiate(format!("[::1]:{}", port).parse().unwrap()).await;
+}
+
+#[tokio::test]
+async fn unspecified_ipv6_is_dual_stack_unless_ipv4_is_also_bound() {
+    if !ipv6_available() {
+        return;
+    }
+    let port = free_port();
+    let dual = SocksServerConfig { bind_address: "::".to_string(), bind_port: port, ..SocksServerConfig::default() };
+    let listeners = dual.bind().await.unwrap();
+    assert_eq!(listeners.len(), 1);
+    TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    drop(listeners);
+
+    let port = free_port();
+    let split = SocksServerConfig {
+        bind_address: "0.0.0.0".to_string(),
+        bind_addresses: vec!["::".to_string()],
+        bind_port: port,
+        ..SocksServerConfig::default()
+    };
+    let listeners = split.bind().await.unwrap();
+    assert_eq!(listeners.len(), 2);
+    assert!(listeners[1].local_addr().unwrap().is_ipv6());
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{echo_server, listener};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use lokipool_core::SelectionStrategy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+
+/// 启动合成代理，第一个标记为US，其余标记为JP
+async fn tagged_pool() -> (SynthFleet, PoolHandle) {
+    let fleet = SynthFleet::start(&SynthConfig { count: 3, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let configs = fleet.proxies().iter().enumerate()
+        .map(|(index, proxy)| ProxyConfig {
+            location: Some(if index == 0 { "US" } else { "JP" }.to_string()),
+            ..ProxyConfig::parse(&proxy.addr.to_string()).unwrap()
+        })
+        .collect();
+    let pool = Pool::new_with_proxies(configs, PoolOptions::default());
+    pool.test_all().await;
+    (fleet, pool.handle())
+}
+
+/// 在共用的关闭信号下启动一个监听端口
+async fn start_listener(config: SocksServerConfig, pool: PoolHandle, shutdown: &broadcast::Sender<()>) -> SocketAddr {
+    let (listener, addr) = listener().await;
+    let server = SocksServer::new(config, pool).with_listener(listener);
+    let shutdown = shutdown.subscribe();
+    tokio::spawn(async move { server.run_with_shutdown(shutdown).await });
+    addr
+}
+
+/// 发送CONNECT请求并确认数据往返
+async fn relay_once(addr: SocketAddr, target: u16) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+    stream.write_all(b"ping").await.unwrap();
+    let mut echoed = [0u8; 4];
+    stream.read_exact(&mut echoed).await.unwrap();
+}
+
+/// 各合成代理转发过的连接数
+fn connections(fleet: &SynthFleet) -> Vec<u64> {
+    fleet.proxies().iter().map(|proxy| proxy.stats.connections.load(Ordering::Relaxed)).collect()
+}
+
+#[tokio::test]
+async fn listener_only_uses_proxies_with_its_locations() {
+    let target = echo_server().await;
+    let (fleet, pool) = tagged_pool().await;
+    let (shutdown, _) = broadcast::channel(1);
+    let us = SocksServerConfig { locations: vec!["us".to_string()], ..SocksServerConfig::default() };
+    let addr = start_listener(us, pool.with_strategy(SelectionStrategy::RoundRobin), &shutdown).await;
+
+    let before = connections(&fleet);
+    for _ in 0..3 {
+        relay_once(addr, target).await;
+    }
+    let after = connections(&fleet);
+    assert_eq!(after[0], before[0] + 3);
+    assert_eq!(after[1..], before[1..]);
+}
+
+#[tokio::test]
+async fn listeners_stop_on_shared_shutdown() {
+    let (_fleet, pool) = tagged_pool().await;
+    let (shutdown, _) = broadcast::channel(1);
+    let first = start_listener(SocksServerConfig::default(), pool.clone(), &shutdown).await;
+    let second = start_listener(SocksServerConfig::default(), pool.with_strategy(SelectionStrategy::Random), &shutdown).await;
+
+    shutdown.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    for addr in [first, second] {
+        assert!(TcpStream::connect(addr).await.is_err(), "{} 应已停止监听", addr);
+    }
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{ConnectionInfo, ConnectionObserver, SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolOptions, Proxy, ProxyConfig};
+use lokipool_core::ConnectionSummary;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    Open,
+    Select(String, Option<String>),
+    Bytes(u64, u64),
+    Close(ConnectionSummary),
+}
+
+/// 按连接编号记录收到的事件
+#[derive(Default)]
+struct Recorder {
+    events: Mutex<Vec<(u64, Event)>>,
+}
+
+impl Recorder {
+    fn push(&self, connection: &ConnectionInfo, event: Event) {
+        self.events.lock().unwrap().push((connection.id, event));
+    }
+
+    /// 请求了目标的连接收到的全部事件
+    fn of_target(&self, target: &str) -> Vec<Event> {
+        let events = self.events.lock().unwrap();
+        let id = events.iter()
+            .find(|(_, event)| matches!(event, Event::Select(t, _) if t == target))
+            .map(|(id, _)| *id)
+            .unwrap();
+        events.iter().filter(|(event_id, _)| *event_id == id).map(|(_, event)| event.clone()).collect()
+    }
+}
+
+impl ConnectionObserver for Recorder {
+    fn on_open(&self, connection: &ConnectionInfo) {
+        self.push(connection, Event::Open);
+    }
+
+    fn on_select_proxy(&self, connection: &ConnectionInfo, target: &str, proxy: Option<&Proxy>) {
+        let proxy = proxy.map(|proxy| format!("{}:{}", proxy.info.host, proxy.info.port));
+        self.push(connection, Event::Select(target.to_string(), proxy));
+    }
+
+    fn on_bytes(&self, connection: &ConnectionInfo, up: u64, down: u64) {
+        self.push(connection, Event::Bytes(up, down));
+    }
+
+    fn on_close(&self, connection: &ConnectionInfo, summary: &ConnectionSummary) {
+        self.push(connection, Event::Close(summary.clone()));
+    }
+}
+
+/// 启动只有一个合成代理、注册了两个观察者的SOCKS5服务器
+async fn start_server(fleet: &SynthFleet, observers: &[Arc<Recorder>]) -> SocketAddr {
+    let pool = Pool::new_with_proxies(
+        fleet.proxies().iter().map(|proxy| ProxyConfig::parse(&proxy.addr.to_string()).unwrap()).collect(),
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    let config = SocksServerConfig::default();
+    let server = observers.iter().fold(SocksServer::new(config, pool.handle()), |server, observer| {
+        server.with_observer(Arc::clone(observer) as Arc<dyn ConnectionObserver>)
+    });
+    let addr = start_socks(server).await;
+    addr
+}
+
+#[tokio::test]
+async fn observers_see_the_whole_connection() {
+    let target = echo_server().await;
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let observers = [Arc::new(Recorder::default()), Arc::new(Recorder::default())];
+    let addr = start_server(&fleet, &observers).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+    stream.write_all(b"hello").await.unwrap();
+    stream.read_exact(&mut [0u8; 5]).await.unwrap();
+    drop(stream);
+
+    let target = format!("127.0.0.1:{}", target);
+    for _ in 0..100 {
+        if observers.iter().all(|observer| matches!(observer.of_target(&target).last(), Some(Event::Close(_)))) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let proxy = fleet.proxies()[0].addr.to_string();
+    for observer in &observers {
+        let events = observer.of_target(&target);
+        assert_eq!(events[0], Event::Open);
+        assert_eq!(events[1], Event::Select(target.clone(), Some(proxy.clone())));
+        let (up, down) = events.iter().fold((0, 0), |(up, down), event| match event {
+            Event::Bytes(u, d) => (up + u, down + d),
+            _ => (up, down),
+        });
+        assert_eq!((up, down), (5, 5));
+        let Some(Event::Close(summary)) = events.last() else {
+            panic!("连接结束后应收到 on_close: {:?}", events);
+        };
+        assert!(summary.success);
+        assert_eq!(summary.proxy.as_deref(), Some(proxy.as_str()));
+        assert_eq!((summary.bytes_up, summary.bytes_down), (5, 5));
+    }
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::start_socks;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::{Pool, PoolOptions};
+use lokipool_core::{PolicyAction, PortPolicy, PortPolicySettings, PortRuleSettings, Transport};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动只放行80与443端口的SOCKS5服务器，代理池为空
+async fn start_server() -> SocketAddr {
+    let policy = PortPolicySettings {
+        default_action: PolicyAction::Deny,
+        rules: vec![PortRuleSettings {
+            action: PolicyAction::Allow,
+            ports: vec!["80".to_string(), "443".to_string()],
+            protocol: Transport::Tcp,
+            log: true,
+        }],
+    };
+    let config = SocksServerConfig {
+        port_policy: PortPolicy::from_settings(&policy).unwrap(),
+        ..SocksServerConfig::default()
+    };
+    let server = SocksServer::new(config, Pool::new_with_proxies(Vec::new(), PoolOptions::default()));
+    let addr = start_socks(server).await;
+    addr
+}
+
+/// 发送CONNECT请求并返回应答码
+async fn connect_reply(addr: SocketAddr, port: u16) -> u8 {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00, 0x03, 11]).await.unwrap();
+    stream.write_all(b"example.com").await.unwrap();
+    stream.write_all(&port.to_be_bytes()).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    reply[1]
+}
+
+#[tokio::test]
+async fn ports_outside_policy_are_not_allowed() {
+    let addr = start_server().await;
+
+    assert_eq!(connect_reply(addr, 25).await, 0x02);
+    // 放行的端口进入代理选择，空代理池时得到一般性失败
+    assert_eq!(connect_reply(addr, 443).await, 0x01);
+}
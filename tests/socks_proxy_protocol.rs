@@ -0,0 +1,66 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::start_socks;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::{Pool, PoolOptions};
+use lokipool_core::{Acl, AclSettings};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动只允许 203.0.113.7 的SOCKS5服务器，连接须带PROXY协议头，代理池为空
+async fn start_server() -> SocketAddr {
+    let acl = AclSettings { allow_clients: vec!["203.0.113.7".to_string()], ..AclSettings::default() };
+    let config = SocksServerConfig {
+        proxy_protocol: true,
+        acl: Acl::from_settings(&acl).unwrap(),
+        ..SocksServerConfig::default()
+    };
+    let server = SocksServer::new(config, Pool::new_with_proxies(Vec::new(), PoolOptions::default()));
+    start_socks(server).await
+}
+
+/// 发送给定的前缀与SOCKS5问候，返回读到的回复字节数
+async fn greet(addr: SocketAddr, prefix: &[u8]) -> usize {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut request = prefix.to_vec();
+    request.extend_from_slice(&[0x05, 0x01, 0x00]);
+    let _ = stream.write_all(&request).await;
+    let mut reply = [0u8; 2];
+    match tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut reply)).await.unwrap() {
+        Ok(_) => {
+            assert_eq!(reply, [0x05, 0x00]);
+            2
+        }
+        Err(_) => 0,
+    }
+}
+
+#[tokio::test]
+async fn v1_header_supplies_the_client_address() {
+    let addr = start_server().await;
+
+    assert_eq!(greet(addr, b"PROXY TCP4 203.0.113.7 127.0.0.1 51234 1080\r\n").await, 2);
+    // 连接实际来自127.0.0.1，由协议头声明的地址决定是否允许
+    assert_eq!(greet(addr, b"PROXY TCP4 198.51.100.1 127.0.0.1 51234 1080\r\n").await, 0);
+}
+
+#[tokio::test]
+async fn v2_header_supplies_the_client_address() {
+    let addr = start_server().await;
+
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend_from_slice(&[0x21, 0x11, 0x00, 0x0c, 203, 0, 113, 7, 127, 0, 0, 1]);
+    header.extend_from_slice(&51234u16.to_be_bytes());
+    header.extend_from_slice(&1080u16.to_be_bytes());
+    assert_eq!(greet(addr, &header).await, 2);
+}
+
+#[tokio::test]
+async fn connections_without_a_header_are_dropped() {
+    let addr = start_server().await;
+
+    assert_eq!(greet(addr, b"").await, 0);
+}
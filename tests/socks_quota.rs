@@ -0,0 +1,68 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::{Pool, PoolOptions};
+use lokipool_core::{Bypass, BypassSettings, QuotaLimits};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动带客户端配额的SOCKS5服务器，代理池为空，localhost直接连接
+async fn start_server(quota: QuotaLimits) -> SocketAddr {
+    let bypass = BypassSettings { destinations: vec!["localhost".to_string()], ..BypassSettings::default() };
+    let config = SocksServerConfig { quota, bypass: Bypass::from_settings(&bypass).unwrap(), ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, Pool::new_with_proxies(Vec::new(), PoolOptions::default()));
+    start_socks(server).await
+}
+
+/// 发送到 localhost 的CONNECT请求，返回应答码与连接
+async fn connect(addr: SocketAddr, port: u16) -> (u8, TcpStream) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00, 0x03, 9]).await.unwrap();
+    stream.write_all(b"localhost").await.unwrap();
+    stream.write_all(&port.to_be_bytes()).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    (reply[1], stream)
+}
+
+#[tokio::test]
+async fn concurrent_connections_over_quota_are_refused() {
+    let target = echo_server().await;
+    let addr = start_server(QuotaLimits { max_connections: 1, ..QuotaLimits::default() }).await;
+
+    let (reply, first) = connect(addr, target).await;
+    assert_eq!(reply, 0x00);
+    let (reply, _) = connect(addr, target).await;
+    assert_eq!(reply, 0x02);
+
+    // 第一个连接结束后名额释放
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let (reply, _) = connect(addr, target).await;
+    assert_eq!(reply, 0x00);
+}
+
+#[tokio::test]
+async fn daily_traffic_over_quota_refuses_new_connections() {
+    let target = echo_server().await;
+    let addr = start_server(QuotaLimits { max_bytes_per_day: 1024, ..QuotaLimits::default() }).await;
+
+    let (reply, mut stream) = connect(addr, target).await;
+    assert_eq!(reply, 0x00);
+    stream.write_all(&[0u8; 1024]).await.unwrap();
+    stream.read_exact(&mut [0u8; 1024]).await.unwrap();
+    // 进行中的连接超出配额后仍然可以继续使用
+    let (reply, _) = connect(addr, target).await;
+    assert_eq!(reply, 0x00);
+
+    drop(stream);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let (reply, _) = connect(addr, target).await;
+    assert_eq!(reply, 0x02);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig, ProxyStatus};
+use lokipool_core::SelectionStrategy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动开启候选竞速的SOCKS5服务器，上游为给定的代理并轮流作为首选，失败后不重试
+async fn start_server(proxies: Vec<String>) -> (SocketAddr, PoolHandle) {
+    let pool = Pool::new_with_proxies(
+        proxies.iter().map(|addr| ProxyConfig::parse(addr).unwrap()).collect(),
+        PoolOptions { race_candidates: true, strategy: SelectionStrategy::RoundRobin, ..PoolOptions::default() },
+    );
+    pool.test_all().await;
+    let pool = pool.handle();
+    let config = SocksServerConfig { connect_retries: 0, ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, pool.clone());
+    let addr = start_socks(server).await;
+    (addr, pool)
+}
+
+/// 发送CONNECT请求并确认连接能够往返数据，返回应答码
+async fn connect(addr: SocketAddr, target: u16) -> u8 {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    if reply[1] == 0x00 {
+        stream.write_all(b"race").await.unwrap();
+        let mut echoed = [0u8; 4];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"race");
+    }
+    reply[1]
+}
+
+#[tokio::test]
+async fn slow_candidate_loses_the_race() {
+    let target = echo_server().await;
+    // 第一个代理立即握手，第二个每次握手延迟1.5秒
+    let fleet = SynthFleet::start(&SynthConfig { count: 2, latency_ms: Spread { min: 0.0, max: 1500.0 }, failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let (addr, pool) = start_server(fleet.proxies().iter().map(|proxy| proxy.addr.to_string()).collect()).await;
+
+    for _ in 0..4 {
+        let started = Instant::now();
+        assert_eq!(connect(addr, target).await, 0x00);
+        assert!(started.elapsed() < Duration::from_millis(1000), "耗时 {:?}", started.elapsed());
+    }
+    // 被取消的候选不算失败
+    let proxies = pool.get_all_proxies().await;
+    assert!(proxies.iter().all(|proxy| proxy.status == ProxyStatus::Available));
+}
+
+#[tokio::test]
+async fn failed_candidate_does_not_fail_the_connection() {
+    let target = echo_server().await;
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let (addr, _pool) = start_server(vec![dead, fleet.proxies()[0].addr.to_string()]).await;
+
+    // 不重试时，失败的候选与成功的候选在同一轮中竞速，连接仍然成功
+    for _ in 0..4 {
+        assert_eq!(connect(addr, target).await, 0x00);
+    }
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::listener;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::{Pool, PoolOptions};
+use lokipool_core::{Acl, AclSettings, SocksAccount};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, watch};
+
+/// 启动可重新加载设置的SOCKS5服务器，代理池为空
+async fn start_server() -> (SocketAddr, SocksServerConfig, watch::Sender<SocksServerConfig>, broadcast::Sender<()>) {
+    let (listener, addr) = listener().await;
+    let config = SocksServerConfig { bind_port: addr.port(), ..SocksServerConfig::default() };
+    let (reload, rx) = watch::channel(config.clone());
+    let server = SocksServer::new(config.clone(), Pool::new_with_proxies(Vec::new(), PoolOptions::default()))
+        .with_reload(rx)
+        .with_listener(listener);
+    let (shutdown, shutdown_rx) = broadcast::channel(1);
+    tokio::spawn(async move { server.run_with_shutdown(shutdown_rx).await });
+    (addr, config, reload, shutdown)
+}
+
+/// 协商无认证方法，返回服务器选择的方法
+async fn greet(stream: &mut TcpStream) -> u8 {
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    method[1]
+}
+
+/// 请求连接 mail.com:25，返回应答码
+async fn connect_smtp(stream: &mut TcpStream) -> u8 {
+    stream.write_all(&[0x05, 0x01, 0x00, 0x03, 8]).await.unwrap();
+    stream.write_all(b"mail.com").await.unwrap();
+    stream.write_all(&25u16.to_be_bytes()).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    reply[1]
+}
+
+#[tokio::test]
+async fn reloaded_acl_applies_to_new_connections() {
+    let (addr, config, reload, _shutdown) = start_server().await;
+    let mut before = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(greet(&mut before).await, 0x00);
+
+    let acl = Acl::from_settings(&AclSettings { deny_destinations: vec!["*:25".to_string()], ..AclSettings::default() }).unwrap();
+    reload.send(SocksServerConfig { acl, ..config }).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut after = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(greet(&mut after).await, 0x00);
+    assert_eq!(connect_smtp(&mut after).await, 0x02);
+    // 重新加载之前接受的连接仍使用原来的设置，空代理池时得到一般失败
+    assert_eq!(connect_smtp(&mut before).await, 0x01);
+}
+
+#[tokio::test]
+async fn reloaded_accounts_require_authentication() {
+    let (addr, config, reload, _shutdown) = start_server().await;
+    let accounts = vec![SocksAccount { username: "alice".to_string(), password: "secret".to_string() }];
+    reload.send(SocksServerConfig { accounts, bind_port: 1, ..config }).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // 监听端口的变化被忽略，原端口继续服务
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(greet(&mut stream).await, 0xFF);
+}
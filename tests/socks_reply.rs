@@ -0,0 +1,76 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::start_socks;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 启动SOCKS5服务器，上游为给定的代理
+async fn start_server(proxies: Vec<ProxyConfig>) -> SocketAddr {
+    let pool = Pool::new_with_proxies(proxies, PoolOptions::default());
+    pool.test_all().await;
+    start_socks(SocksServer::new(SocksServerConfig::default(), pool)).await
+}
+
+/// 发送请求并返回应答码
+async fn request(addr: SocketAddr, command: u8, port: u16) -> u8 {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    let mut request = vec![0x05, command, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[0], 0x05);
+    assert_eq!(stream.read(&mut [0u8; 1]).await.unwrap(), 0);
+    reply[1]
+}
+
+#[tokio::test]
+async fn refused_target_is_reported_as_connection_refused() {
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let addr = start_server(vec![ProxyConfig::parse(&fleet.proxies()[0].addr.to_string()).unwrap()]).await;
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+    assert_eq!(request(addr, 0x01, closed).await, 0x05);
+}
+
+#[tokio::test]
+async fn unsupported_command_and_empty_pool_are_reported() {
+    let addr = start_server(Vec::new()).await;
+
+    assert_eq!(request(addr, 0x02, 80).await, 0x07);
+    assert_eq!(request(addr, 0x01, 80).await, 0x01);
+}
+
+#[tokio::test]
+async fn connect_reply_carries_bound_address() {
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target_port = target.local_addr().unwrap().port();
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let addr = start_server(vec![ProxyConfig::parse(&fleet.proxies()[0].addr.to_string()).unwrap()]).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target_port.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+
+    // 合成代理以它连接目标所用的地址作为绑定地址
+    let (_, peer) = target.accept().await.unwrap();
+    assert_eq!(reply[..8], [0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1]);
+    assert_eq!(u16::from_be_bytes([reply[8], reply[9]]), peer.port());
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolOptions, ProxyConfig};
+use lokipool_core::SelectionStrategy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动轮询三个合成代理的SOCKS5服务器
+async fn start_server(fleet: &SynthFleet, sticky_target_ttl: Duration) -> SocketAddr {
+    let pool = Pool::new_with_proxies(
+        fleet.proxies().iter().map(|proxy| ProxyConfig::parse(&proxy.addr.to_string()).unwrap()).collect(),
+        PoolOptions { strategy: SelectionStrategy::RoundRobin, ..PoolOptions::default() },
+    );
+    pool.test_all().await;
+    let config = SocksServerConfig { sticky_target_ttl, ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, pool.handle());
+    let addr = start_socks(server).await;
+    addr
+}
+
+/// 以域名方式连接目标并往返一次数据
+async fn relay_once(addr: SocketAddr, host: &str, port: u16) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+    stream.write_all(b"ping").await.unwrap();
+    stream.read_exact(&mut [0u8; 4]).await.unwrap();
+}
+
+/// 本次连接经过的代理序号
+async fn used_proxy(fleet: &SynthFleet, addr: SocketAddr, host: &str, port: u16) -> usize {
+    let before: Vec<u64> = fleet.proxies().iter().map(|proxy| proxy.stats.connections.load(Ordering::Relaxed)).collect();
+    relay_once(addr, host, port).await;
+    fleet.proxies().iter().zip(before)
+        .position(|(proxy, before)| proxy.stats.connections.load(Ordering::Relaxed) > before)
+        .unwrap()
+}
+
+async fn fleet() -> SynthFleet {
+    SynthFleet::start(&SynthConfig { count: 3, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn same_target_reuses_proxy_until_ttl_expires() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    let addr = start_server(&fleet, Duration::from_millis(500)).await;
+
+    let first = used_proxy(&fleet, addr, "localhost", target).await;
+    for _ in 0..3 {
+        assert_eq!(used_proxy(&fleet, addr, "LOCALHOST", target).await, first);
+    }
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let mut after: HashSet<usize> = HashSet::new();
+    for _ in 0..3 {
+        after.insert(used_proxy(&fleet, addr, "localhost", target).await);
+        tokio::time::sleep(Duration::from_millis(600)).await;
+    }
+    assert!(after.len() > 1, "有效期过后应重新选择代理");
+}
+
+#[tokio::test]
+async fn without_ttl_targets_follow_the_strategy() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    let addr = start_server(&fleet, Duration::ZERO).await;
+
+    let mut used = HashSet::new();
+    for _ in 0..3 {
+        used.insert(used_proxy(&fleet, addr, "localhost", target).await);
+    }
+    assert_eq!(used.len(), 3);
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动SOCKS5服务器，上游为一个合成代理
+async fn start_server(fleet: &SynthFleet, idle_timeout: Duration, max_lifetime: Duration) -> (SocketAddr, PoolHandle) {
+    let pool = Pool::new_with_proxies(
+        vec![ProxyConfig::parse(&fleet.proxies()[0].addr.to_string()).unwrap()],
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    let pool = pool.handle();
+    let config = SocksServerConfig { idle_timeout, max_lifetime, ..SocksServerConfig::default() };
+    let server = SocksServer::new(config, pool.clone());
+    let addr = start_socks(server).await;
+    (addr, pool)
+}
+
+async fn fleet() -> SynthFleet {
+    SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap()
+}
+
+/// 经服务器连接到回显服务器
+async fn connect(addr: SocketAddr, target: u16) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+    stream
+}
+
+#[tokio::test]
+async fn idle_relay_is_closed_and_slot_released() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    let (addr, pool) = start_server(&fleet, Duration::from_millis(200), Duration::ZERO).await;
+
+    let mut stream = connect(addr, target).await;
+    stream.write_all(b"ping").await.unwrap();
+    stream.read_exact(&mut [0u8; 4]).await.unwrap();
+    assert_eq!(pool.get_all_proxies().await[0].active_connections(), 1);
+
+    let started = Instant::now();
+    let closed = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut [0u8; 1])).await.unwrap().unwrap();
+    assert_eq!(closed, 0);
+    assert!(started.elapsed() >= Duration::from_millis(150));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(pool.get_all_proxies().await[0].active_connections(), 0);
+}
+
+#[tokio::test]
+async fn busy_relay_is_closed_after_max_lifetime() {
+    let target = echo_server().await;
+    let fleet = fleet().await;
+    let (addr, _pool) = start_server(&fleet, Duration::ZERO, Duration::from_millis(300)).await;
+
+    let mut stream = connect(addr, target).await;
+    let started = Instant::now();
+    let mut echoed = [0u8; 4];
+    loop {
+        if stream.write_all(b"ping").await.is_err() || stream.read_exact(&mut echoed).await.is_err() {
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5), "连接没有在最长存活时间后关闭");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(started.elapsed() >= Duration::from_millis(250));
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 启动SOCKS5服务器，上游为一个合成代理
+async fn start_server(fleet: &SynthFleet) -> (SocketAddr, PoolHandle) {
+    let pool = Pool::new_with_proxies(
+        vec![ProxyConfig::parse(&fleet.proxies()[0].addr.to_string()).unwrap()],
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    let pool = pool.handle();
+    let addr = start_socks(SocksServer::new(SocksServerConfig::default(), pool.clone())).await;
+    (addr, pool)
+}
+
+/// 经服务器连接到回显服务器，收发一次数据后关闭
+async fn exchange(addr: SocketAddr, target: u16, payload: &[u8]) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+
+    stream.write_all(payload).await.unwrap();
+    let mut echoed = vec![0u8; payload.len()];
+    stream.read_exact(&mut echoed).await.unwrap();
+    stream.shutdown().await.unwrap();
+    assert_eq!(stream.read(&mut [0u8; 1]).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn traffic_is_accounted_per_client_and_destination() {
+    let target = echo_server().await;
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let (addr, pool) = start_server(&fleet).await;
+
+    exchange(addr, target, b"hello").await;
+    exchange(addr, target, b"world!").await;
+
+    // 连接摘要在服务端任务结束时才计入
+    let mut report = pool.traffic().report();
+    for _ in 0..50 {
+        if report.clients.first().is_some_and(|entry| entry.stats.connections == 2) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        report = pool.traffic().report();
+    }
+    assert_eq!(report.clients.len(), 1);
+    assert_eq!(report.clients[0].key, "127.0.0.1");
+    assert_eq!(report.clients[0].stats.connections, 2);
+    assert_eq!(report.clients[0].stats.bytes_up, 11);
+    assert_eq!(report.clients[0].stats.bytes_down, 11);
+    assert_eq!(report.destinations.len(), 1);
+    assert_eq!(report.destinations[0].key, "127.0.0.1");
+
+    pool.traffic().clear();
+    assert!(pool.traffic().report().clients.is_empty());
+}
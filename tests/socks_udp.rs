@@ -0,0 +1,86 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::start_socks;
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::{Pool, PoolHandle, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+/// 启动UDP回显服务器，返回其地址
+async fn udp_echo_server() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 1024];
+        while let Ok((n, from)) = socket.recv_from(&mut buf).await {
+            let _ = socket.send_to(&buf[..n], from).await;
+        }
+    });
+    addr
+}
+
+/// 启动SOCKS5服务器，上游为一个合成代理
+async fn start_server(udp: bool) -> (SocketAddr, PoolHandle, SynthFleet) {
+    let fleet = SynthFleet::start(&SynthConfig { count: 1, latency_ms: Spread::fixed(0.0), failure_rate: Spread::fixed(0.0), udp, ..SynthConfig::default() })
+        .await
+        .unwrap();
+    let pool = Pool::new_with_proxies(
+        vec![ProxyConfig::parse(&fleet.proxies()[0].addr.to_string()).unwrap()],
+        PoolOptions::default(),
+    );
+    pool.test_all().await;
+    let pool = pool.handle();
+    let addr = start_socks(SocksServer::new(SocksServerConfig::default(), pool.clone())).await;
+    (addr, pool, fleet)
+}
+
+/// 发送UDP ASSOCIATE请求
+async fn associate(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+    stream.write_all(&[0x05, 0x03, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await.unwrap();
+    stream
+}
+
+#[tokio::test]
+async fn datagrams_are_relayed_through_upstream() {
+    let target = udp_echo_server().await;
+    let (addr, pool, _fleet) = start_server(true).await;
+
+    let mut control = associate(addr).await;
+    let mut reply = [0u8; 10];
+    control.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[..4], [0x05, 0x00, 0x00, 0x01]);
+    let relay = SocketAddr::from(([reply[4], reply[5], reply[6], reply[7]], u16::from_be_bytes([reply[8], reply[9]])));
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut datagram = vec![0x00, 0x00, 0x00, 0x01, 127, 0, 0, 1];
+    datagram.extend_from_slice(&target.port().to_be_bytes());
+    datagram.extend_from_slice(b"ping");
+    socket.send_to(&datagram, relay).await.unwrap();
+
+    let mut buf = [0u8; 64];
+    let (n, from) = tokio::time::timeout(Duration::from_secs(5), socket.recv_from(&mut buf)).await.unwrap().unwrap();
+    assert_eq!(from, relay);
+    assert_eq!(&buf[..n], &datagram[..]);
+    assert_eq!(pool.get_all_proxies().await[0].udp, Some(true));
+}
+
+#[tokio::test]
+async fn proxy_without_udp_is_marked_unsupported() {
+    let (addr, pool, _fleet) = start_server(false).await;
+
+    let mut control = associate(addr).await;
+    let mut reply = [0u8; 10];
+    control.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x07);
+    assert_eq!(control.read(&mut [0u8; 1]).await.unwrap(), 0);
+    assert_eq!(pool.get_all_proxies().await[0].udp, Some(false));
+}
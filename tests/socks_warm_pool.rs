@@ -0,0 +1,98 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{echo_server, start_socks};
+use lokipool::socks_server::{SocksServer, SocksServerConfig};
+use lokipool::warm_pool::{WarmPool, WarmPoolOptions};
+use lokipool::{Pool, PoolOptions, ProxyConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 启动只支持IPv4 CONNECT的上游SOCKS5代理，返回其地址
+async fn upstream_proxy() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut greeting = [0u8; 3];
+                stream.read_exact(&mut greeting).await?;
+                stream.write_all(&[0x05, 0x00]).await?;
+                let mut request = [0u8; 10];
+                stream.read_exact(&mut request).await?;
+                let target = SocketAddr::from(([request[4], request[5], request[6], request[7]], u16::from_be_bytes([request[8], request[9]])));
+                let mut target = TcpStream::connect(target).await?;
+                stream.write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+                tokio::io::copy_bidirectional(&mut stream, &mut target).await?;
+                Ok::<_, std::io::Error>(())
+            });
+        }
+    });
+    addr
+}
+
+/// 启动使用一个上游代理的服务器，返回监听地址与预热池
+async fn start_server(warm_pool: WarmPoolOptions) -> (SocketAddr, WarmPool) {
+    let upstream = upstream_proxy().await;
+    let pool = Pool::new_with_proxies(vec![ProxyConfig::parse(&upstream.to_string()).unwrap()], PoolOptions::default());
+    pool.test_all().await;
+    let server = SocksServer::new(SocksServerConfig { warm_pool, ..SocksServerConfig::default() }, pool);
+    let warm = server.warm_pool();
+    (start_socks(server).await, warm)
+}
+
+/// 经服务器连接到回显服务器并确认数据原样返回
+async fn echo_through(addr: SocketAddr, target: u16) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream.read_exact(&mut [0u8; 2]).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&target.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+    stream.write_all(b"ping").await.unwrap();
+    let mut echoed = [0u8; 4];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"ping");
+}
+
+/// 等到预热池保持 `count` 个连接
+async fn wait_for_idle(warm: &WarmPool, count: usize) {
+    for _ in 0..100 {
+        if warm.idle() == count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("预热池只有 {} 个连接，期望 {} 个", warm.idle(), count);
+}
+
+#[tokio::test]
+async fn connect_uses_prehandshaked_connection_and_refills() {
+    let target = echo_server().await;
+    let (addr, warm) = start_server(WarmPoolOptions { size: 2, proxies: 1, ..WarmPoolOptions::default() }).await;
+    wait_for_idle(&warm, 2).await;
+
+    echo_through(addr, target).await;
+    assert_eq!((warm.hits(), warm.misses()), (1, 0));
+    // 取用后立即补足
+    wait_for_idle(&warm, 2).await;
+    echo_through(addr, target).await;
+    assert_eq!(warm.hits(), 2);
+}
+
+#[tokio::test]
+async fn stale_warm_connection_is_not_used() {
+    let target = echo_server().await;
+    let (addr, warm) = start_server(WarmPoolOptions { size: 1, proxies: 1, idle_timeout: Duration::from_millis(100) }).await;
+    wait_for_idle(&warm, 1).await;
+
+    // 闲置超时的预热连接不再交给客户端
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    echo_through(addr, target).await;
+    assert_eq!(warm.hits(), 0);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use common::echo_server;
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::ProxyConfig;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// 通过SOCKS5代理连接 127.0.0.1:port，返回连接与应答码
+async fn socks_connect(proxy: std::net::SocketAddr, port: u16) -> (TcpStream, u8) {
+    let mut stream = TcpStream::connect(proxy).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0u8; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    (stream, reply[1])
+}
+
+fn config(count: usize, latency_ms: Spread, failure_rate: Spread) -> SynthConfig {
+    SynthConfig { count, latency_ms, failure_rate, ..SynthConfig::default() }
+}
+
+#[tokio::test]
+async fn healthy_proxy_relays_after_latency() {
+    let target = echo_server().await;
+    let fleet = SynthFleet::start(&config(1, Spread::fixed(100.0), Spread::fixed(0.0))).await.unwrap();
+    let proxy = &fleet.proxies()[0];
+
+    let started = Instant::now();
+    let (mut stream, rep) = socks_connect(proxy.addr, target).await;
+    assert_eq!(rep, 0x00);
+    assert!(started.elapsed() >= Duration::from_millis(100));
+
+    stream.write_all(b"ping").await.unwrap();
+    let mut echoed = [0u8; 4];
+    stream.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"ping");
+    assert_eq!(proxy.stats.connections.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn failing_proxy_rejects_requests() {
+    let target = echo_server().await;
+    let fleet = SynthFleet::start(&config(1, Spread::fixed(0.0), Spread::fixed(1.0))).await.unwrap();
+    let proxy = &fleet.proxies()[0];
+
+    let (_, rep) = socks_connect(proxy.addr, target).await;
+    assert_eq!(rep, 0x01);
+    assert_eq!(proxy.stats.failures.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn parameters_spread_across_servers_and_file_loads() {
+    let fleet = SynthFleet::start(&config(3, Spread { min: 0.0, max: 200.0 }, Spread { min: 0.0, max: 0.5 }))
+        .await
+        .unwrap();
+    let latencies: Vec<u128> = fleet.proxies().iter().map(|p| p.latency.as_millis()).collect();
+    let failure_rates: Vec<f64> = fleet.proxies().iter().map(|p| p.failure_rate).collect();
+    assert_eq!(latencies, vec![0, 100, 200]);
+    assert_eq!(failure_rates, vec![0.0, 0.25, 0.5]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("synth_proxies.txt");
+    fleet.write_proxy_file(&path).unwrap();
+    let loaded = ProxyConfig::load_list(&path).unwrap();
+    let ports: Vec<u16> = loaded.iter().map(|p| p.port).collect();
+    let expected: Vec<u16> = fleet.proxies().iter().map(|p| p.addr.port()).collect();
+    assert_eq!(ports, expected);
+}
+
+#[test]
+fn parses_ranges_and_rejects_invalid_ones() {
+    assert_eq!(Spread::parse("50-500").unwrap(), Spread { min: 50.0, max: 500.0 });
+    assert_eq!(Spread::parse("0.1").unwrap(), Spread::fixed(0.1));
+    assert!(Spread::parse("500-50").is_err());
+    assert!(Spread::parse("abc").is_err());
+
+    let args = ["--count", "5", "--failure-rate", "0-0.3"].map(String::from);
+    let config = SynthConfig::from_args(args).unwrap();
+    assert_eq!(config.count, 5);
+    assert_eq!(config.failure_rate, Spread { min: 0.0, max: 0.3 });
+    assert!(SynthConfig::from_args(["--failure-rate", "2"].map(String::from)).is_err());
+}
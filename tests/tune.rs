@@ -0,0 +1,68 @@
+use std::fs;
+
+use lokipool::synth::{Spread, SynthConfig, SynthFleet};
+use lokipool::tune::{self, TuneConfig};
+use lokipool::Config;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// 启动一个对任何请求都返回 `size` 字节响应体的HTTP服务器，返回其端口
+async fn http_server(size: usize) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", size);
+                let _ = stream.write_all(header.as_bytes()).await;
+                let _ = stream.write_all(&vec![b'x'; size]).await;
+            });
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn tune_writes_overlay_that_overrides_config() {
+    let port = http_server(512 * 1024).await;
+    let fleet = SynthFleet::start(&SynthConfig {
+        count: 3,
+        latency_ms: Spread { min: 20.0, max: 60.0 },
+        failure_rate: Spread::fixed(0.0),
+        ..SynthConfig::default()
+    })
+    .await
+    .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let proxy_file = dir.path().join("proxies.txt");
+    fleet.write_proxy_file(&proxy_file).unwrap();
+    let config_path = dir.path().join("config.toml");
+    fs::write(&config_path, format!("[proxy]\nproxy_file = {:?}\n", proxy_file.display().to_string())).unwrap();
+
+    let recommendations = tune::run(TuneConfig {
+        config: config_path.clone(),
+        url: Some(format!("http://127.0.0.1:{}/file", port)),
+        rounds: 2,
+        ..TuneConfig::default()
+    })
+    .await
+    .unwrap();
+
+    let keys: Vec<&str> = recommendations.iter().map(|rec| rec.key).collect();
+    for key in ["handshake_timeout_ms", "test_timeout", "relay_buffer_size", "retry_concurrency"] {
+        assert!(keys.contains(&key), "缺少推荐项 {}", key);
+    }
+
+    // 覆盖文件中的值优先，其余配置保持不变
+    let overlay = Config::overlay_path(&config_path);
+    assert_eq!(overlay, dir.path().join("config.tuned.toml"));
+    let config = Config::from_file(&config_path).unwrap().with_overlay(&overlay).unwrap();
+    let value = |key: &str| recommendations.iter().find(|rec| rec.key == key).unwrap().value;
+    assert_eq!(config.socks_server.handshake_timeout_ms, value("handshake_timeout_ms"));
+    assert_eq!(config.socks_server.relay_buffer_size as u64, value("relay_buffer_size"));
+    assert_eq!(config.proxy.retry_concurrency as u64, value("retry_concurrency"));
+    assert_eq!(config.proxy.proxy_file, proxy_file.display().to_string());
+}